@@ -1,8 +1,25 @@
 ﻿namespace UTOC.Stream.Emulator.Interfaces;
 public interface IUtocEmulator
 {
+    // engineMinorVersion is only used for UE5 (e.g 3 for 5.3), since container headers and package summaries changed between
+    // 5.0 and 5.3 without a new TOC version
     public void Initialise(TocType? tocType, PakType pakType, string fileIoStoreSig, string readBlockSig, 
-        Action<string> addPakFolder, Action<string> removePakFolder);
+        Action<string> addPakFolder, Action<string> removePakFolder, int engineMinorVersion = 0);
 
     public void AddFromFolder(string modId, string folder);
+
+    // Mount a container at mountPoint (e.g ../../../Game/Plugins/[Plugin]/Content/) instead of the deepest folder that contains
+    // all of it's files. Null goes back to that. Takes effect when the container is next built
+    public void SetMountPoint(string containerName, string? mountPoint);
+
+    // Every file that more than one mod contains, with the mod that won it and the mods it replaced, as JSON. Null if the report
+    // couldn't be made
+    public string? GetConflictReport();
+
+    // Forget every mod folder that was added, e.g after a mod is toggled. Add the folders that are still enabled with AddFromFolder,
+    // then call RebuildContainer
+    public void ResetContainer();
+
+    // Build the container again from the mod folders that have been added since ResetContainer
+    public void RebuildContainer();
 }
@@ -5,7 +5,8 @@
         Initial = 1, // 4.25
         DirectoryIndex = 2, // 4.25+, 4.26
         PartitionSize = 3, // 4.27
-        PerfectHash = 4, // 5.0+
+        PerfectHash = 4, // 5.0
+        PerfectHashWithOverflow = 5, // 5.0+
     }
 
     // UE4 pak type
@@ -95,7 +95,8 @@ public unsafe class Mod : ModBase // <= Do not Remove.
         _hasUtocs = DoesGameUseUtocs(sigs);
 
         _modLoader.GetController<IUtocEmulator>().TryGetTarget(out _utocEmulator);
-        _utocEmulator.Initialise(sigs.TocVersion, sigs.PakVersion, sigs.FileIoStoreOpenContainer, sigs.ReadBlocks, AddPakFolder, RemovePakFolder);
+        _utocEmulator.Initialise(sigs.TocVersion, sigs.PakVersion, sigs.FileIoStoreOpenContainer, sigs.ReadBlocks, AddPakFolder, RemovePakFolder,
+            sigs.EngineMinorVersion);
 
         InitialiseGMalloc(sigs.GMalloc, _hooks);
 
@@ -193,6 +194,10 @@ public unsafe class Mod : ModBase // <= Do not Remove.
         if (!res.Found)
         {
             res = scanner.FindPattern("2B 00 2B 00 75 00 65 00 34 00 2B 00"); // ++ue4+
+            if (!res.Found)
+                res = scanner.FindPattern("2B 00 2B 00 55 00 45 00 35 00 2B 00"); // ++UE5+
+            if (!res.Found)
+                res = scanner.FindPattern("2B 00 2B 00 75 00 65 00 35 00 2B 00"); // ++ue5+
             if (!res.Found)
             {
                 throw new Exception($"Unable to find Unreal Engine version number." +
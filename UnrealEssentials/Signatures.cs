@@ -14,6 +14,7 @@ public struct Signatures
     internal string ReadBlocks { get; set; }
     internal TocType? TocVersion { get; set; }
     internal PakType PakVersion { get; set; }
+    internal int EngineMinorVersion { get; set; } // UE5 only, picks the container header and package summary version
     internal string FileExists { get; set; }
 
     internal static Dictionary<string, Signatures> VersionSigs = new()
@@ -118,6 +119,43 @@ public struct Signatures
                 PakVersion = PakType.Fn64BugFix,
             }
         },
+        // UE5 games are found by branch name, function signatures still need to be found for them
+        {
+            "++UE5+Release-5.0", // 5.0
+            new Signatures
+            {
+                TocVersion = TocType.PerfectHashWithOverflow,
+                PakVersion = PakType.Fn64BugFix,
+                EngineMinorVersion = 0,
+            }
+        },
+        {
+            "++UE5+Release-5.1", // 5.1
+            new Signatures
+            {
+                TocVersion = TocType.PerfectHashWithOverflow,
+                PakVersion = PakType.Fn64BugFix,
+                EngineMinorVersion = 1,
+            }
+        },
+        {
+            "++UE5+Release-5.2", // 5.2
+            new Signatures
+            {
+                TocVersion = TocType.PerfectHashWithOverflow,
+                PakVersion = PakType.Fn64BugFix,
+                EngineMinorVersion = 2,
+            }
+        },
+        {
+            "++UE5+Release-5.3", // 5.3
+            new Signatures
+            {
+                TocVersion = TocType.PerfectHashWithOverflow,
+                PakVersion = PakType.Fn64BugFix,
+                EngineMinorVersion = 3,
+            }
+        },
         {
             "ScarletNexus-Win64-Shipping.exe", // Scarlet Nexus (Modified 4.25+)
             new Signatures
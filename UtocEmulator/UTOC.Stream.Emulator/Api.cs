@@ -6,11 +6,20 @@ public class Api : IUtocEmulator
 {
     private InitialiseDelegate _initialise;
     private Action<string, string> _addFromFolder;
+    private Action<string, string?> _setMountPoint;
+    private Func<string?> _getConflictReport;
+    private Action _resetContainer;
+    private Action _rebuildContainer;
 
-    internal Api(InitialiseDelegate initialise, Action<string, string> addFromFolder)
+    internal Api(InitialiseDelegate initialise, Action<string, string> addFromFolder, Action<string, string?> setMountPoint,
+        Func<string?> getConflictReport, Action resetContainer, Action rebuildContainer)
     {
         _initialise = initialise;
         _addFromFolder = addFromFolder;
+        _setMountPoint = setMountPoint;
+        _getConflictReport = getConflictReport;
+        _resetContainer = resetContainer;
+        _rebuildContainer = rebuildContainer;
     }
 
     public void AddFromFolder(string modId, string folder)
@@ -18,10 +27,32 @@ public class Api : IUtocEmulator
         _addFromFolder(modId, folder);
     }
 
-    public void Initialise(TocType? tocType, PakType pakType, string fileIoStoreSig, string readBlockSig, Action<string> addPakFolder, Action<string> removePakFolder)
+    public void SetMountPoint(string containerName, string? mountPoint)
     {
-        _initialise(tocType, pakType, fileIoStoreSig, readBlockSig, addPakFolder, removePakFolder);
+        _setMountPoint(containerName, mountPoint);
     }
 
-    internal delegate void InitialiseDelegate(TocType? tocType, PakType pakType, string fileIoStoreSig, string readBlockSig, Action<string> addPakFolder, Action<string> removePakFolder);
+    public string? GetConflictReport()
+    {
+        return _getConflictReport();
+    }
+
+    public void ResetContainer()
+    {
+        _resetContainer();
+    }
+
+    public void RebuildContainer()
+    {
+        _rebuildContainer();
+    }
+
+    public void Initialise(TocType? tocType, PakType pakType, string fileIoStoreSig, string readBlockSig, Action<string> addPakFolder, Action<string> removePakFolder,
+        int engineMinorVersion = 0)
+    {
+        _initialise(tocType, pakType, fileIoStoreSig, readBlockSig, addPakFolder, removePakFolder, engineMinorVersion);
+    }
+
+    internal delegate void InitialiseDelegate(TocType? tocType, PakType pakType, string fileIoStoreSig, string readBlockSig, Action<string> addPakFolder, Action<string> removePakFolder,
+        int engineMinorVersion);
 }
@@ -16,6 +16,31 @@ namespace UTOC.Stream.Emulator.Configuration
         [Description("Creates a dump of emulated IO Store files (.utoc + .ucas) as they are written.")]
         [DefaultValue(LogSeverity.Information)]
         public bool DumpFiles { get; set; } = false;
+
+        [DisplayName("Hot Reload")]
+        [Description("Watches mod folders for changed IO Store files and rebuilds the emulated container while the game is running.\nThe game picks up the new container the next time it opens it. Loose files that go into the PAK aren't reloaded.")]
+        [DefaultValue(false)]
+        public bool HotReload { get; set; } = false;
+
+        [DisplayName("Hot Reload Interval")]
+        [Description("How often mod folders are checked for changes when Hot Reload is enabled, in milliseconds.")]
+        [DefaultValue(1000)]
+        public int HotReloadInterval { get; set; } = 1000;
+
+        [DisplayName("Sign Container")]
+        [Description("Writes a hash of each block into the emulated container, for games that only load signed containers.\nThe game's signature check still needs to be patched. Makes building the container slower, since every file has to be copied to be hashed.")]
+        [DefaultValue(false)]
+        public bool SignContainer { get; set; } = false;
+
+        [DisplayName("Partition Size")]
+        [Description("Splits the emulated container into multiple .ucas files of at most this many megabytes, for very large mod sets. 0 keeps it in one file.\nOnly works for games made in Unreal Engine 4.27 or later.")]
+        [DefaultValue(0)]
+        public int PartitionSize { get; set; } = 0;
+
+        [DisplayName("Project Name")]
+        [Description("The name of the game's project folder (e.g P3R for [Game]/P3R/Content), which mods use for files that get mounted at /Game/.\nLeave empty to use the folder that the game's executable is in ([Game]/[Project Name]/Binaries).")]
+        [DefaultValue("")]
+        public string ProjectName { get; set; } = "";
     }
 
     /// <summary>
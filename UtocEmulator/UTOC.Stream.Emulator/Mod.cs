@@ -73,15 +73,27 @@ namespace UTOC.Stream.Emulator
             _log = new Logger(_logger, _configuration.LogLevel);
 
             // Expose API
-            _api = new Api(Initialise, (modId, folder) => _emu.AddFromFolder(modId, folder));
+            _api = new Api(
+                Initialise, (modId, folder) => _emu.AddFromFolder(modId, folder),
+                (containerName, mountPoint) => _emu.SetMountPoint(containerName, mountPoint),
+                () => _emu.GetConflictReport(),
+                () => _emu.ResetContainer(),
+                () => _emu.OnLoaderInit()
+            );
             _modLoader.AddOrReplaceController(context.Owner, _api);
         }
 
-        public void Initialise(TocType? tocType, PakType pakType, string fileIoStoreSig, string readBlockSig, Action<string> addPakFolder, Action<string> removePakFolder)
+        public void Initialise(TocType? tocType, PakType pakType, string fileIoStoreSig, string readBlockSig, Action<string> addPakFolder, Action<string> removePakFolder,
+            int engineMinorVersion)
         {
             _log.Info("Starting UTOC.Stream.Emulator");
             _emu = new UtocEmulator(
                 _log, _configuration.DumpFiles, _modLoader.GetDirectoryForModId(_modConfig.ModId), addPakFolder);
+            _emu.HotReload = _configuration.HotReload;
+            _emu.HotReloadInterval = _configuration.HotReloadInterval;
+            _emu.SignContainer = _configuration.SignContainer;
+            _emu.PartitionSize = (long)Math.Max(_configuration.PartitionSize, 0) * 1024 * 1024;
+            _emu.ProjectName = GetProjectName();
 
             _modLoader.ModLoading += OnModLoading;
             _modLoader.OnModLoaderInitialized += OnLoaderInit;
@@ -90,6 +102,7 @@ namespace UTOC.Stream.Emulator
             _modLoader.GetController<IStartupScanner>().TryGetTarget(out var scanFactory);
             _emu.TocVersion = tocType; // Set Toc Version
             _emu.PakVersion = pakType; // Set Pak Version
+            _emu.EngineMinorVersion = engineMinorVersion;
             framework!.Register(_emu);
             BaseAddress = Process.GetCurrentProcess().MainModule.BaseAddress;
             ContainerFileSizeOverride(scanFactory, fileIoStoreSig, readBlockSig);
@@ -128,6 +141,20 @@ namespace UTOC.Stream.Emulator
             }
         }
 
+        // Packaged games keep their executable in [Game]/[Project Name]/Binaries/[Platform]
+        private string? GetProjectName()
+        {
+            if (!string.IsNullOrEmpty(_configuration.ProjectName))
+                return _configuration.ProjectName;
+            var binariesDir = Path.GetDirectoryName(Path.GetDirectoryName(Process.GetCurrentProcess().MainModule?.FileName));
+            if (binariesDir == null || !Path.GetFileName(binariesDir).Equals("Binaries", StringComparison.OrdinalIgnoreCase))
+            {
+                _log.Info("[UtocEmulator] Couldn't find the game's project name, any folder besides Engine will be mounted at /Game/");
+                return null;
+            }
+            return Path.GetFileName(Path.GetDirectoryName(binariesDir));
+        }
+
         private void OnLoaderInit()
         {
             _modLoader.OnModLoaderInitialized -= OnLoaderInit;
@@ -141,9 +168,10 @@ namespace UTOC.Stream.Emulator
             var returnValue = _openContainerHook.OriginalFunction(thisPtr, containerFilePath, containerFileHandle, containerFileSize);
             unsafe
             {
-                if (Marshal.PtrToStringUni((nint)containerFilePath).Contains($"{Constants.UnrealEssentialsName}{Constants.UcasExtension}"))
+                var casStream = _emu.GetCasStream(Marshal.PtrToStringUni((nint)containerFilePath)!);
+                if (casStream != null)
                 {
-                    *(long*)containerFileSize = _emu.CasStream.Length;
+                    *(long*)containerFileSize = casStream.Length;
                 }
             }
             return returnValue;
@@ -151,11 +179,13 @@ namespace UTOC.Stream.Emulator
 
         public unsafe void ReadBlocks(nuint thisPtr, FFileIoStoreResolvedRequest* ResolvedRequest)
         { // This is a temporary measure due to a bug in FileEmulationFramework
-            var currentContainer = ResolvedRequest->ContainerFile->Partitions;
+            var partitions = ResolvedRequest->ContainerFile->Partitions;
             var name = Marshal.PtrToStringUni((nint)ResolvedRequest->ContainerFile->FilePath);
-            if (name.Contains(Constants.UnrealEssentialsName))
+            var casStreams = _emu.GetCasStreams(name!);
+            if (casStreams != null)
             {
-                currentContainer->FileSize = _emu.CasStream.Length;
+                for (int i = 0; i < casStreams.Count; i++)
+                    partitions[i].FileSize = casStreams[i].Length;
             }
             _readBlocksHook.OriginalFunction(thisPtr, ResolvedRequest);
         }
@@ -196,7 +226,7 @@ namespace UTOC.Stream.Emulator
         [FieldOffset(0x88)] public FFileIoStoreContainerFilePartition* Partitions;
     }
 
-    [StructLayout(LayoutKind.Explicit, Size = 0x10)]
+    [StructLayout(LayoutKind.Explicit, Size = 0x28)] // FileHandle, FileSize, ContainerFileIndex, FilePath, MappedFileHandle
     public unsafe struct FFileIoStoreContainerFilePartition
     {
         [FieldOffset(0x8)] public long FileSize;
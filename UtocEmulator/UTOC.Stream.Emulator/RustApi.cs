@@ -7,17 +7,33 @@ using System.Threading.Tasks;
 
 namespace UTOC.Stream.Emulator
 {
+    // Must be kept in sync with ErrorCode in error.rs
+    public enum ErrorCode : uint
+    {
+        Success = 0,
+        InvalidArgument,
+        Io,
+        UnsupportedVersion,
+        NoModFiles,
+        InvalidAsset,
+        Serialization,
+        InvalidValue
+    }
+
     public static class RustApi
     {
 
         [DllImport("fileemu_utoc_stream_emulator")] // Collect assets
-        public static extern void AddFromFolders(string mod_id, string mod_path);
+        public static extern ErrorCode AddFromFolders(string mod_id, string mod_path);
 
-        [DllImport("fileemu_utoc_stream_emulator")] // Build UTOC
-        public static extern IntPtr BuildTableOfContents(string tocPath, IntPtr settings, uint settingsLength, ref long length);
+        [DllImport("fileemu_utoc_stream_emulator")] // Collect assets into [containerName].utoc instead of UnrealEssentials
+        public static extern ErrorCode AddFromFoldersToContainer(string mod_id, string mod_path, string containerName);
 
-        [DllImport("fileemu_utoc_stream_emulator")] // Build UCAS
-        public static extern bool GetContainerBlocks(string casPath, ref nint blocks, ref nint blockCount, ref nint header, ref nint headerSize);
+        [DllImport("fileemu_utoc_stream_emulator")] // Same as AddFromFoldersToContainer, mapping [projectName]/Content onto /Game/ (null for any folder besides Engine)
+        public static extern ErrorCode AddFromFoldersForProject(string mod_id, string mod_path, string containerName, string? projectName);
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Build UTOC
+        public static extern ErrorCode BuildTableOfContents(string tocPath, uint version, ref nint handle, ref nint tocData, ref long length);
 
         /*
         [DllImport("fileemu_utoc_stream_emulator")]
@@ -28,9 +44,73 @@ namespace UTOC.Stream.Emulator
         public static extern void PrintAssetCollectorResults();
 
         [DllImport("fileemu_utoc_stream_emulator")]
-        public static extern bool BuildTableOfContentsEx(
-            string basePath, uint version, ref nint tocData, ref nint tocLength,
-            ref nint blocks, ref nint blockCount, ref nint header, ref nint headerSize
+        public static extern ErrorCode BuildTableOfContentsEx(
+            string basePath, uint version, ref nint handle, ref nint tocData, ref nint tocLength,
+            ref nint blocks, ref nint blockCount, ref nint header, ref nint headerSize, ref long headerOffset
+        );
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Build the TOC for a container that mods were added to with AddFromFoldersToContainer
+        public static extern ErrorCode BuildContainerTableOfContents(
+            string basePath, string containerName, uint version, ref nint handle, ref nint tocData, ref nint tocLength,
+            ref nint blocks, ref nint blockCount, ref nint header, ref nint headerSize, ref long headerOffset
         );
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Number of .ucas files the container is split into
+        public static extern uint GetContainerPartitionCount(nint handle);
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Blocks for the partition read from [container]_s[partition].ucas
+        public static extern ErrorCode GetContainerPartition(
+            nint handle, uint partition, ref nint blocks, ref nint blockCount, ref nint header, ref nint headerSize, ref long headerOffset
+        );
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Build PAK for files that can't go into IO Store
+        public static extern ErrorCode BuildPak(string basePath, uint version, ref nint handle, ref nint blocks, ref nint blockCount);
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Free a TOC and it's container data once nothing is reading from them
+        public static extern void FreeTableOfContents(nint handle);
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Free a PAK once nothing is reading from it
+        public static extern void FreePak(nint handle);
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Forget every added mod folder so that the container can be rebuilt
+        public static extern void ResetAssetCollector();
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Forget the mod folders added to one container, leaving the others as they are
+        public static extern ErrorCode ResetContainer(string containerName);
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Write block hashes into the emulated TOC
+        public static extern void SetContainerSigning([MarshalAs(UnmanagedType.U1)] bool sign);
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Split the emulated container into partitions of this size (0 for one .ucas)
+        public static extern void SetPartitionSize(ulong partitionSize);
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Mount a container at this path instead of working it out from it's files (null to reset)
+        public static extern ErrorCode SetMountPoint(string containerName, string? mountPoint);
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Watch added mod folders and rebuild the TOC when their files change
+        public static extern ErrorCode StartHotReload(string basePath, uint version, uint pollInterval);
+
+        [DllImport("fileemu_utoc_stream_emulator")]
+        public static extern void StopHotReload();
+
+        [DllImport("fileemu_utoc_stream_emulator")] // handle is 0 if the TOC hasn't been rebuilt since the last call
+        public static extern ErrorCode GetHotReloadedTableOfContents(
+            ref nint handle, ref nint tocData, ref nint tocLength,
+            ref nint blocks, ref nint blockCount, ref nint header, ref nint headerSize, ref long headerOffset
+        );
+
+        [DllImport("fileemu_utoc_stream_emulator")] // Same as GetHotReloadedTableOfContents, for any container
+        public static extern ErrorCode GetHotReloadedContainer(
+            string containerName, ref nint handle, ref nint tocData, ref nint tocLength,
+            ref nint blocks, ref nint blockCount, ref nint header, ref nint headerSize, ref long headerOffset
+        );
+
+        [DllImport("fileemu_utoc_stream_emulator")] // JSON list of files that more than one mod contains, valid until the next call
+        public static extern ErrorCode GetConflictReport(ref nint report);
+
+        [DllImport("fileemu_utoc_stream_emulator", EntryPoint = "GetLastError")] // Message for the last call that didn't return ErrorCode.Success
+        private static extern IntPtr GetLastErrorNative();
+
+        public static string GetLastError() => Marshal.PtrToStringUTF8(GetLastErrorNative()) ?? "Unknown error";
     }
 }
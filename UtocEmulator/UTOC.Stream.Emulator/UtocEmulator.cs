@@ -18,18 +18,51 @@ namespace UTOC.Stream.Emulator
         public long start; // u64
         public long length; // u64
     }
+
+    // Must be kept in sync with PakBlock in pak_factory.rs
+    public struct PakBlock
+    {
+        public IntPtr osPath; // *const u8 (null if the block is in memory)
+        public IntPtr data; // *const u8 (null if the block is read from osPath)
+        public long start; // u64
+        public long length; // u64
+    }
+
+    // A container built from the mod folders that target the same name, which is served as [Name].utoc and [Name].ucas
+    internal class EmulatedContainer
+    {
+        public string Name { get; init; } = "";
+        public nint Handle; // TOC built by the Rust side, which owns the memory that the streams read from
+        public Strim? TocStream { get; set; }
+        public List<Strim> CasStreams { get; set; } = new(); // [Name].ucas, then [Name]_s1.ucas...
+    }
+
     public class UtocEmulator : IEmulator
     {
         public bool DumpFiles { get; set; }
+        public bool HotReload { get; set; }
+        public int HotReloadInterval { get; set; }
+        public bool SignContainer { get; set; }
+        public long PartitionSize { get; set; }
+        public string? ProjectName { get; set; }
         public Logger _logger { get; init; }
         public TocType? TocVersion { get; set; }
         public PakType PakVersion { get; set; }
-        public Strim? TocStream { get; set; }
-        public Strim? CasStream { get; set; }
+        public int EngineMinorVersion { get; set; }
+        public Strim? PakStream { get; set; }
         private string ModPath { get; init; }
         private string ModTargetFilesDirectory { get; init; }
         private string ModDummyPakFilesDirectory { get; init; }
         public Action<string> AddPakFolderCb { get; set; }
+        // Every container that mods have been added to. UnrealEssentials is always built
+        private readonly SortedSet<string> _containerNames = new(StringComparer.OrdinalIgnoreCase) { Constants.UnrealEssentialsName };
+        // Containers that were built, by name
+        private readonly Dictionary<string, EmulatedContainer> _containers = new(StringComparer.OrdinalIgnoreCase);
+        // Handle to the PAK built by the Rust side, which owns the memory that PakStream reads from
+        private nint _pakHandle;
+        // TOCs replaced by hot reload. The game may still be reading from them, so they're only freed when the container is released
+        private readonly List<nint> _retiredTocHandles = new();
+        private Timer? _hotReloadTimer;
 
         private readonly ConcurrentDictionary<string, Strim?> _pathToStream = new(StringComparer.OrdinalIgnoreCase);
 
@@ -63,8 +96,9 @@ namespace UTOC.Stream.Emulator
         {
             stream = null;
             _pathToStream[path] = null; // Avoid recursion into the same file
-            if (!path.Contains(ModTargetFilesDirectory) || TocStream == null) return false;
-            stream = TocStream;
+            if (!path.Contains(ModTargetFilesDirectory)) return false;
+            stream = GetContainer(path, out _)?.TocStream;
+            if (stream == null) return false;
             _pathToStream.TryAdd(path, stream);
             emulated = new EmulatedFile<Strim>(stream);
             _logger.Info($"[UtocEmulator] Created Emulated Table of Contents with Path {path}");
@@ -73,30 +107,112 @@ namespace UTOC.Stream.Emulator
             return true;
         }
 
-        public List<StreamOffsetPair<Strim>> CreateContainerStream(nint blockPtr, int blockCount, nint headerPtr, int headerSize)
+        public List<StreamOffsetPair<Strim>> CreateContainerStream(nint blockPtr, int blockCount, nint headerPtr, int headerSize, long headerOffset)
         {
             var streams = new List<StreamOffsetPair<Strim>>();
             long streamEnd = 0;
+            // Blocks are laid out by the TOC builder in order, fill in any alignment gaps between them with padding
+            void AddSegment(Strim segment, long start, long length)
+            {
+                if (start > streamEnd)
+                    streams.Add(new(new PaddingStream(0, (int)(start - streamEnd)), OffsetRange.FromStartAndLength(streamEnd, start - streamEnd)));
+                streams.Add(new(segment, OffsetRange.FromStartAndLength(start, length)));
+                streamEnd = start + length;
+            }
+            // The container header goes first in 4.25+/4.26 and last in 4.27. 4.25 doesn't have one
+            bool headerPlaced = headerSize == 0;
+            void AddHeader()
+            {
+                unsafe { AddSegment(new UnmanagedMemoryStream((byte*)headerPtr, headerSize), headerOffset, headerSize); }
+                headerPlaced = true;
+            }
             for (int i = 0; i < blockCount; i++)
             {
                 var containerBlock = Marshal.PtrToStructure<PartitionBlock>(blockPtr);
-                streams.Add(new(
-                    new FileStream(Marshal.PtrToStringAnsi(containerBlock.osPath), FileMode.Open),
-                    OffsetRange.FromStartAndLength(containerBlock.start, containerBlock.length)
-                ));
-                var containerBlockEnd = containerBlock.start + containerBlock.length;
-                var diff = Mathematics.RoundUp(containerBlockEnd, Constants.DefaultCompressionBlockAlignment) - containerBlockEnd;
-                if (diff > 0)
-                    streams.Add(new(new PaddingStream(0, (int)diff), OffsetRange.FromStartAndLength(containerBlockEnd, diff)));
+                if (!headerPlaced && headerOffset <= containerBlock.start)
+                    AddHeader();
+                // Mod files can be changed or deleted while hot reload is on, which mustn't be blocked by the mounted container
+                AddSegment(
+                    new FileStream(Marshal.PtrToStringAnsi(containerBlock.osPath)!, FileMode.Open, FileAccess.Read, FileShare.ReadWrite | FileShare.Delete),
+                    containerBlock.start, containerBlock.length
+                );
                 unsafe { blockPtr += sizeof(PartitionBlock); }
-                streamEnd = Mathematics.RoundUp(containerBlockEnd, Constants.DefaultCompressionBlockAlignment);
             }
-            unsafe
+            if (!headerPlaced)
+                AddHeader();
+            return streams;
+        }
+
+        // Make a stream for each partition of the container. The first partition's blocks come from building the TOC, the others are
+        // read from it's handle. The game opens each partition from disk, so an empty placeholder is made for any that don't exist
+        private List<Strim> CreateContainerStreams(string name, nint handle, nint blockPtr, nint blockCount, nint headerPtr, nint headerSize, long headerOffset)
+        {
+            var streams = new List<Strim> { new MultiStream(CreateContainerStream(blockPtr, (int)blockCount, headerPtr, (int)headerSize, headerOffset), _logger) };
+            var partitionCount = RustApi.GetContainerPartitionCount(handle);
+            for (uint i = 1; i < partitionCount; i++)
+            {
+                var result = RustApi.GetContainerPartition(handle, i, ref blockPtr, ref blockCount, ref headerPtr, ref headerSize, ref headerOffset);
+                if (result != ErrorCode.Success)
+                {
+                    _logger.Error($"[UtocEmulator] Failed to get container partition {i} ({result}): {RustApi.GetLastError()}");
+                    break;
+                }
+                streams.Add(new MultiStream(CreateContainerStream(blockPtr, (int)blockCount, headerPtr, (int)headerSize, headerOffset), _logger));
+                CreatePlaceholder($"{name}_s{i}{Constants.UcasExtension}");
+            }
+            if (streams.Count > 1)
+                _logger.Info($"[UtocEmulator] Split {name} into {streams.Count} partitions");
+            return streams;
+        }
+
+        private void CreatePlaceholder(string fileName)
+        {
+            var path = Path.Combine(ModTargetFilesDirectory, fileName);
+            if (!File.Exists(path))
+                File.Create(path).Dispose();
+        }
+
+        // [Name].utoc, [Name].ucas and [Name]_s1.ucas all belong to the container called Name. Container names can't end in _s[number],
+        // so the partition suffix is never part of the name
+        private EmulatedContainer? GetContainer(string path, out int partition)
+        {
+            var name = Path.GetFileNameWithoutExtension(path);
+            var separator = name.LastIndexOf("_s", StringComparison.OrdinalIgnoreCase);
+            if (separator != -1 && int.TryParse(name[(separator + 2)..], out partition))
+                name = name[..separator];
+            else
+                partition = 0;
+            return _containers.TryGetValue(name, out var container) ? container : null;
+        }
+
+        // Every partition of the emulated container that a path in the target files folder belongs to
+        public List<Strim>? GetCasStreams(string path)
+        {
+            if (!path.Contains(ModTargetFilesDirectory)) return null;
+            return GetContainer(path, out _)?.CasStreams;
+        }
+
+        // UnrealEssentials.ucas is the first partition, UnrealEssentials_s1.ucas is the second and so on
+        public Strim? GetCasStream(string path)
+        {
+            if (!path.Contains(ModTargetFilesDirectory)) return null;
+            var container = GetContainer(path, out var partition);
+            return container != null && partition < container.CasStreams.Count ? container.CasStreams[partition] : null;
+        }
+
+        public List<StreamOffsetPair<Strim>> CreatePakStream(nint blockPtr, int blockCount)
+        {
+            var streams = new List<StreamOffsetPair<Strim>>();
+            for (int i = 0; i < blockCount; i++)
             {
-                streams.Add(new(
-                    new UnmanagedMemoryStream((byte*)headerPtr, headerSize),
-                    OffsetRange.FromStartAndLength(streamEnd, (long)headerSize)
-                ));
+                var pakBlock = Marshal.PtrToStructure<PakBlock>(blockPtr);
+                Strim segment;
+                if (pakBlock.osPath != IntPtr.Zero)
+                    segment = new FileStream(Marshal.PtrToStringAnsi(pakBlock.osPath)!, FileMode.Open, FileAccess.Read, FileShare.ReadWrite | FileShare.Delete);
+                else
+                    unsafe { segment = new UnmanagedMemoryStream((byte*)pakBlock.data, pakBlock.length); }
+                streams.Add(new(segment, OffsetRange.FromStartAndLength(pakBlock.start, pakBlock.length)));
+                unsafe { blockPtr += sizeof(PakBlock); }
             }
             return streams;
         }
@@ -105,8 +221,8 @@ namespace UTOC.Stream.Emulator
         {
             stream = null;
             _pathToStream[path] = null;
-            if (!path.Contains(ModTargetFilesDirectory) || CasStream == null) return false;
-            stream = CasStream;
+            stream = GetCasStream(path);
+            if (stream == null) return false;
             _pathToStream.TryAdd(path, stream);
             emulated = new EmulatedFile<Strim>(stream);
             _logger.Info($"[UtocEmulator] Created Emulated Container with Path {path}");
@@ -132,7 +248,10 @@ namespace UTOC.Stream.Emulator
             stream = null;
             _pathToStream[path] = null;
             if (!path.Contains(ModTargetFilesDirectory)) return false;
-            stream = GetDummyPak();
+            // Loose files only go into UnrealEssentials.pak. Other containers, or UnrealEssentials if there weren't any loose files that
+            // needed to go into a PAK, use the dummy PAK
+            var isDefaultPak = Path.GetFileNameWithoutExtension(path).Equals(Constants.UnrealEssentialsName, StringComparison.OrdinalIgnoreCase);
+            stream = isDefaultPak && PakStream != null ? PakStream : GetDummyPak();
             _pathToStream.TryAdd(path, stream);
             emulated = new EmulatedFile<Strim>(stream);
             _logger.Info($"[UtocEmulator] Created Emulated IO Store PAK with Path {path}");
@@ -189,12 +308,162 @@ namespace UTOC.Stream.Emulator
             _logger.Info($"[UtocEmulator] Container Written To {path}");
         }
 
-        public void OnModLoading(string mod_id, string dir_path) => RustApi.AddFromFolders(mod_id, Path.Combine(dir_path, "UTOC", "UnrealEssentials.utoc"));
+        // Each UTOC/[Name].utoc folder in a mod goes into the emulated container called Name
+        public void OnModLoading(string mod_id, string dir_path)
+        {
+            var utocPath = Path.Combine(dir_path, "UTOC");
+            if (!Directory.Exists(utocPath)) return;
+            foreach (var containerPath in Directory.EnumerateDirectories(utocPath, $"*{Constants.UtocExtension}"))
+                AddFromFolder(mod_id, containerPath, Path.GetFileNameWithoutExtension(containerPath));
+        }
+
+        public void AddFromFolder(string mod_id, string dir_path) => AddFromFolder(mod_id, dir_path, Constants.UnrealEssentialsName);
+
+        public void AddFromFolder(string mod_id, string dir_path, string containerName)
+        {
+            var result = RustApi.AddFromFoldersForProject(mod_id, dir_path, containerName, ProjectName);
+            if (result != ErrorCode.Success)
+            {
+                _logger.Error($"[UtocEmulator] Failed to add files from {dir_path} for {mod_id} ({result}): {RustApi.GetLastError()}");
+                return;
+            }
+            _containerNames.Add(containerName);
+        }
+
+        // Mount a container at mountPoint (e.g ../../../Game/Plugins/[Plugin]/Content/) when it's next built. Null mounts it at the
+        // deepest folder that contains all of it's files
+        public void SetMountPoint(string containerName, string? mountPoint)
+        {
+            var result = RustApi.SetMountPoint(containerName, mountPoint);
+            if (result != ErrorCode.Success)
+                _logger.Error($"[UtocEmulator] Failed to set the mount point for {containerName} ({result}): {RustApi.GetLastError()}");
+        }
 
-        public void AddFromFolder(string mod_id, string dir_path) => RustApi.AddFromFolders(mod_id, dir_path);
+        // Every file that more than one mod contains, with the mod that won it and the mods it replaced, as JSON. Null if the
+        // report couldn't be made
+        public string? GetConflictReport()
+        {
+            nint report = 0;
+            var result = RustApi.GetConflictReport(ref report);
+            if (result != ErrorCode.Success)
+            {
+                _logger.Error($"[UtocEmulator] Failed to get the conflict report ({result}): {RustApi.GetLastError()}");
+                return null;
+            }
+            return Marshal.PtrToStringUTF8(report);
+        }
+
+        // Dispose of the emulated streams and free the data they were reading from, so that the container can be rebuilt
+        public void ReleaseContainer()
+        {
+            StopHotReload();
+            lock (_retiredTocHandles) // wait for a hot reload swap that's in progress
+            {
+                foreach (var stream in _pathToStream.Values)
+                    stream?.Dispose();
+                _pathToStream.Clear();
+                foreach (var container in _containers.Values)
+                {
+                    container.TocStream?.Dispose();
+                    foreach (var casStream in container.CasStreams)
+                        casStream.Dispose();
+                    RustApi.FreeTableOfContents(container.Handle);
+                }
+                _containers.Clear();
+                PakStream?.Dispose();
+                PakStream = null;
+                RustApi.FreePak(_pakHandle);
+                foreach (var retiredHandle in _retiredTocHandles)
+                    RustApi.FreeTableOfContents(retiredHandle);
+                _retiredTocHandles.Clear();
+                _pakHandle = 0;
+            }
+        }
+
+        private void StartHotReload()
+        {
+            var result = RustApi.StartHotReload(ModTargetFilesDirectory, GetIoStoreVersion(), (uint)Math.Max(HotReloadInterval, 0));
+            if (result != ErrorCode.Success)
+            {
+                _logger.Error($"[UtocEmulator] Failed to start hot reload ({result}): {RustApi.GetLastError()}");
+                return;
+            }
+            var interval = HotReloadInterval > 0 ? HotReloadInterval : 1000;
+            _hotReloadTimer = new Timer(_ => PollHotReload(), null, interval, interval);
+            _logger.Info($"[UtocEmulator] Watching mod folders for changes every {interval}ms");
+        }
+
+        private void StopHotReload()
+        {
+            if (_hotReloadTimer == null) return;
+            _hotReloadTimer.Dispose();
+            _hotReloadTimer = null;
+            RustApi.StopHotReload();
+        }
+
+        // Swap in the TOCs rebuilt by the hot reload thread, if there are any. Streams that were already handed out keep reading from the
+        // old container, new ones get the rebuilt one
+        private void PollHotReload()
+        {
+            lock (_retiredTocHandles)
+            {
+                foreach (var container in _containers.Values)
+                    PollHotReload(container);
+            }
+        }
+
+        private void PollHotReload(EmulatedContainer container)
+        {
+            nint handle = 0;
+            nint tocLength = 0;
+            nint tocData = 0;
+            nint blockPtr = 0;
+            nint blockCount = 0;
+            nint headerPtr = 0;
+            nint headerSize = 0;
+            long headerOffset = 0;
+            var result = RustApi.GetHotReloadedContainer(
+                container.Name, ref handle, ref tocData, ref tocLength,
+                ref blockPtr, ref blockCount, ref headerPtr, ref headerSize, ref headerOffset
+            );
+            if (result != ErrorCode.Success)
+            {
+                _logger.Error($"[UtocEmulator] Hot reload failed to rebuild IO Store data for {container.Name} ({result}): {RustApi.GetLastError()}");
+                return;
+            }
+            if (handle == 0) return;
+            if (container.Handle != 0)
+                _retiredTocHandles.Add(container.Handle);
+            container.Handle = handle;
+            unsafe
+            {
+                container.TocStream = new UnmanagedMemoryStream((byte*)tocData, (long)tocLength);
+            }
+            container.CasStreams = CreateContainerStreams(container.Name, handle, blockPtr, blockCount, headerPtr, headerSize, headerOffset);
+            foreach (var path in _pathToStream.Keys)
+            {
+                if (GetContainer(path, out _) != container)
+                    continue;
+                if (path.EndsWith(Constants.UtocExtension, StringComparison.OrdinalIgnoreCase))
+                    _pathToStream[path] = container.TocStream;
+                else if (path.EndsWith(Constants.UcasExtension, StringComparison.OrdinalIgnoreCase))
+                    _pathToStream[path] = GetCasStream(path);
+            }
+            _logger.Info($"[UtocEmulator] Hot reload rebuilt {container.Name}");
+        }
+
+        // Release the current container and forget every mod folder. Mods need to be added again before calling MakeFilesOnInit
+        public void ResetContainer()
+        {
+            ReleaseContainer();
+            RustApi.ResetAssetCollector();
+            _containerNames.Clear();
+            _containerNames.Add(Constants.UnrealEssentialsName);
+        }
 
         public void MakeFilesOnInit() // from base Unreal Essentials path
         {
+            ReleaseContainer();
             if (TocVersion == null)
             {
                 _logger.Info($"[UtocEmulator] Toc Version was not provided, stopping here");
@@ -205,37 +474,93 @@ namespace UTOC.Stream.Emulator
                 _logger.Info($"[UtocEmulator] Pak version {PakVersion} is too old, stopping here");
                 return;
             }
+            nint pakBlockPtr = 0;
+            nint pakBlockCount = 0;
+            var pakResult = RustApi.BuildPak(ModTargetFilesDirectory, (uint)PakVersion, ref _pakHandle, ref pakBlockPtr, ref pakBlockCount);
+            if (pakResult != ErrorCode.Success)
+                _logger.Error($"[UtocEmulator] An error occurred while making the PAK ({pakResult}): {RustApi.GetLastError()}");
+            else if (pakBlockCount > 0)
+                PakStream = new MultiStream(CreatePakStream(pakBlockPtr, (int)pakBlockCount), _logger);
+            RustApi.SetContainerSigning(SignContainer);
+            RustApi.SetPartitionSize((ulong)Math.Max(PartitionSize, 0));
+            foreach (var name in _containerNames)
+            {
+                var container = BuildContainer(name);
+                if (container != null)
+                    _containers[name] = container;
+            }
+            RemoveStalePlaceholders();
+            if (_containers.Count == 0)
+            {
+                _logger.Info($"[UtocEmulator] No IO store files found, not creating emulated file.");
+                // Loose files in the PAK can still be loaded without a container
+                if (PakStream != null)
+                    AddPakFolderCb(ModTargetFilesDirectory);
+                return;
+            }
+            AddPakFolderCb(ModTargetFilesDirectory);
+            if (HotReload)
+                StartHotReload();
+        }
+
+        // Remove placeholders for containers or partitions that aren't used anymore, so that the game doesn't try to mount them.
+        // Placeholders are always empty, so nothing else in the folder gets removed
+        private void RemoveStalePlaceholders()
+        {
+            foreach (var path in Directory.EnumerateFiles(ModTargetFilesDirectory))
+            {
+                if (Path.GetFileNameWithoutExtension(path).Equals(Constants.UnrealEssentialsName, StringComparison.OrdinalIgnoreCase)) continue;
+                var container = GetContainer(path, out var partition);
+                var isUsed = container != null && partition < container.CasStreams.Count;
+                if (!isUsed && new FileInfo(path).Length == 0)
+                    File.Delete(path);
+            }
+        }
+
+        // The TOC version goes in the lowest byte and the engine's minor version in the next one, which picks the UE5 container header
+        private uint GetIoStoreVersion() => (uint)TocVersion! | ((uint)Math.Clamp(EngineMinorVersion, 0, 0xff) << 8);
+
+        // Returns null if the container couldn't be built or doesn't have any IO Store files
+        private EmulatedContainer? BuildContainer(string name)
+        {
+            var container = new EmulatedContainer { Name = name };
             nint tocLength = 0;
             nint tocData = 0;
             nint blockPtr = 0;
             nint blockCount = 0;
             nint headerPtr = 0;
             nint headerSize = 0;
-            var result = RustApi.BuildTableOfContentsEx(
-                ModTargetFilesDirectory, (uint)TocVersion, ref tocData, ref tocLength,
-                ref blockPtr, ref blockCount, ref headerPtr, ref headerSize
+            long headerOffset = 0;
+            var result = RustApi.BuildContainerTableOfContents(
+                ModTargetFilesDirectory, name, GetIoStoreVersion(), ref container.Handle, ref tocData, ref tocLength,
+                ref blockPtr, ref blockCount, ref headerPtr, ref headerSize, ref headerOffset
             );
-            if (!result)
+            if (result != ErrorCode.Success && result != ErrorCode.NoModFiles)
             {
-                _logger.Info($"[UtocEmulator] An error occurred while making IO Store data");
-                return;
+                _logger.Error($"[UtocEmulator] An error occurred while making IO Store data for {name} ({result}): {RustApi.GetLastError()}");
+                return null;
             }
-            if(blockCount == 0)
+            if (result == ErrorCode.NoModFiles || blockCount == 0)
             {
-                _logger.Info($"[UtocEmulator] No IO store files found, not creating emulated file.");
-                return;
+                _logger.Info($"[UtocEmulator] No IO store files found for {name}");
+                RustApi.FreeTableOfContents(container.Handle);
+                return null;
             }
             unsafe
             {
-                TocStream = new UnmanagedMemoryStream((byte*)tocData, (long)tocLength);
-                CasStream = new MultiStream(CreateContainerStream(blockPtr, (int)blockCount, headerPtr, (int)headerSize), _logger);
+                container.TocStream = new UnmanagedMemoryStream((byte*)tocData, (long)tocLength);
             }
-            AddPakFolderCb(ModTargetFilesDirectory);
+            container.CasStreams = CreateContainerStreams(name, container.Handle, blockPtr, blockCount, headerPtr, headerSize, headerOffset);
+            // The game only mounts containers that it finds on disk, UnrealEssentials' placeholders are shipped with the mod
+            foreach (var extension in new[] { Constants.UtocExtension, Constants.UcasExtension, Constants.PakExtension })
+                CreatePlaceholder($"{name}{extension}");
+            return container;
         }
         public void OnLoaderInit()
         {
-            RustApi.PrintAssetCollectorResults();
             MakeFilesOnInit();
+            // Printed afterwards so that files dropped while building the TOC are listed with their mod
+            RustApi.PrintAssetCollectorResults();
         }
     }
 }
@@ -1,11 +1,13 @@
 use crate::{
+    error::{EmulatorError, EmulatorResult},
     io_package,
     platform::Metadata,
-    toc_factory::TARGET_TOC
+    toc_factory::{ENGINE_NAME, TOC_NAME}
 };
 use std::{
     cell::RefCell,
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
     fs, fs::File,
     io::BufReader,
     path::{Path, PathBuf},
@@ -13,35 +15,132 @@ use std::{
     sync::{Arc, Mutex, RwLock, Weak},
     time::Instant
 };
+use serde::Serialize;
 
 pub type TocDirectorySyncRef = Arc<RwLock<TocDirectory>>;
 pub type TocFileSyncRef = Arc<RwLock<TocFile>>;
 
-pub const EMULATOR_NAME:                    &'static str = "UTOC";
+pub const EMULATOR_NAME:                    &str = "UTOC";
 //pub const PROJECT_NAME:                     &'static str = "UnrealEssentials";
 
-pub static ROOT_DIRECTORY: Mutex<Option<TocDirectorySyncRef>> = Mutex::new(None);
+// Every container that mods have been added to, with it's own tree of files. Each one is built into a separate TOC and CAS
+pub static ROOT_DIRECTORIES: Mutex<BTreeMap<String, TocDirectorySyncRef>> = Mutex::new(BTreeMap::new());
+// Loose files that can't go into IO Store are collected into a separate tree which is used to build the emulated PAK
+pub static PAK_ROOT_DIRECTORY: Mutex<Option<TocDirectorySyncRef>> = Mutex::new(None);
 pub static ASSET_COLLECTOR_PROFILER: Mutex<Option<AssetCollectorProfiler>> = Mutex::new(None);
+// Every mod folder that's been added, from lowest to highest priority. Used to find which mod a file should come from when hot reloading
+pub static MOD_FOLDERS: Mutex<Vec<ModFolder>> = Mutex::new(vec![]);
+// Held for the whole time that a container's tree is being changed or built into a TOC, so that hot reload can't change a tree
+// while it's being built. Keyed by the lowercase container name, since container names are case insensitive. Take this before
+// any of the other locks in the asset collector
+static CONTAINER_LOCKS: Mutex<BTreeMap<String, Arc<Mutex<()>>>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModFolder {
+    pub mod_id: String,
+    pub os_path: PathBuf,
+    pub container: String, // name of the container that the mod's files went into
+    pub project_name: Option<String> // the mod's project folder, whose Content folder gets mapped onto /Game/ (see get_game_name)
+}
 
 // Create tree of assets that can be used to build a TOC
-pub fn add_from_folders(mod_id: &str, mod_path: &str) {
-    println!("add from folders: {}, {}", mod_id, mod_path);
+pub fn add_from_folders(mod_id: &str, mod_path: &str) -> EmulatorResult<()> {
+    add_from_folders_to_container(mod_id, mod_path, TOC_NAME)
+}
+
+// Container names become the TOC and CAS file names, so they can't contain a path. Names ending in _s[number] are taken, since
+// that's how the partitions of another container are named
+fn check_container_name(container_name: &str) -> EmulatorResult<()> {
+    let is_partition_name = container_name.rsplit_once("_s")
+        .is_some_and(|(_, index)| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()));
+    if container_name.is_empty() || container_name.contains(['/', '\\', ':', '.']) || is_partition_name {
+        return Err(EmulatorError::InvalidArgument(format!("\"{}\" isn't a valid container name", container_name)));
+    }
+    Ok(())
+}
+
+// File names are case insensitive on Windows, so container names are too. The name that the container was first added with is used
+fn find_container_name(roots: &BTreeMap<String, TocDirectorySyncRef>, container_name: &str) -> Option<String> {
+    roots.keys().find(|name| name.eq_ignore_ascii_case(container_name)).cloned()
+}
+
+// Get the tree for a container, if any mods were added to it
+pub fn get_container_root(container_name: &str) -> Option<TocDirectorySyncRef> {
+    let roots = ROOT_DIRECTORIES.lock().unwrap();
+    find_container_name(&roots, container_name).map(|name| Arc::clone(&roots[&name]))
+}
+
+// Get the lock for a container's tree, see CONTAINER_LOCKS
+pub fn get_container_lock(container_name: &str) -> Arc<Mutex<()>> {
+    Arc::clone(CONTAINER_LOCKS.lock().unwrap().entry(container_name.to_lowercase()).or_default())
+}
+
+// Names of every container that mods have been added to, sorted by name
+pub fn get_container_names() -> Vec<String> {
+    ROOT_DIRECTORIES.lock().unwrap().keys().cloned().collect()
+}
+
+// Project names are a single folder, and Engine is always mounted at /Engine/
+fn check_project_name(project_name: Option<&str>) -> EmulatorResult<()> {
+    match project_name {
+        Some(p) if p.is_empty() || p.contains(['/', '\\']) || p.eq_ignore_ascii_case(ENGINE_NAME) =>
+            Err(EmulatorError::InvalidArgument(format!("\"{}\" isn't a valid project name", p))),
+        _ => Ok(())
+    }
+}
+
+// Get the project folder that a mod's files are in, if it was given one when it was added
+pub fn get_project_name(mod_id: &str) -> Option<String> {
+    MOD_FOLDERS.lock().unwrap().iter().find(|m| m.mod_id == mod_id).and_then(|m| m.project_name.clone())
+}
+
+// Add a mod's files to the tree for container_name instead of the default UnrealEssentials container
+pub fn add_from_folders_to_container(mod_id: &str, mod_path: &str, container_name: &str) -> EmulatorResult<()> {
+    add_from_folders_for_project(mod_id, mod_path, container_name, None)
+}
+
+// Same as add_from_folders_to_container, but only project_name/Content gets mapped onto /Game/ for this mod's files. If it's None,
+// every top-level folder besides Engine is treated as the project's folder
+pub fn add_from_folders_for_project(mod_id: &str, mod_path: &str, container_name: &str, project_name: Option<&str>) -> EmulatorResult<()> {
+    println!("add from folders: {}, {} ({})", mod_id, mod_path, container_name);
+    check_container_name(container_name)?;
+    check_project_name(project_name)?;
+    let container_lock = get_container_lock(container_name);
+    let _container_guard = container_lock.lock().unwrap();
     // mod loading happens synchronously, safe to unwrap
     let mut profiler_lock = ASSET_COLLECTOR_PROFILER.lock().unwrap();
-    if *profiler_lock == None { // Check profiler is active
+    if (*profiler_lock).is_none() { // Check profiler is active
         *profiler_lock = Some(AssetCollectorProfiler::new());
     }
     let mod_path: PathBuf = PathBuf::from(mod_path);
     if Path::exists(Path::new(&mod_path)) {
-        let mut profiler_mod = AssetCollectorProfilerMod::new(mod_id, mod_path.to_str().unwrap());
-        let mut root_dir_lock = ROOT_DIRECTORY.lock().unwrap();
-        if let None = *root_dir_lock {
-            *root_dir_lock = Some(TocDirectory::new_rc(None));
+        let mut profiler_mod = AssetCollectorProfilerMod::new(mod_id, &mod_path.to_string_lossy());
+        let mut root_dir_lock = ROOT_DIRECTORIES.lock().unwrap();
+        let container_name = find_container_name(&root_dir_lock, container_name).unwrap_or_else(|| container_name.to_owned());
+        let root = Arc::clone(root_dir_lock.entry(container_name.clone()).or_insert_with(|| TocDirectory::new_rc(None)));
+        drop(root_dir_lock);
+        // Loose files from every container go into the same PAK, since they're all mounted at the game's root anyway
+        let mut pak_root_dir_lock = PAK_ROOT_DIRECTORY.lock().unwrap();
+        if pak_root_dir_lock.is_none() {
+            *pak_root_dir_lock = Some(TocDirectory::new_rc(None));
         }
-        add_from_folders_inner(Arc::clone(&(*root_dir_lock).as_ref().unwrap()), &mod_path, &mut profiler_mod.data, true);
+        let result = add_from_folders_inner(
+            root, Arc::clone((*pak_root_dir_lock).as_ref().unwrap()),
+            &mod_path, mod_id, &mut profiler_mod.data
+        );
         profiler_mod.set_time_to_tree();
         (*profiler_lock).as_mut().unwrap().mods_loaded.push(profiler_mod);
+        MOD_FOLDERS.lock().unwrap().push(ModFolder {
+            mod_id: mod_id.to_owned(), os_path: mod_path, container: container_name, project_name: project_name.map(|p| p.to_owned())
+        });
+        result?;
     }
+    Ok(())
+}
+
+// Unreal looks up paths and hashes package ids ignoring case, so names that only differ in case are the same file or directory
+pub fn is_same_name(a: &str, b: &str) -> bool {
+    a == b || a.to_lowercase() == b.to_lowercase()
 }
 
 //      A <--------
@@ -79,17 +178,11 @@ impl TocDirectory {
     }
     // Returns true/false depending on if the target directory contains any child directories
     pub fn has_children(dir: TocDirectorySyncRef) -> bool {
-        match &dir.read().unwrap().first_child {
-            Some(_) => true,
-            None => false
-        }
+        dir.read().unwrap().first_child.is_some()
     }
     // Returns true/false depending on if the target directory contains any child files
     pub fn has_files(dir: TocDirectorySyncRef) -> bool {
-        match &dir.read().unwrap().first_file {
-            Some(_) => true,
-            None => false
-        }
+        dir.read().unwrap().first_file.is_some()
     }
     // Add a file child into directory that doesn't currently contain any other files
     #[inline]
@@ -97,7 +190,8 @@ impl TocDirectory {
         dir.write().unwrap().first_file = Some(Arc::clone(&file));
         dir.write().unwrap().last_file = Arc::downgrade(&file);
     }
-    // Replace an existing file in the file list. Kick it off the list so it drops on add_or_replace_file's scope
+    // Replace an existing file in the file list. Kick it off the list so it drops on add_or_replace_file's scope. The replacer
+    // takes the replacee's name, so the tree keeps the casing that the file was first added with, and remembers every copy it replaced
     #[inline]
     fn replace_file(
         dir: TocDirectorySyncRef, // containing directory
@@ -105,18 +199,45 @@ impl TocDirectory {
         replacee: TocFileSyncRef, // the file to get replaced (file merging is a future problem)
         replacer: TocFileSyncRef // file that'll take the place of replacee in the chain
     ) {
-        if let None = replacee.read().unwrap().next.as_ref() { // replacee is the last file in chain, dir->last_file = weakref(replacer)
+        {
+            let mut replacee = replacee.write().unwrap();
+            let mut displaced = std::mem::take(&mut replacee.displaced);
+            displaced.push(replacee.get_provenance());
+            let mut replacer = replacer.write().unwrap();
+            replacer.name = replacee.name.clone();
+            replacer.displaced = displaced;
+        }
+        if replacee.read().unwrap().next.is_none() { // replacee is the last file in chain, dir->last_file = weakref(replacer)
             dir.write().unwrap().last_file = Arc::downgrade(&replacer);
         } else { // replacee is at the start or in the middle, set replacer->next = replacee->next
             replacer.write().unwrap().next = Some(Arc::clone(replacee.read().unwrap().next.as_ref().unwrap()));
         }
-        if let None = prev_file { // replacee is at the start, set dir->first_file = replacer
-            dir.write().unwrap().first_file = Some(Arc::clone(&replacer));
-        } else { // prev->next = replacer, replacee drops here
-            prev_file.as_ref().unwrap().write().unwrap().next = Some(Arc::clone(&replacer));
+        match prev_file {
+            None => dir.write().unwrap().first_file = Some(Arc::clone(&replacer)), // replacee is at the start, set dir->first_file = replacer
+            Some(prev) => prev.write().unwrap().next = Some(Arc::clone(&replacer)) // prev->next = replacer, replacee drops here
         }
 
     }
+    // Remove a file from the directory's file list, if it's in there
+    pub fn remove_file(dir: TocDirectorySyncRef, file: TocFileSyncRef) {
+        let mut prev: Option<TocFileSyncRef> = None;
+        let mut curr_file = dir.read().unwrap().first_file.as_ref().map(Arc::clone);
+        while let Some(curr) = curr_file {
+            let next = curr.read().unwrap().next.as_ref().map(Arc::clone);
+            if Arc::ptr_eq(&curr, &file) {
+                match prev.as_ref() {
+                    None => dir.write().unwrap().first_file = next.as_ref().map(Arc::clone),
+                    Some(p) => p.write().unwrap().next = next.as_ref().map(Arc::clone)
+                }
+                if next.is_none() { // removed the tail, previous file (if any) becomes the new tail
+                    dir.write().unwrap().last_file = prev.as_ref().map_or(Weak::new(), Arc::downgrade);
+                }
+                return;
+            }
+            prev = Some(curr);
+            curr_file = next;
+        }
+    }
     // Add a file to the end of the directory's file list, which contains at least 1 existing file
     #[inline]
     fn add_another_file(dir: TocDirectorySyncRef, file: TocFileSyncRef) {
@@ -132,14 +253,14 @@ impl TocDirectory {
                 let mut prev: Option<TocFileSyncRef> = None;
                 let mut curr_file = Arc::clone(dir.read().unwrap().first_file.as_ref().unwrap());
                 loop {
-                    if curr_file.read().unwrap().name == file.read().unwrap().name { // we got the file, replace it
+                    if is_same_name(&curr_file.read().unwrap().name, &file.read().unwrap().name) { // we got the file, replace it
                         found = true;
                         break
                     }
                     match Arc::clone(&curr_file).read().unwrap().next.as_ref() { // check if next points to next entry in chain or ends the chain
                         Some(f) => {
                             prev = Some(Arc::clone(&curr_file));
-                            curr_file = Arc::clone(&f);
+                            curr_file = Arc::clone(f);
                         },
                         None => { // couldn't find it to replace, add it to the end
                             break // we need to escape this scope to prevent creating mut ref of last_file->next while const ref last_file->next is still valid
@@ -165,21 +286,48 @@ impl TocDirectory {
             }
         }
     }
-    // get a child directory from a parent directory if it exists
+    // get a file from a directory's file list if it exists
+    pub fn get_file(dir: TocDirectorySyncRef, name: &str) -> Option<TocFileSyncRef> {
+        let mut curr_file = dir.read().unwrap().first_file.as_ref().map(Arc::clone);
+        while let Some(file) = curr_file {
+            if is_same_name(&file.read().unwrap().name, name) {
+                return Some(file);
+            }
+            curr_file = file.read().unwrap().next.as_ref().map(Arc::clone);
+        }
+        None
+    }
+    // Get the directory for a path relative to a mod folder. Missing directories are created if create is set
+    pub fn get_dir_from_path(root: TocDirectorySyncRef, components: &[&str], create: bool) -> Option<TocDirectorySyncRef> {
+        let mut curr_dir = root;
+        for component in components {
+            curr_dir = match TocDirectory::get_child_dir(Arc::clone(&curr_dir), component) {
+                Some(c) => c,
+                None if create => {
+                    let new_dir = TocDirectory::new_rc(Some(component.to_string()));
+                    TocDirectory::add_directory(Arc::clone(&curr_dir), Arc::clone(&new_dir));
+                    new_dir
+                },
+                None => return None
+            };
+        }
+        Some(curr_dir)
+    }
+    // get a child directory from a parent directory if it exists, ignoring case
     pub fn get_child_dir(parent: TocDirectorySyncRef, exist: &str) -> Option<TocDirectorySyncRef> {
         match TocDirectory::has_children(Arc::clone(&parent)) {
             true => {
-                let mut curr_dir = Arc::clone(&parent.read().unwrap().first_child.as_ref().unwrap());
+                let mut curr_dir = Arc::clone(parent.read().unwrap().first_child.as_ref().unwrap());
                 let mut result = None;
                 loop {
                     if let Some(dir_name) = curr_dir.read().unwrap().name.as_ref() {
-                        if dir_name == exist { // we got our directory
+                        if is_same_name(dir_name, exist) { // we got our directory
                             result = Some(Arc::clone(&curr_dir));
                             break;
                         }
                     }
                     match Arc::clone(&curr_dir).read().unwrap().next_sibling.as_ref() {
-                        Some(ip) => curr_dir = Arc::clone(&ip),
+                        Some(ip) => curr_dir = Arc::clone(ip),
                         None => break
                     }
                 }
@@ -205,22 +353,55 @@ pub struct TocFile {
     pub next: Option<Arc<RwLock<TocFile>>>,
     pub name: String,
     pub file_size: u64,
-    pub os_file_path: String // needed so we can open it, copy it then write it into partition
+    pub os_file_path: String, // needed so we can open it, copy it then write it into partition
+    pub cooked_package: bool, // cooked .uasset/.umap that gets converted into an IO Store package when the TOC is built
+    pub mod_id: String, // mod that this file came from, so problems found while building the TOC can be reported for it
+    pub displaced: Vec<TocFileProvenance> // copies from lower priority mods that this file replaced, lowest priority first
+}
+
+// Where a copy of a file came from, used to report which mods conflict over a file (see conflict_report)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TocFileProvenance {
+    pub mod_id: String,
+    pub os_path: String
 }
 
 impl TocFile {
     // constructor
-    fn new(name: &str, file_size: u64, os_path: &str) -> Self {
+    fn new(name: &str, file_size: u64, os_path: &str, mod_id: &str, cooked_package: bool) -> Self {
         Self {
             next: None,
             name: String::from(name),
             file_size,
-            os_file_path: String::from(os_path)
+            os_file_path: String::from(os_path),
+            cooked_package,
+            mod_id: String::from(mod_id),
+            displaced: vec![]
         }
     }
+    pub fn get_provenance(&self) -> TocFileProvenance {
+        TocFileProvenance { mod_id: self.mod_id.clone(), os_path: self.os_file_path.clone() }
+    }
     #[inline] // convenience function to create reference counted toc files
-    pub fn new_rc(name: &str, file_size: u64, os_path: &str) -> Arc<RwLock<Self>> {
-        Arc::new(RwLock::new(TocFile::new(name, file_size, os_path)))
+    pub fn new_rc(name: &str, file_size: u64, os_path: &str, mod_id: &str) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(TocFile::new(name, file_size, os_path, mod_id, false)))
+    }
+    #[inline]
+    pub fn new_cooked_rc(name: &str, file_size: u64, os_path: &str, mod_id: &str) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(TocFile::new(name, file_size, os_path, mod_id, true)))
+    }
+    // Create a file with one of the SUITABLE_FILE_EXTENSIONS. Export bundles (.uasset, .umap) have their header checked for the
+    // cooked asset signature, since cooked packages get converted into IO Store packages once we know which engine version to target
+    pub fn new_io_store_rc(name: &str, file_size: u64, os_path: &str, mod_id: &str) -> Result<Arc<RwLock<Self>>, Box<dyn Error>> {
+        let extension = Path::new(name).extension().and_then(|e| e.to_str()).unwrap_or_default();
+        if extension == "uasset" || extension == "umap" {
+            let mut file_reader = BufReader::with_capacity(4, File::open(os_path)?);
+            if !io_package::is_valid_asset_type::<BufReader<File>, byteorder::NativeEndian>(&mut file_reader)? {
+                return Ok(TocFile::new_cooked_rc(name, file_size, os_path, mod_id));
+            }
+        }
+        Ok(TocFile::new_rc(name, file_size, os_path, mod_id))
     }
 }
 
@@ -229,84 +410,212 @@ pub enum TocFileAddType {
     Replacement
 }
 
-pub const SUITABLE_FILE_EXTENSIONS: &'static [&'static str] = ["uasset", "ubulk", "uptnl", "umap"].as_slice();
-pub const MOUNT_POINT: &'static str = "../../../";
-pub const GAME_ROOT: &'static str = "Game";
+pub const SUITABLE_FILE_EXTENSIONS: &[&str] = ["uasset", "ubulk", "uptnl", "umap"].as_slice();
+// Export data for cooked packages. These are read alongside their .uasset/.umap when it gets converted
+pub const COOKED_EXPORT_DATA_EXTENSION: &str = "uexp";
+// Containers that are placed in the mod folder are mounted by UnrealEssentials, so they shouldn't go into the emulated PAK
+pub const CONTAINER_FILE_EXTENSIONS: &[&str] = ["pak", "utoc", "ucas", "sig"].as_slice();
+pub const MOUNT_POINT: &str = "../../../";
 
-pub fn add_from_folders_inner(parent: TocDirectorySyncRef, os_path: &PathBuf, profiler: &mut AssetCollectorProfilerModContents, mut first: bool) {
-    // We've already checked that this path exists in AddFromFolders, so unwrap directly
-    // This folder is equivalent to /[ProjectName]/Content, so our mount point will be
-    // at least ../../../[ProjectName] (../../../Game/)
+pub fn add_from_folders_inner(
+    parent: TocDirectorySyncRef, pak_parent: TocDirectorySyncRef, os_path: &Path, mod_id: &str,
+    profiler: &mut AssetCollectorProfilerModContents
+) -> EmulatorResult<()> {
+    // Mod folders are laid out like the game's root folder, so each top-level folder keeps it's name (e.g [ProjectName]/Content)
+    // and gets mapped onto it's mount name (/Game/) when package ids are made (see get_project_name). Plugin content folders
+    // ([ProjectName]/Plugins/[PluginName]/Content) are mapped onto /[PluginName]/ instead
     // build an unsorted n-tree of directories and files, preorder traversal
     // higher priority mods should overwrite contents of files, but not directories
-    for i in fs::read_dir(os_path).unwrap() {
+    // Only failing to read the mod's root folder is an error, anything inside of it that can't be read gets logged by the profiler
+    let dir_entries = fs::read_dir(os_path).map_err(|e| EmulatorError::Io(os_path.to_string_lossy().into_owned(), e))?;
+    for i in dir_entries {
         match &i {
             Ok(fs_obj) => { // we have our file system object, now determine if it's a directory or folder
-                let fs_obj_os_name = fs_obj.file_name(); // this does assume that the object name is valid Unicode
-                let mut name = String::from(fs_obj_os_name.to_str().unwrap()); // if it's not i'll be very surprised
-                let file_type = fs_obj.file_type().unwrap();
+                let name = match fs_obj.file_name().to_str() {
+                    Some(n) => n.to_owned(),
+                    None => {
+                        profiler.add_failed_fs_object(&os_path.to_string_lossy(), format!("{:?} isn't a valid Unicode name", fs_obj.file_name()));
+                        continue
+                    }
+                };
+                let file_type = match fs_obj.file_type() {
+                    Ok(t) => t,
+                    Err(e) => {
+                        profiler.add_failed_fs_object(&os_path.to_string_lossy(), e.to_string());
+                        continue
+                    }
+                };
                 if file_type.is_dir() { // new directory. mods can only expand on this
                     let mut inner_path = PathBuf::from(os_path);
                     inner_path.push(&name);
-                    match TocDirectory::get_child_dir(Arc::clone(&parent), &name) {
-                        // check through folder regardless since there may be new inner folders in there
-                        Some(child_dir) => add_from_folders_inner(Arc::clone(&child_dir), &inner_path, profiler, false),
+                    let pak_dir = match TocDirectory::get_child_dir(Arc::clone(&pak_parent), &name) {
+                        Some(child_dir) => child_dir,
                         None => {
-                            // Set the root directory to Game if it isn't engine so people can use the game name (assuming only Engine and Game)
-                            if first && name != "Engine"
-                            {
-                                first = false;
-                                println!("Setting root directory {} to Game", name);
-                                name = GAME_ROOT.to_string();
+                            let new_dir = TocDirectory::new_rc(Some(name.to_owned()));
+                            TocDirectory::add_directory(Arc::clone(&pak_parent), Arc::clone(&new_dir));
+                            new_dir
+                        }
+                    };
+                    let result = match TocDirectory::get_child_dir(Arc::clone(&parent), &name) {
+                        // check through folder regardless since there may be new inner folders in there
+                        Some(child_dir) => {
+                            let dir_name = child_dir.read().unwrap().name.clone().unwrap_or_default();
+                            if dir_name != name {
+                                profiler.add_case_collision(&inner_path.to_string_lossy(), &dir_name);
                             }
-
+                            add_from_folders_inner(Arc::clone(&child_dir), pak_dir, &inner_path, mod_id, profiler)
+                        },
+                        None => {
                             // this is a new directory, create it and then check inside it
-                            let new_dir = TocDirectory::new_rc(Some((&name).to_owned()));
+                            let new_dir = TocDirectory::new_rc(Some(name.to_owned()));
                             TocDirectory::add_directory(Arc::clone(&parent), Arc::clone(&new_dir));
-                            add_from_folders_inner(Arc::clone(&new_dir), &inner_path, profiler, false);
                             profiler.add_directory();
+                            add_from_folders_inner(Arc::clone(&new_dir), pak_dir, &inner_path, mod_id, profiler)
                         }
+                    };
+                    if let Err(e) = result {
+                        profiler.add_failed_fs_object(&inner_path.to_string_lossy(), e.to_string());
                     }
                 } else if file_type.is_file() {
-                    let file_size = Metadata::get_file_size(fs_obj);
+                    let os_file_path = fs_obj.path();
+                    // the folder and file name are both valid Unicode at this point
+                    let os_file_path = os_file_path.to_str().unwrap_or_default();
+                    let file_size = match Metadata::get_file_size(fs_obj) {
+                        Ok(s) => s,
+                        Err(e) => {
+                            profiler.add_failed_fs_object(&os_path.to_string_lossy(), format!("Couldn't get size of \"{}\": {}", name, e));
+                            continue
+                        }
+                    };
                     match PathBuf::from(&name).extension() {
                         Some(ext) => {
-                            let ext_str = ext.to_str().unwrap();
+                            let ext_str = ext.to_str().unwrap_or_default();
                             match SUITABLE_FILE_EXTENSIONS.iter().find(|exist| **exist == ext_str) {
                                 // it's a matter of either replacing an existing file or adding a new file
                                 // ,,,at least until we start thinking about merging P3RE persona tables (lol)
-                                Some(io_ext) => {
-                                    if *io_ext == "uasset" || *io_ext == "umap" { // export bundles - requires checking file header to ensure that it doesn't have the cooked asset signature
-                                        let current_file = File::open(fs_obj.path().to_str().unwrap()).unwrap();
-                                        let mut file_reader = BufReader::with_capacity(4, current_file);
-                                        if !io_package::is_valid_asset_type::<BufReader<File>, byteorder::NativeEndian>(&mut file_reader) {
-                                            profiler.add_skipped_file(os_path.to_str().unwrap(), format!("Uses cooked package"), file_size);
+                                Some(_) => {
+                                    let new_file = match TocFile::new_io_store_rc(&name, file_size, os_file_path, mod_id) {
+                                        Ok(f) => f,
+                                        Err(e) => {
+                                            profiler.add_skipped_file(os_file_path, format!("Couldn't read package header: {}", e), file_size);
                                             continue
                                         }
-                                    }
-                                    let new_file = TocFile::new_rc(&name, file_size, fs_obj.path().to_str().unwrap());
+                                    };
                                     match TocDirectory::add_or_replace_file(Arc::clone(&parent), Arc::clone(&new_file)) {
                                         TocFileAddType::Addition => profiler.add_added_file(file_size),
                                         TocFileAddType::Replacement => profiler.add_replaced_file(file_size)
                                     }
+                                    if new_file.read().unwrap().name != name {
+                                        profiler.add_case_collision(os_file_path, &new_file.read().unwrap().name);
+                                    }
                                 },
-                                // TODO: Unsupported file extensions go into PAK
-                                // Io Store forces you to also make a pak file (hopefully DC's patches can fix this)
-                                None => profiler.add_skipped_file(fs_obj.path().to_str().unwrap(), format!("Unsupported file type"), file_size)
+                                // .uexp is read with it's package when it's converted, so only report ones without a package
+                                None if ext_str == COOKED_EXPORT_DATA_EXTENSION => {
+                                    let path = fs_obj.path();
+                                    if !path.with_extension("uasset").exists() && !path.with_extension("umap").exists() {
+                                        profiler.add_skipped_file(os_file_path, "Export data has no matching package".to_string(), file_size)
+                                    }
+                                },
+                                None if CONTAINER_FILE_EXTENSIONS.contains(&ext_str) => {
+                                    profiler.add_skipped_file(os_file_path, "Container files are loaded by UnrealEssentials".to_string(), file_size)
+                                },
+                                // Everything else can't be read from IO Store, so it goes into the PAK that's emulated alongside the UTOC
+                                None => {
+                                    let new_file = TocFile::new_rc(&name, file_size, os_file_path, mod_id);
+                                    match TocDirectory::add_or_replace_file(Arc::clone(&pak_parent), Arc::clone(&new_file)) {
+                                        TocFileAddType::Addition => profiler.add_added_pak_file(file_size),
+                                        TocFileAddType::Replacement => profiler.add_replaced_pak_file(file_size)
+                                    }
+                                    if new_file.read().unwrap().name != name {
+                                        profiler.add_case_collision(os_file_path, &new_file.read().unwrap().name);
+                                    }
+                                }
                             }
                         }
-                        None => profiler.add_skipped_file(fs_obj.path().to_str().unwrap(), format!("No file extension"), file_size)
+                        None => profiler.add_skipped_file(os_file_path, "No file extension".to_string(), file_size)
                     }
                 }
             },
-            Err(e) => profiler.add_failed_fs_object(os_path.to_str().unwrap(), e.to_string())
+            Err(e) => profiler.add_failed_fs_object(&os_path.to_string_lossy(), e.to_string())
         }
     }
+    Ok(())
+}
+
+// Clear every collected file so that mod folders can be added again and the container rebuilt, e.g after a mod is toggled.
+// TOCs and PAKs that were already built own their data, so they're unaffected until they're freed
+pub fn reset_asset_collector() {
+    // wait for any container that's being built or hot reloaded
+    let container_locks: Vec<Arc<Mutex<()>>> = CONTAINER_LOCKS.lock().unwrap().values().cloned().collect();
+    let _container_guards: Vec<_> = container_locks.iter().map(|l| l.lock().unwrap()).collect();
+    ROOT_DIRECTORIES.lock().unwrap().clear();
+    *PAK_ROOT_DIRECTORY.lock().unwrap() = None;
+    *ASSET_COLLECTOR_PROFILER.lock().unwrap() = None;
+    MOD_FOLDERS.lock().unwrap().clear();
+}
+
+// Clear the files of every mod that was added to container_name, so that just that container can be rebuilt from a new set of mods.
+// Loose files are shared between containers, so only the copies from this container's mods are taken out of the PAK's tree
+pub fn reset_container(container_name: &str) {
+    let container_lock = get_container_lock(container_name);
+    let _container_guard = container_lock.lock().unwrap();
+    let mut root_dir_lock = ROOT_DIRECTORIES.lock().unwrap();
+    let container_name = match find_container_name(&root_dir_lock, container_name) {
+        Some(n) => n,
+        None => return
+    };
+    root_dir_lock.remove(&container_name);
+    drop(root_dir_lock);
+    let mut mod_folders = MOD_FOLDERS.lock().unwrap();
+    let mod_ids: Vec<String> = mod_folders.iter().filter(|m| m.container == container_name).map(|m| m.mod_id.clone()).collect();
+    // the same mod can be added into more than one container
+    mod_folders.retain(|m| m.container != container_name);
+    let removed_ids: Vec<&String> = mod_ids.iter().filter(|id| !mod_folders.iter().any(|m| &m.mod_id == *id)).collect();
+    drop(mod_folders);
+    if let Some(profiler) = ASSET_COLLECTOR_PROFILER.lock().unwrap().as_mut() {
+        profiler.mods_loaded.retain(|m| !removed_ids.contains(&&m.uid));
+    }
+    if let Some(pak_root) = PAK_ROOT_DIRECTORY.lock().unwrap().as_ref() {
+        remove_mod_files(Arc::clone(pak_root), &removed_ids);
+    }
+}
+
+// Take every copy from the given mods out of a tree. Files that one of those mods won go back to the highest priority copy they displaced
+fn remove_mod_files(dir: TocDirectorySyncRef, mod_ids: &[&String]) {
+    let mut curr_file = dir.read().unwrap().first_file.as_ref().map(Arc::clone);
+    while let Some(file) = curr_file {
+        curr_file = file.read().unwrap().next.as_ref().map(Arc::clone);
+        let mut file_lock = file.write().unwrap();
+        file_lock.displaced.retain(|d| !mod_ids.contains(&&d.mod_id));
+        if !mod_ids.contains(&&file_lock.mod_id) {
+            continue;
+        }
+        let mut restored = false;
+        while let Some(provenance) = file_lock.displaced.pop() {
+            // the displaced copy's size wasn't kept, so it has to be read again
+            if let Ok(metadata) = fs::metadata(&provenance.os_path) {
+                file_lock.file_size = metadata.len();
+                file_lock.os_file_path = provenance.os_path;
+                file_lock.mod_id = provenance.mod_id;
+                restored = true;
+                break;
+            }
+        }
+        drop(file_lock);
+        if !restored {
+            TocDirectory::remove_file(Arc::clone(&dir), file);
+        }
+    }
+    let mut curr_dir = dir.read().unwrap().first_child.as_ref().map(Arc::clone);
+    while let Some(child) = curr_dir {
+        remove_mod_files(Arc::clone(&child), mod_ids);
+        curr_dir = child.read().unwrap().next_sibling.as_ref().map(Arc::clone);
+    }
 }
 
 pub fn print_asset_collector_results() {
     let profiler_lock = ASSET_COLLECTOR_PROFILER.lock().unwrap();
-    if *profiler_lock != None {
+    if (*profiler_lock).is_some() {
         (*profiler_lock).as_ref().unwrap().print();
     }
 }
@@ -319,8 +628,15 @@ pub struct AssetCollectorProfilerFailedFsObject {
 
 #[derive(Debug, PartialEq)]
 pub struct AssetCollectorSkippedFileEntry {
-    os_path: String,
-    reason: String,
+    pub os_path: String,
+    pub reason: String,
+}
+
+// A file or directory that was merged with one of a different case, which keeps the name it was first added with
+#[derive(Debug, PartialEq)]
+pub struct AssetCollectorCaseCollision {
+    pub os_path: String,
+    pub tree_name: String,
 }
 
 #[derive(Debug, PartialEq)]
@@ -331,13 +647,24 @@ pub struct AssetCollectorProfilerModContents {
     added_files_size: u64,
     replaced_files_count: u64,
     replaced_files_size: u64,
+    added_pak_files_count: u64,
+    added_pak_files_size: u64,
+    replaced_pak_files_count: u64,
+    replaced_pak_files_size: u64,
     incorrect_asset_header: Vec<String>,
     skipped_files: Vec<AssetCollectorSkippedFileEntry>,
     skipped_file_size: u64,
+    case_collisions: Vec<AssetCollectorCaseCollision>,
     timer: Instant,
     time_to_tree: u128,
 }
 
+impl Default for AssetCollectorProfilerModContents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AssetCollectorProfilerModContents {
     pub fn new() -> Self {
         Self {
@@ -347,9 +674,14 @@ impl AssetCollectorProfilerModContents {
             added_files_count: 0,
             replaced_files_count: 0,
             replaced_files_size: 0,
+            added_pak_files_count: 0,
+            added_pak_files_size: 0,
+            replaced_pak_files_count: 0,
+            replaced_pak_files_size: 0,
             incorrect_asset_header: vec![],
             skipped_files: vec![],
             skipped_file_size: 0,
+            case_collisions: vec![],
             timer: Instant::now(),
             time_to_tree: 0,
         }
@@ -363,6 +695,9 @@ impl AssetCollectorProfilerModContents {
         self.skipped_files.push(AssetCollectorSkippedFileEntry { os_path: os_path.to_owned(), reason });
         self.skipped_file_size += size;
     }
+    pub fn add_case_collision(&mut self, os_path: &str, tree_name: &str) {
+        self.case_collisions.push(AssetCollectorCaseCollision { os_path: os_path.to_owned(), tree_name: tree_name.to_owned() });
+    }
     pub fn add_directory(&mut self) {
         self.directory_count += 1;
     }
@@ -374,6 +709,14 @@ impl AssetCollectorProfilerModContents {
         self.replaced_files_count += 1;
         self.replaced_files_size += size;
     }
+    pub fn add_added_pak_file(&mut self, size: u64) {
+        self.added_pak_files_count += 1;
+        self.added_pak_files_size += size;
+    }
+    pub fn add_replaced_pak_file(&mut self, size: u64) {
+        self.replaced_pak_files_count += 1;
+        self.replaced_pak_files_size += size;
+    }
     pub fn get_tree_time(&mut self) {
         self.time_to_tree = self.timer.elapsed().as_micros();
     }
@@ -383,14 +726,25 @@ impl AssetCollectorProfilerModContents {
         println!("{} directories added", self.directory_count);
         println!("{} added files ({} KB)", self.added_files_count, self.added_files_size / 1024);
         println!("{} replaced files ({} KB)", self.replaced_files_count, self.replaced_files_size / 1024);
-        if self.skipped_files.len() > 0 {
+        if self.added_pak_files_count + self.replaced_pak_files_count > 0 {
+            println!("{} added PAK files ({} KB)", self.added_pak_files_count, self.added_pak_files_size / 1024);
+            println!("{} replaced PAK files ({} KB)", self.replaced_pak_files_count, self.replaced_pak_files_size / 1024);
+        }
+        if !self.skipped_files.is_empty() {
             println!("{}", "-".repeat(80));
             println!("SKIPPED FILES: {} FILES ({} KB)", self.skipped_files.len(), self.skipped_file_size / 1024);
             for i in &self.skipped_files {
                 println!("File \"{}\", reason \"{}\"", i.os_path, i.reason);
             }
         }
-        if self.incorrect_asset_header.len() > 0 {
+        if !self.case_collisions.is_empty() {
+            println!("{}", "-".repeat(AssetCollectorProfiler::get_terminal_length()));
+            println!("CASE COLLISIONS: {} PATHS", self.case_collisions.len());
+            for i in &self.case_collisions {
+                println!("\"{}\" was merged into \"{}\"", i.os_path, i.tree_name);
+            }
+        }
+        if !self.incorrect_asset_header.is_empty() {
             println!("{}", "-".repeat(AssetCollectorProfiler::get_terminal_length()));
             println!("INCORRECT ASSET FORMAT: {} FILES", self.incorrect_asset_header.len());
             for i in &self.incorrect_asset_header {
@@ -398,7 +752,7 @@ impl AssetCollectorProfilerModContents {
             }
             println!("If you're the mod author, please make sure that you've followed the guide at \"https://github.com/AnimatedSwine37/UnrealEssentials\" to create correctly formatted assets");
         }
-        if self.failed_file_system_objects.len() > 0 {
+        if !self.failed_file_system_objects.is_empty() {
             println!("{}", "-".repeat(AssetCollectorProfiler::get_terminal_length()));
             println!("FAILED TO LOAD: {} FILES", self.failed_file_system_objects.len());
             for i in &self.failed_file_system_objects {
@@ -417,7 +771,6 @@ pub struct AssetCollectorProfilerMod {
 
 impl AssetCollectorProfilerMod {
     pub fn new(mod_id: &str, mod_path: &str) -> Self {
-        let modified_os_path = mod_path.split_once("C:\\Users");
         Self {
             uid: mod_id.to_owned(),
             os_path: mod_path.to_owned(),
@@ -439,6 +792,12 @@ pub struct AssetCollectorProfiler {
     mods_loaded: Vec<AssetCollectorProfilerMod>,
 }
 
+impl Default for AssetCollectorProfiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AssetCollectorProfiler {
     pub fn get_terminal_length() -> usize {
         80
@@ -448,6 +807,18 @@ impl AssetCollectorProfiler {
             mods_loaded: vec![],
         }
     }
+    // Files can also be skipped once the TOC gets built, after the mod's tree was made
+    pub fn add_skipped_file(&mut self, mod_id: &str, os_path: &str, reason: String, size: u64) {
+        if let Some(profiler_mod) = self.mods_loaded.iter_mut().find(|m| m.uid == mod_id) {
+            profiler_mod.data.add_skipped_file(os_path, reason, size);
+        }
+    }
+    pub fn get_skipped_files(&self, mod_id: &str) -> Option<&[AssetCollectorSkippedFileEntry]> {
+        self.mods_loaded.iter().find(|m| m.uid == mod_id).map(|m| m.data.skipped_files.as_slice())
+    }
+    pub fn get_case_collisions(&self, mod_id: &str) -> Option<&[AssetCollectorCaseCollision]> {
+        self.mods_loaded.iter().find(|m| m.uid == mod_id).map(|m| m.data.case_collisions.as_slice())
+    }
     pub fn print_centered(text: &str) {
         let left_spaces = (AssetCollectorProfiler::get_terminal_length() - text.len()) / 2;
         println!("{}{}", " ".repeat(left_spaces), text);
@@ -461,4 +832,145 @@ impl AssetCollectorProfiler {
             println!("{}", "=".repeat(AssetCollectorProfiler::get_terminal_length()));
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_test_folder(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(EMULATOR_NAME);
+        path.push("Tests");
+        path.push("AssetCollector");
+        path.push(name);
+        path
+    }
+
+    fn write_mod_file(mod_path: &Path, relative_path: &str, size: usize) {
+        let path = mod_path.join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![0; size]).unwrap();
+    }
+
+    // Make an empty folder for a mod's files, clearing out any from a previous run
+    fn get_mod_folder(test_name: &str, mod_name: &str) -> PathBuf {
+        let mod_path = get_test_folder(test_name).join(mod_name);
+        let _ = fs::remove_dir_all(&mod_path);
+        mod_path
+    }
+
+    fn get_tree_file(root: TocDirectorySyncRef, relative_path: &str) -> Option<TocFileSyncRef> {
+        let components: Vec<&str> = relative_path.split('/').collect();
+        let (file_name, dir_components) = components.split_last().unwrap();
+        TocDirectory::get_dir_from_path(root, dir_components, false).and_then(|d| TocDirectory::get_file(d, file_name))
+    }
+
+    fn get_pak_file(relative_path: &str) -> Option<TocFileSyncRef> {
+        let pak_root = Arc::clone(PAK_ROOT_DIRECTORY.lock().unwrap().as_ref().unwrap());
+        get_tree_file(pak_root, relative_path)
+    }
+
+    #[test]
+    fn containers_are_separate_and_reset_alone() {
+        const FIRST: &str = "ResetContainerFirst";
+        const SECOND: &str = "ResetContainerSecond";
+        let first_mod = get_mod_folder("ResetContainer", FIRST);
+        let second_mod = get_mod_folder("ResetContainer", SECOND);
+        write_mod_file(&first_mod, "Test/Content/First.ubulk", 1);
+        write_mod_file(&second_mod, "Test/Content/Second.ubulk", 2);
+        // loose files from both containers go into the same PAK, so the first container's copy wins this one
+        write_mod_file(&second_mod, "Test/Content/ResetContainer/Shared.bin", 3);
+        write_mod_file(&first_mod, "Test/Content/ResetContainer/Shared.bin", 4);
+        write_mod_file(&first_mod, "Test/Content/ResetContainer/First.bin", 5);
+        add_from_folders_to_container(SECOND, second_mod.to_str().unwrap(), SECOND).unwrap();
+        add_from_folders_to_container(FIRST, first_mod.to_str().unwrap(), FIRST).unwrap();
+        let first_root = get_container_root(FIRST).unwrap();
+        let second_root = get_container_root(SECOND).unwrap();
+        assert!(get_tree_file(Arc::clone(&first_root), "Test/Content/First.ubulk").is_some());
+        assert!(get_tree_file(Arc::clone(&first_root), "Test/Content/Second.ubulk").is_none());
+        assert!(get_tree_file(Arc::clone(&second_root), "Test/Content/Second.ubulk").is_some());
+        assert!(get_tree_file(Arc::clone(&second_root), "Test/Content/First.ubulk").is_none());
+        assert!(!Arc::ptr_eq(&get_container_lock(FIRST), &get_container_lock(SECOND)));
+        assert!(Arc::ptr_eq(&get_container_lock(FIRST), &get_container_lock(&FIRST.to_uppercase())));
+        assert_eq!(get_pak_file("Test/Content/ResetContainer/Shared.bin").unwrap().read().unwrap().mod_id, FIRST);
+
+        reset_container(FIRST);
+        assert!(get_container_root(FIRST).is_none());
+        assert!(get_tree_file(get_container_root(SECOND).unwrap(), "Test/Content/Second.ubulk").is_some());
+        let mod_folders = MOD_FOLDERS.lock().unwrap();
+        assert!(!mod_folders.iter().any(|m| m.mod_id == FIRST));
+        assert!(mod_folders.iter().any(|m| m.mod_id == SECOND));
+        drop(mod_folders);
+        // the copy that the first container's mod displaced takes it's place again
+        let shared = get_pak_file("Test/Content/ResetContainer/Shared.bin").unwrap();
+        assert_eq!(shared.read().unwrap().mod_id, SECOND);
+        assert_eq!(shared.read().unwrap().file_size, 3);
+        assert!(shared.read().unwrap().displaced.is_empty());
+        assert!(get_pak_file("Test/Content/ResetContainer/First.bin").is_none());
+    }
+
+    #[test]
+    fn same_name_ignores_case() {
+        assert!(is_same_name("Foo.uasset", "Foo.uasset"));
+        assert!(is_same_name("Foo.uasset", "foo.UASSET"));
+        assert!(is_same_name("Ünïcode", "üNÏCODE"));
+        assert!(!is_same_name("Foo.uasset", "Foo.ubulk"));
+    }
+
+    // Files and directories that only differ in case from two mods become one entry, which keeps the first mod's casing
+    #[test]
+    fn case_collisions_are_merged() {
+        const CONTAINER: &str = "CaseCollision";
+        const LOWER_MOD: &str = "CaseCollisionLower";
+        const UPPER_MOD: &str = "CaseCollisionUpper";
+        let lower_mod = get_mod_folder(CONTAINER, LOWER_MOD);
+        let upper_mod = get_mod_folder(CONTAINER, UPPER_MOD);
+        write_mod_file(&lower_mod, "Test/Content/Characters/Foo.ubulk", 1);
+        write_mod_file(&upper_mod, "Test/Content/characters/foo.ubulk", 2);
+        add_from_folders_to_container(LOWER_MOD, lower_mod.to_str().unwrap(), CONTAINER).unwrap();
+        add_from_folders_to_container(UPPER_MOD, upper_mod.to_str().unwrap(), CONTAINER).unwrap();
+        let root = get_container_root(CONTAINER).unwrap();
+        let content = TocDirectory::get_dir_from_path(root, &["Test", "Content"], false).unwrap();
+        let characters = content.read().unwrap().first_child.as_ref().map(Arc::clone).unwrap();
+        assert_eq!(characters.read().unwrap().name.as_deref(), Some("Characters"));
+        assert!(characters.read().unwrap().next_sibling.is_none());
+        let file = characters.read().unwrap().first_file.as_ref().map(Arc::clone).unwrap();
+        assert!(file.read().unwrap().next.is_none());
+        assert_eq!(file.read().unwrap().name, "Foo.ubulk");
+        assert_eq!(file.read().unwrap().mod_id, UPPER_MOD);
+        assert_eq!(file.read().unwrap().file_size, 2);
+
+        let profiler_lock = ASSET_COLLECTOR_PROFILER.lock().unwrap();
+        let profiler = profiler_lock.as_ref().unwrap();
+        assert!(profiler.get_case_collisions(LOWER_MOD).unwrap().is_empty());
+        let collisions = profiler.get_case_collisions(UPPER_MOD).unwrap();
+        let tree_names: Vec<&str> = collisions.iter().map(|c| c.tree_name.as_str()).collect();
+        assert_eq!(tree_names, ["Characters", "Foo.ubulk"]);
+        assert!(collisions[1].os_path.ends_with("foo.ubulk"));
+    }
+
+    // Each copy that gets replaced is remembered, lowest priority first, and shows up as a loser in the conflict report
+    #[test]
+    fn displaced_copies_in_priority_order() {
+        const CONTAINER: &str = "DisplacedOrder";
+        let mod_ids: Vec<String> = (0..3).map(|i| format!("{}{}", CONTAINER, i)).collect();
+        for (i, mod_id) in mod_ids.iter().enumerate() {
+            let mod_path = get_mod_folder(CONTAINER, mod_id);
+            write_mod_file(&mod_path, "Test/Content/Shared.ubulk", i + 1);
+            write_mod_file(&mod_path, &format!("Test/Content/{}.ubulk", mod_id), 1);
+            add_from_folders_to_container(mod_id, mod_path.to_str().unwrap(), CONTAINER).unwrap();
+        }
+        let file = get_tree_file(get_container_root(CONTAINER).unwrap(), "Test/Content/Shared.ubulk").unwrap();
+        assert_eq!(file.read().unwrap().mod_id, mod_ids[2]);
+        let displaced: Vec<String> = file.read().unwrap().displaced.iter().map(|d| d.mod_id.clone()).collect();
+        assert_eq!(displaced, mod_ids[..2]);
+        assert!(file.read().unwrap().displaced[0].os_path.contains(&mod_ids[0]));
+
+        let report = crate::conflict_report::get_conflict_report();
+        let conflicts: Vec<_> = report.conflicts.iter().filter(|c| c.container == CONTAINER).collect();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "Test/Content/Shared.ubulk");
+        assert_eq!(conflicts[0].winner.mod_id, mod_ids[2]);
+        assert_eq!(conflicts[0].losers, file.read().unwrap().displaced);
+    }
+}
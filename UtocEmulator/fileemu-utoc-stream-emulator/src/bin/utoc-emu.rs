@@ -0,0 +1,214 @@
+use fileemu_utoc_stream_emulator::{
+    asset_collector,
+    compression::CompressionMethod,
+    conflict_report,
+    encryption, encryption::EncryptionKey,
+    error::{EmulatorError, EmulatorResult},
+    io_toc::IoStoreTocVersion,
+    pak_factory, pak_factory::{PakType, TARGET_PAK},
+    toc_factory, toc_factory::{TocBuildOptions, CAS_EXTENSION, TOC_EXTENSION, TOC_NAME},
+    toc_unpacker::{IoStoreContainer, STORE_ENTRIES_DUMP}
+};
+use std::{
+    env, fs,
+    path::Path,
+    process::ExitCode
+};
+
+// Builds the same container that the emulator would serve in game and writes it to disk, so that it can be built in CI, diffed
+// between versions or shipped to players that don't use the mod loader.
+// Mod folders are given in load order, so files in later folders replace files in earlier ones. Each folder is the one that
+// contains the project folders (e.g [Mod]/UTOC/UnrealEssentials.utoc), and goes into the container that it's named after if it
+// ends in .utoc, or UnrealEssentials otherwise
+// unpack goes the other way, extracting an existing container's files into a folder that can be used as a mod folder
+const USAGE: &str = "Usage: utoc-emu [--compression <method>] [--key [<guid>:]<key>] [--sign] [--partition-size <bytes>] [--mount-point [<container>:]<path>]... [--project-name <name>] <output folder> <engine version> <mod folder>...
+       utoc-emu [--key [<guid>:]<key>]... unpack <utoc path> <output folder>
+       utoc-emu verify <utoc path>
+       utoc-emu conflicts <json path> <mod folder>...
+  engine version: 4.25 (containers can't have packages in them), 4.25+, 4.26, 4.27, 5.0, 5.1, 5.2, 5.3
+  method: None, Zlib, LZ4, Zstd (4.25 can't be compressed)
+  key: AES-256 key in hex, for the encryption key GUID (defaults to 0). Baked containers are encrypted with the last key given
+  --sign: write SHA1 hashes of each block into the TOC, which verify checks against the container
+  --partition-size: split each container into [name].ucas, [name]_s1.ucas... of at most this size (4.27+)
+  --mount-point: mount the container (defaults to UnrealEssentials) at this path, e.g ../../../Game/Content/. Containers are
+    mounted at the deepest folder that contains all of their files otherwise
+  --project-name: the game's project folder, whose Content folder is mounted at /Game/ for every mod folder. Any folder besides
+    Engine is treated as the project folder otherwise
+  mod folder: [Mod]/UTOC/[name].utoc folders are baked into [name].utoc/.ucas, any other folder into UnrealEssentials
+  conflicts: write every file that more than one mod folder contains to a JSON file, with the folder that wins it";
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let (options, project_name) = match get_build_options(&mut args) {
+        Ok(o) => o,
+        Err(e) => {
+            println!("ERROR: {}\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = match (args.first().map(|a| a.as_str()), args.len()) {
+        (Some("unpack"), 3) => unpack_container(&args[1], Path::new(&args[2])),
+        (Some("verify"), 2) => verify_container(&args[1]),
+        (Some("conflicts"), 3..) => write_conflict_report(Path::new(&args[1]), &args[2..], project_name.as_deref()),
+        (Some("unpack" | "verify" | "conflicts"), _) => Err(EmulatorError::InvalidArgument(format!("Wrong number of arguments for {}\n{}", args[0], USAGE))),
+        (Some(_), 3..) => bake_container(Path::new(&args[0]), &args[1], &args[2..], &options, project_name.as_deref()),
+        _ => {
+            println!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            println!("ERROR: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Take the options out of the argument list, leaving the positional arguments. The project name is given to each mod folder
+fn get_build_options(args: &mut Vec<String>) -> EmulatorResult<(TocBuildOptions, Option<String>)> {
+    let mut options = TocBuildOptions::default();
+    let mut project_name = None;
+    while let Some(i) = args.iter().position(|a| a.starts_with("--")) {
+        let option = args.remove(i);
+        let mut get_value = || match i < args.len() {
+            true => Ok(args.remove(i)),
+            false => Err(EmulatorError::InvalidArgument(format!("Missing value for {}", option)))
+        };
+        match option.as_str() {
+            "--compression" => options.compression = CompressionMethod::from_name(&get_value()?)?,
+            "--key" => {
+                let value = get_value()?;
+                let (guid, key) = match value.split_once(':') {
+                    Some((guid, key)) => (encryption::guid_from_str(guid)?, EncryptionKey::from_hex(key)?),
+                    None => (0, EncryptionKey::from_hex(&value)?)
+                };
+                encryption::add_encryption_key(guid, key);
+                options.encryption = Some((guid, key));
+            },
+            "--sign" => options.sign = true,
+            "--mount-point" => {
+                let value = get_value()?;
+                let (container_name, mount_point) = value.split_once(':').unwrap_or((TOC_NAME, &value));
+                toc_factory::set_mount_point(container_name, Some(mount_point))?;
+            },
+            "--project-name" => project_name = Some(get_value()?),
+            "--partition-size" => {
+                let value = get_value()?;
+                options.partition_size = Some(value.parse().map_err(|_| EmulatorError::InvalidArgument(format!("\"{}\" isn't a valid partition size", value)))?);
+            },
+            _ => return Err(EmulatorError::InvalidArgument(format!("Unknown option {}", option)))
+        }
+    }
+    Ok((options, project_name))
+}
+
+// Get the TOC version (see build_table_of_contents_inner) and PAK version for an engine version
+fn get_versions(engine_version: &str) -> EmulatorResult<(u32, PakType)> {
+    let toc_version = match engine_version {
+        "4.25" => return Ok((u8::from(IoStoreTocVersion::Initial) as u32, PakType::FrozenIndex)),
+        "4.25+" | "4.26" => u8::from(IoStoreTocVersion::DirectoryIndex) as u32,
+        "4.27" => u8::from(IoStoreTocVersion::PartitionSize) as u32,
+        "5.0" | "5.1" | "5.2" | "5.3" => {
+            let minor_version: u32 = engine_version[2..].parse().unwrap(); // matched above
+            u8::from(IoStoreTocVersion::PerfectHashWithOverflow) as u32 | minor_version << 8
+        },
+        _ => return Err(EmulatorError::UnsupportedVersion(format!("Engine version \"{}\" isn't supported\n{}", engine_version, USAGE)))
+    };
+    Ok((toc_version, PakType::Fn64BugFix))
+}
+
+// Mod folders are named after themselves, since there's no mod id to use
+fn add_mod_folders(mod_paths: &[String], project_name: Option<&str>) -> EmulatorResult<()> {
+    for mod_path in mod_paths {
+        if !Path::new(mod_path).is_dir() {
+            return Err(EmulatorError::InvalidArgument(format!("Mod folder \"{}\" doesn't exist", mod_path)));
+        }
+        let container_name = match mod_path.trim_end_matches(['/', '\\']).strip_suffix(TOC_EXTENSION) {
+            Some(path) => Path::new(path).file_name().and_then(|n| n.to_str()).unwrap_or(TOC_NAME),
+            None => TOC_NAME
+        };
+        asset_collector::add_from_folders_for_project(mod_path, mod_path, container_name, project_name)?;
+    }
+    Ok(())
+}
+
+fn bake_container(
+    output_path: &Path, engine_version: &str, mod_paths: &[String], options: &TocBuildOptions, project_name: Option<&str>
+) -> EmulatorResult<()> {
+    let (toc_version, pak_version) = get_versions(engine_version)?;
+    add_mod_folders(mod_paths, project_name)?;
+    fs::create_dir_all(output_path).map_err(|e| EmulatorError::Io(output_path.to_string_lossy().to_string(), e))?;
+    let get_output_path = |name: &str| -> String { output_path.join(name).to_string_lossy().to_string() };
+    for container_name in asset_collector::get_container_names() {
+        let toc_path = get_output_path(&format!("{}{}", container_name, TOC_EXTENSION));
+        let toc = toc_factory::build_table_of_contents_with_options(&toc_path, toc_version, options)?;
+        fs::write(&toc_path, &toc.toc).map_err(|e| EmulatorError::Io(toc_path.clone(), e))?;
+        println!("Written to {}", toc_path);
+        let cas_path = get_output_path(&format!("{}{}", container_name, CAS_EXTENSION));
+        toc.container.write_container(&cas_path)?;
+        for i in 0..toc.container.get_partition_count() {
+            println!("Written to {}", toc_factory::get_partition_path(&cas_path, i));
+        }
+        if container_name != TOC_NAME {
+            println!("{} needs a PAK with the same name to be mounted, use the dummy {:?} PAK from UTOC.Stream.Emulator", container_name, pak_version);
+        }
+    }
+    asset_collector::print_asset_collector_results();
+    // Unreal needs a PAK with the same name as the container, which contains any loose files that couldn't go into IO Store
+    let pak_path = get_output_path(TARGET_PAK);
+    match pak_factory::build_pak(&pak_path, pak_version as u32)? {
+        Some(pak) => {
+            pak.write_pak(&pak_path)?;
+            println!("Written to {}", pak_path);
+        },
+        None => println!("No loose files to put in {}, use the dummy {:?} PAK from UTOC.Stream.Emulator alongside the container", TARGET_PAK, pak_version)
+    }
+    Ok(())
+}
+
+fn write_conflict_report(output_path: &Path, mod_paths: &[String], project_name: Option<&str>) -> EmulatorResult<()> {
+    add_mod_folders(mod_paths, project_name)?;
+    let report = conflict_report::get_conflict_report();
+    let json = report.to_json()?;
+    fs::write(output_path, json).map_err(|e| EmulatorError::Io(output_path.to_string_lossy().to_string(), e))?;
+    println!("{} conflicting files written to {}", report.conflicts.len(), output_path.to_string_lossy());
+    Ok(())
+}
+
+fn unpack_container(toc_path: &str, output_path: &Path) -> EmulatorResult<()> {
+    let mut container = IoStoreContainer::open(toc_path)?;
+    println!("Unpacking {} ({:?}, {} chunks) to {}", toc_path, container.toc.get_version(), container.toc.get_chunk_count(), output_path.to_string_lossy());
+    let results = container.extract_all(output_path)?;
+    println!("Extracted {} files ({} bytes), {} failed", results.extracted_files, results.extracted_size, results.failed.len());
+    if container.toc.find_container_header().is_some() {
+        let dump_path = output_path.join(STORE_ENTRIES_DUMP);
+        container.dump_store_entries(&dump_path)?;
+        println!("Store entries written to {}", dump_path.to_string_lossy());
+    }
+    Ok(())
+}
+
+fn verify_container(toc_path: &str) -> EmulatorResult<()> {
+    let mut container = IoStoreContainer::open(toc_path)?;
+    let results = container.verify_blocks()?;
+    let describe = |matches: Option<bool>| match matches {
+        Some(true) => "matches",
+        Some(false) => "DOESN'T MATCH",
+        None => "can't be checked (RSA signature)"
+    };
+    println!("Checked {} blocks, {} didn't match their hash", results.checked_blocks, results.mismatched_blocks.len());
+    for block in &results.mismatched_blocks {
+        println!("  Block {} at offset 0x{:x}", block, container.toc.compression_blocks[*block].get_offset());
+    }
+    println!("TOC signature {}", describe(results.toc_signature_matches));
+    println!("Block signature {}", describe(results.block_signature_matches));
+    if !results.mismatched_blocks.is_empty() {
+        return Err(EmulatorError::InvalidValue(format!("{} blocks are corrupted", results.mismatched_blocks.len())));
+    }
+    match results.toc_signature_matches != Some(false) && results.block_signature_matches != Some(false) {
+        true => Ok(()),
+        false => Err(EmulatorError::InvalidValue("Container signatures don't match".to_owned()))
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    error::Error,
+    io::{Read, Seek, Write}
+};
+use crate::error::{EmulatorError, EmulatorResult};
+
+// IO Store compresses each compression block separately, with the method used for a block stored as an index into the TOC's list of
+// compression method names (0 is uncompressed). Only codecs that have a pure Rust implementation can be used. Oodle is what most games
+// ship with, but it's proprietary, so containers that use it can't be read
+pub const COMPRESSION_METHOD_NAME_LENGTH: u32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionMethod {
+    #[default]
+    None,
+    Zlib,
+    LZ4,
+    Zstd
+}
+
+impl CompressionMethod {
+    // Names are matched case insensitively, since the name that a game registers for a codec isn't consistent (e.g "zstd")
+    pub fn from_name(name: &str) -> EmulatorResult<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" | "" => Ok(Self::None),
+            "zlib" => Ok(Self::Zlib),
+            "lz4" => Ok(Self::LZ4),
+            "zstd" => Ok(Self::Zstd),
+            "oodle" => Err(EmulatorError::UnsupportedVersion("Oodle compression isn't supported, since it's proprietary".to_owned())),
+            _ => Err(EmulatorError::UnsupportedVersion(format!("Compression method \"{}\" isn't supported", name)))
+        }
+    }
+
+    // Name that's written into the TOC
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Zlib => "Zlib",
+            Self::LZ4 => "LZ4",
+            Self::Zstd => "Zstd"
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> EmulatorResult<Vec<u8>> {
+        let map_err = |e: std::io::Error| EmulatorError::Serialization(format!("Couldn't compress block with {}: {}", self.get_name(), e));
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::Zlib => {
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::with_capacity(data.len()), flate2::Compression::default());
+                encoder.write_all(data).map_err(map_err)?;
+                encoder.finish().map_err(map_err)
+            },
+            Self::LZ4 => Ok(lz4_flex::block::compress(data)), // raw LZ4 block, without a frame or size prefix
+            Self::Zstd => Ok(ruzstd::encoding::compress_to_vec(data, ruzstd::encoding::CompressionLevel::Fastest))
+        }
+    }
+
+    // The uncompressed size of each block is stored in it's compression block entry
+    pub fn decompress(&self, data: &[u8], uncompressed_size: usize) -> EmulatorResult<Vec<u8>> {
+        let map_err = |e: String| EmulatorError::InvalidValue(format!("Couldn't decompress {} block: {}", self.get_name(), e));
+        let decompressed = match self {
+            Self::None => data.to_vec(),
+            Self::Zlib => {
+                let mut decompressed = Vec::with_capacity(uncompressed_size);
+                flate2::read::ZlibDecoder::new(data).read_to_end(&mut decompressed).map_err(|e| map_err(e.to_string()))?;
+                decompressed
+            },
+            Self::LZ4 => lz4_flex::block::decompress(data, uncompressed_size).map_err(|e| map_err(e.to_string()))?,
+            Self::Zstd => {
+                let mut decompressed = Vec::with_capacity(uncompressed_size);
+                let mut source = data;
+                ruzstd::decoding::StreamingDecoder::new(&mut source).map_err(|e| map_err(e.to_string()))?
+                    .read_to_end(&mut decompressed).map_err(|e| map_err(e.to_string()))?;
+                decompressed
+            }
+        };
+        match decompressed.len() == uncompressed_size {
+            true => Ok(decompressed),
+            false => Err(map_err(format!("expected {} bytes, got {}", uncompressed_size, decompressed.len())))
+        }
+    }
+
+    // Compression method names go after the compression blocks, as fixed length null padded strings. None isn't included
+    pub fn list_to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(list: &[CompressionMethod], writer: &mut W) -> Result<(), Box<dyn Error>> {
+        for method in list {
+            let mut name = [0; COMPRESSION_METHOD_NAME_LENGTH as usize];
+            name[..method.get_name().len()].copy_from_slice(method.get_name().as_bytes());
+            writer.write_all(&name)?;
+        }
+        Ok(())
+    }
+}
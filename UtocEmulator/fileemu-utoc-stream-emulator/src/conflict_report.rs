@@ -0,0 +1,79 @@
+use crate::{
+    asset_collector::{self, TocDirectorySyncRef, TocFileProvenance, PAK_ROOT_DIRECTORY},
+    error::{EmulatorError, EmulatorResult},
+    pak_factory::TARGET_PAK
+};
+use serde::Serialize;
+use std::{
+    ffi::CString,
+    sync::{Arc, Mutex}
+};
+
+// The last report that was returned over FFI, which is kept here so that the C# side can read it (see GetConflictReport in exports.rs)
+pub static CONFLICT_REPORT_JSON: Mutex<Option<CString>> = Mutex::new(None);
+
+// A file that more than one mod contains. Mod managers read these as JSON (see GetConflictReport in exports.rs) to show users
+// which mod's copy is being used
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileConflict {
+    pub container: String, // IO Store container that the file is in, or UnrealEssentials.pak for loose files
+    pub path: String, // path in the container's tree, e.g P3R/Content/Characters/Player.uasset
+    pub winner: TocFileProvenance,
+    pub losers: Vec<TocFileProvenance> // lowest priority first
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConflictReport {
+    pub conflicts: Vec<FileConflict>
+}
+
+impl ConflictReport {
+    pub fn to_json(&self) -> EmulatorResult<String> {
+        serde_json::to_string_pretty(self).map_err(|e| EmulatorError::Serialization(format!("Couldn't write the conflict report: {}", e)))
+    }
+}
+
+// Find every contested file in the trees that mods have been added to so far, sorted by container then path
+pub fn get_conflict_report() -> ConflictReport {
+    let mut conflicts = vec![];
+    for container_name in asset_collector::get_container_names() {
+        if let Some(root) = asset_collector::get_container_root(&container_name) {
+            get_conflicts(root, &container_name, "", &mut conflicts);
+        }
+    }
+    if let Some(pak_root) = PAK_ROOT_DIRECTORY.lock().unwrap().as_ref() {
+        get_conflicts(Arc::clone(pak_root), TARGET_PAK, "", &mut conflicts);
+    }
+    conflicts.sort_by(|a, b| a.container.cmp(&b.container).then_with(|| a.path.cmp(&b.path)));
+    ConflictReport { conflicts }
+}
+
+
+fn get_conflicts(dir: TocDirectorySyncRef, container: &str, parent_path: &str, conflicts: &mut Vec<FileConflict>) {
+    let dir_path = match dir.read().unwrap().name.as_ref() {
+        Some(name) if parent_path.is_empty() => name.to_owned(),
+        Some(name) => format!("{}/{}", parent_path, name),
+        None => parent_path.to_owned() // root directory
+    };
+    let mut curr_file = dir.read().unwrap().first_file.as_ref().map(Arc::clone);
+    while let Some(file) = curr_file {
+        {
+            let file = file.read().unwrap();
+            if !file.displaced.is_empty() {
+                conflicts.push(FileConflict {
+                    container: container.to_owned(),
+                    path: if dir_path.is_empty() { file.name.clone() } else { format!("{}/{}", dir_path, file.name) },
+                    winner: file.get_provenance(),
+                    losers: file.displaced.clone()
+                });
+            }
+        }
+        curr_file = file.read().unwrap().next.as_ref().map(Arc::clone);
+    }
+    let mut curr_child = dir.read().unwrap().first_child.as_ref().map(Arc::clone);
+    while let Some(child) = curr_child {
+        get_conflicts(Arc::clone(&child), container, &dir_path, conflicts);
+        curr_child = child.read().unwrap().next_sibling.as_ref().map(Arc::clone);
+    }
+}
@@ -0,0 +1,124 @@
+use aes::{
+    Aes256,
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit}
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Mutex
+};
+use crate::{
+    error::{EmulatorError, EmulatorResult},
+    io_toc::GUID
+};
+
+// Encrypted containers use AES-256 in ECB mode on every compression block and the directory index, with each one padded out to the
+// AES block size. The key for a container is found through the encryption key GUID in it's TOC header. Most games only have one key,
+// which uses a GUID of zero
+pub static ENCRYPTION_KEYS: Mutex<Option<HashMap<GUID, EncryptionKey>>> = Mutex::new(None);
+
+pub const AES_BLOCK_SIZE: u64 = 0x10;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 0x20]);
+
+// Don't write the key into logs
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptionKey(..)")
+    }
+}
+
+impl EncryptionKey {
+    // Keys are usually given as 64 hex digits, with or without a 0x prefix
+    pub fn from_hex(key: &str) -> EmulatorResult<Self> {
+        let key = key.trim().trim_start_matches("0x").trim_start_matches("0X");
+        let bytes = parse_hex(key).filter(|b| b.len() == 0x20).ok_or_else(|| EmulatorError::InvalidArgument(
+            "AES key should be 64 hex digits".to_owned()
+        ))?;
+        let mut data = [0; 0x20];
+        data.copy_from_slice(&bytes);
+        Ok(Self(data))
+    }
+
+    pub fn encrypt(&self, data: &mut [u8]) -> EmulatorResult<()> {
+        let cipher = self.get_cipher(data)?;
+        data.chunks_exact_mut(AES_BLOCK_SIZE as usize).for_each(|b| cipher.encrypt_block(GenericArray::from_mut_slice(b)));
+        Ok(())
+    }
+
+    pub fn decrypt(&self, data: &mut [u8]) -> EmulatorResult<()> {
+        let cipher = self.get_cipher(data)?;
+        data.chunks_exact_mut(AES_BLOCK_SIZE as usize).for_each(|b| cipher.decrypt_block(GenericArray::from_mut_slice(b)));
+        Ok(())
+    }
+
+    fn get_cipher(&self, data: &[u8]) -> EmulatorResult<Aes256> {
+        match data.len() as u64 % AES_BLOCK_SIZE {
+            0 => Ok(Aes256::new(GenericArray::from_slice(&self.0))),
+            _ => Err(EmulatorError::InvalidValue(format!("Encrypted data must be a multiple of {} bytes, got {}", AES_BLOCK_SIZE, data.len())))
+        }
+    }
+}
+
+// GUIDs are written as 32 hex digits (optionally split with dashes), made from it's four u32 components in order
+pub fn guid_from_str(guid: &str) -> EmulatorResult<GUID> {
+    let digits: String = guid.trim().chars().filter(|c| *c != '-').collect();
+    let components = (digits.len() == 32).then(|| (0..4).map(|i| u32::from_str_radix(&digits[i * 8..i * 8 + 8], 16).ok()).collect::<Option<Vec<u32>>>())
+        .flatten().ok_or_else(|| EmulatorError::InvalidArgument(format!("\"{}\" isn't a valid GUID", guid)))?;
+    // FGuid is serialized as A, B, C, D, so A ends up in the lowest bits
+    Ok(components.iter().rev().fold(0, |v, c| v << 32 | *c as GUID))
+}
+
+pub fn guid_to_string(guid: GUID) -> String {
+    (0..4).map(|i| format!("{:08X}", (guid >> (i * 32)) as u32)).collect()
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+pub fn add_encryption_key(guid: GUID, key: EncryptionKey) {
+    ENCRYPTION_KEYS.lock().unwrap().get_or_insert_with(HashMap::new).insert(guid, key);
+}
+
+pub fn get_encryption_key(guid: GUID) -> EmulatorResult<EncryptionKey> {
+    ENCRYPTION_KEYS.lock().unwrap().as_ref().and_then(|k| k.get(&guid).copied()).ok_or_else(|| EmulatorError::InvalidArgument(
+        format!("No AES key was given for encryption key GUID {}", guid_to_string(guid))
+    ))
+}
+
+pub fn clear_encryption_keys() {
+    *ENCRYPTION_KEYS.lock().unwrap() = None;
+}
+
+// Pad data out to the AES block size with zeroes so that it can be encrypted
+pub fn align_to_aes_block(data: &mut Vec<u8>) {
+    let alignment = (AES_BLOCK_SIZE - data.len() as u64 % AES_BLOCK_SIZE) % AES_BLOCK_SIZE;
+    data.resize(data.len() + alignment as usize, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aes_256_matches_fips_197() {
+        // FIPS-197 appendix C.3
+        let key = EncryptionKey::from_hex("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f").unwrap();
+        let mut data = parse_hex("00112233445566778899aabbccddeeff").unwrap();
+        key.encrypt(&mut data).unwrap();
+        assert_eq!(data, parse_hex("8ea2b7ca516745bfeafc49904b496089").unwrap());
+        key.decrypt(&mut data).unwrap();
+        assert_eq!(data, parse_hex("00112233445566778899aabbccddeeff").unwrap());
+    }
+
+    #[test]
+    fn unaligned_data_is_rejected() {
+        let key = EncryptionKey::from_hex(&"00".repeat(0x20)).unwrap();
+        assert!(key.encrypt(&mut [0; 0x11]).is_err());
+    }
+}
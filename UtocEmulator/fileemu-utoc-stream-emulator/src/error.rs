@@ -0,0 +1,97 @@
+use std::{
+    error::Error,
+    ffi::CString,
+    fmt,
+    io,
+    sync::Mutex
+};
+
+// The dev and release profiles are built with panic = "abort", so a panic inside of the emulator takes the game down with it.
+// Anything that can fail because of what's in a mod folder returns an EmulatorError instead, which gets turned into an error code
+// at the FFI boundary. The message for the last error is kept around so the C# side can log it (see GetLastError in exports.rs)
+pub static LAST_ERROR: Mutex<Option<CString>> = Mutex::new(None);
+
+pub type EmulatorResult<T> = Result<T, EmulatorError>;
+
+// Must be kept in sync with ErrorCode in RustApi.cs
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Success = 0,
+    InvalidArgument, // null or non UTF-8 string, or a path that isn't one of ours
+    Io, // couldn't read a file or directory
+    UnsupportedVersion, // IO Store or PAK version that can't be built
+    NoModFiles, // nothing was collected for the target container
+    InvalidAsset, // a mod file isn't in the expected format
+    Serialization, // failed to write the TOC, container header or PAK
+    InvalidValue // unexpected value for an enum type
+}
+
+impl From<ErrorCode> for u32 {
+    fn from(value: ErrorCode) -> Self {
+        value as u32
+    }
+}
+
+#[derive(Debug)]
+pub enum EmulatorError {
+    InvalidArgument(String),
+    Io(String, io::Error), // path of the file system object that failed
+    UnsupportedVersion(String),
+    NoModFiles(String),
+    InvalidAsset(String, String), // path of the asset, reason
+    Serialization(String),
+    InvalidValue(String)
+}
+
+impl EmulatorError {
+    pub fn get_code(&self) -> ErrorCode {
+        match self {
+            EmulatorError::InvalidArgument(_) => ErrorCode::InvalidArgument,
+            EmulatorError::Io(_, _) => ErrorCode::Io,
+            EmulatorError::UnsupportedVersion(_) => ErrorCode::UnsupportedVersion,
+            EmulatorError::NoModFiles(_) => ErrorCode::NoModFiles,
+            EmulatorError::InvalidAsset(_, _) => ErrorCode::InvalidAsset,
+            EmulatorError::Serialization(_) => ErrorCode::Serialization,
+            EmulatorError::InvalidValue(_) => ErrorCode::InvalidValue
+        }
+    }
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg),
+            EmulatorError::Io(path, e) => write!(f, "Couldn't access \"{}\": {}", path, e),
+            EmulatorError::UnsupportedVersion(msg) => write!(f, "Unsupported version: {}", msg),
+            EmulatorError::NoModFiles(msg) => write!(f, "No mod files: {}", msg),
+            EmulatorError::InvalidAsset(path, reason) => write!(f, "Invalid asset \"{}\": {}", path, reason),
+            EmulatorError::Serialization(msg) => write!(f, "Serialization failed: {}", msg),
+            EmulatorError::InvalidValue(msg) => write!(f, "Invalid value: {}", msg)
+        }
+    }
+}
+
+impl Error for EmulatorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EmulatorError::Io(_, e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+// Serializers return Box<dyn Error>, which only happens while writing out TOC and container data
+impl From<Box<dyn Error>> for EmulatorError {
+    fn from(value: Box<dyn Error>) -> Self {
+        EmulatorError::Serialization(value.to_string())
+    }
+}
+
+// Store the error's message for GetLastError and return it's code. It isn't printed here, since the C# side logs it along with
+// what it was doing when it failed
+pub fn set_last_error(error: &EmulatorError) -> ErrorCode {
+    // error messages come from paths and format strings, which won't contain a null byte
+    *LAST_ERROR.lock().unwrap() = CString::new(error.to_string()).ok();
+    error.get_code()
+}
@@ -1,98 +1,443 @@
 use crate::{
     asset_collector, 
-    toc_factory, toc_factory::{CONTAINER_DATA, CONTAINER_ENTRIES_OSPATH_POOL, TARGET_TOC, TARGET_CAS, PartitionBlock}
+    conflict_report, conflict_report::CONFLICT_REPORT_JSON,
+    error, error::{EmulatorError, EmulatorResult, ErrorCode, LAST_ERROR},
+    hot_reload,
+    pak_factory, pak_factory::{TARGET_PAK, PakBlock, PakData},
+    toc_factory, toc_factory::{TOC_NAME, ContainerPartition, PartitionBlock, TableOfContents}
 };
 use std::{
-    ffi::CStr,
+    ffi::{CStr, CString},
     os::raw::c_char
 };
 
+// Null pointers and strings that aren't UTF-8 are turned into an error instead of panicking
+unsafe fn str_from_ptr<'a>(ptr: *const c_char, name: &str) -> EmulatorResult<&'a str> {
+    if ptr.is_null() {
+        return Err(EmulatorError::InvalidArgument(format!("{} is null", name)));
+    }
+    CStr::from_ptr(ptr).to_str().map_err(|e| EmulatorError::InvalidArgument(format!("{} isn't valid UTF-8: {}", name, e)))
+}
+
+// Convert the result of an export into an ErrorCode, storing the message for GetLastError if it failed
+fn to_error_code(result: EmulatorResult<()>) -> u32 {
+    match result {
+        Ok(_) => ErrorCode::Success.into(),
+        Err(e) => error::set_last_error(&e).into()
+    }
+}
+
+/// # Safety
+/// `modId` and `modPath` must be valid, null terminated UTF-8 strings
 #[no_mangle]
 #[allow(non_snake_case)]
 // modId is used by the asset collector profiler
-pub unsafe extern "C" fn AddFromFolders(modId: *const c_char, modPath: *const c_char) {
-    asset_collector::add_from_folders(CStr::from_ptr(modId).to_str().unwrap(), CStr::from_ptr(modPath).to_str().unwrap());
+pub unsafe extern "C" fn AddFromFolders(modId: *const c_char, modPath: *const c_char) -> u32 {
+    to_error_code((|| {
+        asset_collector::add_from_folders(str_from_ptr(modId, "modId")?, str_from_ptr(modPath, "modPath")?)
+    })())
 }
 
+/// # Safety
+/// `modId`, `modPath` and `containerName` must be valid, null terminated UTF-8 strings
+#[no_mangle]
+#[allow(non_snake_case)]
+// Add a mod's files to their own container, which is built into [containerName].utoc/.ucas instead of UnrealEssentials
+pub unsafe extern "C" fn AddFromFoldersToContainer(modId: *const c_char, modPath: *const c_char, containerName: *const c_char) -> u32 {
+    to_error_code((|| {
+        asset_collector::add_from_folders_to_container(
+            str_from_ptr(modId, "modId")?, str_from_ptr(modPath, "modPath")?, str_from_ptr(containerName, "containerName")?
+        )
+    })())
+}
+
+/// # Safety
+/// `modId`, `modPath` and `containerName` must be valid, null terminated UTF-8 strings. `projectName` must be null or one too
+#[no_mangle]
+#[allow(non_snake_case)]
+// Same as AddFromFoldersToContainer, but only projectName/Content is mapped onto /Game/ for this mod's files instead of any top-level
+// folder besides Engine. Null or an empty string does the same as AddFromFoldersToContainer
+pub unsafe extern "C" fn AddFromFoldersForProject(
+    modId: *const c_char, modPath: *const c_char, containerName: *const c_char, projectName: *const c_char
+) -> u32 {
+    to_error_code((|| {
+        let project_name = match projectName.is_null() {
+            true => None,
+            false => Some(str_from_ptr(projectName, "projectName")?).filter(|p| !p.is_empty())
+        };
+        asset_collector::add_from_folders_for_project(
+            str_from_ptr(modId, "modId")?, str_from_ptr(modPath, "modPath")?, str_from_ptr(containerName, "containerName")?, project_name
+        )
+    })())
+}
+
+/// # Safety
+/// `tocPath` must be a valid, null terminated UTF-8 string and every out parameter must point to writable memory
 #[no_mangle]
 #[allow(non_snake_case)]
 // haiiii Reloaded!!!! :3
-pub unsafe extern "C" fn BuildTableOfContents(tocPath: *const c_char, settings: *const u32, settings_length: u32, length: *mut u64) -> *const u8 {
-    match toc_factory::build_table_of_contents(CStr::from_ptr(tocPath).to_str().unwrap(), 0) {
-        Some(n) => {
-            *length = n.len() as u64; // set length parameter
-            n.leak().as_ptr() // leak memory lol
-        },
-        None => 0 as *const u8 // couldn't build toc, let C# side know with a null pointer
-    }
+pub unsafe extern "C" fn BuildTableOfContents(
+    tocPath: *const c_char,
+    version: u32, // same as BuildTableOfContentsEx
+    handle: *mut *mut TableOfContents, tocData: *mut *const u8, length: *mut u64
+) -> u32 {
+    to_error_code((|| {
+        let toc = Box::new(toc_factory::build_table_of_contents(str_from_ptr(tocPath, "tocPath")?, version)?);
+        *length = toc.toc.len() as u64; // set length parameter
+        *tocData = toc.toc.as_ptr();
+        *handle = Box::into_raw(toc); // owned by the C# side until FreeTableOfContents
+        Ok(())
+    })())
 }
 
+/// # Safety
+/// `basePath` must be a valid, null terminated UTF-8 string and every out parameter must point to writable memory.
+/// Every pointer that's written out is valid until `handle` is passed to `FreeTableOfContents`
 #[no_mangle]
 #[allow(non_snake_case)]
 // haiiii Reloaded!!!! :3
 pub unsafe extern "C" fn BuildTableOfContentsEx(
     // UTOC
     basePath: *const c_char,
+    version: u32, // IoStoreTocVersion in the lowest byte. For UE5, the engine's minor version goes in the second byte
+    handle: *mut *mut TableOfContents,
+    tocData: *mut *const u8,
+    tocLength: *mut u64,
+    // UCAS
+    blocks: *mut *const PartitionBlock,
+    blockCount: *mut usize,
+    header: *mut *const u8,
+    headerSize: *mut usize,
+    headerOffset: *mut u64
+    ) -> u32 {
+    to_error_code((|| {
+        let base_path_owned = str_from_ptr(basePath, "basePath")?;
+        let toc_path = toc_factory::get_emulated_toc_path(base_path_owned, TOC_NAME);
+        let toc = toc_factory::build_table_of_contents(&toc_path, version)?;
+        println!("Built table of contents");
+        write_table_of_contents(toc, handle, tocData, tocLength, blocks, blockCount, header, headerSize, headerOffset);
+        Ok(())
+    })())
+}
+
+/// # Safety
+/// `basePath` and `containerName` must be valid, null terminated UTF-8 strings and every out parameter must point to writable
+/// memory. Every pointer that's written out is valid until `handle` is passed to `FreeTableOfContents`
+#[no_mangle]
+#[allow(non_snake_case)]
+// Same as BuildTableOfContentsEx, for a container that mods were added to with AddFromFoldersToContainer
+pub unsafe extern "C" fn BuildContainerTableOfContents(
+    // UTOC
+    basePath: *const c_char,
+    containerName: *const c_char,
     version: u32,
+    handle: *mut *mut TableOfContents,
     tocData: *mut *const u8,
     tocLength: *mut u64,
     // UCAS
     blocks: *mut *const PartitionBlock,
     blockCount: *mut usize,
     header: *mut *const u8,
-    headerSize: *mut usize
-    ) -> bool {
-    let base_path_owned = CStr::from_ptr(basePath).to_str().unwrap();
-    let toc_path = base_path_owned.to_owned() + "\\" + TARGET_TOC;
-    let cas_path = base_path_owned.to_owned() + "\\" + TARGET_CAS;
-    match toc_factory::build_table_of_contents(&toc_path, version) {
-        Some(n) => {
-            println!("Built table of contents");
-            // UTOC
-            *tocLength = n.len() as u64; // set length parameter
-            *tocData = n.leak().as_ptr(); // leak memory lol (toc data needs to live for rest of program)
-            // UCAS
-            let container_lock = CONTAINER_DATA.lock().unwrap();
-            match (*container_lock).as_ref() {
-                Some(n) => {
-                    println!("Built container file");
-                    *blocks = n.virtual_blocks.as_ptr();
-                    *blockCount = n.virtual_blocks.len();
-                    *header = n.header.as_ptr();
-                    *headerSize = n.header.len();
-                    true
-                },
-                None => false
-            }
-        },
-        None => false
+    headerSize: *mut usize,
+    headerOffset: *mut u64
+    ) -> u32 {
+    to_error_code((|| {
+        let container_name = str_from_ptr(containerName, "containerName")?;
+        let toc_path = toc_factory::get_emulated_toc_path(str_from_ptr(basePath, "basePath")?, container_name);
+        let toc = toc_factory::build_table_of_contents(&toc_path, version)?;
+        println!("Built table of contents for {}", container_name);
+        write_table_of_contents(toc, handle, tocData, tocLength, blocks, blockCount, header, headerSize, headerOffset);
+        Ok(())
+    })())
+}
+
+// Hand ownership of a built TOC to the C# side, along with pointers to it's data
+#[allow(non_snake_case, clippy::too_many_arguments)]
+unsafe fn write_table_of_contents(
+    toc: TableOfContents,
+    handle: *mut *mut TableOfContents,
+    tocData: *mut *const u8,
+    tocLength: *mut u64,
+    blocks: *mut *const PartitionBlock,
+    blockCount: *mut usize,
+    header: *mut *const u8,
+    headerSize: *mut usize,
+    headerOffset: *mut u64
+) {
+    let toc = Box::new(toc);
+    // UTOC
+    *tocLength = toc.toc.len() as u64; // set length parameter
+    *tocData = toc.toc.as_ptr();
+    // UCAS (other partitions are read through GetContainerPartition)
+    write_container_partition(&toc.container.get_partition(0).unwrap(), blocks, blockCount, header, headerSize, headerOffset);
+    *handle = Box::into_raw(toc); // owned by the C# side until FreeTableOfContents
+}
+
+#[allow(non_snake_case)]
+unsafe fn write_container_partition(
+    partition: &ContainerPartition,
+    blocks: *mut *const PartitionBlock,
+    blockCount: *mut usize,
+    header: *mut *const u8,
+    headerSize: *mut usize,
+    headerOffset: *mut u64
+) {
+    *blocks = partition.blocks.as_ptr();
+    *blockCount = partition.blocks.len();
+    *header = partition.header.as_ptr();
+    *headerSize = partition.header.len();
+    *headerOffset = partition.header_offset;
+}
+
+/// # Safety
+/// `handle` must have been returned by `BuildTableOfContentsEx` or `GetHotReloadedTableOfContents` and not already been freed
+#[no_mangle]
+#[allow(non_snake_case)]
+// Number of .ucas files that the container is split into. This is only more than 1 if a partition size was set
+pub unsafe extern "C" fn GetContainerPartitionCount(handle: *const TableOfContents) -> u32 {
+    match handle.as_ref() {
+        Some(toc) => toc.container.get_partition_count() as u32,
+        None => 0
+    }
+}
+
+/// # Safety
+/// `handle` must have been returned by `BuildTableOfContentsEx` or `GetHotReloadedTableOfContents` and not already been freed.
+/// Every out parameter must point to writable memory. Pointers that are written out are valid until `handle` is passed to
+/// `FreeTableOfContents`
+#[no_mangle]
+#[allow(non_snake_case)]
+// Get the blocks for partition [partition] of the container, which is read from [container]_s[partition].ucas. Block and header
+// offsets start from the beginning of that partition, and headerSize is 0 if the container header is in another partition
+pub unsafe extern "C" fn GetContainerPartition(
+    handle: *const TableOfContents,
+    partition: u32,
+    blocks: *mut *const PartitionBlock,
+    blockCount: *mut usize,
+    header: *mut *const u8,
+    headerSize: *mut usize,
+    headerOffset: *mut u64
+) -> u32 {
+    to_error_code((|| {
+        let toc = handle.as_ref().ok_or_else(|| EmulatorError::InvalidArgument("handle is null".to_owned()))?;
+        let container_partition = toc.container.get_partition(partition as usize).ok_or_else(|| EmulatorError::InvalidArgument(
+            format!("Partition {} doesn't exist, the container only has {}", partition, toc.container.get_partition_count())
+        ))?;
+        write_container_partition(&container_partition, blocks, blockCount, header, headerSize, headerOffset);
+        Ok(())
+    })())
+}
+
+/// # Safety
+/// `basePath` must be a valid, null terminated UTF-8 string
+#[no_mangle]
+#[allow(non_snake_case)]
+// Watch mod folders for changes, rebuilding the TOCs in basePath for the containers that they went into. Mods that are added after
+// this is called are watched too. pollInterval is in milliseconds, 0 uses the default
+pub unsafe extern "C" fn StartHotReload(basePath: *const c_char, version: u32, pollInterval: u32) -> u32 {
+    to_error_code((|| {
+        hot_reload::start_hot_reload(str_from_ptr(basePath, "basePath")?, version, pollInterval)
+    })())
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+// Write a hash of each block into the emulated TOC, for games that refuse to load unsigned containers (their signature check still
+// needs to be patched). Every chunk has to be copied into the temp folder to be hashed, so this makes building the TOC slower
+pub extern "C" fn SetContainerSigning(sign: bool) {
+    toc_factory::EMULATED_TOC_OPTIONS.lock().unwrap().get_or_insert_with(Default::default).sign = sign;
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+// Split the emulated container into partitions of at most partitionSize bytes (4.27+), so that large mod sets stay under the size
+// limits for a single file. 0 keeps the whole container in one .ucas
+pub extern "C" fn SetPartitionSize(partitionSize: u64) {
+    toc_factory::EMULATED_TOC_OPTIONS.lock().unwrap().get_or_insert_with(Default::default).partition_size = match partitionSize {
+        0 => None,
+        s => Some(s)
+    };
+}
+
+/// # Safety
+/// `containerName` must be a valid, null terminated UTF-8 string. `mountPoint` must be null or a valid, null terminated UTF-8 string
+#[no_mangle]
+#[allow(non_snake_case)]
+// Mount a container at mountPoint (e.g ../../../Game/Plugins/[Plugin]/Content/) instead of the deepest directory that contains all
+// of it's files. Null or an empty string goes back to working it out from the files
+pub unsafe extern "C" fn SetMountPoint(containerName: *const c_char, mountPoint: *const c_char) -> u32 {
+    to_error_code((|| {
+        let mount_point = match mountPoint.is_null() {
+            true => None,
+            false => Some(str_from_ptr(mountPoint, "mountPoint")?).filter(|m| !m.is_empty())
+        };
+        toc_factory::set_mount_point(str_from_ptr(containerName, "containerName")?, mount_point)
+    })())
+}
+
+/// # Safety
+/// Must not be called from inside of the hot reload thread
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "C" fn StopHotReload() {
+    hot_reload::stop_hot_reload();
+}
+
+/// # Safety
+/// Every out parameter must point to writable memory. Pointers that are written out are valid until `handle` is passed to
+/// `FreeTableOfContents`
+#[no_mangle]
+#[allow(non_snake_case)]
+// Get the TOC that was rebuilt after mod files changed. handle is null if nothing has changed since the last call. Returns the
+// error that happened while rebuilding it if it failed
+pub unsafe extern "C" fn GetHotReloadedTableOfContents(
+    handle: *mut *mut TableOfContents,
+    tocData: *mut *const u8,
+    tocLength: *mut u64,
+    blocks: *mut *const PartitionBlock,
+    blockCount: *mut usize,
+    header: *mut *const u8,
+    headerSize: *mut usize,
+    headerOffset: *mut u64
+) -> u32 {
+    to_error_code((|| {
+        *handle = std::ptr::null_mut();
+        if let Some(toc) = hot_reload::take_hot_reloaded_toc(TOC_NAME)? {
+            write_table_of_contents(toc, handle, tocData, tocLength, blocks, blockCount, header, headerSize, headerOffset);
+        }
+        Ok(())
+    })())
+}
+
+/// # Safety
+/// `containerName` must be a valid, null terminated UTF-8 string and every out parameter must point to writable memory. Pointers
+/// that are written out are valid until `handle` is passed to `FreeTableOfContents`
+#[no_mangle]
+#[allow(non_snake_case)]
+// Same as GetHotReloadedTableOfContents, for a container that mods were added to with AddFromFoldersToContainer
+pub unsafe extern "C" fn GetHotReloadedContainer(
+    containerName: *const c_char,
+    handle: *mut *mut TableOfContents,
+    tocData: *mut *const u8,
+    tocLength: *mut u64,
+    blocks: *mut *const PartitionBlock,
+    blockCount: *mut usize,
+    header: *mut *const u8,
+    headerSize: *mut usize,
+    headerOffset: *mut u64
+) -> u32 {
+    to_error_code((|| {
+        *handle = std::ptr::null_mut();
+        if let Some(toc) = hot_reload::take_hot_reloaded_toc(str_from_ptr(containerName, "containerName")?)? {
+            write_table_of_contents(toc, handle, tocData, tocLength, blocks, blockCount, header, headerSize, headerOffset);
+        }
+        Ok(())
+    })())
+}
+
+/// # Safety
+/// `handle` must have been returned by `BuildTableOfContents` or `BuildTableOfContentsEx` and not already been freed.
+/// Streams over the TOC data or container blocks must be disposed of first
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "C" fn FreeTableOfContents(handle: *mut TableOfContents) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
     }
 }
 
+/// # Safety
+/// `basePath` must be a valid, null terminated UTF-8 string and every out parameter must point to writable memory.
+/// The blocks are valid until `handle` is passed to `FreePak`
+#[no_mangle]
+#[allow(non_snake_case)]
+// handle is null and blockCount is 0 if there aren't any files for the PAK, in which case the dummy PAK should be used
+pub unsafe extern "C" fn BuildPak(
+    basePath: *const c_char,
+    version: u32, // PakType
+    handle: *mut *mut PakData,
+    blocks: *mut *const PakBlock,
+    blockCount: *mut usize
+    ) -> u32 {
+    to_error_code((|| {
+        *handle = std::ptr::null_mut();
+        *blockCount = 0;
+        let pak_path = str_from_ptr(basePath, "basePath")?.to_owned() + "\\" + TARGET_PAK;
+        if let Some(pak) = pak_factory::build_pak(&pak_path, version)? {
+            println!("Built PAK file");
+            let pak = Box::new(pak);
+            *blocks = pak.blocks.as_ptr();
+            *blockCount = pak.blocks.len();
+            *handle = Box::into_raw(pak); // owned by the C# side until FreePak
+        }
+        Ok(())
+    })())
+}
+
+/// # Safety
+/// `handle` must have been returned by `BuildPak` and not already been freed. Streams over the PAK blocks must be disposed of first
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "C" fn FreePak(handle: *mut PakData) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// # Safety
+/// Must not be called while mod folders are being added or a container is being built
+#[no_mangle]
+#[allow(non_snake_case)]
+// Forget every mod folder that was added so that the container can be rebuilt from a new set of mods
+pub unsafe extern "C" fn ResetAssetCollector() {
+    asset_collector::reset_asset_collector();
+}
+
+/// # Safety
+/// `containerName` must be a valid, null terminated UTF-8 string
+#[no_mangle]
+#[allow(non_snake_case)]
+// Forget the mod folders that were added to containerName, leaving every other container as it is
+pub unsafe extern "C" fn ResetContainer(containerName: *const c_char) -> u32 {
+    to_error_code((|| {
+        asset_collector::reset_container(str_from_ptr(containerName, "containerName")?);
+        Ok(())
+    })())
+}
+
+/// # Safety
+/// `report` must be a valid pointer. The string written to it is owned by the emulator and is only valid until the next call
+#[no_mangle]
+#[allow(non_snake_case)]
+// Write every file that more than one mod contains as JSON, with the mod that won it and the mods whose copy was replaced
+pub unsafe extern "C" fn GetConflictReport(report: *mut *const c_char) -> u32 {
+    to_error_code((|| {
+        if report.is_null() {
+            return Err(EmulatorError::InvalidArgument("report is null".to_owned()));
+        }
+        let json = conflict_report::get_conflict_report().to_json()?;
+        // JSON escapes control characters, so it won't contain a null byte
+        let mut report_lock = CONFLICT_REPORT_JSON.lock().unwrap();
+        *report_lock = CString::new(json).ok();
+        *report = report_lock.as_ref().map_or(std::ptr::null(), |r| r.as_ptr());
+        Ok(())
+    })())
+}
 
+/// # Safety
+/// The returned string is owned by the emulator and is only valid until the next error occurs
 #[no_mangle]
 #[allow(non_snake_case)]
-pub unsafe extern "C" fn GetContainerBlocks(
-    casPath: *const c_char, 
-    blocks: *mut *const PartitionBlock, blockCount: *mut usize, 
-    header: *mut *const u8, headerSize: *mut usize
-) -> bool {
-    false
-    /* 
-    let block_managed = toc_factory::get_virtual_partition(CStr::from_ptr(casPath).to_str().unwrap());
-    match block_managed {
-        Some(n) => {
-            *blockCount = n.0.len(); // container blocks
-            *blocks = n.0.as_ptr();
-            *headerSize = n.1.len(); // container header
-            *header = n.1.as_ptr();
-            true
-        },
-        None => false
+// Message for the last export that didn't return ErrorCode::Success, or null if nothing has failed yet
+pub unsafe extern "C" fn GetLastError() -> *const c_char {
+    match LAST_ERROR.lock().unwrap().as_ref() {
+        Some(msg) => msg.as_ptr(),
+        None => std::ptr::null()
     }
-    */
 }
 
+/// # Safety
+/// Must not be called while mod folders are being added
 #[no_mangle]
 #[allow(non_snake_case)]
 pub unsafe extern "C" fn PrintAssetCollectorResults() {
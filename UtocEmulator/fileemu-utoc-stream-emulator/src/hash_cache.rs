@@ -0,0 +1,140 @@
+use rayon::prelude::*;
+use sha1::{Sha1, Digest};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::UNIX_EPOCH
+};
+use crate::asset_collector::EMULATOR_NAME;
+
+// Every chunk's meta contains a SHA1 hash of it's data. Reading every file to hash it is slow, so hashes are saved into the temp
+// folder and reused for as long as the file's size and modified time stay the same. Each line is the hash, size, modified time and
+// path, separated by tabs (paths can contain spaces)
+pub const META_HASH_CACHE: &str = "MetaHashes.txt";
+// Held from loading the cache until it's saved, so that containers being built at the same time (e.g by hot reload) don't overwrite
+// each other's hashes
+static META_HASH_CACHE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CachedHash {
+    size: u64,
+    modified: u128, // nanoseconds since the unix epoch
+    hash: [u8; 0x14]
+}
+
+impl CachedHash {
+    fn from_line(line: &str) -> Option<(String, Self)> {
+        let mut parts = line.splitn(4, '\t');
+        let hash_str = parts.next()?;
+        let size = parts.next()?.parse().ok()?;
+        let modified = parts.next()?.parse().ok()?;
+        let path = parts.next()?.to_owned();
+        if hash_str.len() != 0x28 || !hash_str.is_ascii() {
+            return None;
+        }
+        let mut hash = [0; 0x14];
+        for (i, b) in hash.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hash_str[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some((path, Self { size, modified, hash }))
+    }
+
+    fn to_line(self, path: &str) -> String {
+        let hash: String = self.hash.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}\t{}\t{}\t{}\n", hash, self.size, self.modified, path)
+    }
+}
+
+#[derive(Debug)]
+pub struct MetaHashCache {
+    entries: HashMap<String, CachedHash>,
+    changed: bool,
+    _guard: MutexGuard<'static, ()>
+}
+
+impl MetaHashCache {
+    fn get_cache_path() -> PathBuf {
+        let mut cache_path = std::env::temp_dir();
+        cache_path.push(EMULATOR_NAME);
+        cache_path.push(META_HASH_CACHE);
+        cache_path
+    }
+
+    // A missing cache is the same as an empty one. Lines that can't be parsed are skipped, so they'll be hashed again
+    pub fn load() -> Self {
+        let guard = META_HASH_CACHE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let entries = match fs::read_to_string(Self::get_cache_path()) {
+            Ok(cache) => cache.lines().filter_map(CachedHash::from_line).collect(),
+            Err(_) => HashMap::new()
+        };
+        Self { entries, changed: false, _guard: guard }
+    }
+
+    // Files that no longer exist are removed so that the cache doesn't keep growing as mods are removed. The cache is written to a
+    // temporary file first, so a game that's closed partway through saving doesn't leave a truncated cache behind
+    pub fn save(&mut self) -> Result<(), Box<dyn Error>> {
+        let count = self.entries.len();
+        self.entries.retain(|path, _| Path::new(path).exists());
+        if !self.changed && count == self.entries.len() {
+            return Ok(());
+        }
+        let cache_path = Self::get_cache_path();
+        fs::create_dir_all(cache_path.parent().unwrap())?;
+        let cache: String = self.entries.iter().map(|(path, entry)| entry.to_line(path)).collect();
+        let temp_path = cache_path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temp_path, cache)?;
+        if let Err(e) = fs::rename(&temp_path, &cache_path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+        self.changed = false;
+        Ok(())
+    }
+
+    // Get the SHA1 hash of each file, hashing files that aren't in the cache (or have changed since) on multiple threads. Files that
+    // can't be read are None, since they'll fail when the container is read anyway
+    pub fn hash_files(&mut self, paths: &[&str]) -> Vec<Option<[u8; 0x14]>> {
+        let results: Vec<Option<(CachedHash, bool)>> = paths.par_iter().map(|path| {
+            let metadata = fs::metadata(path).ok()?;
+            let size = metadata.len();
+            let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+            match self.entries.get(*path) {
+                Some(cached) if cached.size == size && cached.modified == modified => Some((*cached, false)),
+                _ => Some((CachedHash { size, modified, hash: hash_file(path).ok()? }, true))
+            }
+        }).collect();
+        paths.iter().zip(results).map(|(path, result)| {
+            let (entry, is_new) = result?;
+            if is_new {
+                self.entries.insert(path.to_string(), entry);
+                self.changed = true;
+            }
+            Some(entry.hash)
+        }).collect()
+    }
+}
+
+fn hash_file(path: &str) -> io::Result<[u8; 0x14]> {
+    let mut hasher = Sha1::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_hash_line_round_trip() {
+        let entry = CachedHash { size: 0x1234, modified: 1_700_000_000_123_456_789, hash: std::array::from_fn(|i| i as u8 * 0xd) };
+        let path = "C:\\Reloaded II\\Mods\\Some Mod\\UTOC\\Test\\Content\\Asset.ubulk";
+        let line = entry.to_line(path);
+        assert_eq!(line.matches('\t').count(), 3);
+        assert_eq!(CachedHash::from_line(line.trim_end()), Some((path.to_owned(), entry)));
+        assert_eq!(CachedHash::from_line("not a cache line"), None);
+    }
+}
@@ -0,0 +1,353 @@
+use crate::{
+    asset_collector,
+    asset_collector::{
+        COOKED_EXPORT_DATA_EXTENSION, MOD_FOLDERS, SUITABLE_FILE_EXTENSIONS,
+        ModFolder, TocDirectory, TocFile, TocFileProvenance},
+    error::{EmulatorError, EmulatorResult},
+    toc_factory, toc_factory::TableOfContents
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    thread, thread::JoinHandle,
+    time::{Duration, SystemTime}
+};
+
+// Hot reload polls each mod folder that was added for changed IO Store files, applies those changes to the asset collector's tree
+// and rebuilds the TOC of each container that changed, so that assets can be iterated on without restarting the game. The C# side
+// picks up the rebuilt TOCs with GetHotReloadedContainer and swaps them in.
+// Files that go into the emulated PAK aren't watched, since the PAK would need to be remounted to pick them up
+pub static HOT_RELOAD: Mutex<Option<HotReloadWatcher>> = Mutex::new(None);
+// The most recent rebuild of each container that hasn't been picked up yet. Rebuilds that fail are kept here too so that the C# side
+// can report them
+pub static HOT_RELOADED_TOCS: Mutex<BTreeMap<String, EmulatorResult<TableOfContents>>> = Mutex::new(BTreeMap::new());
+
+// A change is only applied once a poll finds no further changes, so files that are still being written aren't read
+pub const DEFAULT_POLL_INTERVAL: u32 = 1000; // in milliseconds
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileStamp {
+    size: u64,
+    modified: Option<SystemTime>
+}
+
+// Path relative to the mod folder -> when that file was last changed
+type FolderSnapshot = HashMap<PathBuf, FileStamp>;
+// The same folder can be added by more than one mod, or into more than one container
+type ModFolderKey = (String, PathBuf, String);
+
+pub struct HotReloadWatcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl Drop for HotReloadWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark(); // wake it up instead of waiting for the poll interval to pass
+            let _ = thread.join();
+        }
+    }
+}
+
+// Start watching mod folders for changes. Rebuilt TOCs are for the emulated files in base_path. Mod folders are looked up on every poll,
+// so mods that are added afterwards get watched from then on, and ones that are cleared by reset_asset_collector stop being watched.
+// Any existing watcher is stopped first
+pub fn start_hot_reload(base_path: &str, version: u32, poll_interval: u32) -> EmulatorResult<()> {
+    stop_hot_reload();
+    let poll_interval = Duration::from_millis(if poll_interval == 0 { DEFAULT_POLL_INTERVAL } else { poll_interval } as u64);
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = Arc::clone(&stop);
+    let base_path = base_path.to_owned();
+    // snapshot before returning so that changes made straight afterwards are still picked up
+    let mut snapshots: HashMap<ModFolderKey, FolderSnapshot> = HashMap::new();
+    update_snapshots(&MOD_FOLDERS.lock().unwrap().clone(), &mut snapshots);
+    let thread = thread::Builder::new().name("UtocEmulator hot reload".to_owned()).spawn(move || {
+        let mut pending: BTreeMap<String, BTreeSet<PathBuf>> = BTreeMap::new(); // changed files for each container
+        loop {
+            thread::park_timeout(poll_interval);
+            if thread_stop.load(Ordering::Relaxed) {
+                break
+            }
+            let mods = MOD_FOLDERS.lock().unwrap().clone();
+            let mut changed = false;
+            for (container, path) in update_snapshots(&mods, &mut snapshots) {
+                changed |= pending.entry(container).or_default().insert(path);
+            }
+            if changed || pending.is_empty() {
+                continue // wait until the files have settled
+            }
+            for (container, paths) in std::mem::take(&mut pending) {
+                println!("Hot reload: {} files changed, rebuilding {}", paths.len(), container);
+                let container_mods: Vec<ModFolder> = mods.iter().filter(|m| m.container == container).cloned().collect();
+                // the tree can't be built or reset by anything else until every change has been applied. The lock is released once
+                // the tree has been flattened, so converting and hashing files doesn't hold up the container's other users
+                let container_lock = asset_collector::get_container_lock(&container);
+                let container_guard = container_lock.lock().unwrap();
+                for path in paths {
+                    apply_change(&container_mods, &container, &path);
+                }
+                let options = toc_factory::EMULATED_TOC_OPTIONS.lock().unwrap().unwrap_or_default();
+                let toc_path = toc_factory::get_emulated_toc_path(&base_path, &container);
+                let result = toc_factory::build_locked_table_of_contents(&toc_path, version, &options, container_guard);
+                if let Err(e) = &result {
+                    println!("WARNING: Hot reload failed to rebuild {}: {}", container, e);
+                }
+                HOT_RELOADED_TOCS.lock().unwrap().insert(container, result);
+            }
+        }
+    }).map_err(|e| EmulatorError::Io("hot reload thread".to_owned(), e))?;
+    *HOT_RELOAD.lock().unwrap() = Some(HotReloadWatcher { stop, thread: Some(thread) });
+    Ok(())
+}
+
+pub fn stop_hot_reload() {
+    // take the watcher out first so that it's thread isn't joined while the lock is held
+    let watcher = HOT_RELOAD.lock().unwrap().take();
+    drop(watcher);
+    HOT_RELOADED_TOCS.lock().unwrap().clear();
+}
+
+// Take the most recently rebuilt TOC for a container, if there's been one since the last call
+pub fn take_hot_reloaded_toc(container_name: &str) -> EmulatorResult<Option<TableOfContents>> {
+    let mut tocs = HOT_RELOADED_TOCS.lock().unwrap();
+    let name = tocs.keys().find(|name| name.eq_ignore_ascii_case(container_name)).cloned();
+    name.and_then(|name| tocs.remove(&name)).transpose()
+}
+
+fn is_watched_file(path: &Path) -> bool {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    SUITABLE_FILE_EXTENSIONS.contains(&extension) || extension == COOKED_EXPORT_DATA_EXTENSION
+}
+
+// Snapshot every mod folder, returning the container and path of each file that changed since the last snapshot. Mods without a
+// snapshot were just added, and their files are already in the tree, so they're only snapshotted. Snapshots of mods that are gone
+// are dropped
+fn update_snapshots(mods: &[ModFolder], snapshots: &mut HashMap<ModFolderKey, FolderSnapshot>) -> Vec<(String, PathBuf)> {
+    let mut changed = vec![];
+    let mut new_snapshots = HashMap::with_capacity(mods.len());
+    for mod_folder in mods {
+        let key = (mod_folder.mod_id.clone(), mod_folder.os_path.clone(), mod_folder.container.clone());
+        let new_snapshot = snapshot_mod_folder(&mod_folder.os_path);
+        if let Some(snapshot) = snapshots.get(&key) {
+            changed.extend(get_changed_files(snapshot, &new_snapshot).into_iter().map(|p| (mod_folder.container.clone(), p)));
+        }
+        new_snapshots.insert(key, new_snapshot);
+    }
+    *snapshots = new_snapshots;
+    changed
+}
+
+fn snapshot_mod_folder(mod_path: &Path) -> FolderSnapshot {
+    let mut snapshot = HashMap::new();
+    snapshot_mod_folder_inner(mod_path, Path::new(""), &mut snapshot);
+    snapshot
+}
+
+// Folders that can't be read are treated as empty, which removes their files until they can be read again
+fn snapshot_mod_folder_inner(os_path: &Path, relative_path: &Path, snapshot: &mut FolderSnapshot) {
+    let dir_entries = match fs::read_dir(os_path) {
+        Ok(d) => d,
+        Err(_) => return
+    };
+    for fs_obj in dir_entries.flatten() {
+        let file_type = match fs_obj.file_type() {
+            Ok(t) => t,
+            Err(_) => continue
+        };
+        let inner_relative_path = relative_path.join(fs_obj.file_name());
+        if file_type.is_dir() {
+            snapshot_mod_folder_inner(&fs_obj.path(), &inner_relative_path, snapshot);
+        } else if file_type.is_file() && is_watched_file(&inner_relative_path) {
+            if let Ok(meta) = fs_obj.metadata() {
+                snapshot.insert(inner_relative_path, FileStamp { size: meta.len(), modified: meta.modified().ok() });
+            }
+        }
+    }
+}
+
+// Get every file that was added, changed or removed between two snapshots of the same mod folder
+fn get_changed_files(old: &FolderSnapshot, new: &FolderSnapshot) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = new.iter()
+        .filter(|(path, stamp)| old.get(*path) != Some(*stamp))
+        .map(|(path, _)| path.clone())
+        .collect();
+    changed.extend(old.keys().filter(|path| !new.contains_key(*path)).cloned());
+    changed
+}
+
+// Update the container's tree with whichever of it's mods should now provide the file at this path. Files are resolved from the
+// highest priority mod downwards, so removing a file from one mod lets a lower priority mod's copy take it's place
+fn apply_change(mods: &[ModFolder], container: &str, relative_path: &Path) {
+    // Export data is read when it's package gets converted, so reload the package instead
+    if relative_path.extension().and_then(|e| e.to_str()) == Some(COOKED_EXPORT_DATA_EXTENSION) {
+        apply_change(mods, container, &relative_path.with_extension("uasset"));
+        apply_change(mods, container, &relative_path.with_extension("umap"));
+        return;
+    }
+    let components: Vec<&str> = relative_path.iter().filter_map(|c| c.to_str()).collect();
+    let (file_name, dir_components) = match components.split_last() {
+        Some(c) => c,
+        None => return
+    };
+    let root = match asset_collector::get_container_root(container) {
+        Some(r) => r,
+        None => return
+    };
+    let winner = mods.iter().rev().find(|m| m.os_path.join(relative_path).is_file());
+    match winner {
+        Some(mod_folder) => {
+            let os_path = mod_folder.os_path.join(relative_path);
+            let os_path_str = os_path.to_str().unwrap_or_default();
+            let file_size = fs::metadata(&os_path).map(|m| m.len()).unwrap_or_default();
+            match TocFile::new_io_store_rc(file_name, file_size, os_path_str, &mod_folder.mod_id) {
+                Ok(file) => {
+                    let dir = TocDirectory::get_dir_from_path(root, dir_components, true).unwrap(); // always exists when created
+                    TocDirectory::add_or_replace_file(dir, Arc::clone(&file));
+                    // The copy being replaced may have come from any mod, so work out which lower priority mods it displaces again
+                    file.write().unwrap().displaced = mods.iter()
+                        .take_while(|m| !std::ptr::eq(*m, mod_folder))
+                        .filter_map(|m| {
+                            let os_path = m.os_path.join(relative_path);
+                            os_path.is_file().then(|| TocFileProvenance { mod_id: m.mod_id.clone(), os_path: os_path.to_string_lossy().into_owned() })
+                        })
+                        .collect();
+                    println!("Hot reload: updated {} from {}", relative_path.to_string_lossy(), mod_folder.mod_id);
+                },
+                Err(e) => println!("WARNING: Hot reload skipped \"{}\": {}", os_path_str, e)
+            }
+        },
+        None => {
+            if let Some(dir) = TocDirectory::get_dir_from_path(root, dir_components, false) {
+                if let Some(file) = TocDirectory::get_file(Arc::clone(&dir), file_name) {
+                    TocDirectory::remove_file(dir, file);
+                    println!("Hot reload: removed {}", relative_path.to_string_lossy());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset_collector::{EMULATOR_NAME, TocFileSyncRef};
+
+    const SHARED_FILE: &str = "Test/Content/Shared.ubulk";
+
+    fn get_test_folder(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(EMULATOR_NAME);
+        path.push("Tests");
+        path.push("HotReload");
+        path.push(name);
+        path
+    }
+
+    fn write_mod_file(mod_path: &Path, relative_path: &str, size: usize) {
+        let path = mod_path.join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![0; size]).unwrap();
+    }
+
+    // Add a mod for each size (lowest priority first), each of which has their own copy of SHARED_FILE. Returns the container's
+    // mod folders in priority order
+    fn add_test_mods(container: &str, sizes: &[usize]) -> Vec<ModFolder> {
+        let container_path = get_test_folder(container);
+        let _ = fs::remove_dir_all(&container_path);
+        for (i, size) in sizes.iter().enumerate() {
+            let mod_path = container_path.join(i.to_string());
+            write_mod_file(&mod_path, SHARED_FILE, *size);
+            asset_collector::add_from_folders_to_container(&format!("{}{}", container, i), mod_path.to_str().unwrap(), container).unwrap();
+        }
+        MOD_FOLDERS.lock().unwrap().iter().filter(|m| m.container == container).cloned().collect()
+    }
+
+    fn get_tree_file(container: &str, relative_path: &str) -> Option<TocFileSyncRef> {
+        let components: Vec<&str> = relative_path.split('/').collect();
+        let (file_name, dir_components) = components.split_last().unwrap();
+        let root = asset_collector::get_container_root(container).unwrap();
+        TocDirectory::get_dir_from_path(root, dir_components, false).and_then(|d| TocDirectory::get_file(d, file_name))
+    }
+
+    fn get_displaced_mods(file: &TocFileSyncRef) -> Vec<String> {
+        file.read().unwrap().displaced.iter().map(|d| d.mod_id.clone()).collect()
+    }
+
+    #[test]
+    fn changed_files_between_snapshots() {
+        let stamp = |size| FileStamp { size, modified: None };
+        let old: FolderSnapshot = [("a.uasset", 1), ("b.ubulk", 2), ("c.ubulk", 3)].into_iter().map(|(p, s)| (PathBuf::from(p), stamp(s))).collect();
+        let new: FolderSnapshot = [("a.uasset", 1), ("b.ubulk", 4), ("d.ubulk", 5)].into_iter().map(|(p, s)| (PathBuf::from(p), stamp(s))).collect();
+        let mut changed = get_changed_files(&old, &new);
+        changed.sort();
+        assert_eq!(changed, [PathBuf::from("b.ubulk"), PathBuf::from("c.ubulk"), PathBuf::from("d.ubulk")]);
+        assert!(get_changed_files(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn apply_added_file() {
+        const CONTAINER: &str = "HotReloadAdded";
+        let mods = add_test_mods(CONTAINER, &[1]);
+        write_mod_file(&mods[0].os_path, "Test/Content/Sub/Added.ubulk", 2);
+        apply_change(&mods, CONTAINER, Path::new("Test/Content/Sub/Added.ubulk"));
+        let file = get_tree_file(CONTAINER, "Test/Content/Sub/Added.ubulk").unwrap();
+        assert_eq!(file.read().unwrap().file_size, 2);
+        assert_eq!(file.read().unwrap().mod_id, mods[0].mod_id);
+    }
+
+    #[test]
+    fn apply_modified_file() {
+        const CONTAINER: &str = "HotReloadModified";
+        let mods = add_test_mods(CONTAINER, &[1, 2]);
+        write_mod_file(&mods[1].os_path, SHARED_FILE, 3);
+        apply_change(&mods, CONTAINER, Path::new(SHARED_FILE));
+        let file = get_tree_file(CONTAINER, SHARED_FILE).unwrap();
+        assert_eq!(file.read().unwrap().file_size, 3);
+        assert_eq!(file.read().unwrap().mod_id, mods[1].mod_id);
+        assert_eq!(get_displaced_mods(&file), [mods[0].mod_id.clone()]);
+        // a lower priority mod's copy changing doesn't replace the higher priority copy
+        write_mod_file(&mods[0].os_path, SHARED_FILE, 4);
+        apply_change(&mods, CONTAINER, Path::new(SHARED_FILE));
+        assert_eq!(get_tree_file(CONTAINER, SHARED_FILE).unwrap().read().unwrap().file_size, 3);
+    }
+
+    // Removing the highest priority copy lets the next highest priority mod's copy take it's place, which only displaces the mods
+    // below it
+    #[test]
+    fn apply_removed_top_priority_file() {
+        const CONTAINER: &str = "HotReloadRemoved";
+        let mods = add_test_mods(CONTAINER, &[1, 2, 3]);
+        let file = get_tree_file(CONTAINER, SHARED_FILE).unwrap();
+        assert_eq!(get_displaced_mods(&file), [mods[0].mod_id.clone(), mods[1].mod_id.clone()]);
+        fs::remove_file(mods[2].os_path.join(SHARED_FILE)).unwrap();
+        apply_change(&mods, CONTAINER, Path::new(SHARED_FILE));
+        let file = get_tree_file(CONTAINER, SHARED_FILE).unwrap();
+        assert_eq!(file.read().unwrap().file_size, 2);
+        assert_eq!(file.read().unwrap().mod_id, mods[1].mod_id);
+        assert_eq!(get_displaced_mods(&file), [mods[0].mod_id.clone()]);
+        // once every copy is gone, the file is removed from the tree
+        fs::remove_file(mods[1].os_path.join(SHARED_FILE)).unwrap();
+        fs::remove_file(mods[0].os_path.join(SHARED_FILE)).unwrap();
+        apply_change(&mods, CONTAINER, Path::new(SHARED_FILE));
+        assert!(get_tree_file(CONTAINER, SHARED_FILE).is_none());
+    }
+
+    // Mods that are added while hot reload is running are only snapshotted, since their files were just added to the tree
+    #[test]
+    fn snapshot_added_mod() {
+        const CONTAINER: &str = "HotReloadAddedMod";
+        let mods = add_test_mods(CONTAINER, &[1, 2]);
+        let mut snapshots = HashMap::new();
+        assert!(update_snapshots(&mods[..1], &mut snapshots).is_empty());
+        assert!(update_snapshots(&mods, &mut snapshots).is_empty());
+        write_mod_file(&mods[1].os_path, SHARED_FILE, 3);
+        assert_eq!(update_snapshots(&mods, &mut snapshots), [(CONTAINER.to_owned(), PathBuf::from(SHARED_FILE))]);
+        // removed mods stop being watched
+        update_snapshots(&mods[..1], &mut snapshots);
+        assert_eq!(snapshots.len(), 1);
+    }
+}
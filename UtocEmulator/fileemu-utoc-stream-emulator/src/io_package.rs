@@ -11,8 +11,9 @@ type ExportFilterFlags = u8; // and this one too...
 
 use byteorder::{NativeEndian, ReadBytesExt, WriteBytesExt};
 use crate::{
-    pak_package::{FObjectImport, FObjectExport, GameName, NameMap},
-    string::FMappedName,
+    io_toc::IoContainerHeaderVersion,
+    pak_package::{CookedPackage, FObjectImport, FObjectExport, GameName, NameMap, PackageFileSummary, PackageFileSummaryType4},
+    string::{FMappedName, FNameBatch, FString16, FStringSerializerBlockAlign, FStringSerializerHash, Hasher16},
     toc_factory::{TocResolverCommon, TocResolverType2}
 };
 use std::{
@@ -33,21 +34,20 @@ pub enum IoStoreObjectIndex {
 }
 
 impl IoStoreObjectIndex {
-    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(&self, reader: &mut R) -> IoStoreObjectIndex {
-        let raw_value = reader.read_u64::<E>().unwrap();
-        let obj_type = raw_value & (3 << 62);
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(&self, reader: &mut R) -> Result<IoStoreObjectIndex, Box<dyn Error>> {
+        let raw_value = reader.read_u64::<E>()?;
+        let obj_type = raw_value >> 62;
         match obj_type {
-            0 => IoStoreObjectIndex::Export(0), // can't derive string name from hash, will likely need to separate this off to another type for container header building
-            1 => IoStoreObjectIndex::ScriptImport(String::new()),
-            2 => IoStoreObjectIndex::PackageImport(String::new()),
-            3 => IoStoreObjectIndex::Empty,
-            _ => panic!("Invalid obj type {}", obj_type),
+            0 => Ok(IoStoreObjectIndex::Export(0)), // can't derive string name from hash, will likely need to separate this off to another type for container header building
+            1 => Ok(IoStoreObjectIndex::ScriptImport(String::new())),
+            2 => Ok(IoStoreObjectIndex::PackageImport(String::new())),
+            _ => Ok(IoStoreObjectIndex::Empty),
         }
     }
     // TOOO: upgrade trait bounds to Write + Seek
     pub fn to_buffer<W: Write, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
         match self {
-            Self::Export(i) => writer.write_u64::<E>(*i as u64)?,
+            Self::Export(i) => writer.write_u64::<E>(*i)?,
             Self::ScriptImport(v) => writer.write_u64::<E>(IoStoreObjectIndex::generate_hash(v, 1))?,
             Self::PackageImport(v) => writer.write_u64::<E>(IoStoreObjectIndex::generate_hash(v, 2))?,
             Self::Empty => writer.write_u64::<E>(u64::MAX)?,
@@ -73,15 +73,15 @@ impl IoStoreObjectIndex {
 pub struct ObjectImport;
 impl ObjectImport {
     // Convert FObjectImport into named ObjectImport
-    pub fn from_pak_asset<N: NameMap>(import_map: &Vec<FObjectImport>, name_map: &N) -> Vec<IoStoreObjectIndex> {
+    pub fn from_pak_asset<N: NameMap>(import_map: &[FObjectImport], name_map: &N) -> Result<Vec<IoStoreObjectIndex>, String> {
         let mut resolves = vec![];
-        for (i, v) in import_map.into_iter().enumerate() {
+        for (i, v) in import_map.iter().enumerate() {
             match v.resolve(name_map, import_map) {
                 Ok(obj) => resolves.push(obj),
-                Err(e) => panic!("Error converting PAK formatted import to IO Store import on ID {} \nValue {:?}\nReason: {}", i, v, e.to_string())
+                Err(e) => return Err(format!("Error converting PAK formatted import to IO Store import on ID {}: {}", i, e))
             }
         }
-        resolves
+        Ok(resolves)
     }
 
     pub fn map_to_buffer<W: Write, E: byteorder::ByteOrder>(map: &Vec<IoStoreObjectIndex>, writer: &mut W) -> Result<(), Box<dyn Error>> {
@@ -93,11 +93,15 @@ impl ObjectImport {
 }
 
 // Generic package summary implementation that contains fields appropriate for creating virtual container header
-// The fields that are relevant are export_offset, export_bundle_offset and graph_offset
+// The fields that are relevant are export_offset, export_bundle_offset and graph_offset. For UE 5.3+ packages, graph_offset is where
+// the dependency bundle headers start
+// dependency_bundle_entries_offset and imported_package_names_offset are only used by UE 5.3+ packages (0 otherwise)
 pub struct PackageSummaryExports {
     export_offset: u32,
     export_bundle_offset: u32,
-    graph_offset: u32
+    graph_offset: u32,
+    dependency_bundle_entries_offset: u32,
+    imported_package_names_offset: u32
 }
 
 impl PackageSummaryExports {
@@ -110,10 +114,20 @@ impl PackageSummaryExports {
 }
 
 pub trait PackageIoSummaryDeserialize {
+    // How this package summary lays out it's export bundles
+    type ExportBundle: ExportBundle;
     // Create a PackageSummary instance from a given serialized FPackageSummary type. Since IO store packages don't include a file magic, 
     // this assumes that the reader stream is positioned correctly at the beginning of the package's header. An incorrect stream position can
     // lead to weird errors
     fn to_package_summary<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<PackageSummaryExports, Box<dyn Error>>;
+    // Get the package ids of every package that this package imports. These are written into it's store entry in the container header
+    fn get_imported_packages<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, summary: &PackageSummaryExports) -> Result<Vec<u64>, Box<dyn Error>>;
+}
+
+// 4.25 - 4.27 packages contain an FGraphPackage for each imported package
+fn get_imported_packages_from_graph<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, summary: &PackageSummaryExports) -> Result<Vec<u64>, Box<dyn Error>> {
+    reader.seek(SeekFrom::Start(summary.graph_offset as u64))?; // go to FGraphPackage (imported_packages_count)
+    Ok(FGraphPackage::list_from_buffer::<R, E>(reader)?.iter().map(|p| p.imported_package_id).collect())
 }
 
 // Io Store Asset Header
@@ -132,12 +146,16 @@ pub struct PackageSummary1 { // Unreal Engine 4.25 (untested)
 }
 
 impl PackageIoSummaryDeserialize for PackageSummary1 {
+    type ExportBundle = ExportBundleHeader4;
     fn to_package_summary<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<PackageSummaryExports, Box<dyn Error>> {
         reader.seek(SeekFrom::Current(0xc));
         let export_offset = reader.read_u32::<E>()?; // FPackageSummary->export_map_offset
         let export_bundle_offset = reader.read_u32::<E>()?; // FPackageSummary->export_bundle_export
         let graph_offset = reader.read_u32::<E>()?; // FPackageSummary->graph_offset
-        Ok(PackageSummaryExports { export_offset, export_bundle_offset, graph_offset })
+        Ok(PackageSummaryExports { export_offset, export_bundle_offset, graph_offset, dependency_bundle_entries_offset: 0, imported_package_names_offset: 0 })
+    }
+    fn get_imported_packages<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, summary: &PackageSummaryExports) -> Result<Vec<u64>, Box<dyn Error>> {
+        get_imported_packages_from_graph::<R, E>(reader, summary)
     }
 }
 
@@ -160,31 +178,35 @@ pub struct PackageSummary2 { // Unreal Engine 4.25+, 4.26-4.27 (normal, plus, ch
 }
 
 impl PackageIoSummaryDeserialize for PackageSummary2 {
+    type ExportBundle = ExportBundleHeader4;
     fn to_package_summary<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<PackageSummaryExports, Box<dyn Error>> {
         reader.seek(SeekFrom::Current(0x2c));
         let export_offset = reader.read_u32::<E>()?; // FPackageSummary->export_map_offset
         let export_bundle_offset = reader.read_u32::<E>()?; // FPackageSummary->export_bundle_export
         let graph_offset = reader.read_u32::<E>()?; // FPackageSummary->graph_offset
-        Ok(PackageSummaryExports { export_offset, export_bundle_offset, graph_offset })
+        Ok(PackageSummaryExports { export_offset, export_bundle_offset, graph_offset, dependency_bundle_entries_offset: 0, imported_package_names_offset: 0 })
+    }
+    fn get_imported_packages<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, summary: &PackageSummaryExports) -> Result<Vec<u64>, Box<dyn Error>> {
+        get_imported_packages_from_graph::<R, E>(reader, summary)
     }
 }
 
 impl PackageSummary2 {
-    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Self {
-        let name = reader.read_u64::<E>().unwrap().into();
-        let source_name = reader.read_u64::<E>().unwrap().into();
-        let package_flags = reader.read_u32::<E>().unwrap();
-        let cooked_header_size = reader.read_u32::<E>().unwrap();
-        let name_map_names_offset = reader.read_i32::<E>().unwrap();
-        let name_map_names_size = reader.read_i32::<E>().unwrap();
-        let name_map_hashes_offset = reader.read_i32::<E>().unwrap();
-        let name_map_hashes_size = reader.read_i32::<E>().unwrap();
-        let import_map_offset = reader.read_i32::<E>().unwrap();
-        let export_map_offset = reader.read_i32::<E>().unwrap();
-        let export_bundles_offset = reader.read_i32::<E>().unwrap();
-        let graph_data_offset = reader.read_i32::<E>().unwrap();
-        let graph_data_size = reader.read_i32::<E>().unwrap();
-        Self {
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let name = reader.read_u64::<E>()?.into();
+        let source_name = reader.read_u64::<E>()?.into();
+        let package_flags = reader.read_u32::<E>()?;
+        let cooked_header_size = reader.read_u32::<E>()?;
+        let name_map_names_offset = reader.read_i32::<E>()?;
+        let name_map_names_size = reader.read_i32::<E>()?;
+        let name_map_hashes_offset = reader.read_i32::<E>()?;
+        let name_map_hashes_size = reader.read_i32::<E>()?;
+        let import_map_offset = reader.read_i32::<E>()?;
+        let export_map_offset = reader.read_i32::<E>()?;
+        let export_bundles_offset = reader.read_i32::<E>()?;
+        let graph_data_offset = reader.read_i32::<E>()?;
+        let graph_data_size = reader.read_i32::<E>()?;
+        Ok(Self {
             name,
             source_name,
             package_flags,
@@ -199,12 +221,30 @@ impl PackageSummary2 {
             graph_data_offset,
             graph_data_size,
             pad: 0
-        }
+        })
+    }
+
+    pub fn to_buffer<W: Write, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        writer.write_u64::<E>(self.name.into())?;
+        writer.write_u64::<E>(self.source_name.into())?;
+        writer.write_u32::<E>(self.package_flags)?;
+        writer.write_u32::<E>(self.cooked_header_size)?;
+        writer.write_i32::<E>(self.name_map_names_offset)?;
+        writer.write_i32::<E>(self.name_map_names_size)?;
+        writer.write_i32::<E>(self.name_map_hashes_offset)?;
+        writer.write_i32::<E>(self.name_map_hashes_size)?;
+        writer.write_i32::<E>(self.import_map_offset)?;
+        writer.write_i32::<E>(self.export_map_offset)?;
+        writer.write_i32::<E>(self.export_bundles_offset)?;
+        writer.write_i32::<E>(self.graph_data_offset)?;
+        writer.write_i32::<E>(self.graph_data_size)?;
+        writer.write_i32::<E>(self.pad)?;
+        Ok(())
     }
 }
 
 #[repr(C)]
-pub struct ZenPackageSummaryType1 { // Unreal Engine 5.0-5.2 (untested)
+pub struct ZenPackageSummaryType1 { // Unreal Engine 5.0-5.2
     bool_has_version_info: u32,
     header_size: u32,
     name: FMappedName,
@@ -218,17 +258,28 @@ pub struct ZenPackageSummaryType1 { // Unreal Engine 5.0-5.2 (untested)
 }
 
 impl PackageIoSummaryDeserialize for ZenPackageSummaryType1 {
+    type ExportBundle = ExportBundleHeader5;
     fn to_package_summary<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<PackageSummaryExports, Box<dyn Error>> {
         reader.seek(SeekFrom::Current(0x20));
-        let export_offset = reader.read_u32::<E>()?; // FPackageSummary->export_map_offset
-        let export_bundle_offset = reader.read_u32::<E>()?; // FPackageSummary->export_bundle_export
-        let graph_offset = reader.read_u32::<E>()?; // FPackageSummary->graph_offset
-        Ok(PackageSummaryExports { export_offset, export_bundle_offset, graph_offset })
+        let export_offset = reader.read_u32::<E>()?; // FZenPackageSummary->export_map_offset
+        let export_bundle_offset = reader.read_u32::<E>()?; // FZenPackageSummary->export_bundle_entries_offset
+        let graph_offset = reader.read_u32::<E>()?; // FZenPackageSummary->graph_data_offset (export bundle headers are here)
+        Ok(PackageSummaryExports { export_offset, export_bundle_offset, graph_offset, dependency_bundle_entries_offset: 0, imported_package_names_offset: 0 })
+    }
+    fn get_imported_packages<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, summary: &PackageSummaryExports) -> Result<Vec<u64>, Box<dyn Error>> {
+        // 5.0 - 5.2 packages only store the index of each imported package in the store entry's import list, so their ids can't be
+        // recovered from the package itself. A store entry without them would load the package without it's dependencies, so the
+        // package is skipped instead (see TocResolverType2::skip_file)
+        // TODO: Get imported packages from the game's original container header
+        Err(ZEN_IMPORTED_PACKAGES_UNSUPPORTED.into())
     }
 }
 
+const ZEN_IMPORTED_PACKAGES_UNSUPPORTED: &str = "UE 5.0 - 5.2 packages don't contain the ids of the packages that they import, which \
+    the container needs to load them, so they can't be added to a container yet";
+
 #[repr(C)]
-pub struct ZenPackageSummaryType2 { // Unreal Engine 5.3 (untested)
+pub struct ZenPackageSummaryType2 { // Unreal Engine 5.3
     bool_has_version_info: u32,
     header_size: u32,
     name: FMappedName,
@@ -242,8 +293,32 @@ pub struct ZenPackageSummaryType2 { // Unreal Engine 5.3 (untested)
     dependency_bundle_entries_offset: i32,
     imported_package_names_offset: i32
 }
-// ZenPackageSummaryType2 looks like it has something different going on with how it's dependency graph works
-// This can be worked on later
+// ZenPackageSummaryType2 replaces the graph data with dependency bundles, but includes the name of each imported package, which lets us
+// generate the imported package ids for it's store entry
+
+impl PackageIoSummaryDeserialize for ZenPackageSummaryType2 {
+    type ExportBundle = DependencyBundleHeader;
+    fn to_package_summary<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<PackageSummaryExports, Box<dyn Error>> {
+        reader.seek(SeekFrom::Current(0x20));
+        let export_offset = reader.read_u32::<E>()?; // FZenPackageSummary->export_map_offset
+        let export_bundle_offset = reader.read_u32::<E>()?; // FZenPackageSummary->export_bundle_entries_offset
+        let graph_offset = reader.read_u32::<E>()?; // FZenPackageSummary->dependency_bundle_headers_offset
+        let dependency_bundle_entries_offset = reader.read_u32::<E>()?; // FZenPackageSummary->dependency_bundle_entries_offset
+        let imported_package_names_offset = reader.read_u32::<E>()?; // FZenPackageSummary->imported_package_names_offset
+        Ok(PackageSummaryExports { export_offset, export_bundle_offset, graph_offset, dependency_bundle_entries_offset, imported_package_names_offset })
+    }
+    fn get_imported_packages<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, summary: &PackageSummaryExports) -> Result<Vec<u64>, Box<dyn Error>> {
+        reader.seek(SeekFrom::Start(summary.imported_package_names_offset as u64))?;
+        let names = FNameBatch::from_buffer::<R, E>(reader)?;
+        let mut import_ids = Vec::with_capacity(names.len());
+        for name in names {
+            let number = reader.read_i32::<E>()?; // FName number, stored after the name batch
+            let name = if number > 0 { format!("{}_{}", name, number - 1) } else { name };
+            import_ids.push(Hasher16::get_cityhash64(&name)); // FPackageId::FromName
+        }
+        Ok(import_ids)
+    }
+}
 
 pub struct FGraphExternalArc {
     from_export_bundle_index: u32,
@@ -251,10 +326,10 @@ pub struct FGraphExternalArc {
 }
 
 impl FGraphExternalArc {
-    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Self {
-        let from_export_bundle_index = reader.read_u32::<E>().unwrap();
-        let to_export_bundle_index = reader.read_u32::<E>().unwrap();
-        Self { from_export_bundle_index, to_export_bundle_index }
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let from_export_bundle_index = reader.read_u32::<E>()?;
+        let to_export_bundle_index = reader.read_u32::<E>()?;
+        Ok(Self { from_export_bundle_index, to_export_bundle_index })
     }
 }
 
@@ -264,26 +339,26 @@ pub struct FGraphPackage {
 }
 
 impl FGraphPackage {
-    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Self {
-        let imported_package_id = reader.read_u64::<E>().unwrap();
-        let external_arc_count = reader.read_u32::<E>().unwrap();
-        let mut external_arcs = Vec::with_capacity(external_arc_count as usize);
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let imported_package_id = reader.read_u64::<E>()?;
+        let external_arc_count = reader.read_u32::<E>()?;
+        let mut external_arcs = vec![];
         for _ in 0..external_arc_count {
-            external_arcs.push(FGraphExternalArc::from_buffer::<R, E>(reader));
+            external_arcs.push(FGraphExternalArc::from_buffer::<R, E>(reader)?);
         }
-        Self {
+        Ok(Self {
             imported_package_id,
             external_arcs
-        }
+        })
     }
 
-    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Vec<Self> {
-        let imported_packages_count = reader.read_u32::<E>().unwrap();
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Vec<Self>, Box<dyn Error>> {
+        let imported_packages_count = reader.read_u32::<E>()?;
         let mut values = vec![];
         for _ in 0..imported_packages_count {
-            values.push(FGraphPackage::from_buffer::<R, E>(reader));
+            values.push(FGraphPackage::from_buffer::<R, E>(reader)?);
         }
-        values
+        Ok(values)
     }
 }
 
@@ -306,13 +381,14 @@ impl TryFrom<u32> for ExportBundleCommandType {
 }
 pub struct ExportBundleEntry { // same across all versions of Unreal Engine
     local_export_index: u32,
-    command_type: ExportBundleCommandType
+    command_type: ExportBundleCommandType,
+    bundle_index: u32 // not serialized, index of the export bundle header that owns this entry
 }
 pub trait ExportBundle {
-    // Create a list of export bundles from a serialized byte stream. It's up to the user to ensure that the cursor is in the correct position
-    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Vec<ExportBundleEntry>, Box<dyn Error>>;
+    // Create a list of export bundle entries from a package, using the package summary to find the export bundle headers and entries
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, summary: &PackageSummaryExports) -> Result<Vec<ExportBundleEntry>, Box<dyn Error>>;
     // Get the number of export bundles that a package has. This info is used to build it's entry in 
-    fn get_export_bundle_count(entries: &Vec<ExportBundleEntry>) -> u32;
+    fn get_export_bundle_count(entries: &[ExportBundleEntry]) -> u32;
 }
 
 #[repr(C)]
@@ -322,18 +398,19 @@ pub struct ExportBundleHeader4 { // Unreal Engine 4.25-4.27
 }
 
 impl ExportBundle for ExportBundleHeader4 {
-    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Vec<ExportBundleEntry>, Box<dyn Error>> {
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, summary: &PackageSummaryExports) -> Result<Vec<ExportBundleEntry>, Box<dyn Error>> {
+        reader.seek(SeekFrom::Start(summary.export_bundle_offset as u64))?; // jump to FExportBundleHeader start
         reader.read_u32::<E>()?; // FirstEntryIndex, not important
         let entry_count = reader.read_u32::<E>()?;
         let mut entries = Vec::with_capacity(entry_count as usize);
         for _ in 0..entry_count {
             let local_export_index = reader.read_u32::<E>()?;
             let command_type = reader.read_u32::<E>()?.try_into()?;
-            entries.push(ExportBundleEntry{ local_export_index, command_type })
+            entries.push(ExportBundleEntry{ local_export_index, command_type, bundle_index: 0 })
         }
         Ok(entries)
     }
-    fn get_export_bundle_count(entries: &Vec<ExportBundleEntry>) -> u32 {
+    fn get_export_bundle_count(entries: &[ExportBundleEntry]) -> u32 {
         let mut count = 0;
         for i in entries {
             let export_count_maybe = i.local_export_index + 1;
@@ -349,31 +426,108 @@ pub struct ExportBundleHeader5 { // Unreal Engine 5.0+
     first_entry_index: u32,
     entry_count: u32,
 }
-// impl ExportBundle for ExportBundleHeader5...
+
+pub const EXPORT_BUNDLE_ENTRY_SERIALIZED_SIZE: u64 = 0x8;
+pub const DEPENDENCY_BUNDLE_HEADER_SERIALIZED_SIZE: u64 = 0x14;
+pub const DEPENDENCY_BUNDLE_ENTRY_SERIALIZED_SIZE: u64 = 0x4;
+
+impl ExportBundle for ExportBundleHeader5 {
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, summary: &PackageSummaryExports) -> Result<Vec<ExportBundleEntry>, Box<dyn Error>> {
+        // Export bundle entries for every bundle are stored together, followed by the graph data, which starts with the export bundle headers
+        // The package doesn't store how many headers there are, so keep reading them until every entry has been accounted for
+        let entry_count = (summary.graph_offset - summary.export_bundle_offset) as u64 / EXPORT_BUNDLE_ENTRY_SERIALIZED_SIZE;
+        reader.seek(SeekFrom::Start(summary.graph_offset as u64))?;
+        let mut bundle_entry_counts = vec![];
+        let mut entries_found = 0;
+        while entries_found < entry_count {
+            reader.read_u64::<E>()?; // SerialOffset
+            let first_entry_index = reader.read_u32::<E>()?;
+            let bundle_entry_count = reader.read_u32::<E>()?;
+            if first_entry_index as u64 != entries_found || bundle_entry_count == 0 {
+                return Err(Box::new(std::io::Error::new(ErrorKind::InvalidData, "Export bundle headers don't match the package's export bundle entries")));
+            }
+            bundle_entry_counts.push(bundle_entry_count);
+            entries_found += bundle_entry_count as u64;
+        }
+        reader.seek(SeekFrom::Start(summary.export_bundle_offset as u64))?;
+        let mut entries = Vec::with_capacity(entry_count as usize);
+        for (bundle_index, bundle_entry_count) in bundle_entry_counts.iter().enumerate() {
+            for _ in 0..*bundle_entry_count {
+                let local_export_index = reader.read_u32::<E>()?;
+                let command_type = reader.read_u32::<E>()?.try_into()?;
+                entries.push(ExportBundleEntry{ local_export_index, command_type, bundle_index: bundle_index as u32 })
+            }
+        }
+        Ok(entries)
+    }
+    fn get_export_bundle_count(entries: &[ExportBundleEntry]) -> u32 {
+        entries.last().map_or(0, |e| e.bundle_index + 1)
+    }
+}
+
+#[repr(C)]
+pub struct DependencyBundleHeader { // Unreal Engine 5.3+
+    first_entry_index: i32,
+    entry_count: [[u32; 2]; 2] // [ExportBundleCommandType of the export][ExportBundleCommandType of the dependency]
+}
+
+impl ExportBundle for DependencyBundleHeader {
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, summary: &PackageSummaryExports) -> Result<Vec<ExportBundleEntry>, Box<dyn Error>> {
+        // 5.3 packages have a single export bundle, so it's entries aren't split by export bundle headers. Each export instead has a
+        // dependency bundle header, followed by every header's dependency bundle entries (an FPackageIndex each). Check that they
+        // line up so that a package with a different layout isn't given a store entry
+        let invalid = |reason: &str| -> Box<dyn Error> { Box::new(std::io::Error::new(ErrorKind::InvalidData, reason)) };
+        let section_size = |start: u32, end: u32| end.checked_sub(start).map(|s| s as u64).ok_or_else(|| invalid("Package sections are out of order"));
+        let entry_count = section_size(summary.export_bundle_offset, summary.graph_offset)? / EXPORT_BUNDLE_ENTRY_SERIALIZED_SIZE;
+        let header_count = section_size(summary.graph_offset, summary.dependency_bundle_entries_offset)? / DEPENDENCY_BUNDLE_HEADER_SERIALIZED_SIZE;
+        let dependency_count = section_size(summary.dependency_bundle_entries_offset, summary.imported_package_names_offset)? / DEPENDENCY_BUNDLE_ENTRY_SERIALIZED_SIZE;
+        reader.seek(SeekFrom::Start(summary.graph_offset as u64))?;
+        let mut dependencies_found = 0;
+        for _ in 0..header_count {
+            let first_entry_index = reader.read_i32::<E>()?;
+            let mut header_entry_count = 0;
+            for _ in 0..4 {
+                header_entry_count += reader.read_u32::<E>()? as u64;
+            }
+            if header_entry_count > 0 && first_entry_index as u64 != dependencies_found {
+                return Err(invalid("Dependency bundle headers don't match the package's dependency bundle entries"));
+            }
+            dependencies_found += header_entry_count;
+        }
+        if dependencies_found != dependency_count {
+            return Err(invalid("Dependency bundle headers don't match the package's dependency bundle entries"));
+        }
+        reader.seek(SeekFrom::Start(summary.export_bundle_offset as u64))?;
+        let mut entries = Vec::with_capacity(entry_count as usize);
+        for _ in 0..entry_count {
+            let local_export_index = reader.read_u32::<E>()?;
+            let command_type = reader.read_u32::<E>()?.try_into()?;
+            entries.push(ExportBundleEntry{ local_export_index, command_type, bundle_index: 0 })
+        }
+        Ok(entries)
+    }
+    fn get_export_bundle_count(entries: &[ExportBundleEntry]) -> u32 {
+        if entries.is_empty() { 0 } else { 1 }
+    }
+}
 
 pub trait ContainerHeaderPosition {
-    fn cursor_to_header<TResolver: TocResolverCommon>(resolver: &mut TResolver) -> u64;
-    fn cursor_to_beginning_of_files<TResolver: TocResolverCommon>(resolver: &mut TResolver) -> u64;
-    //fn get_fixed_container_size<TResolver: TocResolverCommon>(resolver: &mut TResolver) -> u64; // 4.25+ and 4.26 have a fixed size of 0x10000
+    // Returns true if the container header is placed in front of every other chunk in the UCAS. Otherwise, it's placed after the
+    // last chunk. This only affects where the header sits in the container file - the header's TOC entry is always written last
+    fn is_header_first() -> bool;
 }
 
 pub struct ContainerHeaderPosition1; // 4.25+, 4.26
 impl ContainerHeaderPosition for ContainerHeaderPosition1 {
-    fn cursor_to_beginning_of_files<TResolver: TocResolverCommon>(resolver: &mut TResolver) -> u64 {
-        0x10000
-    }
-    fn cursor_to_header<TResolver: TocResolverCommon>(resolver: &mut TResolver) -> u64 {
-        0
+    fn is_header_first() -> bool {
+        true
     }
 }
 
 pub struct ContainerHeaderPosition2; // 4.27
 impl ContainerHeaderPosition for ContainerHeaderPosition2 {
-    fn cursor_to_beginning_of_files<TResolver: TocResolverCommon>(resolver: &mut TResolver) -> u64 {
-        0
-    }
-    fn cursor_to_header<TResolver: TocResolverCommon>(resolver: &mut TResolver) -> u64 {
-        0 // self.compression_blocks
+    fn is_header_first() -> bool {
+        false
     }
 }
 
@@ -392,32 +546,25 @@ pub struct ContainerHeaderPackage {
 impl ContainerHeaderPackage {
     // Parse the package file to extract the values needed to build a store entry in the container header
     pub fn from_package_summary<
-        TExportBundle: ExportBundle,
         TSummary: PackageIoSummaryDeserialize,
         TReader: Read + Seek,
         TByteOrder: byteorder::ByteOrder
-    >(file_reader: &mut TReader, hash: u64, size: u64) -> Self { // consume the file object, we're only going to need it in here
+    >(file_reader: &mut TReader, hash: u64, size: u64) -> Result<Self, Box<dyn Error>> { // consume the file object, we're only going to need it in here
         type Endian = byteorder::NativeEndian;
-        let package_summary = TSummary::to_package_summary::<TReader, TByteOrder>(file_reader).unwrap();
+        let package_summary = TSummary::to_package_summary::<TReader, TByteOrder>(file_reader)?;
         let export_count = package_summary.get_export_count() as u32;
-        file_reader.seek(SeekFrom::Start(package_summary.export_bundle_offset as u64)).unwrap(); // jump to FExportBundleHeader start
-        let export_bundles = TExportBundle::from_buffer::<TReader, Endian>(file_reader).unwrap(); // Deserialize ExportBundle to get export bundle count
-        let export_bundle_count = TExportBundle::get_export_bundle_count(&export_bundles); // Go through each export bundle to look for the highest index
-        file_reader.seek(SeekFrom::Start(package_summary.graph_offset as u64)).unwrap(); // go to FGraphPackage (imported_packages_count)
-        let graph_packages = FGraphPackage::list_from_buffer::<TReader, Endian>(file_reader);
-        let mut import_ids = Vec::with_capacity(graph_packages.len());
-        for i in &graph_packages {
-            import_ids.push(i.imported_package_id);
-        }
+        let export_bundles = TSummary::ExportBundle::from_buffer::<TReader, Endian>(file_reader, &package_summary)?; // Deserialize ExportBundle to get export bundle count
+        let export_bundle_count = TSummary::ExportBundle::get_export_bundle_count(&export_bundles); // Go through each export bundle to look for the highest index
+        let import_ids = TSummary::get_imported_packages::<TReader, Endian>(file_reader, &package_summary)?;
         let load_order = 0; // This doesn't seem to matter?
-        Self {
+        Ok(Self {
             hash,
             export_bundle_size: size,
             export_count,
             export_bundle_count,
             load_order,
             import_ids
-        }
+        })
     }
     // Do a very incomplete serialization of an IO Store packaged asset to obtain it's export count, export bundle count and imported packages
     // Imports are Header.ExportMapOffset - Header.ImportMapOffset / 8
@@ -425,31 +572,97 @@ impl ContainerHeaderPackage {
     // Export bundle count is export bundle count - export count
     // imported packages count determined (grab the hash from there and copy that)
     // Later, this code can do a more full serialization
-    pub fn from_header_package<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, hash: u64, size: u64) -> Self { // beginning of IO store package
-        reader.seek(SeekFrom::Start(0x2c));
-        let export_offset = reader.read_u32::<E>().unwrap();
-        let export_bundle_offset = reader.read_u32::<E>().unwrap();
+    pub fn from_header_package<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, hash: u64, size: u64) -> Result<Self, Box<dyn Error>> { // beginning of IO store package
+        reader.seek(SeekFrom::Start(0x2c))?;
+        let export_offset = reader.read_u32::<E>()?;
+        let export_bundle_offset = reader.read_u32::<E>()?;
         //println!("0x{:X}, 0x{:X}", export_offset, export_bundle_offset);
-        let graph_offset = reader.read_u32::<E>().unwrap();
-        let export_count = (export_bundle_offset - export_offset) / IO_PACKAGE_FEXPORTMAP_SERIALIZED_SIZE as u32;
-        reader.seek(SeekFrom::Start(export_bundle_offset as u64 + 4)); // FExportBundleHeader->EntryCount
-        let export_bundle_count_serialized = reader.read_u32::<E>().unwrap();
-        let export_bundle_count = export_bundle_count_serialized - export_count;
-        reader.seek(SeekFrom::Start(graph_offset as u64)); // FGraphPackage->ImportedPackagesCount
-        let imported_package_count = reader.read_u32::<E>().unwrap();
-        let mut import_ids: Vec<u64> = Vec::with_capacity(imported_package_count as usize);
+        let graph_offset = reader.read_u32::<E>()?;
+        let export_count = export_bundle_offset.checked_sub(export_offset).ok_or("Export bundles are before the export map")? / IO_PACKAGE_FEXPORTMAP_SERIALIZED_SIZE as u32;
+        reader.seek(SeekFrom::Start(export_bundle_offset as u64 + 4))?; // FExportBundleHeader->EntryCount
+        let export_bundle_count_serialized = reader.read_u32::<E>()?;
+        let export_bundle_count = export_bundle_count_serialized.checked_sub(export_count).ok_or("Export bundle has less entries than there are exports")?;
+        reader.seek(SeekFrom::Start(graph_offset as u64))?; // FGraphPackage->ImportedPackagesCount
+        let imported_package_count = reader.read_u32::<E>()?;
+        let mut import_ids: Vec<u64> = vec![];
         for _ in 0..imported_package_count {
-            import_ids.push(FGraphPackage::from_buffer::<R, E>(reader).imported_package_id);
+            import_ids.push(FGraphPackage::from_buffer::<R, E>(reader)?.imported_package_id);
         }
         let load_order = 0; // For now, we'll see if this makes things crash
-        Self {
+        Ok(Self {
             hash,
             export_bundle_size: size,
             export_count,
             export_bundle_count,
             load_order,
             import_ids
+        })
+    }
+
+    // Read a store entry back out of a container header's store entry buffer. The reader must be positioned at the start of the entry,
+    // and imported package ids are read from wherever the entry's array view points to
+    pub fn from_buffer_store_entry<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, hash: u64) -> Result<Self, Box<dyn Error>> {
+        let export_bundle_size = reader.read_u64::<E>()?; // 0x0
+        let export_count = reader.read_u32::<E>()?; // 0x8
+        let export_bundle_count = reader.read_u32::<E>()?; // 0xc
+        let load_order = reader.read_u32::<E>()?; // 0x10
+        reader.read_u32::<E>()?; // 0x14 padding
+        let import_ids = read_store_entry_array_view::<R, E>(reader)?; // 0x18
+        Ok(Self { hash, export_bundle_size, export_count, export_bundle_count, load_order, import_ids })
+    }
+
+    pub fn from_buffer_store_entry5<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, hash: u64, version: IoContainerHeaderVersion) -> Result<Self, Box<dyn Error>> {
+        let (export_count, export_bundle_count) = match version < IoContainerHeaderVersion::NoExportInfo {
+            true => (reader.read_u32::<E>()?, reader.read_u32::<E>()?),
+            false => (0, 0)
+        };
+        let import_ids = read_store_entry_array_view::<R, E>(reader)?; // ImportedPackages
+        read_store_entry_array_view::<R, E>(reader)?; // ShaderMapHashes
+        Ok(Self { hash, export_bundle_size: 0, export_count, export_bundle_count, load_order: 0, import_ids })
+    }
+
+    pub fn get_export_bundle_size(&self) -> u64 {
+        self.export_bundle_size
+    }
+    pub fn get_export_count(&self) -> u32 {
+        self.export_count
+    }
+    pub fn get_export_bundle_count(&self) -> u32 {
+        self.export_bundle_count
+    }
+    pub fn get_load_order(&self) -> u32 {
+        self.load_order
+    }
+    pub fn get_import_ids(&self) -> &[u64] {
+        &self.import_ids
+    }
+
+    // Size of FFilePackageStoreEntry for a UE5 container header version
+    pub fn get_store_entry_size(version: IoContainerHeaderVersion) -> u64 {
+        if version >= IoContainerHeaderVersion::NoExportInfo { 0x10 } else { 0x18 }
+    }
+
+    pub fn to_buffer_store_entry5<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W, version: IoContainerHeaderVersion, base_offset: u64, curr_offset: &mut u64) -> Result<(), Box<dyn Error>> {
+        if version < IoContainerHeaderVersion::NoExportInfo {
+            writer.write_u32::<E>(self.export_count)?; // 0x0
+            writer.write_u32::<E>(self.export_bundle_count)?; // 0x4
         }
+        // ImportedPackages (offset is relative to the start of the array view)
+        let relative_offset = if !self.import_ids.is_empty() { Some((base_offset + *curr_offset - writer.stream_position()?) as u32) } else { None };
+        writer.write_u32::<E>(self.import_ids.len() as u32)?; // ArrayNum
+        writer.write_u32::<E>(relative_offset.unwrap_or_default())?; // OffsetToDataFromThis
+        if let Some(rel) = relative_offset {
+            let return_ptr = writer.stream_position()?;
+            writer.seek(SeekFrom::Current(rel as i64 - 8))?;
+            for i in &self.import_ids {
+                writer.write_u64::<E>(*i)?;
+            }
+            writer.seek(SeekFrom::Start(return_ptr))?;
+            *curr_offset += 8 * self.import_ids.len() as u64;
+        }
+        writer.write_u32::<E>(0)?; // ShaderMapHashes ArrayNum
+        writer.write_u32::<E>(0)?; // ShaderMapHashes OffsetToDataFromThis
+        Ok(())
     }
 
     pub fn to_buffer_store_entry<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W, base_offset: u64, curr_offset: &mut u64) -> Result<(), Box<dyn Error>> {
@@ -459,24 +672,38 @@ impl ContainerHeaderPackage {
         writer.write_u32::<E>(1)?; // 0xc
         writer.write_u32::<E>(self.load_order)?; // 0x10
         writer.write_u32::<E>(0)?; // 0x14 padding
-        let relative_offset = if self.import_ids.len() > 0 { Some((base_offset + *curr_offset - writer.stream_position().unwrap()) as u32) } else { None };
+        let relative_offset = if !self.import_ids.is_empty() { Some((base_offset + *curr_offset - writer.stream_position()?) as u32) } else { None };
         writer.write_u32::<E>(self.import_ids.len() as u32)?; // 0x18 ImportedPackageCount
-        writer.write_u32::<E>(match relative_offset {Some(n) => n, None => 0})?; // 0x1c RelativeOffsetToImports
+        writer.write_u32::<E>(relative_offset.unwrap_or_default())?; // 0x1c RelativeOffsetToImports
         if let Some(rel) = relative_offset {
-            let return_ptr = writer.stream_position().unwrap();
-            writer.seek(SeekFrom::Current(rel as i64 - 8));
+            let return_ptr = writer.stream_position()?;
+            writer.seek(SeekFrom::Current(rel as i64 - 8))?;
             for i in &self.import_ids {
                 writer.write_u64::<E>(*i)?;
             }
-            writer.seek(SeekFrom::Start(return_ptr));
+            writer.seek(SeekFrom::Start(return_ptr))?;
             *curr_offset += 8 * self.import_ids.len() as u64;
         }
         Ok(())
     }
 }
 
-// Use this to check if a mod user is trying to load a cooked package
-// Support for directly using cooked assets will hopefully be working soon...
+// TFilePackageStoreEntryCArrayView: element count and an offset to the elements that's relative to the start of the view
+fn read_store_entry_array_view<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Vec<u64>, Box<dyn Error>> {
+    let view_start = reader.stream_position()?;
+    let count = reader.read_u32::<E>()?;
+    let offset = reader.read_u32::<E>()?;
+    if count == 0 {
+        return Ok(vec![]);
+    }
+    let return_ptr = reader.stream_position()?;
+    reader.seek(SeekFrom::Start(view_start + offset as u64))?;
+    let values = (0..count).map(|_| reader.read_u64::<E>()).collect::<Result<Vec<u64>, _>>()?;
+    reader.seek(SeekFrom::Start(return_ptr))?;
+    Ok(values)
+}
+
+// Use this to check if a mod user is trying to load a cooked package, which needs to be converted into an IO Store package
 pub const UASSET_MAGIC: u32 = 0x9E2A83C1;
 
 #[derive(Debug)]
@@ -497,14 +724,16 @@ impl ObjectExport2 {
     pub fn from_pak_asset<
         N: NameMap,
         G: GameName
-    >(map: &Vec<FObjectExport>, names: &N, imports: &Vec<IoStoreObjectIndex>, file_name: &str, game_name: &G) -> Vec<ObjectExport2> {
-        // Convert FObjectImport into named ObjectImport
+    >(map: &[FObjectExport], names: &N, imports: &[IoStoreObjectIndex], file_name: &str, game_name: &G) -> Result<Vec<ObjectExport2>, String> {
+        // Convert FObjectExport into IO Store export map entries
         let mut resolves = vec![];
-        for (i, v) in map.into_iter().enumerate() {
-            println!("{}, {:?}", i, v);
-            resolves.push(v.resolve(names, imports, map, file_name, game_name));
+        for (i, v) in map.iter().enumerate() {
+            match v.resolve(names, imports, map, file_name, game_name) {
+                Ok(obj) => resolves.push(obj),
+                Err(e) => return Err(format!("Error converting PAK formatted export to IO Store export on ID {}: {}", i, e))
+            }
         }
-        resolves
+        Ok(resolves)
     }
 
     pub fn map_to_buffer<W: Write, E: byteorder::ByteOrder>(map: &Vec<Self>, writer: &mut W) -> Result<(), Box<dyn Error>> {
@@ -529,11 +758,151 @@ impl ObjectExport2 {
     }
 }
 
+pub trait PackageIoSummarySerialize {
+    // Convert a cooked package into an IO Store package that uses this package summary. file_name is the package's path in the
+    // project without an extension ([ProjectName]/Content/...), which game_name uses to find the package's path in the game
+    fn from_cooked_package<
+        G: GameName,
+        E: byteorder::ByteOrder
+    >(asset: &[u8], uexp: Option<&[u8]>, file_name: &str, game_name: &G) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    // Why cooked packages can't be converted for this summary yet, if they can't. They're skipped when the container is built
+    // instead of failing to convert one by one
+    fn get_unsupported_reason() -> Option<&'static str> {
+        None
+    }
+}
+
+impl PackageIoSummarySerialize for PackageSummary1 {
+    fn from_cooked_package<
+        G: GameName,
+        E: byteorder::ByteOrder
+    >(asset: &[u8], uexp: Option<&[u8]>, file_name: &str, game_name: &G) -> Result<Vec<u8>, Box<dyn Error>> {
+        Err(Self::get_unsupported_reason().unwrap().into())
+    }
+
+    fn get_unsupported_reason() -> Option<&'static str> {
+        Some("Cooked packages can't be converted for UE 4.25 yet, cook the mod for IO Store instead")
+    }
+}
+
+pub const PACKAGE_SUMMARY_2_SERIALIZED_SIZE: u64 = 0x40;
+
+impl PackageIoSummarySerialize for PackageSummary2 {
+    fn from_cooked_package<
+        G: GameName,
+        E: byteorder::ByteOrder
+    >(asset: &[u8], uexp: Option<&[u8]>, file_name: &str, game_name: &G) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut package = CookedPackage::<PackageFileSummaryType4>::from_buffer::<Cursor<&[u8]>, E>(&mut Cursor::new(asset))?;
+        let export_data = package.get_export_data(asset, uexp)?;
+        let package_name = game_name.project_path_to_game_path(file_name)?;
+        let imports = ObjectImport::from_pak_asset(&package.imports, &package.names)?;
+        let exports = ObjectExport2::from_pak_asset(&package.exports, &package.names, &imports, file_name, game_name)?;
+        // The package's name may not be in a cooked package's name map. Adding it onto the end keeps every other name's index the same
+        let package_name_index = package.names.get_or_add(&package_name) as u32;
+        let mut writer = Cursor::new(vec![]);
+        writer.seek(SeekFrom::Start(PACKAGE_SUMMARY_2_SERIALIZED_SIZE))?; // write FPackageSummary once every offset is known
+        // Name map: names, then the hash algorithm and each name's hash, aligned to 8 bytes. Names that aren't ASCII are written
+        // as UTF-16, so the size of the names comes from where they end instead of their length
+        let name_map_names_offset = writer.stream_position()? as i32;
+        package.names.to_buffer_text_only::<Cursor<Vec<u8>>, FString16, E>(&mut writer)?;
+        let name_map_names_size = writer.stream_position()? as i32 - name_map_names_offset;
+        FString16::to_buffer_alignment::<Cursor<Vec<u8>>, E>(&mut writer);
+        let name_map_hashes_offset = writer.stream_position()? as i32 - 8; // includes the hash algorithm
+        for i in 0..package.names.len() {
+            FString16::to_buffer_hash::<Cursor<Vec<u8>>, E>(&package.names[i], &mut writer)?;
+        }
+        let name_map_hashes_size = writer.stream_position()? as i32 - name_map_hashes_offset;
+        let import_map_offset = writer.stream_position()? as i32;
+        ObjectImport::map_to_buffer::<Cursor<Vec<u8>>, E>(&imports, &mut writer)?;
+        let export_map_offset = writer.stream_position()? as i32;
+        ObjectExport2::map_to_buffer::<Cursor<Vec<u8>>, E>(&exports, &mut writer)?;
+        // Put every export into a single export bundle. Each export is created first, then serialized in the same order that
+        // their data is stored in the cooked package
+        let export_bundles_offset = writer.stream_position()? as i32;
+        let export_count = package.exports.len() as u32;
+        writer.write_u32::<E>(0)?; // FirstEntryIndex
+        writer.write_u32::<E>(export_count * 2)?; // EntryCount
+        for i in 0..export_count {
+            writer.write_u32::<E>(i)?;
+            writer.write_u32::<E>(ExportBundleCommandType::Create as u32)?;
+        }
+        let mut serialize_order: Vec<u32> = (0..export_count).collect();
+        serialize_order.sort_by_key(|i| package.exports[*i as usize].serial_offset);
+        for i in serialize_order {
+            writer.write_u32::<E>(i)?;
+            writer.write_u32::<E>(ExportBundleCommandType::Serialize as u32)?;
+        }
+        // Graph data: each imported package, with an arc from it's first export bundle to our export bundle
+        let graph_data_offset = writer.stream_position()? as i32;
+        let imported_packages: Vec<u64> = package.imports.iter()
+            .filter_map(|i| i.get_imported_package_name(&package.names))
+            .map(Hasher16::get_cityhash64).collect();
+        writer.write_u32::<E>(imported_packages.len() as u32)?;
+        for id in imported_packages {
+            writer.write_u64::<E>(id)?; // ImportedPackageId
+            writer.write_u32::<E>(1)?; // ExternalArcCount
+            writer.write_u32::<E>(0)?; // FromExportBundleIndex
+            writer.write_u32::<E>(0)?; // ToExportBundleIndex
+        }
+        let graph_data_size = writer.stream_position()? as i32 - graph_data_offset;
+        writer.write_all(export_data)?;
+        let package_name = FMappedName::from(package_name_index as u64);
+        let summary = PackageSummary2 {
+            name: package_name,
+            source_name: package_name,
+            package_flags: package.summary.get_package_flags(),
+            cooked_header_size: package.summary.get_total_header_size() as u32,
+            name_map_names_offset,
+            name_map_names_size,
+            name_map_hashes_offset,
+            name_map_hashes_size,
+            import_map_offset,
+            export_map_offset,
+            export_bundles_offset,
+            graph_data_offset,
+            graph_data_size,
+            pad: 0
+        };
+        writer.rewind()?;
+        summary.to_buffer::<Cursor<Vec<u8>>, E>(&mut writer)?;
+        Ok(writer.into_inner())
+    }
+}
+
+const ZEN_COOKED_PACKAGES_UNSUPPORTED: &str = "Cooked packages can't be converted for UE 5 yet, cook the mod for IO Store instead";
+
+impl PackageIoSummarySerialize for ZenPackageSummaryType1 {
+    fn from_cooked_package<
+        G: GameName,
+        E: byteorder::ByteOrder
+    >(asset: &[u8], uexp: Option<&[u8]>, file_name: &str, game_name: &G) -> Result<Vec<u8>, Box<dyn Error>> {
+        Err(Self::get_unsupported_reason().unwrap().into())
+    }
+
+    fn get_unsupported_reason() -> Option<&'static str> {
+        Some(ZEN_COOKED_PACKAGES_UNSUPPORTED)
+    }
+}
+
+impl PackageIoSummarySerialize for ZenPackageSummaryType2 {
+    fn from_cooked_package<
+        G: GameName,
+        E: byteorder::ByteOrder
+    >(asset: &[u8], uexp: Option<&[u8]>, file_name: &str, game_name: &G) -> Result<Vec<u8>, Box<dyn Error>> {
+        Err(Self::get_unsupported_reason().unwrap().into())
+    }
+
+    fn get_unsupported_reason() -> Option<&'static str> {
+        Some(ZEN_COOKED_PACKAGES_UNSUPPORTED)
+    }
+}
+
 // Check that the first bytes of the file don't contain the magic used for cooked assets
-pub fn is_valid_asset_type<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> bool {
-    reader.seek(SeekFrom::Start(0));
-    let magic_check = reader.read_u32::<E>().unwrap();
-    magic_check != UASSET_MAGIC
+pub fn is_valid_asset_type<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<bool, Box<dyn Error>> {
+    reader.rewind()?;
+    let magic_check = reader.read_u32::<E>()?;
+    Ok(magic_check != UASSET_MAGIC)
 }
 
 /*
@@ -659,4 +1028,171 @@ pub struct MappedName<'a> {
     pub name: &'a str,
     pub value: FMappedName
 }
-*/
\ No newline at end of file
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::NativeEndian as EN;
+
+    const TEST_EXPORT_COUNT: u32 = 2;
+
+    // Zen packages start with FZenPackageSummary, followed by sections in the order that it's offsets are in. Only the sections
+    // that store entries are made from are filled in, everything else is left zeroed
+    fn write_export_map(writer: &mut Cursor<Vec<u8>>) -> u32 {
+        let export_map_offset = writer.position() as u32;
+        writer.write_all(&vec![0; (TEST_EXPORT_COUNT as u64 * IO_PACKAGE_FEXPORTMAP_SERIALIZED_SIZE) as usize]).unwrap();
+        export_map_offset
+    }
+
+    // Create and serialize each export, in the order that UE puts them into export bundles
+    fn write_export_bundle_entries(writer: &mut Cursor<Vec<u8>>) -> u32 {
+        let export_bundle_entries_offset = writer.position() as u32;
+        for i in 0..TEST_EXPORT_COUNT {
+            for command_type in [ExportBundleCommandType::Create, ExportBundleCommandType::Serialize] {
+                writer.write_u32::<EN>(i).unwrap();
+                writer.write_u32::<EN>(command_type as u32).unwrap();
+            }
+        }
+        export_bundle_entries_offset
+    }
+
+    // 5.0 - 5.2: an export bundle for each export, whose headers start the graph data
+    fn get_zen_package_type1() -> Vec<u8> {
+        let mut writer = Cursor::new(vec![]);
+        writer.seek(SeekFrom::Start(0x30)).unwrap(); // FZenPackageSummary (0x2c), aligned to 8 bytes
+        let export_map_offset = write_export_map(&mut writer);
+        let export_bundle_entries_offset = write_export_bundle_entries(&mut writer);
+        let graph_data_offset = writer.position() as u32;
+        for i in 0..TEST_EXPORT_COUNT {
+            writer.write_u64::<EN>(0).unwrap(); // SerialOffset
+            writer.write_u32::<EN>(i * 2).unwrap(); // FirstEntryIndex
+            writer.write_u32::<EN>(2).unwrap(); // EntryCount
+        }
+        writer.write_u32::<EN>(0).unwrap(); // imported package count
+        writer.seek(SeekFrom::Start(0x20)).unwrap();
+        writer.write_u32::<EN>(export_map_offset).unwrap();
+        writer.write_u32::<EN>(export_bundle_entries_offset).unwrap();
+        writer.write_u32::<EN>(graph_data_offset).unwrap();
+        writer.into_inner()
+    }
+
+    // 5.3: a single export bundle, with a dependency bundle for each export and the name of each imported package
+    fn get_zen_package_type2(dependency_counts: &[[u32; 4]], imported_packages: &[(&str, i32)]) -> Vec<u8> {
+        let mut writer = Cursor::new(vec![]);
+        writer.seek(SeekFrom::Start(0x38)).unwrap(); // FZenPackageSummary (0x34), aligned to 8 bytes
+        let export_map_offset = write_export_map(&mut writer);
+        let export_bundle_entries_offset = write_export_bundle_entries(&mut writer);
+        let dependency_bundle_headers_offset = writer.position() as u32;
+        let mut first_entry_index = 0;
+        for counts in dependency_counts {
+            writer.write_i32::<EN>(first_entry_index as i32).unwrap();
+            for count in counts {
+                writer.write_u32::<EN>(*count).unwrap();
+            }
+            first_entry_index += counts.iter().sum::<u32>();
+        }
+        let dependency_bundle_entries_offset = writer.position() as u32;
+        for i in 0..first_entry_index {
+            writer.write_i32::<EN>(-(i as i32) - 1).unwrap(); // FPackageIndex of an import
+        }
+        // FNameBatch of imported package names, followed by the number of each name
+        let imported_package_names_offset = writer.position() as u32;
+        writer.write_u32::<EN>(imported_packages.len() as u32).unwrap();
+        if !imported_packages.is_empty() {
+            writer.write_u32::<EN>(imported_packages.iter().map(|(name, _)| name.len() as u32).sum()).unwrap();
+            writer.write_u64::<EN>(0).unwrap(); // hash version
+            for _ in imported_packages {
+                writer.write_u64::<EN>(0).unwrap(); // hash
+            }
+            for (name, _) in imported_packages {
+                writer.write_u16::<byteorder::BigEndian>(name.len() as u16).unwrap();
+            }
+            for (name, _) in imported_packages {
+                writer.write_all(name.as_bytes()).unwrap();
+            }
+        }
+        for (_, number) in imported_packages {
+            writer.write_i32::<EN>(*number).unwrap();
+        }
+        writer.seek(SeekFrom::Start(0x20)).unwrap();
+        writer.write_u32::<EN>(export_map_offset).unwrap();
+        writer.write_u32::<EN>(export_bundle_entries_offset).unwrap();
+        writer.write_u32::<EN>(dependency_bundle_headers_offset).unwrap();
+        writer.write_u32::<EN>(dependency_bundle_entries_offset).unwrap();
+        writer.write_u32::<EN>(imported_package_names_offset).unwrap();
+        writer.into_inner()
+    }
+
+    #[test]
+    fn read_zen_package_type1_export_bundles() {
+        let package = get_zen_package_type1();
+        let mut reader = Cursor::new(package.as_slice());
+        let summary = ZenPackageSummaryType1::to_package_summary::<_, EN>(&mut reader).unwrap();
+        assert_eq!(summary.get_export_count(), TEST_EXPORT_COUNT as u64);
+        let entries = ExportBundleHeader5::from_buffer::<_, EN>(&mut reader, &summary).unwrap();
+        assert_eq!(entries.len(), TEST_EXPORT_COUNT as usize * 2);
+        assert_eq!(entries.iter().map(|e| e.bundle_index).collect::<Vec<u32>>(), vec![0, 0, 1, 1]);
+        assert_eq!(ExportBundleHeader5::get_export_bundle_count(&entries), TEST_EXPORT_COUNT);
+    }
+
+    // Type1 packages can't say which packages they import, so they mustn't get a store entry that leaves them out
+    #[test]
+    fn zen_package_type1_store_entry_is_refused() {
+        let package = get_zen_package_type1();
+        let result = ContainerHeaderPackage::from_package_summary::<ZenPackageSummaryType1, _, EN>(
+            &mut Cursor::new(package.as_slice()), 0, package.len() as u64);
+        assert_eq!(result.err().unwrap().to_string(), ZEN_IMPORTED_PACKAGES_UNSUPPORTED);
+    }
+
+    #[test]
+    fn read_zen_package_type2_store_entry() {
+        let package = get_zen_package_type2(&[[1, 0, 1, 0], [0, 0, 0, 1]], &[("/Script/Engine", 0), ("/Game/Foo", 2)]);
+        let entry = ContainerHeaderPackage::from_package_summary::<ZenPackageSummaryType2, _, EN>(
+            &mut Cursor::new(package.as_slice()), 0x1234, package.len() as u64).unwrap();
+        assert_eq!(entry.hash, 0x1234);
+        assert_eq!(entry.export_bundle_size, package.len() as u64);
+        assert_eq!(entry.export_count, TEST_EXPORT_COUNT);
+        assert_eq!(entry.export_bundle_count, 1);
+        assert_eq!(entry.import_ids, vec![Hasher16::get_cityhash64("/Script/Engine"), Hasher16::get_cityhash64("/Game/Foo_1")]);
+    }
+
+    // Reading the dependency bundle headers as 4.25 - 5.2 export bundle headers would misparse them, so a 5.3 package whose headers
+    // don't cover it's dependency bundle entries is rejected
+    #[test]
+    fn zen_package_type2_mismatched_dependency_bundles() {
+        let mut package = get_zen_package_type2(&[[1, 0, 1, 0], [0, 0, 0, 1]], &[]);
+        let mut reader = Cursor::new(package.as_slice());
+        let summary = ZenPackageSummaryType2::to_package_summary::<_, EN>(&mut reader).unwrap();
+        assert_eq!(DependencyBundleHeader::from_buffer::<_, EN>(&mut reader, &summary).unwrap().len(), TEST_EXPORT_COUNT as usize * 2);
+        let second_header = summary.graph_offset as usize + DEPENDENCY_BUNDLE_HEADER_SERIALIZED_SIZE as usize;
+        package[second_header..second_header + 4].copy_from_slice(&5i32.to_ne_bytes()); // FirstEntryIndex
+        let error = DependencyBundleHeader::from_buffer::<_, EN>(&mut Cursor::new(package.as_slice()), &summary).err().unwrap();
+        assert!(error.to_string().starts_with("Dependency bundle headers don't match"));
+    }
+
+    // Convert the cooked package fixture, then read it's store entry back the same way that mod IO Store packages are read
+    #[test]
+    fn convert_cooked_package_type2() {
+        use crate::pak_package::{tests::*, GameNameImpl};
+        let (asset, uexp) = get_cooked_package(&[1, 2, 3, 4]);
+        let game_name = GameNameImpl::new(TEST_PROJECT_NAME, "/Game");
+        let package = PackageSummary2::from_cooked_package::<_, EN>(&asset, Some(&uexp), TEST_PACKAGE_PATH, &game_name).unwrap();
+        assert!(package.ends_with(&[1, 2, 3, 4]));
+        let entry = ContainerHeaderPackage::from_package_summary::<PackageSummary2, _, EN>(
+            &mut Cursor::new(package.as_slice()), 0, package.len() as u64).unwrap();
+        assert_eq!(entry.export_count, 1);
+        assert_eq!(entry.export_bundle_count, 1);
+        assert_eq!(entry.import_ids, vec![Hasher16::get_cityhash64(TEST_IMPORTED_PACKAGE)]);
+        // packages outside of the project's Content folder don't have a path in the game
+        assert!(PackageSummary2::from_cooked_package::<_, EN>(&asset, Some(&uexp), "Other/Content/TestAsset", &game_name).is_err());
+    }
+
+    #[test]
+    fn convert_cooked_package_without_export_data() {
+        use crate::pak_package::{tests::*, GameNameImpl};
+        let (asset, _) = get_cooked_package(&[1, 2, 3, 4]);
+        let game_name = GameNameImpl::new(TEST_PROJECT_NAME, "/Game");
+        let error = PackageSummary2::from_cooked_package::<_, EN>(&asset, None, TEST_PACKAGE_PATH, &game_name).err().unwrap();
+        assert_eq!(error.to_string(), "Export data doesn't end with the package file tag");
+    }
+}
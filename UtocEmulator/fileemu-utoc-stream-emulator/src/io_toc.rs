@@ -1,10 +1,10 @@
 use bitflags::bitflags;
 use byteorder::{ReadBytesExt, WriteBytesExt};
 use crate::{
+    error::EmulatorError,
     io_package::FGraphPackage,
-    string::{FString32NoHash, FStringSerializer, Hasher, Hasher16},
+    string::{FString32NoHash, FStringDeserializer, FStringSerializer, Hasher, Hasher16},
 };
-#[cfg(feature = "hash_meta")]
 use sha1::{Sha1, Digest};
 use std::{
     cmp::Ordering,
@@ -12,8 +12,32 @@ use std::{
     io::{Cursor, Read, Seek, SeekFrom, Write}
 };
 
-pub type IoContainerId = u64; // TODO: ContainerID is a UID as a CityHash64 of the container name
-                              // represent that with a distinct CityHashID type
+// Containers are identified by a CityHash64 of their name, the same as FIoContainerId::FromName (e.g "pakchunk0" is b9f66c62c549f00c).
+// This goes into the TOC header and the container header, and is used for the container header's chunk id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct IoContainerId(u64);
+
+impl IoContainerId {
+    pub fn from_name(name: &str) -> Self {
+        Self(Hasher16::get_cityhash64(name))
+    }
+    pub fn get_value(&self) -> u64 {
+        self.0
+    }
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        Ok(Self(reader.read_u64::<E>()?))
+    }
+    pub fn to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        writer.write_u64::<E>(self.0)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::LowerHex for IoContainerId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::LowerHex::fmt(&self.0, f)
+    }
+}
 pub type GUID = u128;
 
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
@@ -44,21 +68,23 @@ impl From<IoStoreTocVersion> for u8 {
     }
 }
 
-impl From<u8> for IoStoreTocVersion {
-    fn from(val: u8) -> IoStoreTocVersion {
+impl TryFrom<u8> for IoStoreTocVersion {
+    type Error = EmulatorError;
+    fn try_from(val: u8) -> Result<IoStoreTocVersion, Self::Error> {
         match val {
-            1 => IoStoreTocVersion::Initial,
-            2 => IoStoreTocVersion::DirectoryIndex,
-            3 => IoStoreTocVersion::PartitionSize,
-            4 => IoStoreTocVersion::PerfectHash,
-            5 => IoStoreTocVersion::PerfectHashWithOverflow,
-            _ => panic!("ERROR: Cannot create an IoStoreTocVersion from value {}", val)
+            1 => Ok(IoStoreTocVersion::Initial),
+            2 => Ok(IoStoreTocVersion::DirectoryIndex),
+            3 => Ok(IoStoreTocVersion::PartitionSize),
+            4 => Ok(IoStoreTocVersion::PerfectHash),
+            5 => Ok(IoStoreTocVersion::PerfectHashWithOverflow),
+            _ => Err(EmulatorError::UnsupportedVersion(format!("Cannot create an IoStoreTocVersion from value {}", val)))
         }
     }
 }
 
 bitflags! {
-    struct IoContainerFlags : u8 {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct IoContainerFlags : u8 {
         const NoFlags = 0;
         const Compressed = 1 << 0;
         const Encrypted = 1 << 1;
@@ -73,8 +99,21 @@ bitflags! {
 pub const IO_STORE_TOC_MAGIC: [u8; 0x10] = *b"-==--==--==--==-";
 
 pub trait IoStoreTocHeaderCommon {
-    fn new(container_id: u64, entries: u32, compressed_blocks: u32, compression_block_size: u32, dir_index_size: u32) -> impl IoStoreTocHeaderCommon;
+    fn new(container_id: IoContainerId, entries: u32, compressed_blocks: u32, compression_block_size: u32, dir_index_size: u32) -> impl IoStoreTocHeaderCommon;
     fn to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>>;
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> where Self: Sized;
+    // Set the TOC version and perfect hash table sizes for UE5 headers. Headers from before UE5 don't have a perfect hash, so this does nothing
+    fn set_perfect_hash(&mut self, version: IoStoreTocVersion, seed_count: u32, without_perfect_hash_count: u32) {}
+    // Set the number of compression method names that follow the compression blocks. 4.25 can't be compressed, so this does nothing
+    fn set_compression_methods(&mut self, method_count: u32) {}
+    // Set the GUID of the key that the container is encrypted with. 4.25 can't be encrypted, so this does nothing
+    fn set_encryption_key_guid(&mut self, guid: GUID) {}
+    // Mark the TOC as having block hashes and signatures. 4.25 can't be signed, so this does nothing
+    fn set_signed(&mut self) {}
+    // Partition count and size were added in 4.27, containers for earlier versions are always a single .ucas
+    fn supports_partitions() -> bool { false }
+    // Set how many partitions the container is split into, and the most that can go into each one
+    fn set_partitions(&mut self, count: u32, size: u64) {}
 }
 
 #[repr(C)]
@@ -86,12 +125,13 @@ pub struct IoStoreTocHeaderType1 { // Unreal Engine 4.25 (size: 0x80) (unverifie
     toc_pad: [u32; 25]
 }
 
-impl IoStoreTocHeaderType1 {
-    fn new(entries: u32) -> IoStoreTocHeaderType1 {
+impl IoStoreTocHeaderCommon for IoStoreTocHeaderType1 {
+    // 4.25 has no container id, compression blocks or directory index, so only the entry count is used
+    fn new(container_id: IoContainerId, entries: u32, compressed_blocks: u32, compression_block_size: u32, dir_index_size: u32) -> impl IoStoreTocHeaderCommon {
         let toc_magic: [u8; 0x10] = IO_STORE_TOC_MAGIC;
         let toc_header_size = std::mem::size_of::<Self>() as u32;
         let toc_entry_count = entries;
-        let toc_entry_size = 0x16;
+        let toc_entry_size = TOC_ENTRY_SERIALIZED_SIZE as u32;
         let toc_pad = [0; 25];
         IoStoreTocHeaderType1 {
             toc_magic,
@@ -101,6 +141,25 @@ impl IoStoreTocHeaderType1 {
             toc_pad
         }
     }
+    fn to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        writer.write_all(self.toc_magic.as_slice())?; // 0x0
+        writer.write_u32::<E>(self.toc_header_size)?; // 0x10
+        writer.write_u32::<E>(self.toc_entry_count)?; // 0x14
+        writer.write_u32::<E>(self.toc_entry_size)?; // 0x18
+        for _ in 0..25 {
+            writer.write_u32::<E>(0)?; // padding
+        }
+        Ok(())
+    }
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut toc_magic = [0; 0x10];
+        reader.read_exact(&mut toc_magic)?; // 0x0
+        let toc_header_size = reader.read_u32::<E>()?; // 0x10
+        let toc_entry_count = reader.read_u32::<E>()?; // 0x14
+        let toc_entry_size = reader.read_u32::<E>()?; // 0x18
+        reader.seek(SeekFrom::Current(25 * 4))?; // padding
+        Ok(Self { toc_magic, toc_header_size, toc_entry_count, toc_entry_size, toc_pad: [0; 25] })
+    }
 }
 
 #[repr(C)]
@@ -122,7 +181,7 @@ pub struct IoStoreTocHeaderType2 { // Unreal Engine 4.25+, 4.26
 }
 
 impl IoStoreTocHeaderCommon for IoStoreTocHeaderType2 {
-    fn new(container_id: u64, entries: u32, compressed_blocks: u32, compression_block_size: u32, dir_index_size: u32) -> impl IoStoreTocHeaderCommon {
+    fn new(container_id: IoContainerId, entries: u32, compressed_blocks: u32, compression_block_size: u32, dir_index_size: u32) -> impl IoStoreTocHeaderCommon {
         Self {
             toc_magic: IO_STORE_TOC_MAGIC,
             version: IoStoreTocVersion::DirectoryIndex,
@@ -153,15 +212,51 @@ impl IoStoreTocHeaderCommon for IoStoreTocHeaderType2 {
         writer.write_u32::<E>(self.compression_block_size)?;
         writer.write_u32::<E>(self.directory_index_size)?;
         writer.write_u32::<E>(0)?; // padding
-        writer.write_u64::<E>(self.container_id)?;
+        self.container_id.to_buffer::<W, E>(writer)?;
         writer.write_u128::<E>(self.encryption_key_guid)?;
         writer.write_u8(self.container_flags.bits())?;
         writer.write_u24::<E>(0)?; // padding
         for _ in 0..15 {
-            writer.write_u64::<E>(0)?; // padding
+            writer.write_u32::<E>(0)?; // padding
         }
         Ok(())
     }
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut toc_magic = [0; 0x10];
+        reader.read_exact(&mut toc_magic)?; // 0x0
+        let version = IoStoreTocVersion::try_from(reader.read_u8()?)?;
+        reader.read_u24::<E>()?; // padding
+        let toc_header_size = reader.read_u32::<E>()?;
+        let toc_entry_count = reader.read_u32::<E>()?;
+        let toc_compressed_block_entry_count = reader.read_u32::<E>()?;
+        let toc_compressed_block_entry_size = reader.read_u32::<E>()?;
+        let compression_method_name_count = reader.read_u32::<E>()?;
+        let compression_method_name_length = reader.read_u32::<E>()?;
+        let compression_block_size = reader.read_u32::<E>()?;
+        let directory_index_size = reader.read_u32::<E>()?;
+        reader.read_u32::<E>()?; // padding
+        let container_id = IoContainerId::from_buffer::<R, E>(reader)?;
+        let encryption_key_guid = reader.read_u128::<E>()?;
+        let container_flags = IoContainerFlags::from_bits_retain(reader.read_u8()?);
+        reader.read_u24::<E>()?; // padding
+        reader.seek(SeekFrom::Current(15 * 4))?; // padding
+        Ok(Self {
+            toc_magic, version, toc_header_size, toc_entry_count, toc_compressed_block_entry_count, toc_compressed_block_entry_size,
+            compression_method_name_count, compression_method_name_length, compression_block_size, directory_index_size,
+            container_id, encryption_key_guid, container_flags, reserved: [0; 15]
+        })
+    }
+    fn set_compression_methods(&mut self, method_count: u32) {
+        self.compression_method_name_count = method_count;
+        self.container_flags.set(IoContainerFlags::Compressed, method_count > 0);
+    }
+    fn set_encryption_key_guid(&mut self, guid: GUID) {
+        self.encryption_key_guid = guid;
+        self.container_flags.insert(IoContainerFlags::Encrypted);
+    }
+    fn set_signed(&mut self) {
+        self.container_flags.insert(IoContainerFlags::Signed);
+    }
 }
 
 #[repr(C)]
@@ -185,7 +280,7 @@ pub struct IoStoreTocHeaderType3 { // Unreal Engine 4.27
 }
 
 impl IoStoreTocHeaderCommon for IoStoreTocHeaderType3 {
-    fn new(container_id: u64, entries: u32, compressed_blocks: u32, compression_block_size: u32, dir_index_size: u32) -> impl IoStoreTocHeaderCommon {
+    fn new(container_id: IoContainerId, entries: u32, compressed_blocks: u32, compression_block_size: u32, dir_index_size: u32) -> impl IoStoreTocHeaderCommon {
         Self {
             toc_magic: IO_STORE_TOC_MAGIC,
             version: IoStoreTocVersion::PartitionSize,
@@ -218,7 +313,7 @@ impl IoStoreTocHeaderCommon for IoStoreTocHeaderType3 {
         writer.write_u32::<E>(self.compression_block_size);
         writer.write_u32::<E>(self.directory_index_size);
         writer.write_u32::<E>(self.partition_count);
-        writer.write_u64::<E>(self.container_id);
+        self.container_id.to_buffer::<W, E>(writer)?;
         writer.write_u128::<E>(self.encryption_key_guid);
         writer.write_u8(self.container_flags.bits());
         writer.write_u24::<E>(0)?; // padding
@@ -229,6 +324,51 @@ impl IoStoreTocHeaderCommon for IoStoreTocHeaderType3 {
         }
         Ok(())
     }
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut toc_magic = [0; 0x10];
+        reader.read_exact(&mut toc_magic)?; // 0x0
+        let version = IoStoreTocVersion::try_from(reader.read_u8()?)?;
+        reader.read_u24::<E>()?; // padding
+        let toc_header_size = reader.read_u32::<E>()?;
+        let toc_entry_count = reader.read_u32::<E>()?;
+        let toc_compressed_block_entry_count = reader.read_u32::<E>()?;
+        let toc_compressed_block_entry_size = reader.read_u32::<E>()?;
+        let compression_method_name_count = reader.read_u32::<E>()?;
+        let compression_method_name_length = reader.read_u32::<E>()?;
+        let compression_block_size = reader.read_u32::<E>()?;
+        let directory_index_size = reader.read_u32::<E>()?;
+        let partition_count = reader.read_u32::<E>()?;
+        let container_id = IoContainerId::from_buffer::<R, E>(reader)?;
+        let encryption_key_guid = reader.read_u128::<E>()?;
+        let container_flags = IoContainerFlags::from_bits_retain(reader.read_u8()?);
+        reader.read_u24::<E>()?; // padding
+        reader.read_u32::<E>()?; // padding
+        let partition_size = reader.read_u64::<E>()?;
+        reader.seek(SeekFrom::Current(6 * 8))?; // padding
+        Ok(Self {
+            toc_magic, version, toc_header_size, toc_entry_count, toc_compressed_block_entry_count, toc_compressed_block_entry_size,
+            compression_method_name_count, compression_method_name_length, compression_block_size, directory_index_size, partition_count,
+            container_id, encryption_key_guid, container_flags, partition_size, reserved: [0; 6]
+        })
+    }
+    fn set_compression_methods(&mut self, method_count: u32) {
+        self.compression_method_name_count = method_count;
+        self.container_flags.set(IoContainerFlags::Compressed, method_count > 0);
+    }
+    fn set_encryption_key_guid(&mut self, guid: GUID) {
+        self.encryption_key_guid = guid;
+        self.container_flags.insert(IoContainerFlags::Encrypted);
+    }
+    fn set_signed(&mut self) {
+        self.container_flags.insert(IoContainerFlags::Signed);
+    }
+    fn supports_partitions() -> bool {
+        true
+    }
+    fn set_partitions(&mut self, count: u32, size: u64) {
+        self.partition_count = count;
+        self.partition_size = size;
+    }
 }
 
 #[repr(C)]
@@ -253,6 +393,219 @@ pub struct IoStoreTocHeaderType4 { // Unreal Engine 5.0+ (size: 0x90)
     reserved: [u32; 11]
 }
 
+impl IoStoreTocHeaderCommon for IoStoreTocHeaderType4 {
+    fn new(container_id: IoContainerId, entries: u32, compressed_blocks: u32, compression_block_size: u32, dir_index_size: u32) -> impl IoStoreTocHeaderCommon {
+        Self {
+            toc_magic: IO_STORE_TOC_MAGIC,
+            version: IoStoreTocVersion::PerfectHashWithOverflow,
+            toc_header_size: std::mem::size_of::<Self>() as u32,
+            toc_entry_count: entries,
+            toc_compressed_block_entry_count: compressed_blocks,
+            toc_compressed_block_entry_size: std::mem::size_of::<IoStoreTocCompressedBlockEntry>() as u32, // for sanity checking
+            compression_method_name_count: 0,
+            compression_method_name_length: 32,
+            compression_block_size,
+            directory_index_size: dir_index_size,
+            partition_count: 1,
+            container_id,
+            encryption_key_guid: 0,
+            container_flags: IoContainerFlags::Indexed,
+            toc_chunks_perfect_hash_seeds_count: 0,
+            partition_size: u64::MAX,
+            toc_chunks_without_perfect_hash_count: 0,
+            reserved: [0; 11]
+        }
+    }
+    fn to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        writer.write_all(self.toc_magic.as_slice())?; // 0x0
+        writer.write_u8(self.version.into())?; // 0x10
+        writer.write_u24::<E>(0)?; // padding
+        writer.write_u32::<E>(self.toc_header_size)?; // 0x14
+        writer.write_u32::<E>(self.toc_entry_count)?; // 0x18
+        writer.write_u32::<E>(self.toc_compressed_block_entry_count)?; // 0x1c
+        writer.write_u32::<E>(self.toc_compressed_block_entry_size)?; // 0x20
+        writer.write_u32::<E>(self.compression_method_name_count)?; // 0x24
+        writer.write_u32::<E>(self.compression_method_name_length)?; // 0x28
+        writer.write_u32::<E>(self.compression_block_size)?; // 0x2c
+        writer.write_u32::<E>(self.directory_index_size)?; // 0x30
+        writer.write_u32::<E>(self.partition_count)?; // 0x34
+        self.container_id.to_buffer::<W, E>(writer)?; // 0x38
+        writer.write_u128::<E>(self.encryption_key_guid)?; // 0x40
+        writer.write_u8(self.container_flags.bits())?; // 0x50
+        writer.write_u24::<E>(0)?; // padding
+        writer.write_u32::<E>(self.toc_chunks_perfect_hash_seeds_count)?; // 0x54
+        writer.write_u64::<E>(self.partition_size)?; // 0x58
+        writer.write_u32::<E>(self.toc_chunks_without_perfect_hash_count)?; // 0x60
+        for _ in 0..11 {
+            writer.write_u32::<E>(0)?; // padding
+        }
+        Ok(())
+    }
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut toc_magic = [0; 0x10];
+        reader.read_exact(&mut toc_magic)?; // 0x0
+        let version = IoStoreTocVersion::try_from(reader.read_u8()?)?; // 0x10
+        reader.read_u24::<E>()?; // padding
+        let toc_header_size = reader.read_u32::<E>()?; // 0x14
+        let toc_entry_count = reader.read_u32::<E>()?; // 0x18
+        let toc_compressed_block_entry_count = reader.read_u32::<E>()?; // 0x1c
+        let toc_compressed_block_entry_size = reader.read_u32::<E>()?; // 0x20
+        let compression_method_name_count = reader.read_u32::<E>()?; // 0x24
+        let compression_method_name_length = reader.read_u32::<E>()?; // 0x28
+        let compression_block_size = reader.read_u32::<E>()?; // 0x2c
+        let directory_index_size = reader.read_u32::<E>()?; // 0x30
+        let partition_count = reader.read_u32::<E>()?; // 0x34
+        let container_id = IoContainerId::from_buffer::<R, E>(reader)?; // 0x38
+        let encryption_key_guid = reader.read_u128::<E>()?; // 0x40
+        let container_flags = IoContainerFlags::from_bits_retain(reader.read_u8()?); // 0x50
+        reader.read_u24::<E>()?; // padding
+        let toc_chunks_perfect_hash_seeds_count = reader.read_u32::<E>()?; // 0x54
+        let partition_size = reader.read_u64::<E>()?; // 0x58
+        let toc_chunks_without_perfect_hash_count = reader.read_u32::<E>()?; // 0x60
+        reader.seek(SeekFrom::Current(11 * 4))?; // padding
+        Ok(Self {
+            toc_magic, version, toc_header_size, toc_entry_count, toc_compressed_block_entry_count, toc_compressed_block_entry_size,
+            compression_method_name_count, compression_method_name_length, compression_block_size, directory_index_size, partition_count,
+            container_id, encryption_key_guid, container_flags, toc_chunks_perfect_hash_seeds_count, partition_size,
+            toc_chunks_without_perfect_hash_count, reserved: [0; 11]
+        })
+    }
+    fn set_perfect_hash(&mut self, version: IoStoreTocVersion, seed_count: u32, without_perfect_hash_count: u32) {
+        self.version = version;
+        self.toc_chunks_perfect_hash_seeds_count = seed_count;
+        self.toc_chunks_without_perfect_hash_count = without_perfect_hash_count;
+    }
+    fn set_compression_methods(&mut self, method_count: u32) {
+        self.compression_method_name_count = method_count;
+        self.container_flags.set(IoContainerFlags::Compressed, method_count > 0);
+    }
+    fn set_encryption_key_guid(&mut self, guid: GUID) {
+        self.encryption_key_guid = guid;
+        self.container_flags.insert(IoContainerFlags::Encrypted);
+    }
+    fn set_signed(&mut self) {
+        self.container_flags.insert(IoContainerFlags::Signed);
+    }
+    fn supports_partitions() -> bool {
+        true
+    }
+    fn set_partitions(&mut self, count: u32, size: u64) {
+        self.partition_count = count;
+        self.partition_size = size;
+    }
+}
+
+// Header of a TOC that's been read, for any version
+pub enum IoStoreTocHeader {
+    Type1(IoStoreTocHeaderType1),
+    Type2(IoStoreTocHeaderType2),
+    Type3(IoStoreTocHeaderType3),
+    Type4(IoStoreTocHeaderType4)
+}
+
+// Get a field that's shared by every header from 4.25+ onwards, or the value that 4.25 implicitly uses
+macro_rules! toc_header_field {
+    ($header:expr, $field:ident, $type1:expr) => {
+        match $header {
+            IoStoreTocHeader::Type1(_) => $type1,
+            IoStoreTocHeader::Type2(h) => h.$field,
+            IoStoreTocHeader::Type3(h) => h.$field,
+            IoStoreTocHeader::Type4(h) => h.$field,
+        }
+    }
+}
+
+impl IoStoreTocHeader {
+    // The header's layout depends on it's version. 4.25 headers don't have one, and have their header size in that position instead
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let start = reader.stream_position()?;
+        let mut toc_magic = [0; 0x10];
+        reader.read_exact(&mut toc_magic)?;
+        if toc_magic != IO_STORE_TOC_MAGIC {
+            return Err(Box::new(EmulatorError::InvalidValue("TOC header has the wrong magic".to_owned())));
+        }
+        let version = reader.read_u32::<E>()?;
+        reader.seek(SeekFrom::Start(start))?;
+        if version as usize == std::mem::size_of::<IoStoreTocHeaderType1>() {
+            return Ok(Self::Type1(IoStoreTocHeaderType1::from_buffer::<R, E>(reader)?));
+        }
+        let header = match IoStoreTocVersion::try_from(version as u8)? {
+            IoStoreTocVersion::PartitionSize => Self::Type3(IoStoreTocHeaderType3::from_buffer::<R, E>(reader)?),
+            IoStoreTocVersion::PerfectHash | IoStoreTocVersion::PerfectHashWithOverflow => Self::Type4(IoStoreTocHeaderType4::from_buffer::<R, E>(reader)?),
+            _ => Self::Type2(IoStoreTocHeaderType2::from_buffer::<R, E>(reader)?)
+        };
+        // skip anything in the header that isn't known about
+        reader.seek(SeekFrom::Start(start + header.get_header_size() as u64))?;
+        Ok(header)
+    }
+    pub fn get_version(&self) -> IoStoreTocVersion {
+        toc_header_field!(self, version, IoStoreTocVersion::Initial)
+    }
+    pub fn get_header_size(&self) -> u32 {
+        match self {
+            Self::Type1(h) => h.toc_header_size,
+            _ => toc_header_field!(self, toc_header_size, 0)
+        }
+    }
+    pub fn get_entry_count(&self) -> u32 {
+        match self {
+            Self::Type1(h) => h.toc_entry_count,
+            _ => toc_header_field!(self, toc_entry_count, 0)
+        }
+    }
+    pub fn get_compressed_block_entry_count(&self) -> u32 {
+        toc_header_field!(self, toc_compressed_block_entry_count, 0)
+    }
+    pub fn get_compression_method_name_count(&self) -> u32 {
+        toc_header_field!(self, compression_method_name_count, 0)
+    }
+    pub fn get_compression_method_name_length(&self) -> u32 {
+        toc_header_field!(self, compression_method_name_length, 0)
+    }
+    pub fn get_compression_block_size(&self) -> u32 {
+        toc_header_field!(self, compression_block_size, 0)
+    }
+    pub fn get_directory_index_size(&self) -> u32 {
+        toc_header_field!(self, directory_index_size, 0)
+    }
+    pub fn get_container_id(&self) -> IoContainerId {
+        toc_header_field!(self, container_id, IoContainerId::default())
+    }
+    pub fn get_encryption_key_guid(&self) -> GUID {
+        toc_header_field!(self, encryption_key_guid, 0)
+    }
+    pub fn get_container_flags(&self) -> IoContainerFlags {
+        toc_header_field!(self, container_flags, IoContainerFlags::NoFlags)
+    }
+    // Containers from before 4.27 are always stored in a single partition
+    pub fn get_partition_count(&self) -> u32 {
+        match self {
+            Self::Type3(h) => h.partition_count,
+            Self::Type4(h) => h.partition_count,
+            _ => 1
+        }
+    }
+    pub fn get_partition_size(&self) -> u64 {
+        match self {
+            Self::Type3(h) => h.partition_size,
+            Self::Type4(h) => h.partition_size,
+            _ => u64::MAX
+        }
+    }
+    pub fn get_perfect_hash_seeds_count(&self) -> u32 {
+        match self {
+            Self::Type4(h) if h.version >= IoStoreTocVersion::PerfectHash => h.toc_chunks_perfect_hash_seeds_count,
+            _ => 0
+        }
+    }
+    pub fn get_chunks_without_perfect_hash_count(&self) -> u32 {
+        match self {
+            Self::Type4(h) if h.version >= IoStoreTocVersion::PerfectHashWithOverflow => h.toc_chunks_without_perfect_hash_count,
+            _ => 0
+        }
+    }
+}
+
 // IO CHUNK ID
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
 #[repr(u8)]
@@ -271,20 +624,21 @@ pub enum IoChunkType4 {
     ContainerHeader // added in UE 4.25+/4.26
 }
 
-impl From<u8> for IoChunkType4 {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for IoChunkType4 {
+    type Error = EmulatorError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            1 => IoChunkType4::InstallManifest,
-            2 => IoChunkType4::ExportBundleData,
-            3 => IoChunkType4::BulkData,
-            4 => IoChunkType4::OptionalBulkData,
-            5 => IoChunkType4::MemoryMappedBulkData,
-            6 => IoChunkType4::LoaderGlobalMeta,
-            7 => IoChunkType4::LoaderInitialLoadMeta,
-            8 => IoChunkType4::LoaderGlobalNames,
-            9 => IoChunkType4::LoaderGlobalNameHashes,
-            10 => IoChunkType4::ContainerHeader,
-            _ => panic!("Invalid type {} for IoChunkType4", value)
+            1 => Ok(IoChunkType4::InstallManifest),
+            2 => Ok(IoChunkType4::ExportBundleData),
+            3 => Ok(IoChunkType4::BulkData),
+            4 => Ok(IoChunkType4::OptionalBulkData),
+            5 => Ok(IoChunkType4::MemoryMappedBulkData),
+            6 => Ok(IoChunkType4::LoaderGlobalMeta),
+            7 => Ok(IoChunkType4::LoaderInitialLoadMeta),
+            8 => Ok(IoChunkType4::LoaderGlobalNames),
+            9 => Ok(IoChunkType4::LoaderGlobalNameHashes),
+            10 => Ok(IoChunkType4::ContainerHeader),
+            _ => Err(EmulatorError::InvalidValue(format!("Invalid type {} for IoChunkType4", value)))
         }
     }
 }
@@ -327,23 +681,24 @@ pub enum IoChunkType5 {
     PackageResource // added in UE 5.2
 }
 
-impl From<u8> for IoChunkType5 {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for IoChunkType5 {
+    type Error = EmulatorError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            1 => IoChunkType5::ExportBundleData,
-            2 => IoChunkType5::BulkData,
-            3 => IoChunkType5::OptionalBulkData,
-            4 => IoChunkType5::MemoryMappedBulkData,
-            5 => IoChunkType5::ScriptObjects,
-            6 => IoChunkType5::ContainerHeader,
-            7 => IoChunkType5::ExternalFile,
-            8 => IoChunkType5::ShaderCodeLibrary,
-            9 => IoChunkType5::ShaderCode,
-            10 => IoChunkType5::PackageStoreEntry,
-            11 => IoChunkType5::DerivedData,
-            12 => IoChunkType5::EditorDerivedData,
-            13 => IoChunkType5::PackageResource,
-            _ => panic!("Invalid type {} for IoChunkType4", value)
+            1 => Ok(IoChunkType5::ExportBundleData),
+            2 => Ok(IoChunkType5::BulkData),
+            3 => Ok(IoChunkType5::OptionalBulkData),
+            4 => Ok(IoChunkType5::MemoryMappedBulkData),
+            5 => Ok(IoChunkType5::ScriptObjects),
+            6 => Ok(IoChunkType5::ContainerHeader),
+            7 => Ok(IoChunkType5::ExternalFile),
+            8 => Ok(IoChunkType5::ShaderCodeLibrary),
+            9 => Ok(IoChunkType5::ShaderCode),
+            10 => Ok(IoChunkType5::PackageStoreEntry),
+            11 => Ok(IoChunkType5::DerivedData),
+            12 => Ok(IoChunkType5::EditorDerivedData),
+            13 => Ok(IoChunkType5::PackageResource),
+            _ => Err(EmulatorError::InvalidValue(format!("Invalid type {} for IoChunkType5", value)))
         }
     }
 }
@@ -369,12 +724,73 @@ impl From<IoChunkType5> for u8 {
     }
 }
 
+impl From<IoChunkType5> for IoChunkType4 {
+    // Chunk types that were added in UE5 don't have a UE4 equivalent, so they become Invalid
+    fn from(value: IoChunkType5) -> Self {
+        match value {
+            IoChunkType5::ExportBundleData => IoChunkType4::ExportBundleData,
+            IoChunkType5::BulkData => IoChunkType4::BulkData,
+            IoChunkType5::OptionalBulkData => IoChunkType4::OptionalBulkData,
+            IoChunkType5::MemoryMappedBulkData => IoChunkType4::MemoryMappedBulkData,
+            IoChunkType5::ContainerHeader => IoChunkType4::ContainerHeader,
+            _ => IoChunkType4::Invalid
+        }
+    }
+}
+
+impl From<IoChunkType4> for IoChunkType5 {
+    // UE5 removed the global loader chunks, which shifts the value of every chunk type after them
+    fn from(value: IoChunkType4) -> Self {
+        match value {
+            IoChunkType4::ExportBundleData => IoChunkType5::ExportBundleData,
+            IoChunkType4::BulkData => IoChunkType5::BulkData,
+            IoChunkType4::OptionalBulkData => IoChunkType5::OptionalBulkData,
+            IoChunkType4::MemoryMappedBulkData => IoChunkType5::MemoryMappedBulkData,
+            IoChunkType4::ContainerHeader => IoChunkType5::ContainerHeader,
+            _ => IoChunkType5::Invalid
+        }
+    }
+}
+
+pub const TOC_ENTRY_SERIALIZED_SIZE: usize = 0x16; // IoChunk1 (0xc) + IoOffsetAndLength (0xa)
+
 #[derive(Debug)]
 pub struct TocEntry { // For Unreal Engine 4.25
     chunk_id: IoChunk1,
     offset_length: IoOffsetAndLength
 }
 
+impl TocEntry {
+    pub fn new(chunk_id: IoChunk1, offset_length: IoOffsetAndLength) -> Self {
+        Self { chunk_id, offset_length }
+    }
+    pub fn to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        self.chunk_id.to_buffer::<W, E>(writer)?; // 0x0
+        self.offset_length.to_buffer::<W, E>(writer)?; // 0xc
+        Ok(())
+    }
+    pub fn list_to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(list: &[TocEntry], writer: &mut W) -> Result<(), Box<dyn Error>> {
+        for i in list {
+            i.to_buffer::<W, E>(writer)?;
+        }
+        Ok(())
+    }
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let chunk_id = IoChunk1::from_buffer::<R, E>(reader)?; // 0x0
+        let offset_length = IoOffsetAndLength::from_buffer::<R, E>(reader)?; // 0xc
+        Ok(Self { chunk_id, offset_length })
+    }
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, count: usize) -> Result<Vec<TocEntry>, Box<dyn Error>> {
+        (0..count).map(|_| TocEntry::from_buffer::<R, E>(reader)).collect()
+    }
+    pub fn get_chunk_id(&self) -> &IoChunk1 {
+        &self.chunk_id
+    }
+    pub fn get_offset_length(&self) -> &IoOffsetAndLength {
+        &self.offset_length
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord)]
 #[repr(C)]
 pub struct IoChunk1 { // For Unreal Engine 4.25
@@ -384,6 +800,31 @@ pub struct IoChunk1 { // For Unreal Engine 4.25
     obj_type: IoChunkType4
 }
 
+impl IoChunk1 {
+    // 4.25 package ids are the package's index in the game's global package store, not a hash of it's path
+    pub fn new(global_package_index: u32, chunk_index: u16, obj_type: IoChunkType4) -> Self {
+        Self { global_package_id: global_package_index, chunk_index, obj_type }
+    }
+    pub fn to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        writer.write_u32::<E>(self.global_package_id)?; // 0x0
+        writer.write_u16::<byteorder::BigEndian>(self.chunk_index)?; // 0x4 (network order)
+        writer.write_u8(self.obj_type.into())?; // 0x6
+        writer.write_all(&[0; 5])?; // 0x7: padding
+        Ok(())
+    }
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let global_package_id = reader.read_u32::<E>()?; // 0x0
+        let chunk_index = reader.read_u16::<byteorder::BigEndian>()?; // 0x4 (network order)
+        let obj_type = IoChunkType4::try_from(reader.read_u8()?)?; // 0x6
+        reader.seek(SeekFrom::Current(5))?; // 0x7: padding
+        Ok(Self { global_package_id, chunk_index, obj_type })
+    }
+    // The global package index is kept in the lower half of the hash
+    pub fn to_chunk_id(&self) -> IoChunkId {
+        IoChunkId { hash: self.global_package_id as u64, index: self.chunk_index, obj_type: self.obj_type }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord)]
 #[repr(C/* , align(4)*/)] // Unreal Engine 4.25+ onwards
 pub struct IoChunkId {
@@ -405,6 +846,11 @@ impl IoChunkId {
     pub fn new_from_hash(hash: u64, obj_type: IoChunkType4) -> Self {
         Self { hash, index: 0, obj_type }
     }
+    // The container header's chunk id is made from the id of the container that it's in
+    #[inline]
+    pub fn new_container_header(container_id: IoContainerId) -> Self {
+        Self::new_from_hash(container_id.get_value(), IoChunkType4::ContainerHeader)
+    }
     // TODO: split to_buffer off as a trait method
     pub fn to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
         writer.write_u64::<E>(self.hash)?; // 0x0
@@ -419,9 +865,52 @@ impl IoChunkId {
         }
         Ok(())
     }
+    // Unreal Engine 5.0+ uses the same layout, but chunk types are serialized as IoChunkType5
+    pub fn to_buffer5<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        writer.write_all(&self.to_bytes5::<E>())?;
+        Ok(())
+    }
+    pub fn list_to_buffer5<W: Write + Seek, E: byteorder::ByteOrder>(list: &[IoChunkId], writer: &mut W) -> Result<(), Box<dyn Error>> {
+        for i in list {
+            i.to_buffer5::<W, E>(writer)?;
+        }
+        Ok(())
+    }
+    // Serialized UE5 chunk id. This is also what's hashed to generate the TOC's perfect hash
+    pub fn to_bytes5<E: byteorder::ByteOrder>(&self) -> [u8; 0xc] {
+        let mut bytes = [0; 0xc];
+        E::write_u64(&mut bytes[0..8], self.hash); // 0x0
+        <byteorder::BigEndian as byteorder::ByteOrder>::write_u16(&mut bytes[8..10], self.index); // 0x8 (network order)
+        bytes[0xb] = IoChunkType5::from(self.obj_type).into(); // 0xb
+        bytes
+    }
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let hash = reader.read_u64::<E>()?; // 0x0
+        let index = reader.read_u16::<E>()?; // 0x8
+        reader.read_u8()?; // 0xa: padding
+        let obj_type = IoChunkType4::try_from(reader.read_u8()?)?; // 0xb
+        Ok(Self { hash, index, obj_type })
+    }
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, count: usize) -> Result<Vec<IoChunkId>, Box<dyn Error>> {
+        (0..count).map(|_| IoChunkId::from_buffer::<R, E>(reader)).collect()
+    }
+    // Returns the chunk id along with it's UE5 chunk type, since UE5 only chunk types can't be stored in IoChunkId
+    pub fn from_buffer5<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<(Self, IoChunkType5), Box<dyn Error>> {
+        let hash = reader.read_u64::<E>()?; // 0x0
+        let index = reader.read_u16::<byteorder::BigEndian>()?; // 0x8 (network order)
+        reader.read_u8()?; // 0xa: padding
+        let chunk_type = IoChunkType5::try_from(reader.read_u8()?)?; // 0xb
+        Ok((Self { hash, index, obj_type: chunk_type.into() }, chunk_type))
+    }
+    pub fn list_from_buffer5<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, count: usize) -> Result<Vec<(IoChunkId, IoChunkType5)>, Box<dyn Error>> {
+        (0..count).map(|_| IoChunkId::from_buffer5::<R, E>(reader)).collect()
+    }
     pub fn get_raw_hash(&self) -> u64 {
         self.hash
     }
+    pub fn get_index(&self) -> u16 {
+        self.index
+    }
     pub fn get_type(&self) -> IoChunkType4 {
         self.obj_type
     }
@@ -458,9 +947,161 @@ impl IoOffsetAndLength {
         }
         Ok(())
     }
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut data = [0; 0xa];
+        reader.read_exact(&mut data)?;
+        Ok(Self { data })
+    }
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, count: usize) -> Result<Vec<IoOffsetAndLength>, Box<dyn Error>> {
+        (0..count).map(|_| IoOffsetAndLength::from_buffer::<R, E>(reader)).collect()
+    }
+    // Both values are stored as 5 byte big endian integers
+    pub fn get_offset(&self) -> u64 {
+        self.data[0..5].iter().fold(0, |v, b| v << 8 | *b as u64)
+    }
+    pub fn get_length(&self) -> u64 {
+        self.data[5..10].iter().fold(0, |v, b| v << 8 | *b as u64)
+    }
 }
 
 // (UE 5 ONLY) Perfect Hash
+// Chunk ids are sorted into a table of size [chunk count] so that the runtime can find a chunk's TOC entry without searching through the
+// entire chunk list. Chunks are split into buckets (one per seed), then a seed is found for each bucket so that each chunk in that bucket
+// hashes into a unique slot.
+// - Seed > 0: chunk slot is hash(seed, chunk id) % chunk count
+// - Seed < 0: bucket only has one chunk, and it's slot is -seed - 1. Buckets that overflowed use -(chunk count) - 1, and their chunks are listed in
+//   the chunks without perfect hash list
+pub const PERFECT_HASH_MAX_ITERATIONS: u32 = 0x20000; // same as UE
+
+pub struct IoPerfectHash {
+    pub seeds: Vec<i32>,
+    pub chunks_without_perfect_hash: Vec<i32>, // slot indices of chunks that couldn't be placed with a seed
+    pub slots: Vec<usize> // new slot for each chunk, indexed by the chunk's original index
+}
+
+impl IoPerfectHash {
+    pub fn hash_chunk_id_with_seed(seed: i32, chunk_id: &[u8; 0xc]) -> u64 {
+        let mut hash: u64 = if seed != 0 { seed as u64 } else { 0xcbf29ce484222325 };
+        for i in chunk_id {
+            hash = hash.wrapping_mul(0x00000100000001B3) ^ *i as u64;
+        }
+        hash
+    }
+    // Generate seeds for every chunk id. If overflow isn't allowed (PerfectHash TOCs) and a bucket can't be placed, no seeds are written and
+    // the runtime falls back to searching the chunk list
+    pub fn new<E: byteorder::ByteOrder>(chunk_ids: &[IoChunkId], allow_overflow: bool) -> Self {
+        let chunk_count = chunk_ids.len();
+        let seed_count = std::cmp::max(1, (chunk_count as f64 / 2.0).round() as usize);
+        let chunk_bytes: Vec<[u8; 0xc]> = chunk_ids.iter().map(|id| id.to_bytes5::<E>()).collect();
+        let mut buckets: Vec<Vec<usize>> = vec![vec![]; seed_count];
+        for (i, v) in chunk_bytes.iter().enumerate() {
+            buckets[(Self::hash_chunk_id_with_seed(0, v) % seed_count as u64) as usize].push(i);
+        }
+        let mut seeds = vec![0; seed_count];
+        let mut slots = vec![usize::MAX; chunk_count];
+        let mut free_slots = vec![true; chunk_count];
+        let mut overflow = vec![];
+        // Place the largest buckets first since they're the hardest to find a seed for
+        let mut bucket_order: Vec<usize> = (0..seed_count).collect();
+        bucket_order.sort_by(|a, b| buckets[*b].len().cmp(&buckets[*a].len()));
+        for bucket_index in &bucket_order {
+            let bucket = &buckets[*bucket_index];
+            if bucket.len() <= 1 {
+                break;
+            }
+            let mut bucket_slots = Vec::with_capacity(bucket.len());
+            let mut found_seed = None;
+            for seed in 1..PERFECT_HASH_MAX_ITERATIONS {
+                bucket_slots.clear();
+                let fits = bucket.iter().all(|chunk| {
+                    let slot = (Self::hash_chunk_id_with_seed(seed as i32, &chunk_bytes[*chunk]) % chunk_count as u64) as usize;
+                    let is_free = free_slots[slot] && !bucket_slots.contains(&slot);
+                    bucket_slots.push(slot);
+                    is_free
+                });
+                if fits {
+                    found_seed = Some(seed as i32);
+                    break;
+                }
+            }
+            match found_seed {
+                Some(seed) => {
+                    seeds[*bucket_index] = seed;
+                    for (chunk, slot) in bucket.iter().zip(bucket_slots.iter()) {
+                        slots[*chunk] = *slot;
+                        free_slots[*slot] = false;
+                    }
+                },
+                None if !allow_overflow => return Self::new_without_perfect_hash(chunk_count),
+                None => {
+                    seeds[*bucket_index] = -(chunk_count as i32) - 1;
+                    overflow.extend_from_slice(bucket);
+                }
+            }
+        }
+        // Buckets with a single chunk point straight to a free slot
+        let mut next_free_slot = 0;
+        for bucket_index in &bucket_order {
+            let bucket = &buckets[*bucket_index];
+            if bucket.len() != 1 {
+                continue;
+            }
+            while !free_slots[next_free_slot] { next_free_slot += 1; }
+            seeds[*bucket_index] = -(next_free_slot as i32) - 1;
+            slots[bucket[0]] = next_free_slot;
+            free_slots[next_free_slot] = false;
+        }
+        // Overflowed chunks go in the remaining slots
+        overflow.sort();
+        let mut chunks_without_perfect_hash = Vec::with_capacity(overflow.len());
+        for chunk in overflow {
+            while !free_slots[next_free_slot] { next_free_slot += 1; }
+            slots[chunk] = next_free_slot;
+            free_slots[next_free_slot] = false;
+            chunks_without_perfect_hash.push(next_free_slot as i32);
+        }
+        Self { seeds, chunks_without_perfect_hash, slots }
+    }
+    fn new_without_perfect_hash(chunk_count: usize) -> Self {
+        Self { seeds: vec![], chunks_without_perfect_hash: vec![], slots: (0..chunk_count).collect() }
+    }
+    // Move each item into it's perfect hash slot
+    pub fn reorder<T>(&self, list: Vec<T>) -> Vec<T> {
+        let mut ordered: Vec<Option<T>> = (0..list.len()).map(|_| None).collect();
+        for (i, v) in list.into_iter().enumerate() {
+            ordered[self.slots[i]] = Some(v);
+        }
+        ordered.into_iter().map(|v| v.unwrap()).collect()
+    }
+    pub fn list_to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(list: &[i32], writer: &mut W) -> Result<(), Box<dyn Error>> {
+        for i in list {
+            writer.write_i32::<E>(*i)?;
+        }
+        Ok(())
+    }
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, count: usize) -> Result<Vec<i32>, Box<dyn Error>> {
+        (0..count).map(|_| Ok(reader.read_i32::<E>()?)).collect()
+    }
+    // Find a chunk's slot using the seeds from a TOC, the same way that the runtime does. Chunks in an overflowing bucket are searched for in
+    // the chunks without perfect hash list. The caller still has to check that the chunk in the returned slot is the one it's looking for
+    pub fn get_slot(seeds: &[i32], chunks_without_perfect_hash: &[i32], chunk_ids: &[[u8; 0xc]], chunk_id: &[u8; 0xc]) -> Option<usize> {
+        if seeds.is_empty() || chunk_ids.is_empty() {
+            return None;
+        }
+        let seed = seeds[(Self::hash_chunk_id_with_seed(0, chunk_id) % seeds.len() as u64) as usize];
+        if seed == 0 {
+            return None;
+        }
+        if seed > 0 {
+            return Some((Self::hash_chunk_id_with_seed(seed, chunk_id) % chunk_ids.len() as u64) as usize);
+        }
+        let slot = (-seed - 1) as usize;
+        if slot < chunk_ids.len() {
+            return Some(slot);
+        }
+        chunks_without_perfect_hash.iter().map(|s| *s as usize).find(|s| chunk_ids.get(*s) == Some(chunk_id))
+    }
+}
 
 // IO Compression Blocks
 #[derive(Debug)]
@@ -471,16 +1112,18 @@ pub struct IoStoreTocCompressedBlockEntry {
 }
 
 impl IoStoreTocCompressedBlockEntry {
+    // Uncompressed block. The offset uses all 5 bytes, since partitioned containers put blocks past 4GB
     pub fn new(offset: u64, length: u32) -> Self {
-        type ByteBlock = Cursor<[u8; 0xc]>;
-        type E = byteorder::NativeEndian;
-        let mut byte_builder = Cursor::new([0; 0xc]);
-        byte_builder.write_u32::<E>(offset as u32).unwrap();
-        byte_builder.seek(SeekFrom::Current(1)); // 0x4
-        let cmp_size = &length.to_ne_bytes()[0..3];
-        byte_builder.write_all(cmp_size).unwrap(); // cmp_size
-        byte_builder.write_all(cmp_size).unwrap(); // decmp_size
-        Self { data: byte_builder.into_inner() }
+        Self::new_compressed(offset, length, length, 0)
+    }
+    // Block that's been compressed using the compression method at method_index (see CompressionMethod::list_to_buffer)
+    pub fn new_compressed(offset: u64, compressed_size: u32, uncompressed_size: u32, method_index: u8) -> Self {
+        let mut data = [0; 0xc];
+        data[0..5].copy_from_slice(&offset.to_le_bytes()[0..5]);
+        data[5..8].copy_from_slice(&compressed_size.to_le_bytes()[0..3]);
+        data[8..11].copy_from_slice(&uncompressed_size.to_le_bytes()[0..3]);
+        data[11] = method_index;
+        Self { data }
     }
     pub fn to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
         writer.write_all(self.data.as_slice())?;
@@ -492,6 +1135,39 @@ impl IoStoreTocCompressedBlockEntry {
         }
         Ok(())
     }
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut data = [0; 0xc];
+        reader.read_exact(&mut data)?;
+        Ok(Self { data })
+    }
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, count: usize) -> Result<Vec<IoStoreTocCompressedBlockEntry>, Box<dyn Error>> {
+        (0..count).map(|_| IoStoreTocCompressedBlockEntry::from_buffer::<R, E>(reader)).collect()
+    }
+    // Offset and sizes are stored as little endian 5 and 3 byte integers
+    pub fn get_offset(&self) -> u64 {
+        self.data[0..5].iter().rev().fold(0, |v, b| v << 8 | *b as u64)
+    }
+    pub fn set_offset(&mut self, offset: u64) {
+        self.data[0..5].copy_from_slice(&offset.to_le_bytes()[0..5]);
+    }
+    pub fn get_compressed_size(&self) -> u32 {
+        self.data[5..8].iter().rev().fold(0, |v, b| v << 8 | *b as u32)
+    }
+    pub fn get_uncompressed_size(&self) -> u32 {
+        self.data[8..11].iter().rev().fold(0, |v, b| v << 8 | *b as u32)
+    }
+    // Index into the TOC's compression method names, where 0 is uncompressed
+    pub fn get_compression_method(&self) -> u8 {
+        self.data[11]
+    }
+    // Size of the block in the container. Blocks are padded to the AES block size, even when the container isn't encrypted
+    pub fn get_disk_size(&self) -> u64 {
+        (self.get_compressed_size() as u64).div_ceil(0x10) * 0x10
+    }
+    // Signed containers store a SHA1 hash of each block's data on disk (after it's been compressed and encrypted, including padding)
+    pub fn hash_block(data: &[u8]) -> [u8; 0x14] {
+        Sha1::digest(data).into()
+    }
 }
 
 // (usually, compression info and signature data would be included here, but we have no reason to
@@ -525,6 +1201,17 @@ impl IoDirectoryIndexEntry {
         }
         Ok(())
     }
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let name = reader.read_u32::<E>()?;
+        let first_child = reader.read_u32::<E>()?;
+        let next_sibling = reader.read_u32::<E>()?;
+        let first_file = reader.read_u32::<E>()?;
+        Ok(Self { name, first_child, next_sibling, first_file })
+    }
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Vec<IoDirectoryIndexEntry>, Box<dyn Error>> {
+        let count = reader.read_u32::<E>()?;
+        (0..count).map(|_| IoDirectoryIndexEntry::from_buffer::<R, E>(reader)).collect()
+    }
 }
 
 pub const IO_FILE_INDEX_ENTRY_SERIALIZED_SIZE: usize = 0xc;
@@ -540,6 +1227,8 @@ pub struct IoFileIndexEntry {
     pub file_size: u64,
     pub os_path: String,
     pub hash_path: String,
+    pub mod_id: String,
+    pub cooked_package: bool, // os_path is a cooked package, which is converted when the container is laid out
 }
 
 impl IoFileIndexEntry {
@@ -557,6 +1246,17 @@ impl IoFileIndexEntry {
         }
         Ok(())
     }
+    // Fields that aren't serialized are left empty
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let name = reader.read_u32::<E>()?;
+        let next_file = reader.read_u32::<E>()?;
+        let user_data = reader.read_u32::<E>()?;
+        Ok(Self { name, next_file, user_data, file_size: 0, os_path: String::new(), hash_path: String::new(), mod_id: String::new(), cooked_package: false })
+    }
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Vec<IoFileIndexEntry>, Box<dyn Error>> {
+        let count = reader.read_u32::<E>()?;
+        (0..count).map(|_| IoFileIndexEntry::from_buffer::<R, E>(reader)).collect()
+    }
 }
 
 pub struct IoStringPool;
@@ -569,15 +1269,30 @@ impl IoStringPool {
         }
         Ok(())
     }
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Vec<String>, Box<dyn Error>> {
+        let count = reader.read_u32::<E>()?;
+        (0..count).map(|_| Ok(FString32NoHash::from_buffer::<R, E>(reader)?.unwrap_or_default())).collect()
+    }
 }
 
 // NON NATIVE - REQUIRES SERIALIZATION
 #[allow(dead_code)]
 pub struct IoFileResource {
-    mount_point: String,
-    directory_entries: Vec<IoDirectoryIndexEntry>,
-    file_entries: Vec<IoFileIndexEntry>,
-    strings: Vec<String>
+    pub mount_point: String,
+    pub directory_entries: Vec<IoDirectoryIndexEntry>,
+    pub file_entries: Vec<IoFileIndexEntry>,
+    pub strings: Vec<String>
+}
+
+impl IoFileResource {
+    // FIoDirectoryIndexResource: mount point, directories, files and strings
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mount_point = FString32NoHash::from_buffer::<R, E>(reader)?.unwrap_or_default();
+        let directory_entries = IoDirectoryIndexEntry::list_from_buffer::<R, E>(reader)?;
+        let file_entries = IoFileIndexEntry::list_from_buffer::<R, E>(reader)?;
+        let strings = IoStringPool::list_from_buffer::<R, E>(reader)?;
+        Ok(Self { mount_point, directory_entries, file_entries, strings })
+    }
 }
 
 // META (WIP)
@@ -595,21 +1310,20 @@ impl IoStoreTocEntryMeta {
     pub fn new_empty() -> Self {
         Self::new_inner([0; 0x20])
     }
-    // Used for situations where it's required to generate a new TOC. This requires reading the entire contents of every file and creating a SHA1
-    // hash for it, which is a very slow operation. If this is *required* for any game, a cache will be created to store previously calculated hashes
+    // Hashing a chunk requires reading all of it, which is slow for files on disk, so those are hashed through hash_cache instead
     // (I tested this on a blank UE project and had a serialization time of about 130 ms for 1 file)
     #[cfg(feature = "hash_meta")]
-    pub fn new_with_hash(buffer: &Vec<u8>) -> Self {
-        let mut hasher = Sha1::new();
-        hasher.update(buffer);
-        let mut data: Cursor<[u8; 0x20]> = Cursor::new([0; 0x20]);
-        data.write_all(&hasher.finalize().to_vec());
-        let hash = data.into_inner();
+    pub fn new_with_hash(buffer: &[u8]) -> Self {
+        Self::new_from_hash(&Sha1::digest(buffer).into())
+    }
+    // FIoChunkHash is 32 bytes, but only the first 20 are used for the SHA1 hash
+    pub fn new_from_hash(sha1: &[u8; 0x14]) -> Self {
+        let mut hash = [0; 0x20];
+        hash[..0x14].copy_from_slice(sha1);
         Self::new_inner(hash)
     }
     #[inline]
     fn new_inner(hash: [u8; 32]) -> Self {
-        let hash = [0; 0x20];
         let flags = 0;
         Self { hash, flags }
     }
@@ -625,18 +1339,129 @@ impl IoStoreTocEntryMeta {
         }
         Ok(())
     }
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut hash = [0; 0x20];
+        reader.read_exact(&mut hash)?;
+        let flags = reader.read_u8()?;
+        Ok(Self { hash, flags })
+    }
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, count: usize) -> Result<Vec<IoStoreTocEntryMeta>, Box<dyn Error>> {
+        (0..count).map(|_| IoStoreTocEntryMeta::from_buffer::<R, E>(reader)).collect()
+    }
+    pub fn get_hash(&self) -> &[u8; 0x20] {
+        &self.hash
+    }
+    pub fn get_flags(&self) -> u8 {
+        self.flags
+    }
+    // FIoStoreTocEntryMetaFlags::Compressed, for chunks that have at least one compressed block
+    pub fn set_compressed(&mut self) {
+        self.flags |= 1 << 0;
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+#[repr(i32)]
+#[allow(dead_code)]
+pub enum IoContainerHeaderVersion {
+    BeforeVersionWasAdded = -1, // UE 4.25+ - 4.27
+    Initial, 
+    LocalizedPackages, // UE 5.0
+    OptionalSegmentPackages, // UE 5.1, 5.2
+    NoExportInfo // UE 5.3
 }
 
+impl IoContainerHeaderVersion {
+    // Container header version used by a given UE5 minor version
+    pub fn from_engine_minor_version(minor: u32) -> Self {
+        match minor {
+            0 => IoContainerHeaderVersion::LocalizedPackages,
+            1 | 2 => IoContainerHeaderVersion::OptionalSegmentPackages,
+            _ => IoContainerHeaderVersion::NoExportInfo
+        }
+    }
+}
+
+impl TryFrom<i32> for IoContainerHeaderVersion {
+    type Error = EmulatorError;
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            -1 => Ok(IoContainerHeaderVersion::BeforeVersionWasAdded),
+            0 => Ok(IoContainerHeaderVersion::Initial),
+            1 => Ok(IoContainerHeaderVersion::LocalizedPackages),
+            2 => Ok(IoContainerHeaderVersion::OptionalSegmentPackages),
+            3 => Ok(IoContainerHeaderVersion::NoExportInfo),
+            _ => Err(EmulatorError::UnsupportedVersion(format!("Cannot create an IoContainerHeaderVersion from value {}", value)))
+        }
+    }
+}
+
+pub const IO_CONTAINER_HEADER_SIGNATURE: u32 = 0x496f436e;
+
 pub struct ContainerHeader {
-    container_id: u64,
+    container_id: IoContainerId,
+    version: IoContainerHeaderVersion,
     pub packages: Vec<crate::io_package::ContainerHeaderPackage>,
 }
 impl ContainerHeader {
     // Write package header data into ucas
-    pub fn new(container_id: u64) -> Self {
-        Self { container_id, packages: vec![] }
+    pub fn new(container_id: IoContainerId) -> Self {
+        Self { container_id, version: IoContainerHeaderVersion::BeforeVersionWasAdded, packages: vec![] }
+    }
+    pub fn new_with_version(container_id: IoContainerId, version: IoContainerHeaderVersion) -> Self {
+        Self { container_id, version, packages: vec![] }
+    }
+    // Read the package ids and store entries from a container header. Name maps, localized packages and redirects aren't kept
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let start = reader.stream_position()?;
+        if reader.read_u32::<E>()? == IO_CONTAINER_HEADER_SIGNATURE {
+            let version = IoContainerHeaderVersion::try_from(reader.read_i32::<E>()?)?;
+            let container_id = IoContainerId::from_buffer::<R, E>(reader)?;
+            let package_ids = Self::package_ids_from_buffer::<R, E>(reader)?;
+            return Self::store_entries_from_buffer::<R, E>(reader, container_id, version, package_ids);
+        }
+        reader.seek(SeekFrom::Start(start))?;
+        let container_id = IoContainerId::from_buffer::<R, E>(reader)?;
+        reader.read_u32::<E>()?; // package count
+        let names_size = reader.read_u32::<E>()?;
+        reader.seek(SeekFrom::Current(names_size as i64))?; // TArray<u8> Names
+        let name_hashes_size = reader.read_u32::<E>()?;
+        reader.seek(SeekFrom::Current(name_hashes_size as i64))?; // TArray<u8> NameHashes
+        let package_ids = Self::package_ids_from_buffer::<R, E>(reader)?;
+        Self::store_entries_from_buffer::<R, E>(reader, container_id, IoContainerHeaderVersion::BeforeVersionWasAdded, package_ids)
+    }
+    fn package_ids_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Vec<u64>, Box<dyn Error>> {
+        let count = reader.read_u32::<E>()?;
+        Ok((0..count).map(|_| reader.read_u64::<E>()).collect::<Result<Vec<u64>, _>>()?)
+    }
+    // Store entries are in the same order as package ids, and the imports they point to are stored after them in the same buffer
+    fn store_entries_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(
+        reader: &mut R, container_id: IoContainerId, version: IoContainerHeaderVersion, package_ids: Vec<u64>
+    ) -> Result<Self, Box<dyn Error>> {
+        let store_entries_size = reader.read_u32::<E>()?;
+        let mut store_entries = vec![0; store_entries_size as usize];
+        reader.read_exact(&mut store_entries)?;
+        let mut store_entry_reader = Cursor::new(store_entries);
+        let mut packages = Vec::with_capacity(package_ids.len());
+        for hash in package_ids {
+            packages.push(match version {
+                IoContainerHeaderVersion::BeforeVersionWasAdded =>
+                    crate::io_package::ContainerHeaderPackage::from_buffer_store_entry::<_, E>(&mut store_entry_reader, hash)?,
+                _ => crate::io_package::ContainerHeaderPackage::from_buffer_store_entry5::<_, E>(&mut store_entry_reader, hash, version)?
+            });
+        }
+        Ok(Self { container_id, version, packages })
+    }
+    pub fn get_container_id(&self) -> IoContainerId {
+        self.container_id
+    }
+    pub fn get_version(&self) -> IoContainerHeaderVersion {
+        self.version
     }
     pub fn to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<Vec<u8>, Box<dyn Error>> {
+        if self.version != IoContainerHeaderVersion::BeforeVersionWasAdded {
+            return self.to_buffer5::<W, E>(writer);
+        }
         // Container Header:
         // - ContainerId + Package Name Count (number of ExportBundleData)
         // - Names array - empty
@@ -647,7 +1472,7 @@ impl ContainerHeader {
         // Package Redirects - empty
         // Padding to align it to nearest 0x10
         let mut container_header_writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
-        container_header_writer.write_u64::<E>(self.container_id)?;
+        self.container_id.to_buffer::<Cursor<Vec<u8>>, E>(&mut container_header_writer)?;
         container_header_writer.write_u32::<E>(self.packages.len() as u32)?;
         // TODO: Implement TArray to_buffer
         container_header_writer.write_u32::<E>(0)?; // TArray<u8> Names
@@ -676,4 +1501,84 @@ impl ContainerHeader {
         //writer.write(&[0x0])?;
         Ok(serialized)
     }
-}
\ No newline at end of file
+
+    fn to_buffer5<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<Vec<u8>, Box<dyn Error>> {
+        // Container Header (UE5):
+        // - Signature + Version + ContainerId
+        // - Package Ids - hashes of each export bundle
+        // - Store Entries - FFilePackageStoreEntry for each export bundle, followed by imported package ids
+        // - Optional Segment Package Ids + Store Entries (5.1+) - empty
+        // - Redirects Name Map - empty name batch
+        // - Localized Packages - empty
+        // - Package Redirects - empty
+        let mut container_header_writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        container_header_writer.write_u32::<E>(IO_CONTAINER_HEADER_SIGNATURE)?;
+        container_header_writer.write_i32::<E>(self.version as i32)?;
+        self.container_id.to_buffer::<Cursor<Vec<u8>>, E>(&mut container_header_writer)?;
+        container_header_writer.write_u32::<E>(self.packages.len() as u32)?; // TArray<FPackageId> PackageIds
+        for i in &self.packages {
+            container_header_writer.write_u64::<E>(i.hash)?;
+        }
+        let import_list_base_offset = crate::io_package::ContainerHeaderPackage::get_store_entry_size(self.version) * self.packages.len() as u64;
+        let mut import_list_already_written_offset = 0;
+        let mut store_entry_writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        for i in &self.packages {
+            i.to_buffer_store_entry5::<Cursor<Vec<u8>>, E>(&mut store_entry_writer, self.version, import_list_base_offset, &mut import_list_already_written_offset)?;
+        }
+        let store_entry_writer = store_entry_writer.into_inner();
+        container_header_writer.write_u32::<E>(store_entry_writer.len() as u32)?; // TArray<u8> StoreEntries
+        container_header_writer.write_all(&store_entry_writer)?;
+        if self.version >= IoContainerHeaderVersion::OptionalSegmentPackages {
+            container_header_writer.write_u32::<E>(0)?; // OptionalSegmentPackageIds
+            container_header_writer.write_u32::<E>(0)?; // OptionalSegmentStoreEntries
+        }
+        container_header_writer.write_u32::<E>(0)?; // RedirectsNameMap
+        container_header_writer.write_u32::<E>(0)?; // LocalizedPackages
+        container_header_writer.write_u32::<E>(0)?; // PackageRedirects
+        let serialized = container_header_writer.into_inner();
+        writer.write_all(&serialized)?;
+        Ok(serialized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_chunk_ids(count: usize) -> Vec<IoChunkId> {
+        (0..count).map(|i| IoChunkId::new(&format!("/Game/Test/Asset_{}", i), IoChunkType4::ExportBundleData)).collect()
+    }
+
+    #[test]
+    fn hash_chunk_id_with_seed_matches_ue() {
+        // FIoStoreTocResource::HashChunkIdWithSeed. A seed of 0 uses the FNV offset basis, and negative seeds are sign extended
+        let chunk_id: [u8; 0xc] = std::array::from_fn(|i| i as u8);
+        assert_eq!(IoPerfectHash::hash_chunk_id_with_seed(0, &chunk_id), 0xf0cbbfcb24ef5661);
+        assert_eq!(IoPerfectHash::hash_chunk_id_with_seed(7, &chunk_id), 0x67001524d5c38b23);
+        assert_eq!(IoPerfectHash::hash_chunk_id_with_seed(-3, &chunk_id), 0x46fac41045c5e4b9);
+    }
+
+    #[test]
+    fn every_chunk_is_found_in_its_slot() {
+        for count in [1, 2, 3, 10, 257] {
+            let chunk_ids = get_chunk_ids(count);
+            let perfect_hash = IoPerfectHash::new::<byteorder::LittleEndian>(&chunk_ids, true);
+            assert_eq!(perfect_hash.seeds.len(), std::cmp::max(1, (count as f64 / 2.0).round() as usize));
+            let chunk_bytes: Vec<[u8; 0xc]> = chunk_ids.iter().map(|id| id.to_bytes5::<byteorder::LittleEndian>()).collect();
+            let ordered = perfect_hash.reorder(chunk_bytes.clone());
+            for (i, bytes) in chunk_bytes.iter().enumerate() {
+                let slot = IoPerfectHash::get_slot(&perfect_hash.seeds, &perfect_hash.chunks_without_perfect_hash, &ordered, bytes);
+                assert_eq!(slot, Some(perfect_hash.slots[i]));
+                assert_eq!(&ordered[perfect_hash.slots[i]], bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn slots_are_unique() {
+        let perfect_hash = IoPerfectHash::new::<byteorder::LittleEndian>(&get_chunk_ids(100), false);
+        let mut slots = perfect_hash.slots.clone();
+        slots.sort();
+        assert_eq!(slots, (0..100).collect::<Vec<usize>>());
+    }
+}
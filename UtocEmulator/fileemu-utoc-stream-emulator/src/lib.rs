@@ -2,10 +2,20 @@
 //! Here's the crate documentation.
 
 pub mod asset_collector; // Building tree of directories/files
+pub mod compression; // Compressing and decompressing container blocks
+pub mod conflict_report; // Which mod won each file that more than one mod contains
+pub mod encryption; // AES keys for encrypted containers
+pub mod error; // Errors returned across FFI
+#[cfg(feature = "hash_meta")]
+pub mod hash_cache; // Cached SHA1 hashes of chunk data for TOC metas
 pub mod exports; // FFI (called from C#)
+pub mod hot_reload; // Rebuild the TOC when mod files change
 pub mod io_package; // Handling IO Store packages
 pub mod io_toc; // Types for IO Store Table of Contents
+pub mod pak_factory; // Build PAK for loose files that can't go into IO Store
 pub mod pak_package; // Handling cooked packages (WIP)
 pub mod toc_factory; // Build IO Store TOC
+pub mod toc_reader; // Read existing IO Store TOCs
+pub mod toc_unpacker; // Extract files from existing IO Store containers
 pub mod platform; // Platform agnostic abstractions
 pub mod string; // Unreal serialized string types
\ No newline at end of file
@@ -0,0 +1,467 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use sha1::{Sha1, Digest};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs::File,
+    io, io::{Cursor, Read, Seek, Write},
+    path::PathBuf,
+    sync::Arc
+};
+use crate::{
+    asset_collector::{MOUNT_POINT, PAK_ROOT_DIRECTORY, TocDirectorySyncRef},
+    error::{EmulatorError, EmulatorResult},
+    toc_factory::write_padding,
+    string::{FString32NoHash, FStringSerializer, FStringSerializerExpectedLength}
+};
+
+// Loose files that can't be stored in IO Store (sound banks, movies, fonts, config files...) are served from a PAK that's emulated
+// alongside the UTOC. Unreal requires every IO Store container to have a PAK with the same name anyway, so this takes the place of the
+// dummy PAK when a mod contains those files.
+// The PAK is laid out as:
+//  - For each file, an FPakEntry header followed by the file's contents
+//  - The primary index (mount point + entries). From PathHashIndex onwards, this is followed by the path hash index and full directory index
+//  - FPakInfo (footer)
+// Only the PAK versions that shipped alongside IO Store are supported (FrozenIndex for 4.25, Fn64BugFix for 4.25+ onwards)
+pub const TARGET_PAK: &str = "UnrealEssentials.pak";
+
+// Must be kept in sync with PakType in UTOC.Stream.Emulator.Interfaces/Structures.cs
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PakType {
+    NoTimestamps = 1,
+    CompressionEncryption,
+    IndexEncryption,
+    RelativeChunkOffsets,
+    EncryptionKeyGuid,
+    FNameBasedCompressionA,
+    FNameBasedCompressionB,
+    FrozenIndex,
+    Fn64BugFix
+}
+
+impl TryFrom<u32> for PakType {
+    type Error = EmulatorError;
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(PakType::NoTimestamps),
+            2 => Ok(PakType::CompressionEncryption),
+            3 => Ok(PakType::IndexEncryption),
+            4 => Ok(PakType::RelativeChunkOffsets),
+            5 => Ok(PakType::EncryptionKeyGuid),
+            6 => Ok(PakType::FNameBasedCompressionA),
+            7 => Ok(PakType::FNameBasedCompressionB),
+            8 => Ok(PakType::FrozenIndex),
+            9 => Ok(PakType::Fn64BugFix),
+            v => Err(EmulatorError::UnsupportedVersion(format!("Unknown PAK type {}", v)))
+        }
+    }
+}
+
+impl PakType {
+    // Version number that's written into FPakInfo (EPakFileVersion)
+    pub fn get_file_version(&self) -> i32 {
+        match self {
+            PakType::NoTimestamps => 2,
+            PakType::CompressionEncryption => 3,
+            PakType::IndexEncryption => 4,
+            PakType::RelativeChunkOffsets => 5,
+            PakType::EncryptionKeyGuid => 7,
+            PakType::FNameBasedCompressionA | PakType::FNameBasedCompressionB => 8,
+            PakType::FrozenIndex => 9,
+            PakType::Fn64BugFix => 11 // PathHashIndex (10) had a bug in it's path hashing and was only used internally
+        }
+    }
+    // From 4.26 onwards, file entries are encoded into a byte array, while file paths are looked up through a path hash index
+    // or a full directory index
+    pub fn has_path_hash_index(&self) -> bool {
+        *self > PakType::FrozenIndex
+    }
+}
+
+pub const PAK_FILE_MAGIC: u32 = 0x5A6F12E1;
+pub const PAK_COMPRESSION_METHOD_COUNT: usize = 5;
+pub const PAK_COMPRESSION_METHOD_NAME_LENGTH: usize = 32;
+pub const PAK_ENTRY_SERIALIZED_SIZE: u64 = 0x35;
+
+#[derive(Debug)]
+pub struct PakEntry {
+    offset: u64,
+    size: u64,
+    // SHA1 hash of the file's contents. The game never checks this when reading files (signed PAKs are verified with the
+    // .sig file's chunk hashes instead, and only UnrealPak -Test compares entry hashes), so it's left empty rather than reading
+    // every mod file while building the PAK, for the same reason as IoStoreTocEntryMeta
+    hash: [u8; 0x14]
+}
+
+impl PakEntry {
+    pub fn new(offset: u64, size: u64) -> Self {
+        Self { offset, size, hash: [0; 0x14] }
+    }
+
+    // FPakEntry::Serialize. Entry headers in front of file data always have an offset of 0
+    pub fn to_buffer<W: Write, E: byteorder::ByteOrder>(&self, writer: &mut W, in_index: bool) -> Result<(), Box<dyn Error>> {
+        writer.write_u64::<E>(if in_index { self.offset } else { 0 })?;
+        writer.write_u64::<E>(self.size)?; // size
+        writer.write_u64::<E>(self.size)?; // uncompressed size
+        writer.write_u32::<E>(0)?; // compression method index (0 is uncompressed)
+        writer.write_all(&self.hash)?;
+        // no compression blocks for uncompressed files
+        writer.write_u8(0)?; // flags
+        writer.write_u32::<E>(0)?; // compression block size
+        Ok(())
+    }
+
+    pub fn get_encoded_size(&self) -> u64 {
+        let offset_size = if self.offset <= u32::MAX as u64 { 4 } else { 8 };
+        let size_size = if self.size <= u32::MAX as u64 { 4 } else { 8 };
+        4 + offset_size + size_size
+    }
+
+    // FPakFile::EncodePakEntry. The flags contain the compression block size (0x0 - 0x5), compression block count (0x6 - 0x15),
+    // encrypted flag (0x16), compression method (0x17 - 0x1c) and whether size (0x1d), uncompressed size (0x1e) and offset (0x1f)
+    // fit inside of 32 bits
+    pub fn encode<W: Write, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let offset_32 = self.offset <= u32::MAX as u64;
+        let size_32 = self.size <= u32::MAX as u64;
+        let flags = (offset_32 as u32) << 31 | (size_32 as u32) << 30 | (size_32 as u32) << 29;
+        writer.write_u32::<E>(flags)?;
+        match offset_32 {
+            true => writer.write_u32::<E>(self.offset as u32)?,
+            false => writer.write_u64::<E>(self.offset)?
+        }
+        match size_32 { // compressed size is only written for compressed files
+            true => writer.write_u32::<E>(self.size as u32)?,
+            false => writer.write_u64::<E>(self.size)?
+        }
+        Ok(())
+    }
+}
+
+// A file that's been placed into the PAK, where path is relative to the mount point
+struct PakFile {
+    path: String,
+    os_path: String,
+    entry: PakEntry
+}
+
+// FPakFile::HashPath for Fn64BugFix: FNV64 of the lowercase UTF-16 path, using the PAK's seed
+fn hash_path(path: &str, seed: u64) -> u64 {
+    const FNV64_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV64_PRIME: u64 = 0x00000100000001b3;
+    let mut hash = FNV64_OFFSET.wrapping_add(seed);
+    for b in path.to_lowercase().encode_utf16().flat_map(u16::to_le_bytes) {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(FNV64_PRIME);
+    }
+    hash
+}
+
+// FCrc::StrCrc32, which runs CRC32 over each character as a 32-bit integer. Used to make the path hash seed from the PAK's file name
+fn str_crc32(value: &str) -> u32 {
+    let mut crc = !0u32;
+    for b in value.chars().flat_map(|c| (c as u32).to_le_bytes()) {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn hash_buffer(buffer: &[u8]) -> [u8; 0x14] {
+    let mut hasher = Sha1::new();
+    hasher.update(buffer);
+    hasher.finalize().into()
+}
+
+// Split a path relative to the mount point into it's directory (ending in a slash, or "/" for the mount point) and file name
+fn split_path_child(path: &str) -> Option<(&str, &str)> {
+    if path == "/" || path.is_empty() {
+        return None;
+    }
+    let path = path.strip_suffix('/').unwrap_or(path);
+    match path.rfind('/') {
+        Some(i) => Some(path.split_at(i + 1)),
+        None => Some(("/", path))
+    }
+}
+
+#[repr(C)]
+// Must be kept in sync with PakBlock in UtocEmulator.cs
+pub struct PakBlock {
+    os_path: usize, // 0x0 (null if the block is stored in memory)
+    data: usize, // 0x8 (null if the block is read from a file)
+    start: u64, // 0x10
+    length: u64 // 0x18
+}
+
+// Passed to the C# side as a handle that owns the data each PAK block points to, until FreePak is called
+pub struct PakData {
+    pub blocks: Vec<PakBlock>,
+    entry_headers: Vec<u8>,
+    index: Vec<u8>, // primary index, path hash index, full directory index and footer
+    os_paths: Vec<String>
+}
+
+impl PakData {
+    // Write the PAK to disk, for containers that are baked ahead of time instead of emulated
+    pub fn write_pak(&self, pak_path: &str) -> EmulatorResult<()> {
+        let map_err = |e| EmulatorError::Io(pak_path.to_owned(), e);
+        let mut writer = io::BufWriter::new(File::create(pak_path).map_err(map_err)?);
+        let mut position = 0;
+        let mut os_paths = self.os_paths.iter();
+        for block in &self.blocks {
+            position = write_padding(&mut writer, position, block.start).map_err(map_err)?;
+            if block.os_path == 0 {
+                // in memory blocks point into entry_headers or index, which this owns
+                let data = unsafe { std::slice::from_raw_parts(block.data as *const u8, block.length as usize) };
+                writer.write_all(data).map_err(map_err)?;
+            } else {
+                // file blocks are in the same order as their paths
+                let os_path = os_paths.next()
+                    .ok_or_else(|| EmulatorError::InvalidValue(format!("PAK block at 0x{:x} doesn't have a file path", block.start)))?
+                    .trim_end_matches('\0');
+                let file = File::open(os_path).map_err(|e| EmulatorError::Io(os_path.to_owned(), e))?;
+                let copied = io::copy(&mut file.take(block.length), &mut writer).map_err(map_err)?;
+                if copied != block.length {
+                    return Err(EmulatorError::InvalidAsset(os_path.to_owned(), "File was changed while the PAK was being written".to_owned()));
+                }
+            }
+            position += block.length;
+        }
+        writer.flush().map_err(map_err)
+    }
+}
+
+// Returns None if there aren't any files that need to go in the PAK, in which case the dummy PAK gets used instead
+pub fn build_pak(pak_path: &str, version: u32) -> EmulatorResult<Option<PakData>> {
+    let path_check = PathBuf::from(pak_path);
+    let file_name = path_check.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    if file_name != TARGET_PAK { // Not our target PAK
+        return Err(EmulatorError::InvalidArgument(format!("\"{}\" isn't the emulated PAK {}", pak_path, TARGET_PAK)));
+    }
+    let pak_type = PakType::try_from(version)?;
+    if pak_type < PakType::FrozenIndex {
+        return Err(EmulatorError::UnsupportedVersion(format!("PAK type {:?} is too old to be used alongside IO Store", pak_type)));
+    }
+    let root_dir_lock = PAK_ROOT_DIRECTORY.lock().unwrap();
+    let root = match (*root_dir_lock).as_ref() {
+        Some(r) => Arc::clone(r),
+        None => return Ok(None)
+    };
+    let mut files = vec![];
+    flatten_pak_tree(root, "", &mut files);
+    if files.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serialize_pak(&file_name.to_lowercase(), pak_type, files)?))
+}
+
+fn flatten_pak_tree(node: TocDirectorySyncRef, path: &str, files: &mut Vec<PakFile>) {
+    let mut curr_file = node.read().unwrap().first_file.as_ref().map(Arc::clone);
+    while let Some(file) = curr_file {
+        {
+            let file_lock = file.read().unwrap();
+            files.push(PakFile {
+                path: path.to_owned() + &file_lock.name,
+                os_path: file_lock.os_file_path.clone(),
+                entry: PakEntry::new(0, file_lock.file_size)
+            });
+        }
+        curr_file = file.read().unwrap().next.as_ref().map(Arc::clone);
+    }
+    let mut curr_dir = node.read().unwrap().first_child.as_ref().map(Arc::clone);
+    while let Some(dir) = curr_dir {
+        let dir_path = path.to_owned() + dir.read().unwrap().name.as_ref().unwrap() + "/";
+        flatten_pak_tree(Arc::clone(&dir), &dir_path, files);
+        curr_dir = dir.read().unwrap().next_sibling.as_ref().map(Arc::clone);
+    }
+}
+
+fn serialize_pak(pak_name: &str, pak_type: PakType, mut files: Vec<PakFile>) -> Result<PakData, Box<dyn Error>> {
+    // Each file's data is preceded by it's entry header
+    let mut entry_headers = Cursor::new(vec![]);
+    let mut pak_pointer = 0;
+    for file in &mut files {
+        file.entry.offset = pak_pointer;
+        file.entry.to_buffer::<_, LittleEndian>(&mut entry_headers, false)?;
+        pak_pointer += PAK_ENTRY_SERIALIZED_SIZE + file.entry.size;
+    }
+    let index_offset = pak_pointer;
+    let mut index = Cursor::new(vec![]);
+    match pak_type.has_path_hash_index() {
+        true => write_path_hash_index(&mut index, pak_name, index_offset, &files)?,
+        false => write_legacy_index(&mut index, &files)?
+    };
+    // Index size and hash only cover the primary index
+    let index_size = match pak_type.has_path_hash_index() {
+        true => primary_index_size(&files),
+        false => index.position()
+    };
+    let index_hash = hash_buffer(&index.get_ref()[..index_size as usize]);
+    write_footer(&mut index, pak_type, index_offset, index_size, &index_hash)?;
+    // Blocks can be created now that the buffers they point to won't move
+    let entry_headers = entry_headers.into_inner();
+    let index = index.into_inner();
+    let os_paths: Vec<String> = files.iter().map(|f| f.os_path.clone() + "\0").collect(); // make C formatted string
+    let mut blocks = Vec::with_capacity(files.len() * 2 + 1);
+    for (i, file) in files.iter().enumerate() {
+        let header_start = i as u64 * PAK_ENTRY_SERIALIZED_SIZE;
+        blocks.push(PakBlock {
+            os_path: 0, data: entry_headers[header_start as usize..].as_ptr() as usize,
+            start: file.entry.offset, length: PAK_ENTRY_SERIALIZED_SIZE
+        });
+        blocks.push(PakBlock {
+            os_path: os_paths[i].as_ptr() as usize, data: 0,
+            start: file.entry.offset + PAK_ENTRY_SERIALIZED_SIZE, length: file.entry.size
+        });
+    }
+    blocks.push(PakBlock { os_path: 0, data: index.as_ptr() as usize, start: index_offset, length: index.len() as u64 });
+    Ok(PakData { blocks, entry_headers, index, os_paths })
+}
+
+// FrozenIndex: mount point, followed by the relative path and entry for each file
+fn write_legacy_index<W: Write + Seek>(writer: &mut W, files: &[PakFile]) -> Result<(), Box<dyn Error>> {
+    FString32NoHash::to_buffer::<W, LittleEndian>(MOUNT_POINT, writer)?;
+    writer.write_u32::<LittleEndian>(files.len() as u32)?;
+    for file in files {
+        FString32NoHash::to_buffer::<W, LittleEndian>(&file.path, writer)?;
+        file.entry.to_buffer::<W, LittleEndian>(writer, true)?;
+    }
+    Ok(())
+}
+
+// Mount point, entry count, seed, 2 * (has index, offset, size, hash), encoded entries, non-encoded entry count
+// Every entry is uncompressed, so each encoded entry is a u32 of flags followed by either a u32 or u64 offset and size
+fn primary_index_size(files: &[PakFile]) -> u64 {
+    let encoded_entries_size: u64 = files.iter().map(|f| f.entry.get_encoded_size()).sum();
+    FString32NoHash::get_expected_length(MOUNT_POINT) + 4 + 8 + 2 * (4 + 8 + 8 + 0x14) + 4 + encoded_entries_size + 4
+}
+
+// PathHashIndex onwards: the primary index contains the encoded entries, which are located using either the path hash index (hash of the path
+// relative to the mount point) or the full directory index (directory -> file name). Both are written so the game can use whichever it's configured for.
+// Their offsets are absolute, and they're placed directly after the primary index.
+fn write_path_hash_index<W: Write + Seek>(writer: &mut W, pak_name: &str, index_offset: u64, files: &[PakFile]) -> Result<(), Box<dyn Error>> {
+    let seed = str_crc32(pak_name) as u64;
+    let mut encoded_entries = Cursor::new(vec![]);
+    let mut locations = Vec::with_capacity(files.len());
+    for file in files {
+        locations.push(encoded_entries.position() as u32); // FPakEntryLocation, byte offset into the encoded entries
+        file.entry.encode::<_, LittleEndian>(&mut encoded_entries)?;
+    }
+    let encoded_entries = encoded_entries.into_inner();
+    // TMap<uint64, FPakEntryLocation>, followed by an empty pruned directory index
+    let mut path_hash_index = Cursor::new(vec![]);
+    path_hash_index.write_u32::<LittleEndian>(files.len() as u32)?;
+    for (file, location) in files.iter().zip(&locations) {
+        path_hash_index.write_u64::<LittleEndian>(hash_path(&file.path, seed))?;
+        path_hash_index.write_u32::<LittleEndian>(*location)?;
+    }
+    path_hash_index.write_u32::<LittleEndian>(0)?;
+    let path_hash_index = path_hash_index.into_inner();
+    // TMap<FString, TMap<FString, FPakEntryLocation>>. Every parent directory needs an entry too
+    let mut directories: BTreeMap<&str, BTreeMap<&str, u32>> = BTreeMap::new();
+    for (file, location) in files.iter().zip(&locations) {
+        let mut parent = file.path.as_str();
+        while let Some((dir, _)) = split_path_child(parent) {
+            parent = dir;
+            directories.entry(dir).or_default();
+        }
+        let (dir, name) = split_path_child(&file.path).unwrap();
+        directories.entry(dir).or_default().insert(name, *location);
+    }
+    let mut directory_index = Cursor::new(vec![]);
+    directory_index.write_u32::<LittleEndian>(directories.len() as u32)?;
+    for (dir, dir_files) in &directories {
+        FString32NoHash::to_buffer::<_, LittleEndian>(dir, &mut directory_index)?;
+        directory_index.write_u32::<LittleEndian>(dir_files.len() as u32)?;
+        for (name, location) in dir_files {
+            FString32NoHash::to_buffer::<_, LittleEndian>(name, &mut directory_index)?;
+            directory_index.write_u32::<LittleEndian>(*location)?;
+        }
+    }
+    let directory_index = directory_index.into_inner();
+    let path_hash_index_offset = index_offset + primary_index_size(files);
+    let directory_index_offset = path_hash_index_offset + path_hash_index.len() as u64;
+    FString32NoHash::to_buffer::<W, LittleEndian>(MOUNT_POINT, writer)?;
+    writer.write_u32::<LittleEndian>(files.len() as u32)?;
+    writer.write_u64::<LittleEndian>(seed)?;
+    for (offset, buffer) in [(path_hash_index_offset, &path_hash_index), (directory_index_offset, &directory_index)] {
+        writer.write_u32::<LittleEndian>(1)?; // bool
+        writer.write_u64::<LittleEndian>(offset)?;
+        writer.write_u64::<LittleEndian>(buffer.len() as u64)?;
+        writer.write_all(&hash_buffer(buffer))?;
+    }
+    writer.write_u32::<LittleEndian>(encoded_entries.len() as u32)?;
+    writer.write_all(&encoded_entries)?;
+    writer.write_u32::<LittleEndian>(0)?; // every uncompressed entry can be encoded
+    writer.write_all(&path_hash_index)?;
+    writer.write_all(&directory_index)?;
+    Ok(())
+}
+
+// FPakInfo
+fn write_footer<W: Write>(writer: &mut W, pak_type: PakType, index_offset: u64, index_size: u64, index_hash: &[u8; 0x14]) -> Result<(), Box<dyn Error>> {
+    writer.write_all(&[0; 0x10])?; // encryption key guid
+    writer.write_u8(0)?; // encrypted index
+    writer.write_u32::<LittleEndian>(PAK_FILE_MAGIC)?;
+    writer.write_i32::<LittleEndian>(pak_type.get_file_version())?;
+    writer.write_u64::<LittleEndian>(index_offset)?;
+    writer.write_u64::<LittleEndian>(index_size)?;
+    writer.write_all(index_hash)?;
+    if pak_type == PakType::FrozenIndex {
+        writer.write_u8(0)?; // index is frozen
+    }
+    writer.write_all(&[0; PAK_COMPRESSION_METHOD_COUNT * PAK_COMPRESSION_METHOD_NAME_LENGTH])?; // no compression methods
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn str_crc32_matches_crc32_of_utf32() {
+        // FCrc::StrCrc32 is the same as a regular CRC32 over the string as UTF-32, so these are from zlib's crc32
+        assert_eq!(str_crc32(""), 0);
+        assert_eq!(str_crc32("unrealessentials.pak"), 0xa920c927);
+        assert_eq!(str_crc32("pakchunk0-windowsnoeditor.pak"), 0xe7862a5e);
+    }
+
+    #[test]
+    fn hash_path_is_seeded_fnv64_of_utf16() {
+        assert_eq!(hash_path("", 0), 0xcbf29ce484222325);
+        assert_eq!(hash_path("a", 0), 0x089be207b544f1e4);
+        assert_eq!(hash_path("Content/Movies/Intro.mp4", 0xa920c927), 0x5bc59cc650116507);
+    }
+
+    #[test]
+    fn hash_path_ignores_case() {
+        let seed = str_crc32("unrealessentials.pak") as u64;
+        assert_eq!(hash_path("Content/Movies/Intro.mp4", seed), hash_path("content/movies/intro.MP4", seed));
+    }
+
+    #[test]
+    fn split_path_child_splits_off_last_component() {
+        assert_eq!(split_path_child("Content/Movies/Intro.mp4"), Some(("Content/Movies/", "Intro.mp4")));
+        assert_eq!(split_path_child("Content/Movies/"), Some(("Content/", "Movies")));
+        assert_eq!(split_path_child("Intro.mp4"), Some(("/", "Intro.mp4")));
+        assert_eq!(split_path_child("/"), None);
+    }
+
+    #[test]
+    fn write_pak_without_file_path() {
+        let pak = PakData {
+            blocks: vec![PakBlock { os_path: 1, data: 0, start: 0x35, length: 0x10 }],
+            entry_headers: vec![], index: vec![], os_paths: vec![]
+        };
+        let mut pak_path = std::env::temp_dir();
+        pak_path.push(crate::asset_collector::EMULATOR_NAME);
+        pak_path.push("Tests");
+        std::fs::create_dir_all(&pak_path).unwrap();
+        pak_path.push("MissingPath.pak");
+        assert!(matches!(pak.write_pak(pak_path.to_str().unwrap()), Err(EmulatorError::InvalidValue(_))));
+    }
+}
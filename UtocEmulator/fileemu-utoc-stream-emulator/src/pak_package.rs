@@ -6,8 +6,10 @@ pub type GUID = u128;
 use bitflags::bitflags;
 use byteorder::ReadBytesExt;
 use crate::{
-    io_package::{IoStoreObjectIndex, ObjectExport2},
+    error::EmulatorError,
+    io_package::{IoStoreObjectIndex, ObjectExport2, UASSET_MAGIC},
     string::{
+        FString32, FString32NoHash,
         FStringDeserializer, FStringSerializer, FStringSerializerHash, 
         FStringSerializerText, FStringSerializerBlockAlign, FMappedName
     }
@@ -20,13 +22,31 @@ use std::{
     ops::Index
 };
 
+// Version independent access to a cooked package's FPackageFileSummary
 pub trait PackageFileSummary {
-
+    // Deserialize an FPackageFileSummary from the start of a cooked package
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> where Self: Sized;
+    // Version gated fields that are present in this package, derived from the package's file version
+    fn get_features(&self) -> PackageFlags;
+    fn get_package_flags(&self) -> u32;
+    fn get_total_header_size(&self) -> i32;
+    fn get_name_count(&self) -> i32;
+    fn get_name_offset(&self) -> i32;
+    fn get_import_count(&self) -> i32;
+    fn get_import_offset(&self) -> i32;
+    fn get_export_count(&self) -> i32;
+    fn get_export_offset(&self) -> i32;
+    fn get_custom_version(&self, key: GUID) -> Option<i32>;
+    // Cooked packages don't contain editor only data in their summary, imports or exports
+    fn is_filter_editor_only(&self) -> bool {
+        self.get_package_flags() & PKG_FILTER_EDITOR_ONLY != 0
+    }
 }
 
 
 bitflags! {
-    struct PackageFlags: u32 {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PackageFlags: u32 {
         // Only include flags for versions above 400 (this will be far below 4.25, somewhere in the low 4.1x range most likely)
         // For FPackageFileSummary
         const UE5_ADD_SOFTOBJECTPATH_LIST = 1 << 0; // 1008
@@ -44,8 +64,53 @@ bitflags! {
         const UE4_64BIT_EXPORTMAP_SERIALSIZES = 1 << 0xB; // 511
         const UE5_TRACK_OBJECT_EXPORT_IS_INHERITED = 1 << 0xC; // 1006
         const UE4_COOKED_ASSETS_IN_EDITOR_SUPPORT = 1 << 0xD; // 485 (also thank god)
-        const UE5_OPTIONAL_RESOURCES = 1 << 0xE; // 1003
+        const UE5_OPTIONAL_RESOURCES = 1 << 0xE; // 1003 (also FObjectImport)
         const UE5_SCRIPT_SERIALIZATION_OFFSET = 1 << 0xF; // 1010
+        const UE5_REMOVE_OBJECT_EXPORT_PACKAGE_GUID = 1 << 0x10; // 1005
+        // For FObjectImport
+        const UE4_NON_OUTER_PACKAGE_IMPORT = 1 << 0x11; // 520
+    }
+}
+
+// The range of file versions (start inclusive, end exclusive) that contain each field
+const PACKAGE_FLAG_UE4_VERSIONS: &[(PackageFlags, i32, i32)] = &[
+    (PackageFlags::UE4_PACKAGE_SUMMARY_HAS_COMPATIBLE_ENGINE_VERSION, 444, i32::MAX),
+    (PackageFlags::UE4_SERIALIZE_TEXT_IN_PACKAGES, 459, i32::MAX),
+    (PackageFlags::UE4_COOKED_ASSETS_IN_EDITOR_SUPPORT, 485, i32::MAX),
+    (PackageFlags::UE4_PRELOAD_DEPENDENCIES_IN_COOKED_EXPORTS, 507, i32::MAX),
+    (PackageFlags::UE4_ADDED_SEARCHABLE_NAMES, 510, i32::MAX),
+    (PackageFlags::UE4_64BIT_EXPORTMAP_SERIALSIZES, 511, i32::MAX),
+    (PackageFlags::UE4_ADDED_PACKAGE_SUMMARY_LOCALIZATION_ID, 516, i32::MAX),
+    (PackageFlags::UE4_ADDED_PACKAGE_OWNER, 518, i32::MAX),
+    (PackageFlags::UE4_HAS_OWNER_PERSISTENT_GUID, 518, 520),
+    (PackageFlags::UE4_NON_OUTER_PACKAGE_IMPORT, 520, i32::MAX),
+];
+const PACKAGE_FLAG_UE5_VERSIONS: &[(PackageFlags, i32, i32)] = &[
+    (PackageFlags::UE5_NAMES_REFERENCED_FROM_EXPORT_DATA, 1001, i32::MAX),
+    (PackageFlags::UE5_PAYLOAD_TOC, 1002, i32::MAX),
+    (PackageFlags::UE5_OPTIONAL_RESOURCES, 1003, i32::MAX),
+    (PackageFlags::UE5_REMOVE_OBJECT_EXPORT_PACKAGE_GUID, 1005, i32::MAX),
+    (PackageFlags::UE5_TRACK_OBJECT_EXPORT_IS_INHERITED, 1006, i32::MAX),
+    (PackageFlags::UE5_ADD_SOFTOBJECTPATH_LIST, 1008, i32::MAX),
+    (PackageFlags::UE5_DATA_RESOURCES, 1009, i32::MAX),
+    (PackageFlags::UE5_SCRIPT_SERIALIZATION_OFFSET, 1010, i32::MAX),
+];
+
+impl PackageFlags {
+    // UE5 packages also contain every UE4 field
+    pub fn from_file_version(file_version_ue4: i32, file_version_ue5: i32) -> Self {
+        let mut flags = PackageFlags::empty();
+        for (flag, start, end) in PACKAGE_FLAG_UE4_VERSIONS {
+            if (*start..*end).contains(&file_version_ue4) {
+                flags |= *flag;
+            }
+        }
+        for (flag, start, end) in PACKAGE_FLAG_UE5_VERSIONS {
+            if (*start..*end).contains(&file_version_ue5) {
+                flags |= *flag;
+            }
+        }
+        flags
     }
 }
 
@@ -79,14 +144,99 @@ pub const PACKAGE_ASSET_MAGIC: u32 = 0xC1832A9E;
 // Dependencies
 // Preload Dependencies
 
+// Legacy file versions that we can read. -7 is used from 4.2x to 4.27, -8 adds the UE5 file version
+pub const LEGACY_FILE_VERSION_UE4: i32 = -7;
+pub const LEGACY_FILE_VERSION_UE5: i32 = -8;
+// Unversioned packages (saved with -unversioned) are assumed to be using the latest file version
+pub const FILE_VERSION_UE4_LATEST: i32 = 522; // VER_UE4_CORRECT_LICENSEE_FLAG
+pub const FILE_VERSION_UE5_LATEST: i32 = 1010; // SCRIPT_SERIALIZATION_OFFSET
+pub const FILE_VERSION_UE4_OLDEST_LOADABLE: i32 = 214; // VER_UE4_OLDEST_LOADABLE_PACKAGE
+
 #[repr(C)]
 pub struct CustomVersion {
     key: GUID,
     version: i32
 }
 
+impl CustomVersion {
+    // Custom versions are serialized differently depending on the package's legacy file version
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, legacy_file_version: i32) -> Result<Self, Box<dyn Error>> {
+        match legacy_file_version {
+            -2 => { // ECustomVersionSerializationFormat::Enums
+                let key = reader.read_u32::<E>()? as GUID;
+                let version = reader.read_i32::<E>()?;
+                Ok(Self { key, version })
+            },
+            -5..=-3 => { // ECustomVersionSerializationFormat::Guids
+                let key = reader.read_u128::<E>()?;
+                let version = reader.read_i32::<E>()?;
+                FString32NoHash::from_buffer::<R, E>(reader)?; // FriendlyName
+                Ok(Self { key, version })
+            },
+            _ => { // ECustomVersionSerializationFormat::Optimized
+                let key = reader.read_u128::<E>()?;
+                let version = reader.read_i32::<E>()?;
+                Ok(Self { key, version })
+            }
+        }
+    }
+    pub fn list_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, legacy_file_version: i32) -> Result<Vec<Self>, Box<dyn Error>> {
+        if legacy_file_version > -2 { // added in legacy version -2
+            return Ok(vec![]);
+        }
+        let count = reader.read_i32::<E>()?;
+        let mut values = Vec::with_capacity(count.max(0) as usize);
+        for _ in 0..count {
+            values.push(CustomVersion::from_buffer::<R, E>(reader, legacy_file_version)?);
+        }
+        Ok(values)
+    }
+}
+
+// FGenerationInfo
+pub struct GenerationInfo {
+    export_count: i32,
+    name_count: i32
+}
+
+// FEngineVersion
+pub struct EngineVersion {
+    major: u16,
+    minor: u16,
+    patch: u16,
+    changelist: u32, // highest bit is set for licensee versions
+    branch: Option<String>
+}
+
+impl EngineVersion {
+    pub const LICENSEE_BIT: u32 = 0x80000000;
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let major = reader.read_u16::<E>()?;
+        let minor = reader.read_u16::<E>()?;
+        let patch = reader.read_u16::<E>()?;
+        let changelist = reader.read_u32::<E>()?;
+        let branch = FString32NoHash::from_buffer::<R, E>(reader)?;
+        Ok(Self { major, minor, patch, changelist, branch })
+    }
+    pub fn is_licensee_version(&self) -> bool {
+        self.changelist & EngineVersion::LICENSEE_BIT != 0
+    }
+}
+
+fn read_i32_array<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Vec<i32>, Box<dyn Error>> {
+    let count = reader.read_i32::<E>()?;
+    let mut values = Vec::with_capacity(count.max(0) as usize);
+    for _ in 0..count {
+        values.push(reader.read_i32::<E>()?);
+    }
+    Ok(values)
+}
+
+// EPackageFlags::PKG_FilterEditorOnly. Set on every cooked package, editor only fields are skipped when this is set
+pub const PKG_FILTER_EDITOR_ONLY: u32 = 0x80000000;
+
 #[repr(C)]
-pub struct PackageFileSummaryType4 {
+pub struct PackageFileSummaryType4 { // Unreal Engine 4.x
     tag: i32, // 0xC1832A9E
     legacy_file_version: i32,
     legacy_ue3_version: i32,
@@ -94,26 +244,280 @@ pub struct PackageFileSummaryType4 {
     file_version_licensee_ue4: i32,
     //custom_version_count: i32,
     custom_versions: Vec<CustomVersion>, // likely change these types later for serialization
+    unversioned: bool, // file versions were 0, the latest versions are used instead
+    features: PackageFlags, // fields included for this file version
     total_header_size: i32,
-    folder_name: String,
-    package_flags: PackageFlags,
-    //name_count: i32,
-    names: Vec<String>,
+    folder_name: Option<String>,
+    package_flags: u32, // EPackageFlags
+    name_count: i32,
+    name_offset: i32,
+    localization_id: Option<String>,
+    gatherable_text_data_count: i32,
+    gatherable_text_data_offset: i32,
+    export_count: i32,
+    export_offset: i32,
+    import_count: i32,
+    import_offset: i32,
+    depends_offset: i32,
+    soft_package_references_count: i32,
+    soft_package_references_offset: i32,
+    searchable_names_offset: i32,
+    thumbnail_table_offset: i32,
+    guid: GUID,
+    persistent_guid: Option<GUID>,
+    owner_persistent_guid: Option<GUID>,
+    generations: Vec<GenerationInfo>,
+    saved_by_engine_version: EngineVersion,
+    compatible_with_engine_version: Option<EngineVersion>, // same as saved_by_engine_version when missing
+    compression_flags: u32,
+    package_source: u32,
+    additional_packages_to_cook: Vec<String>,
+    asset_registry_data_offset: i32,
+    bulk_data_start_offset: i64,
+    world_tile_info_data_offset: i32,
+    chunk_ids: Vec<i32>,
+    preload_dependency_count: i32,
+    preload_dependency_offset: i32
 }
 
+impl PackageFileSummaryType4 {
+    pub fn get_file_version(&self) -> i32 {
+        self.file_version_ue4
+    }
+    pub fn get_licensee_version(&self) -> i32 {
+        self.file_version_licensee_ue4
+    }
+    pub fn is_licensee_version(&self) -> bool {
+        self.file_version_licensee_ue4 != 0 || self.saved_by_engine_version.is_licensee_version()
+    }
+    pub fn is_unversioned(&self) -> bool {
+        self.unversioned
+    }
+    pub fn get_bulk_data_start_offset(&self) -> i64 {
+        self.bulk_data_start_offset
+    }
+    pub fn get_preload_dependencies(&self) -> (i32, i32) {
+        (self.preload_dependency_count, self.preload_dependency_offset)
+    }
+}
 
+// Deserialize the summary for both UE4 and UE5 packages, since UE5 only adds a few fields in between UE4's fields.
+// (see FPackageFileSummary's operator<<)
+fn package_file_summary_from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<PackageFileSummaryType5, Box<dyn Error>> {
+    let tag = reader.read_i32::<E>()?;
+    if tag as u32 == UASSET_MAGIC.swap_bytes() {
+        return Err("Package was saved with a different byte order".into());
+    } else if tag as u32 != UASSET_MAGIC {
+        return Err(format!("Package has an invalid file tag 0x{:X}", tag as u32).into());
+    }
+    let legacy_file_version = reader.read_i32::<E>()?;
+    if legacy_file_version >= 0 {
+        return Err("UE3 packages aren't supported".into());
+    } else if legacy_file_version < LEGACY_FILE_VERSION_UE5 {
+        return Err(format!("Legacy file version {} is newer than what can be read", legacy_file_version).into());
+    }
+    let legacy_ue3_version = if legacy_file_version != -4 { reader.read_i32::<E>()? } else { 0 };
+    let mut file_version_ue4 = reader.read_i32::<E>()?;
+    let mut file_version_ue5 = if legacy_file_version <= LEGACY_FILE_VERSION_UE5 { reader.read_i32::<E>()? } else { 0 };
+    let file_version_licensee_ue4 = reader.read_i32::<E>()?;
+    let custom_versions = CustomVersion::list_from_buffer::<R, E>(reader, legacy_file_version)?;
+    let unversioned = file_version_ue4 == 0 && file_version_ue5 == 0 && file_version_licensee_ue4 == 0;
+    if unversioned {
+        file_version_ue4 = FILE_VERSION_UE4_LATEST;
+        if legacy_file_version <= LEGACY_FILE_VERSION_UE5 {
+            file_version_ue5 = FILE_VERSION_UE5_LATEST;
+        }
+    }
+    if file_version_ue4 < FILE_VERSION_UE4_OLDEST_LOADABLE {
+        return Err(format!("File version {} is too old to be loaded", file_version_ue4).into());
+    }
+    let features = PackageFlags::from_file_version(file_version_ue4, file_version_ue5);
+    let total_header_size = reader.read_i32::<E>()?;
+    let folder_name = FString32NoHash::from_buffer::<R, E>(reader)?;
+    let package_flags = reader.read_u32::<E>()?;
+    let filter_editor_only = package_flags & PKG_FILTER_EDITOR_ONLY != 0;
+    let name_count = reader.read_i32::<E>()?;
+    let name_offset = reader.read_i32::<E>()?;
+    let (soft_object_paths_count, soft_object_paths_offset) = match features.contains(PackageFlags::UE5_ADD_SOFTOBJECTPATH_LIST) {
+        true => (reader.read_i32::<E>()?, reader.read_i32::<E>()?),
+        false => (0, 0)
+    };
+    let localization_id = match !filter_editor_only && features.contains(PackageFlags::UE4_ADDED_PACKAGE_SUMMARY_LOCALIZATION_ID) {
+        true => FString32NoHash::from_buffer::<R, E>(reader)?,
+        false => None
+    };
+    let (gatherable_text_data_count, gatherable_text_data_offset) = match features.contains(PackageFlags::UE4_SERIALIZE_TEXT_IN_PACKAGES) {
+        true => (reader.read_i32::<E>()?, reader.read_i32::<E>()?),
+        false => (0, 0)
+    };
+    let export_count = reader.read_i32::<E>()?;
+    let export_offset = reader.read_i32::<E>()?;
+    let import_count = reader.read_i32::<E>()?;
+    let import_offset = reader.read_i32::<E>()?;
+    let depends_offset = reader.read_i32::<E>()?;
+    let soft_package_references_count = reader.read_i32::<E>()?;
+    let soft_package_references_offset = reader.read_i32::<E>()?;
+    let searchable_names_offset = if features.contains(PackageFlags::UE4_ADDED_SEARCHABLE_NAMES) { reader.read_i32::<E>()? } else { 0 };
+    let thumbnail_table_offset = reader.read_i32::<E>()?;
+    let guid = reader.read_u128::<E>()?;
+    let persistent_guid = match !filter_editor_only && features.contains(PackageFlags::UE4_ADDED_PACKAGE_OWNER) {
+        true => Some(reader.read_u128::<E>()?),
+        false => None
+    };
+    let owner_persistent_guid = match !filter_editor_only && features.contains(PackageFlags::UE4_HAS_OWNER_PERSISTENT_GUID) {
+        true => Some(reader.read_u128::<E>()?),
+        false => None
+    };
+    let generation_count = reader.read_i32::<E>()?;
+    let mut generations = Vec::with_capacity(generation_count.max(0) as usize);
+    for _ in 0..generation_count {
+        let export_count = reader.read_i32::<E>()?;
+        let name_count = reader.read_i32::<E>()?;
+        generations.push(GenerationInfo { export_count, name_count });
+    }
+    let saved_by_engine_version = EngineVersion::from_buffer::<R, E>(reader)?;
+    let compatible_with_engine_version = match features.contains(PackageFlags::UE4_PACKAGE_SUMMARY_HAS_COMPATIBLE_ENGINE_VERSION) {
+        true => Some(EngineVersion::from_buffer::<R, E>(reader)?),
+        false => None
+    };
+    let compression_flags = reader.read_u32::<E>()?;
+    if reader.read_i32::<E>()? != 0 { // CompressedChunks
+        return Err("Package contains compressed chunks, which are no longer supported".into());
+    }
+    let package_source = reader.read_u32::<E>()?;
+    let additional_packages_to_cook_count = reader.read_i32::<E>()?;
+    let mut additional_packages_to_cook = Vec::with_capacity(additional_packages_to_cook_count.max(0) as usize);
+    for _ in 0..additional_packages_to_cook_count {
+        additional_packages_to_cook.push(FString32NoHash::from_buffer::<R, E>(reader)?.unwrap_or_default());
+    }
+    if legacy_file_version > LEGACY_FILE_VERSION_UE4 {
+        reader.read_i32::<E>()?; // NumTextureAllocations
+    }
+    let asset_registry_data_offset = reader.read_i32::<E>()?;
+    let bulk_data_start_offset = reader.read_i64::<E>()?;
+    let world_tile_info_data_offset = reader.read_i32::<E>()?;
+    let chunk_ids = read_i32_array::<R, E>(reader)?;
+    let (preload_dependency_count, preload_dependency_offset) = match features.contains(PackageFlags::UE4_PRELOAD_DEPENDENCIES_IN_COOKED_EXPORTS) {
+        true => (reader.read_i32::<E>()?, reader.read_i32::<E>()?),
+        false => (-1, 0)
+    };
+    let names_referenced_from_export_data_count = match features.contains(PackageFlags::UE5_NAMES_REFERENCED_FROM_EXPORT_DATA) {
+        true => reader.read_i32::<E>()?,
+        false => name_count
+    };
+    let payload_toc_offset = if features.contains(PackageFlags::UE5_PAYLOAD_TOC) { reader.read_i64::<E>()? } else { -1 };
+    let data_resource_offset = if features.contains(PackageFlags::UE5_DATA_RESOURCES) { reader.read_i32::<E>()? } else { -1 };
+    Ok(PackageFileSummaryType5 {
+        base: PackageFileSummaryType4 {
+            tag, legacy_file_version, legacy_ue3_version, file_version_ue4, file_version_licensee_ue4, custom_versions, unversioned, features,
+            total_header_size, folder_name, package_flags, name_count, name_offset, localization_id,
+            gatherable_text_data_count, gatherable_text_data_offset, export_count, export_offset, import_count, import_offset,
+            depends_offset, soft_package_references_count, soft_package_references_offset, searchable_names_offset,
+            thumbnail_table_offset, guid, persistent_guid, owner_persistent_guid, generations,
+            saved_by_engine_version, compatible_with_engine_version, compression_flags, package_source, additional_packages_to_cook,
+            asset_registry_data_offset, bulk_data_start_offset, world_tile_info_data_offset, chunk_ids,
+            preload_dependency_count, preload_dependency_offset
+        },
+        file_version_ue5, soft_object_paths_count, soft_object_paths_offset,
+        names_referenced_from_export_data_count, payload_toc_offset, data_resource_offset
+    })
+}
 
 impl PackageFileSummary for PackageFileSummaryType4 {
-
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let summary = package_file_summary_from_buffer::<R, E>(reader)?;
+        if summary.base.legacy_file_version <= LEGACY_FILE_VERSION_UE5 {
+            return Err("Package was saved by UE5, expected a UE4 package".into());
+        }
+        Ok(summary.base)
+    }
+    fn get_features(&self) -> PackageFlags {
+        self.features
+    }
+    fn get_package_flags(&self) -> u32 {
+        self.package_flags
+    }
+    fn get_total_header_size(&self) -> i32 {
+        self.total_header_size
+    }
+    fn get_name_count(&self) -> i32 {
+        self.name_count
+    }
+    fn get_name_offset(&self) -> i32 {
+        self.name_offset
+    }
+    fn get_import_count(&self) -> i32 {
+        self.import_count
+    }
+    fn get_import_offset(&self) -> i32 {
+        self.import_offset
+    }
+    fn get_export_count(&self) -> i32 {
+        self.export_count
+    }
+    fn get_export_offset(&self) -> i32 {
+        self.export_offset
+    }
+    fn get_custom_version(&self, key: GUID) -> Option<i32> {
+        self.custom_versions.iter().find(|v| v.key == key).map(|v| v.version)
+    }
 }
 
 #[repr(C)]
-pub struct PackageFileSummaryType5 {
+pub struct PackageFileSummaryType5 { // Unreal Engine 5.0+
+    base: PackageFileSummaryType4,
+    file_version_ue5: i32,
+    soft_object_paths_count: i32,
+    soft_object_paths_offset: i32,
+    names_referenced_from_export_data_count: i32,
+    payload_toc_offset: i64,
+    data_resource_offset: i32
+}
 
+impl PackageFileSummaryType5 {
+    pub fn get_file_version_ue5(&self) -> i32 {
+        self.file_version_ue5
+    }
 }
 
 impl PackageFileSummary for PackageFileSummaryType5 {
-
+    fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let summary = package_file_summary_from_buffer::<R, E>(reader)?;
+        if summary.base.legacy_file_version > LEGACY_FILE_VERSION_UE5 {
+            return Err("Package was saved by UE4, expected a UE5 package".into());
+        }
+        Ok(summary)
+    }
+    fn get_features(&self) -> PackageFlags {
+        self.base.get_features()
+    }
+    fn get_package_flags(&self) -> u32 {
+        self.base.get_package_flags()
+    }
+    fn get_total_header_size(&self) -> i32 {
+        self.base.get_total_header_size()
+    }
+    fn get_name_count(&self) -> i32 {
+        self.base.get_name_count()
+    }
+    fn get_name_offset(&self) -> i32 {
+        self.base.get_name_offset()
+    }
+    fn get_import_count(&self) -> i32 {
+        self.base.get_import_count()
+    }
+    fn get_import_offset(&self) -> i32 {
+        self.base.get_import_offset()
+    }
+    fn get_export_count(&self) -> i32 {
+        self.base.get_export_count()
+    }
+    fn get_export_offset(&self) -> i32 {
+        self.base.get_export_offset()
+    }
+    fn get_custom_version(&self, key: GUID) -> Option<i32> {
+        self.base.get_custom_version(key)
+    }
 }
 
 
@@ -124,7 +528,7 @@ pub trait NameMap {
         R: Read + Seek,
         T: FStringDeserializer,
         E: byteorder::ByteOrder
-    >(&mut self, reader: &mut R, count: usize);
+    >(&mut self, reader: &mut R, count: usize) -> Result<(), Box<dyn Error>>;
     // Write a contiguous block of names with text info only into a buffer.
     fn to_buffer_text_only<
         W: Write + Seek,
@@ -153,12 +557,13 @@ impl NameMap for NameMapImpl {
         R: Read + Seek,
         T: FStringDeserializer,
         E: byteorder::ByteOrder
-    >(&mut self, reader: &mut R, count: usize) {
+    >(&mut self, reader: &mut R, count: usize) -> Result<(), Box<dyn Error>> {
         for _ in 0..count {
-            if let Some(fstr) = T::from_buffer::<R, E>(reader).unwrap() {
+            if let Some(fstr) = T::from_buffer::<R, E>(reader)? {
                 self.0.push(fstr);
             }
         }
+        Ok(())
     }
     fn to_buffer_text_only<
         W: Write + Seek,
@@ -196,14 +601,12 @@ impl NameMap for NameMapImpl {
         Ok(())
     }
     fn get_string_from_index(&self, index: usize) -> Result<&str, String> {
-        let a = self.0.get(index);
         match self.0.get(index) {
             Some(s) => Ok(s),
             None => Err(
-                String::from(format!(
+                format!(
                     "Attempted out of bounds access read. 
                     Name map has {} entries, tried reading index {}", self.0.len(), index)
-                )
             )
         }
     }
@@ -214,6 +617,12 @@ impl Index<usize> for NameMapImpl {
         self.0.index(index)
     }
 }
+impl Default for NameMapImpl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NameMapImpl {
     pub fn new() -> Self {
         Self(vec![])
@@ -223,10 +632,26 @@ impl NameMapImpl {
         R: Read + Seek,
         T: FStringDeserializer,
         E: byteorder::ByteOrder
-    >(reader: &mut R, count: usize) -> Self {
+    >(reader: &mut R, count: usize) -> Result<Self, Box<dyn Error>> {
         let mut map = NameMapImpl::new();
-        map.add_from_buffer::<R, T, E>(reader, count);
-        map
+        map.add_from_buffer::<R, T, E>(reader, count)?;
+        Ok(map)
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    // Get the index of a name, adding it onto the end of the name map if it doesn't exist yet. Existing indices are unaffected
+    pub fn get_or_add(&mut self, name: &str) -> usize {
+        match self.0.iter().position(|n| n == name) {
+            Some(i) => i,
+            None => {
+                self.0.push(name.to_owned());
+                self.0.len() - 1
+            }
+        }
     }
 }
 
@@ -247,18 +672,14 @@ impl PakObjectIndex {
 
 pub struct IntBool(i32);
 impl IntBool {
-    pub fn new(val: i32) -> Self {
+    pub fn new(val: i32) -> Result<Self, EmulatorError> {
         match val {
-            0 | 1 => Self(val),
-            _ => panic!("ERROR: Tried to initialize an IntBool with a value other than 0 or 1")
+            0 | 1 => Ok(Self(val)),
+            _ => Err(EmulatorError::InvalidValue(format!("Tried to initialize an IntBool with {}, expected 0 or 1", val)))
         }
     }
     pub fn value(&self) -> bool {
-        match self.0 {
-            0 => false,
-            1 => true,
-            _ => panic!("ERROR: IntBool has value other than 0 or 1")
-        }
+        self.0 == 1 // only ever 0 or 1
     }
 }
 
@@ -272,31 +693,69 @@ pub struct FObjectImport {
 }
 
 impl FObjectImport {
-    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<FObjectImport, Box<dyn Error>> {
+    pub fn from_buffer<
+        R: Read + Seek, 
+        E: byteorder::ByteOrder,
+        S: PackageFileSummary
+    >(reader: &mut R, summary: &S) -> Result<FObjectImport, Box<dyn Error>> {
+        let features = summary.get_features();
         let class_package = reader.read_u64::<E>()?;
         let class_name = reader.read_u64::<E>()?;
         let outer_index = reader.read_i32::<E>()?;
         let object_name = reader.read_u64::<E>()?.into();
+        if features.contains(PackageFlags::UE4_NON_OUTER_PACKAGE_IMPORT) && !summary.is_filter_editor_only() {
+            reader.read_u64::<E>()?; // PackageName (editor only)
+        }
+        if features.contains(PackageFlags::UE5_OPTIONAL_RESOURCES) {
+            reader.read_i32::<E>()?; // bImportOptional
+        }
         Ok(FObjectImport { class_package, class_name, outer_index, object_name })
     }
-    pub fn resolve<'a, N: NameMap>(&'a self, names: &'a N, imports: &Vec<FObjectImport>) -> Result<IoStoreObjectIndex, String> {
+    pub fn resolve<'a, N: NameMap>(&'a self, names: &'a N, imports: &[FObjectImport]) -> Result<IoStoreObjectIndex, String> {
         // Check if the target import item is a leaf on the import tree
         match PakObjectIndex::get_package_index(self.outer_index) {
-            PakObjectIndex::Import(i) => {
+            PakObjectIndex::Import(_) => {
                 // import could be a ScriptImport (/script/...) or a PackageImport (/game/...)
-                let mut out = String::from(names.get_string_from_index(imports[i as usize].object_name.get_name_index() as usize).unwrap()) + "/";
-                out.push_str(names.get_string_from_index(self.object_name.get_name_index() as usize).unwrap());
+                let out = self.get_full_path(names, imports)?;
                 // check beginning of path to determine import type
-                Ok(FObjectImport::begins_with_script_else(out, |n| IoStoreObjectIndex::PackageImport(n)))
+                Ok(FObjectImport::begins_with_script_else(out, IoStoreObjectIndex::PackageImport))
             },
             PakObjectIndex::Export(i) => Ok(IoStoreObjectIndex::Export(i as u64)),
             PakObjectIndex::None => {
                 // It's the root import node, though it could be a root script
-                let name_copy = String::from(names.get_string_from_index(self.object_name.get_name_index() as usize).unwrap());
+                let name_copy = String::from(names.get_string_from_index(self.object_name.get_name_index() as usize)?);
                 Ok(FObjectImport::begins_with_script_else(name_copy, |_| IoStoreObjectIndex::Empty))
             },
         }
     }
+    // Walk up the import tree to build the import's path ([package_name]/[outer]/[name])
+    fn get_full_path<N: NameMap>(&self, names: &N, imports: &[FObjectImport]) -> Result<String, String> {
+        let mut path = String::from(names.get_string_from_index(self.object_name.get_name_index() as usize)?);
+        let mut outer_index = self.outer_index;
+        // every import has a unique outer, so an import tree can't be deeper than the import map
+        for _ in 0..imports.len() {
+            match PakObjectIndex::get_package_index(outer_index) {
+                PakObjectIndex::Import(i) => {
+                    let outer = imports.get(i as usize).ok_or(format!("Import outer index {} is out of bounds", i))?;
+                    path = String::from(names.get_string_from_index(outer.object_name.get_name_index() as usize)?) + "/" + &path;
+                    outer_index = outer.outer_index;
+                },
+                PakObjectIndex::Export(_) => return Err("Import can't be contained inside of an export".to_owned()),
+                PakObjectIndex::None => return Ok(path)
+            }
+        }
+        Err("Import tree contains a cycle".to_owned())
+    }
+    // Get the name of the package that this root import refers to, if it isn't a script package
+    pub fn get_imported_package_name<'a, N: NameMap>(&self, names: &'a N) -> Option<&'a str> {
+        match PakObjectIndex::get_package_index(self.outer_index) {
+            PakObjectIndex::None => {
+                let name = names.get_string_from_index(self.object_name.get_name_index() as usize).ok()?;
+                if name.starts_with("/Script/") { None } else { Some(name) }
+            },
+            _ => None
+        }
+    }
     fn begins_with_script_else<F>(tstr: String, not_script: F) -> IoStoreObjectIndex
     where F: Fn(String) -> IoStoreObjectIndex
     {
@@ -309,17 +768,15 @@ impl FObjectImport {
         not_script(tstr)
     }
     // Deserializes a byte stream containing a contigous array of elements into a list of it's respective type
-    pub fn build_map<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, count: usize) -> Vec<FObjectImport> {
+    pub fn build_map<R: Read + Seek, E: byteorder::ByteOrder, S: PackageFileSummary>(reader: &mut R, summary: &S, count: usize) -> Result<Vec<FObjectImport>, Box<dyn Error>> {
         let mut map = vec![];
         for i in 0..count {
-            match FObjectImport::from_buffer::<R, E>(reader) {
+            match FObjectImport::from_buffer::<R, E, S>(reader, summary) {
                 Ok(obj) => map.push(obj),
-                Err(e) => {
-                    panic!("Error deserializing import object on ID {}: {}", i, e.to_string())
-                }
+                Err(e) => return Err(format!("Error deserializing import object on ID {}: {}", i, e).into())
             }
         }
-        map
+        Ok(map)
     }
 }
 
@@ -348,28 +805,49 @@ pub struct FObjectExport {
 }
 
 impl FObjectExport {
-    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<FObjectExport, Box<dyn Error>> {
+    pub fn from_buffer<
+        R: Read + Seek, 
+        E: byteorder::ByteOrder,
+        S: PackageFileSummary
+    >(reader: &mut R, summary: &S) -> Result<FObjectExport, Box<dyn Error>> {
+        let features = summary.get_features();
         let class_index = reader.read_i32::<E>()?;
         let super_index = reader.read_i32::<E>()?;
         let template_index = reader.read_i32::<E>()?;
         let outer_index = reader.read_i32::<E>()?;
         let object_name = reader.read_u64::<E>()?.into();
         let object_flags = reader.read_u32::<E>()?;
-        let serial_size = reader.read_i64::<E>()?;
-        let serial_offset = reader.read_i64::<E>()?;
-        let bool_forced_export = IntBool(reader.read_i32::<E>()?).value();
-        let bool_not_for_client = IntBool(reader.read_i32::<E>()?).value();
-        let bool_not_for_server = IntBool(reader.read_i32::<E>()?).value();
-        reader.seek(SeekFrom::Current(0x10)); // Package GUID (not used)
+        let (serial_size, serial_offset) = match features.contains(PackageFlags::UE4_64BIT_EXPORTMAP_SERIALSIZES) {
+            true => (reader.read_i64::<E>()?, reader.read_i64::<E>()?),
+            false => (reader.read_i32::<E>()? as i64, reader.read_i32::<E>()? as i64)
+        };
+        let bool_forced_export = reader.read_i32::<E>()? != 0;
+        let bool_not_for_client = reader.read_i32::<E>()? != 0;
+        let bool_not_for_server = reader.read_i32::<E>()? != 0;
+        if !features.contains(PackageFlags::UE5_REMOVE_OBJECT_EXPORT_PACKAGE_GUID) {
+            reader.seek(SeekFrom::Current(0x10))?; // Package GUID (not used)
+        }
+        if features.contains(PackageFlags::UE5_TRACK_OBJECT_EXPORT_IS_INHERITED) {
+            reader.read_i32::<E>()?; // bIsInheritedInstance
+        }
         let package_flags = reader.read_u32::<E>()?;
-        let not_always_loaded_for_editor_game = IntBool(reader.read_i32::<E>()?).value();
-        let is_asset = IntBool(reader.read_i32::<E>()?).value();
-        let first_export_dependency = reader.read_i32::<E>()?;
-        let serialization_before_serialization_dependencies = reader.read_i32::<E>()?;
-        let create_before_serialization_dependencies = reader.read_i32::<E>()?;
-        let serialization_before_create_dependencies = reader.read_i32::<E>()?;
-        let create_before_create_dependencies = reader.read_i32::<E>()?;
-
+        let not_always_loaded_for_editor_game = reader.read_i32::<E>()? != 0;
+        let is_asset = if features.contains(PackageFlags::UE4_COOKED_ASSETS_IN_EDITOR_SUPPORT) { reader.read_i32::<E>()? != 0 } else { false };
+        if features.contains(PackageFlags::UE5_OPTIONAL_RESOURCES) {
+            reader.read_i32::<E>()?; // bGeneratePublicHash
+        }
+        let (
+            first_export_dependency, 
+            serialization_before_serialization_dependencies, create_before_serialization_dependencies,
+            serialization_before_create_dependencies, create_before_create_dependencies
+        ) = match features.contains(PackageFlags::UE4_PRELOAD_DEPENDENCIES_IN_COOKED_EXPORTS) {
+            true => (reader.read_i32::<E>()?, reader.read_i32::<E>()?, reader.read_i32::<E>()?, reader.read_i32::<E>()?, reader.read_i32::<E>()?),
+            false => (-1, 0, 0, 0, 0)
+        };
+        if features.contains(PackageFlags::UE5_SCRIPT_SERIALIZATION_OFFSET) {
+            reader.read_i64::<E>()?; // ScriptSerializationStartOffset
+            reader.read_i64::<E>()?; // ScriptSerializationEndOffset
+        }
 
         Ok(FObjectExport{
             class_index,
@@ -394,76 +872,74 @@ impl FObjectExport {
         })
     }
 
-    pub fn build_map<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R, count: usize) -> Vec<FObjectExport> {
+    pub fn build_map<R: Read + Seek, E: byteorder::ByteOrder, S: PackageFileSummary>(reader: &mut R, summary: &S, count: usize) -> Result<Vec<FObjectExport>, Box<dyn Error>> {
         let mut map = vec![];
         for _ in 0..count {
-            map.push(FObjectExport::from_buffer::<R, E>(reader).unwrap());
+            map.push(FObjectExport::from_buffer::<R, E, S>(reader, summary)?);
         }
-        map
+        Ok(map)
     }
     // Own all our values for now i'm too busy trying to make this work to optimize lol
-    fn get_outer_object_index(&self) -> IoStoreObjectIndex {
+    fn get_outer_object_index(&self) -> Result<IoStoreObjectIndex, String> {
         match PakObjectIndex::get_package_index(self.outer_index) {
-            PakObjectIndex::Import(n) => panic!("Import index is invalid for export outer"),
-            PakObjectIndex::Export(n) => IoStoreObjectIndex::Export(n as u64),
-            PakObjectIndex::None => IoStoreObjectIndex::Empty,
+            PakObjectIndex::Import(n) => Err("Import index is invalid for export outer".to_owned()),
+            PakObjectIndex::Export(n) => Ok(IoStoreObjectIndex::Export(n as u64)),
+            PakObjectIndex::None => Ok(IoStoreObjectIndex::Empty),
         }
     }
-    fn get_class_object_index(&self, imports: &Vec<IoStoreObjectIndex>) -> IoStoreObjectIndex {
-        match PakObjectIndex::get_package_index(self.class_index) {
-            PakObjectIndex::Import(n) => (&imports[n as usize]).clone(),
-            PakObjectIndex::Export(n) => panic!("Export index is invalid for export class"),
-            PakObjectIndex::None => panic!("None is invalid for export class"),
+    // Class, super and template can point to either an import or to another export in the same package (e.g blueprint classes)
+    fn get_object_index(index: i32, imports: &[IoStoreObjectIndex]) -> Result<IoStoreObjectIndex, String> {
+        match PakObjectIndex::get_package_index(index) {
+            PakObjectIndex::Import(n) => imports.get(n as usize).cloned().ok_or(format!("Import index {} is out of bounds", n)),
+            PakObjectIndex::Export(n) => Ok(IoStoreObjectIndex::Export(n as u64)),
+            PakObjectIndex::None => Ok(IoStoreObjectIndex::Empty),
         }
     }
-    fn get_super_object_index(&self) -> IoStoreObjectIndex {
-        match PakObjectIndex::get_package_index(self.super_index) {
-            PakObjectIndex::Import(n) => panic!("Import index is invalid for export super"),
-            PakObjectIndex::Export(n) => panic!("Export index is invalid for export super"),
-            PakObjectIndex::None => IoStoreObjectIndex::Empty,
-        }
-    }
-    fn get_template_object_index(&self, imports: &Vec<IoStoreObjectIndex>) -> IoStoreObjectIndex {
-        match PakObjectIndex::get_package_index(self.template_index) {
-            PakObjectIndex::Import(n) => (&imports[n as usize]).clone(),
-            PakObjectIndex::Export(n) => panic!("Export index is invalid for export template"),
-            PakObjectIndex::None => panic!("None is invalid for export template"),
-        }
-    }
-    fn get_global_import_name_object_index<N: NameMap, G: GameName>(&self, imports: &Vec<IoStoreObjectIndex>, names: &N, file_name: &str, game_name: &G) -> IoStoreObjectIndex {
-        match PakObjectIndex::get_package_index(self.outer_index) {
-            PakObjectIndex::Import(n) => panic!("Import index is invalid for export global import"),
-            PakObjectIndex::Export(n) => IoStoreObjectIndex::Empty,
-            PakObjectIndex::None => {
-                let asset_proj_path = String::from(file_name) + "/" + names.get_string_from_index(self.object_name.get_name_index() as usize).unwrap();
-                let global_import_name = game_name.project_path_to_game_path(&asset_proj_path).unwrap();
-                IoStoreObjectIndex::PackageImport(global_import_name)
+    // Walk up the export tree to build the export's path ([outer]/[name])
+    fn get_full_path<N: NameMap>(&self, names: &N, exports: &[FObjectExport]) -> Result<String, String> {
+        let mut path = String::from(names.get_string_from_index(self.object_name.get_name_index() as usize)?);
+        let mut outer_index = self.outer_index;
+        for _ in 0..exports.len() {
+            match PakObjectIndex::get_package_index(outer_index) {
+                PakObjectIndex::Export(i) => {
+                    let outer = exports.get(i as usize).ok_or(format!("Export outer index {} is out of bounds", i))?;
+                    path = String::from(names.get_string_from_index(outer.object_name.get_name_index() as usize)?) + "/" + &path;
+                    outer_index = outer.outer_index;
+                },
+                PakObjectIndex::Import(_) => return Err("Import index is invalid for export outer".to_owned()),
+                PakObjectIndex::None => return Ok(path)
             }
         }
+        Err("Export tree contains a cycle".to_owned())
+    }
+    fn get_global_import_name_object_index<N: NameMap, G: GameName>(&self, names: &N, exports: &[FObjectExport], file_name: &str, game_name: &G) -> Result<IoStoreObjectIndex, String> {
+        let asset_proj_path = String::from(file_name) + "/" + &self.get_full_path(names, exports)?;
+        let global_import_name = game_name.project_path_to_game_path(&asset_proj_path)?;
+        Ok(IoStoreObjectIndex::PackageImport(global_import_name))
     }
     // Resolving requires that we have an import map loaded
     // outer_index is always of either type Export if it's not the root export or Null if it is
-    // class_index is always of type ScriptImport
-    // super_index is always of type Null
-    // template_index is always of type ScriptImport
-    // global_import_index is always of type Null if it's not the root export, or PackageImport if it is
+    // class_index is of type ScriptImport, PackageImport or Export (for classes defined in the same package)
+    // super_index is of type Null, unless this export is a struct or class
+    // template_index is of type ScriptImport, PackageImport or Export
+    // global_import_index is the export's full path in the game's mount point, which lets other packages import it
 
     pub fn resolve<
         N: NameMap,
         G: GameName
-    >(&self, names: &N, imports: &Vec<IoStoreObjectIndex>, exports: &Vec<FObjectExport>, file_name: &str, game_name: &G) -> ObjectExport2 {
+    >(&self, names: &N, imports: &[IoStoreObjectIndex], exports: &[FObjectExport], file_name: &str, game_name: &G) -> Result<ObjectExport2, String> {
         let cooked_serial_offset = self.serial_offset - 4; // PAK package serial offset - magic bytes
         let cooked_serial_size = self.serial_size;
 
         let object_name = self.object_name; // this can just be passed straight through, but we'll still need to get that string for global_import_name
-        let outer_index = self.get_outer_object_index();
-        let class_name = self.get_class_object_index(&imports);
-        let super_name = self.get_super_object_index();
-        let template_name = self.get_template_object_index(&imports);
-        let global_import_name = self.get_global_import_name_object_index(&imports, names, file_name, game_name);
+        let outer_index = self.get_outer_object_index()?;
+        let class_name = FObjectExport::get_object_index(self.class_index, imports)?;
+        let super_name = FObjectExport::get_object_index(self.super_index, imports)?;
+        let template_name = FObjectExport::get_object_index(self.template_index, imports)?;
+        let global_import_name = self.get_global_import_name_object_index(names, exports, file_name, game_name)?;
         let object_flags = self.object_flags;
         let filter_flags = 0; // EExportFilterFlags::None
-        ObjectExport2 {
+        Ok(ObjectExport2 {
             cooked_serial_offset,
             cooked_serial_size,
             object_name,
@@ -474,9 +950,56 @@ impl FObjectExport {
             global_import_name,
             object_flags,
             filter_flags
+        })
+    }
+}
+
+// A cooked package (.uasset) with it's name, import and export maps read
+pub struct CookedPackage<S: PackageFileSummary = PackageFileSummaryType4> {
+    pub summary: S,
+    pub names: NameMapImpl,
+    pub imports: Vec<FObjectImport>,
+    pub exports: Vec<FObjectExport>
+}
+
+impl<S: PackageFileSummary> CookedPackage<S> {
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        reader.rewind()?;
+        let summary = S::from_buffer::<R, E>(reader)?;
+        if summary.get_name_count() < 0 || summary.get_import_count() < 0 || summary.get_export_count() < 0 {
+            return Err("Package summary has a negative name, import or export count".into());
+        }
+        reader.seek(SeekFrom::Start(summary.get_name_offset() as u64))?;
+        let mut names = NameMapImpl::new();
+        for _ in 0..summary.get_name_count() {
+            names.0.push(FString32::from_buffer::<R, E>(reader)?.unwrap_or_default());
         }
+        reader.seek(SeekFrom::Start(summary.get_import_offset() as u64))?;
+        let mut imports = Vec::with_capacity(summary.get_import_count() as usize);
+        for _ in 0..summary.get_import_count() {
+            imports.push(FObjectImport::from_buffer::<R, E, S>(reader, &summary)?);
+        }
+        reader.seek(SeekFrom::Start(summary.get_export_offset() as u64))?;
+        let mut exports = Vec::with_capacity(summary.get_export_count() as usize);
+        for _ in 0..summary.get_export_count() {
+            exports.push(FObjectExport::from_buffer::<R, E, S>(reader, &summary)?);
+        }
+        Ok(Self { summary, names, imports, exports })
+    }
+    // Get the export data for a package. This is stored in the .uexp for split packages, otherwise it's after the header in
+    // the .uasset. Both end with the package file tag, which isn't included in IO Store packages
+    pub fn get_export_data<'a>(&self, asset: &'a [u8], uexp: Option<&'a [u8]>) -> Result<&'a [u8], Box<dyn Error>> {
+        let data = match uexp {
+            Some(d) => d,
+            None => asset.get(self.summary.get_total_header_size() as usize..).ok_or("Package header is larger than the package")?
+        };
+        if data.len() < 4 || data[data.len() - 4..] != PACKAGE_ASSET_MAGIC.to_be_bytes() {
+            return Err("Export data doesn't end with the package file tag".into());
+        }
+        Ok(&data[..data.len() - 4])
     }
 }
+
 pub struct FExportBundleEntry {
 
 }
@@ -545,11 +1068,14 @@ impl GameName for GameNameImpl {
     fn get_game_name(&self) -> &str {
         &self.game_name
     }
+    // Only the start of the path is replaced, and it's matched ignoring case since that's how the game looks up it's folders
     fn project_path_to_game_path(&self, path: &str) -> Result<String, &'static str> {
         let path_match = String::from(&self.project_name) + "/Content";
-        match path.rfind(&path_match) {
-            Some(_) => Ok(String::from(path).replace(&path_match, &self.game_name)),
-            None => Err("Couldn't convert the project path to game path")
+        match path.get(..path_match.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(&path_match)
+                && (path.len() == path_match.len() || path[path_match.len()..].starts_with('/')) =>
+                Ok(String::from(&self.game_name) + &path[path_match.len()..]),
+            _ => Err("Couldn't convert the project path to game path")
         }
     }
 }
@@ -563,4 +1089,169 @@ pub struct AssetPath {
 
 impl AssetPath {
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use byteorder::{NativeEndian as EN, WriteBytesExt};
+
+    // Package, import and export names, followed by the package that's imported. The package is Test/Content/TestAsset
+    const TEST_NAMES: [&str; 6] = ["/Script/CoreUObject", "Package", "Class", "Object", "TestAsset", "/Game/Other"];
+    pub(crate) const TEST_PROJECT_NAME: &str = "Test";
+    pub(crate) const TEST_PACKAGE_PATH: &str = "Test/Content/TestAsset";
+    pub(crate) const TEST_IMPORTED_PACKAGE: &str = "/Game/Other";
+
+    fn write_engine_version(writer: &mut Cursor<Vec<u8>>) {
+        writer.write_u16::<EN>(4).unwrap(); // Major
+        writer.write_u16::<EN>(27).unwrap(); // Minor
+        writer.write_u16::<EN>(2).unwrap(); // Patch
+        writer.write_u32::<EN>(0).unwrap(); // Changelist
+        FString32NoHash::to_buffer::<_, EN>("++UE4+Release-4.27", writer).unwrap(); // Branch
+    }
+
+    // FPackageFileSummary for an unversioned UE 4.27 package, which is read as the latest UE4 file version. Cooked packages
+    // are filtered for editor only data, so none of the editor only fields are written
+    fn write_summary(writer: &mut Cursor<Vec<u8>>, total_header_size: i32, name_offset: i32, import_offset: i32, export_offset: i32) {
+        writer.write_u32::<EN>(UASSET_MAGIC).unwrap();
+        writer.write_i32::<EN>(LEGACY_FILE_VERSION_UE4).unwrap();
+        writer.write_i32::<EN>(864).unwrap(); // LegacyUE3Version
+        writer.write_i32::<EN>(0).unwrap(); // FileVersionUE4
+        writer.write_i32::<EN>(0).unwrap(); // FileVersionLicenseeUE4
+        writer.write_i32::<EN>(0).unwrap(); // CustomVersions
+        writer.write_i32::<EN>(total_header_size).unwrap();
+        FString32NoHash::to_buffer::<_, EN>("None", writer).unwrap(); // FolderName
+        writer.write_u32::<EN>(PKG_FILTER_EDITOR_ONLY).unwrap();
+        writer.write_i32::<EN>(TEST_NAMES.len() as i32).unwrap();
+        writer.write_i32::<EN>(name_offset).unwrap();
+        writer.write_i32::<EN>(0).unwrap(); // GatherableTextDataCount
+        writer.write_i32::<EN>(0).unwrap(); // GatherableTextDataOffset
+        writer.write_i32::<EN>(1).unwrap(); // ExportCount
+        writer.write_i32::<EN>(export_offset).unwrap();
+        writer.write_i32::<EN>(3).unwrap(); // ImportCount
+        writer.write_i32::<EN>(import_offset).unwrap();
+        writer.write_i32::<EN>(0).unwrap(); // DependsOffset
+        writer.write_i32::<EN>(0).unwrap(); // SoftPackageReferencesCount
+        writer.write_i32::<EN>(0).unwrap(); // SoftPackageReferencesOffset
+        writer.write_i32::<EN>(0).unwrap(); // SearchableNamesOffset
+        writer.write_i32::<EN>(0).unwrap(); // ThumbnailTableOffset
+        writer.write_u128::<EN>(0).unwrap(); // Guid
+        writer.write_i32::<EN>(0).unwrap(); // Generations
+        write_engine_version(writer); // SavedByEngineVersion
+        write_engine_version(writer); // CompatibleWithEngineVersion
+        writer.write_u32::<EN>(0).unwrap(); // CompressionFlags
+        writer.write_i32::<EN>(0).unwrap(); // CompressedChunks
+        writer.write_u32::<EN>(0).unwrap(); // PackageSource
+        writer.write_i32::<EN>(0).unwrap(); // AdditionalPackagesToCook
+        writer.write_i32::<EN>(0).unwrap(); // AssetRegistryDataOffset
+        writer.write_i64::<EN>(0).unwrap(); // BulkDataStartOffset
+        writer.write_i32::<EN>(0).unwrap(); // WorldTileInfoDataOffset
+        writer.write_i32::<EN>(0).unwrap(); // ChunkIDs
+        writer.write_i32::<EN>(0).unwrap(); // PreloadDependencyCount
+        writer.write_i32::<EN>(0).unwrap(); // PreloadDependencyOffset
+    }
+
+    fn write_import(writer: &mut Cursor<Vec<u8>>, class_name: u64, outer_index: i32, object_name: u64) {
+        writer.write_u64::<EN>(0).unwrap(); // ClassPackage
+        writer.write_u64::<EN>(class_name).unwrap();
+        writer.write_i32::<EN>(outer_index).unwrap();
+        writer.write_u64::<EN>(object_name).unwrap();
+    }
+
+    // A split cooked package (.uasset and .uexp) with a single export, whose class is /Script/CoreUObject.Object. It also imports
+    // /Game/Other, which is the only package that the converted package should depend on
+    pub(crate) fn get_cooked_package(export_data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut summary_writer = Cursor::new(vec![]);
+        write_summary(&mut summary_writer, 0, 0, 0, 0);
+        let mut writer = Cursor::new(vec![]);
+        writer.seek(SeekFrom::Start(summary_writer.position())).unwrap();
+        let name_offset = writer.position() as i32;
+        for name in TEST_NAMES {
+            FString32NoHash::to_buffer::<_, EN>(name, &mut writer).unwrap();
+            writer.write_u32::<EN>(0).unwrap(); // hash
+        }
+        let import_offset = writer.position() as i32;
+        write_import(&mut writer, 1, 0, 0); // /Script/CoreUObject
+        write_import(&mut writer, 2, -1, 3); // /Script/CoreUObject.Object
+        write_import(&mut writer, 1, 0, 5); // /Game/Other
+        let export_offset = writer.position() as i32;
+        let total_header_size = export_offset + 0x68; // FObjectExport for the latest UE4 file version
+        writer.write_i32::<EN>(-2).unwrap(); // ClassIndex
+        writer.write_i32::<EN>(0).unwrap(); // SuperIndex
+        writer.write_i32::<EN>(0).unwrap(); // TemplateIndex
+        writer.write_i32::<EN>(0).unwrap(); // OuterIndex
+        writer.write_u64::<EN>(4).unwrap(); // ObjectName
+        writer.write_u32::<EN>(0).unwrap(); // ObjectFlags
+        writer.write_i64::<EN>(export_data.len() as i64).unwrap(); // SerialSize
+        writer.write_i64::<EN>(total_header_size as i64).unwrap(); // SerialOffset
+        writer.write_all(&[0; 0xc]).unwrap(); // bForcedExport, bNotForClient, bNotForServer
+        writer.write_u128::<EN>(0).unwrap(); // PackageGuid
+        writer.write_u32::<EN>(0).unwrap(); // PackageFlags
+        writer.write_i32::<EN>(0).unwrap(); // bNotAlwaysLoadedForEditorGame
+        writer.write_i32::<EN>(1).unwrap(); // bIsAsset
+        writer.write_i32::<EN>(-1).unwrap(); // FirstExportDependency
+        writer.write_all(&[0; 0x10]).unwrap(); // export dependency counts
+        assert_eq!(writer.position() as i32, total_header_size);
+        writer.rewind().unwrap();
+        write_summary(&mut writer, total_header_size, name_offset, import_offset, export_offset);
+        let mut uexp = export_data.to_vec();
+        uexp.extend_from_slice(&PACKAGE_ASSET_MAGIC.to_be_bytes());
+        (writer.into_inner(), uexp)
+    }
+
+    #[test]
+    fn read_unversioned_package_summary() {
+        let (asset, _) = get_cooked_package(&[1, 2, 3, 4]);
+        let package = CookedPackage::<PackageFileSummaryType4>::from_buffer::<_, EN>(&mut Cursor::new(asset.as_slice())).unwrap();
+        assert!(package.summary.is_unversioned());
+        assert!(package.summary.is_filter_editor_only());
+        assert_eq!(package.summary.get_file_version(), FILE_VERSION_UE4_LATEST);
+        assert_eq!(package.summary.get_total_header_size(), asset.len() as i32);
+        assert!(package.summary.get_features().contains(PackageFlags::UE4_64BIT_EXPORTMAP_SERIALSIZES | PackageFlags::UE4_NON_OUTER_PACKAGE_IMPORT));
+        assert!(!package.summary.get_features().contains(PackageFlags::UE4_HAS_OWNER_PERSISTENT_GUID));
+        assert_eq!(package.names.len(), TEST_NAMES.len());
+        assert_eq!(package.names[4], "TestAsset");
+        assert_eq!(package.imports.len(), 3);
+        assert_eq!(package.exports.len(), 1);
+        assert_eq!(package.exports[0].serial_size, 4);
+        assert_eq!(package.exports[0].serial_offset, asset.len() as i64);
+    }
+
+    #[test]
+    fn resolve_package_imports() {
+        let (asset, _) = get_cooked_package(&[]);
+        let package = CookedPackage::<PackageFileSummaryType4>::from_buffer::<_, EN>(&mut Cursor::new(asset.as_slice())).unwrap();
+        assert_eq!(
+            package.imports[1].resolve(&package.names, &package.imports).unwrap(),
+            IoStoreObjectIndex::ScriptImport("/Script/CoreUObject/Object".to_owned())
+        );
+        let imported_packages: Vec<&str> = package.imports.iter().filter_map(|i| i.get_imported_package_name(&package.names)).collect();
+        assert_eq!(imported_packages, [TEST_IMPORTED_PACKAGE]);
+    }
+
+    #[test]
+    fn read_export_data() {
+        let (asset, uexp) = get_cooked_package(&[1, 2, 3, 4]);
+        let package = CookedPackage::<PackageFileSummaryType4>::from_buffer::<_, EN>(&mut Cursor::new(asset.as_slice())).unwrap();
+        assert_eq!(package.get_export_data(&asset, Some(&uexp)).unwrap(), [1, 2, 3, 4]);
+        // the export data is after the header in packages that aren't split
+        let mut combined = asset.clone();
+        combined.extend_from_slice(&uexp);
+        assert_eq!(package.get_export_data(&combined, None).unwrap(), [1, 2, 3, 4]);
+        assert!(package.get_export_data(&asset, Some(&uexp[..4])).is_err());
+    }
+
+    #[test]
+    fn package_with_other_byte_order() {
+        let (mut asset, _) = get_cooked_package(&[]);
+        asset[..4].reverse();
+        let error = PackageFileSummaryType4::from_buffer::<_, EN>(&mut Cursor::new(asset.as_slice())).err().unwrap();
+        assert_eq!(error.to_string(), "Package was saved with a different byte order");
+    }
+
+    #[test]
+    fn ue4_package_read_as_ue5() {
+        let (asset, _) = get_cooked_package(&[]);
+        let error = PackageFileSummaryType5::from_buffer::<_, EN>(&mut Cursor::new(asset.as_slice())).err().unwrap();
+        assert_eq!(error.to_string(), "Package was saved by UE4, expected a UE5 package");
+    }
+}
@@ -1,9 +1,9 @@
-use std::fs::DirEntry;
+use std::{fs::DirEntry, io};
 
 #[cfg(target_os = "linux")]
 use std::os::linux;
 
-#[cfg(target_os = "unix")]
+#[cfg(all(unix, not(target_os = "linux")))]
 use std::os::unix;
 
 #[cfg(target_os = "windows")]
@@ -13,20 +13,20 @@ pub struct Metadata;
 
 impl Metadata {
     #[cfg(target_os = "linux")]
-    pub fn get_file_size(fs_obj: &DirEntry) -> u64 {
-        let meta = fs_obj.metadata().unwrap();
-        linux::fs::MetadataExt::st_size(&meta)
+    pub fn get_file_size(fs_obj: &DirEntry) -> io::Result<u64> {
+        let meta = fs_obj.metadata()?;
+        Ok(linux::fs::MetadataExt::st_size(&meta))
     }
 
-    #[cfg(target_os = "unix")]
-    pub fn get_file_size(fs_obj: &DirEntry) -> u64 {
-        let meta = fs_obj.metadata().unwrap();
-        linux::fs::MetadataExt::size(&meta)
+    #[cfg(all(unix, not(target_os = "linux")))]
+    pub fn get_file_size(fs_obj: &DirEntry) -> io::Result<u64> {
+        let meta = fs_obj.metadata()?;
+        Ok(unix::fs::MetadataExt::size(&meta))
     }
 
     #[cfg(target_os = "windows")]
-    pub fn get_file_size(fs_obj: &DirEntry) -> u64 {
-        let meta = fs_obj.metadata().unwrap();
-        windows::fs::MetadataExt::file_size(&meta)
+    pub fn get_file_size(fs_obj: &DirEntry) -> io::Result<u64> {
+        let meta = fs_obj.metadata()?;
+        Ok(windows::fs::MetadataExt::file_size(&meta))
     }
 }
\ No newline at end of file
@@ -38,7 +38,7 @@ pub trait FStringSerializerBlockAlign {
     fn get_block_alignment() -> u64;
     #[inline]
     fn to_buffer_alignment<W: Write + Seek, E: byteorder::ByteOrder>(writer: &mut W) {
-        to_buffer_alignment_super::<Self, W, E>(writer);
+        to_buffer_alignment_super::<Self, W>(writer);
     }
 }
 pub trait FStringSerializerExpectedLength {
@@ -51,8 +51,7 @@ pub trait FStringSerializerExpectedLength {
 // (Java classes in university gave me OOP brainrot)
 fn to_buffer_alignment_super<
         T: FStringSerializerBlockAlign + ?Sized, 
-        W: Write + Seek, 
-        E: byteorder::ByteOrder
+        W: Write + Seek
     >(writer: &mut W) {
     let align = writer.stream_position().unwrap() % T::get_block_alignment();
     if align == 0 {
@@ -73,10 +72,16 @@ pub struct FString32NoHash;
     // 0x4: data: [u8; len]
 impl FString32NoHash {
     fn from_buffer_inner<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Option<String>, Box<dyn Error>> {
-        let len = reader.read_u32::<E>()?; // length
-        if len < 1 {
+        let len = reader.read_i32::<E>()?; // length
+        if len == 0 {
             return Ok(None); // we correctly parsed it, there's just nothing there lol
         }
+        if len < 0 { // negative length is a UTF-16 string
+            let mut buf = vec![0; (-len - 1) as usize];
+            reader.read_u16_into::<E>(&mut buf)?;
+            reader.seek(SeekFrom::Current(2));
+            return Ok(Some(String::from_utf16(&buf)?));
+        }
         let mut buf = vec![0; (len - 1) as usize]; // get rid of that pesky \0
         reader.read_exact(&mut buf);
         reader.seek(SeekFrom::Current(1));
@@ -156,13 +161,13 @@ impl FString16 {
         Hasher::get_cityhash64(rstr)
     }
 
-    fn to_buffer_text_inner<W: Write, E: byteorder::ByteOrder>(rstr: &str, writer: &mut W) -> Result<(), Box<dyn Error>> {
+    fn to_buffer_text_inner<W: Write>(rstr: &str, writer: &mut W) -> Result<(), Box<dyn Error>> {
         writer.write_u16::<byteorder::BigEndian>(rstr.len().try_into()?)?; // length
         writer.write_all(rstr.as_bytes());
         Ok(())
     }
     fn to_buffer_hash_inner<W: Write, E: byteorder::ByteOrder>(rstr: &str, writer: &mut W) -> Result<(), Box<dyn Error>> {
-        writer.write_u64::<E>(Hasher::get_cityhash64(&rstr));
+        writer.write_u64::<E>(Hasher::get_cityhash64(rstr));
         Ok(())
     }
 }
@@ -181,14 +186,14 @@ impl FStringDeserializer for FString16 {
 }
 impl FStringSerializer for FString16 {
     fn to_buffer<W: Write, E: byteorder::ByteOrder>(rstr: &str, writer: &mut W) -> Result<(), Box<dyn Error>> {
-        FString16::to_buffer_text_inner::<W, E>(rstr, writer);
+        FString16::to_buffer_text_inner::<W>(rstr, writer);
         FString16::to_buffer_hash_inner::<W, E>(rstr, writer);
         Ok(())
     }
 }
 impl FStringSerializerText for FString16 {
     fn to_buffer_text<W: Write, E: byteorder::ByteOrder>(rstr: &str, writer: &mut W) -> Result<(), Box<dyn Error>> {
-        FString16::to_buffer_text_inner::<W, E>(rstr, writer);
+        FString16::to_buffer_text_inner::<W>(rstr, writer);
         Ok(())
     }
 }
@@ -203,7 +208,7 @@ impl FStringSerializerBlockAlign for FString16 {
         8
     }
     fn to_buffer_alignment<W: Write + Seek, E: byteorder::ByteOrder>(writer: &mut W) {
-        to_buffer_alignment_super::<Self, W, E>(writer);
+        to_buffer_alignment_super::<Self, W>(writer);
         writer.write_u64::<E>(NAME_HASH_ALGORITHM);
     }
 }
@@ -231,6 +236,45 @@ impl Hasher16 {
     }
 }
 
+// Serialized list of FNames used by UE5 Zen packages and container headers (see SaveNameBatch)
+pub struct FNameBatch;
+    // 0x0:         count: u32 (nothing else is written if this is 0)
+    // 0x4:         string_bytes: u32
+    // 0x8:         hash_version: u64
+    // 0x10:        hashes: [u64; count]
+    //              headers: [u16; count] (big endian, highest bit set for UTF-16 strings)
+    //              data: [u8; string_bytes]
+
+impl FNameBatch {
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Vec<String>, Box<dyn Error>> {
+        let count = reader.read_u32::<E>()?;
+        if count == 0 {
+            return Ok(vec![]);
+        }
+        reader.read_u32::<E>()?; // string bytes
+        reader.read_u64::<E>()?; // hash version
+        reader.seek(SeekFrom::Current(count as i64 * 8))?; // hashes
+        let mut headers = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            headers.push(reader.read_u16::<byteorder::BigEndian>()?);
+        }
+        let mut names = Vec::with_capacity(count as usize);
+        for header in headers {
+            let len = (header & 0x7fff) as usize;
+            if header & 0x8000 != 0 { // UTF-16
+                let mut buf = vec![0; len];
+                reader.read_u16_into::<byteorder::LittleEndian>(&mut buf)?;
+                names.push(String::from_utf16(&buf)?);
+            } else {
+                let mut buf = vec![0; len];
+                reader.read_exact(&mut buf)?;
+                names.push(buf.iter().map(|c| *c as char).collect());
+            }
+        }
+        Ok(names)
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct FMappedName(u32, u32); // NameIndex, ExtraIndex
 // first field is index in name map
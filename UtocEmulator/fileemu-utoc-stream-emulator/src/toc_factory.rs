@@ -1,5 +1,7 @@
+use byteorder::WriteBytesExt;
 use std::{
     cell::RefCell,
+    collections::BTreeMap,
     error::Error,
     path::{Path, PathBuf},
     fs, fs::{DirEntry, File},
@@ -7,26 +9,30 @@ use std::{
     mem,
     pin::Pin,
     //rc::{Rc, Weak},
-    sync::{Arc, Mutex, MutexGuard, RwLock, Weak},
+    sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, MutexGuard, RwLock, Weak},
     time::Instant,
 };
 use crate::{
+    asset_collector,
     asset_collector::{
-        MOUNT_POINT, SUITABLE_FILE_EXTENSIONS, ROOT_DIRECTORY, 
+        ASSET_COLLECTOR_PROFILER, COOKED_EXPORT_DATA_EXTENSION, EMULATOR_NAME, MOUNT_POINT, SUITABLE_FILE_EXTENSIONS,
         TocDirectory, TocDirectorySyncRef, TocFile, TocFileSyncRef},
+    compression::CompressionMethod,
+    encryption, encryption::EncryptionKey,
     io_package::{
-        ContainerHeaderPackage,
-        ExportBundle, ExportBundleHeader4,
-        PackageIoSummaryDeserialize, 
-        PackageSummary2},
+        ContainerHeaderPackage, ContainerHeaderPosition, ContainerHeaderPosition1, ContainerHeaderPosition2,
+        PackageIoSummaryDeserialize, PackageIoSummarySerialize,
+        PackageSummary1, PackageSummary2, ZenPackageSummaryType1, ZenPackageSummaryType2},
     io_toc::{
         IO_FILE_INDEX_ENTRY_SERIALIZED_SIZE,
-        ContainerHeader, 
-        IoChunkId, IoChunkType4, IoDirectoryIndexEntry, IoFileIndexEntry, 
-        IoStringPool, IoStoreTocEntryMeta, 
-        IoStoreTocHeaderCommon, IoStoreTocHeaderType2, IoStoreTocHeaderType3,
-        IoStoreTocCompressedBlockEntry, IoOffsetAndLength
+        ContainerHeader, IoContainerHeaderVersion, IoContainerId, IoPerfectHash,
+        GUID, IoChunkId, IoChunkType4, IoDirectoryIndexEntry, IoFileIndexEntry, 
+        IoStringPool, IoStoreTocEntryMeta, IoStoreTocVersion,
+        IoStoreTocHeaderCommon, IoStoreTocHeaderType1, IoStoreTocHeaderType2, IoStoreTocHeaderType3, IoStoreTocHeaderType4,
+        IoStoreTocCompressedBlockEntry, IoOffsetAndLength, IoChunk1, TocEntry
     },
+    pak_package::{GameName, GameNameImpl},
+    error::{EmulatorError, EmulatorResult},
     platform::Metadata,
     string::{FString32NoHash, FStringSerializer, FStringSerializerExpectedLength, Hasher, Hasher16}
 };
@@ -38,55 +44,178 @@ use crate::{
 //      - Sorting file entries within folders by file size
 //      - Using the default compression alignment for each version
 //      - Make the root mount folder have no name
-//      - (Still won't generate metas by default though, that takes too long)
-//  - Support for Type 1 (4.25) and Zen (5.0+) (currently only Type2 is supported (4.25+, 4.26, 4.27))
+//  - Test Zen (5.0+) support on more games (Type2 (4.25+, 4.26, 4.27) and Type3 (5.0 - 5.3) are supported)
+//  - Build 4.25 (Type1) containers, which needs the game's global package store to give mod packages an index
 //  - Include benchmarking and code coverage tools as per the Reloaded's Rust template - 
 //      https://github.com/Reloaded-Project/reloaded-templates-rust
-pub const TOC_NAME:     &'static str = "UnrealEssentials";
-pub const TARGET_TOC:   &'static str = "UnrealEssentials.utoc";
-pub const TARGET_CAS:   &'static str = "UnrealEssentials.ucas";
-
-pub static CONTAINER_ENTRIES_OSPATH_POOL: Mutex<Option<Vec<String>>> = Mutex::new(None);
-pub static CONTAINER_DATA: Mutex<Option<ContainerData>> = Mutex::new(None);
-
-pub fn build_table_of_contents(toc_path: &str, version: u32) -> Option<Vec<u8>> {
-    let path_check = PathBuf::from(toc_path); // build TOC here
-    let file_name = path_check.file_name().unwrap().to_str().unwrap(); // unwrap, this is a file
-    if file_name == TARGET_TOC { // check that we're targeting the correct UTOC
-        let root_dir_lock = ROOT_DIRECTORY.lock().unwrap();
-        match (*root_dir_lock).as_ref() {
-            Some(root) => Some(build_table_of_contents_inner(Arc::clone(root), toc_path)),
-            None => {
-                println!("WARNING: No mod files were loaded for {}", file_name);
-                None
+// Name of the container that mods go into unless they target another one (see add_from_folders_to_container)
+pub const TOC_NAME:     &str = "UnrealEssentials";
+pub const TARGET_TOC:   &str = "UnrealEssentials.utoc";
+pub const TARGET_CAS:   &str = "UnrealEssentials.ucas";
+pub const TOC_EXTENSION: &str = ".utoc";
+pub const CAS_EXTENSION: &str = ".ucas";
+// Packages in the project's Content folder are mounted at /Game/, and the engine's at /Engine/
+pub const GAME_MOUNT_NAME:   &str = "/Game";
+pub const ENGINE_NAME:       &str = "Engine";
+pub const ENGINE_MOUNT_NAME: &str = "/Engine";
+// Plugins have their own Content folder, which is mounted at /[PluginName]/ (e.g [ProjectName]/Plugins/GameFeatures/[PluginName]/Content)
+pub const PLUGINS_NAME:      &str = "Plugins";
+pub const CONTENT_NAME:      &str = "Content";
+// Folder inside of the temp directory where cooked packages are written to after being converted
+pub const CONVERTED_PACKAGE_CACHE: &str = "ConvertedPackages";
+// Folder inside of the temp directory where chunks are written to after being compressed or encrypted
+pub const COMPRESSED_CHUNK_CACHE: &str = "CompressedChunks";
+const PARTITIONS_UNSUPPORTED: &str = "Containers for this version can't be split into partitions";
+
+// Settings for containers that are baked ahead of time (see utoc-emu). The emulated container uses EMULATED_TOC_OPTIONS
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TocBuildOptions {
+    pub compression: CompressionMethod,
+    pub encryption: Option<(GUID, EncryptionKey)>, // encrypt the container with this key, and store it's GUID in the TOC header
+    pub sign: bool, // write a SHA1 hash of each block into the TOC (see write_signatures)
+    pub partition_size: Option<u64> // split the container into .ucas files of at most this many bytes (4.27+)
+}
+
+impl TocBuildOptions {
+    // Chunks can only be streamed straight from their file if nothing needs to be done to their data
+    fn is_encoded(&self) -> bool {
+        self.compression != CompressionMethod::None || self.encryption.is_some() || self.sign
+    }
+}
+
+// Options for the emulated container, set through SetContainerSigning. This is also used when the TOC is hot reloaded
+pub static EMULATED_TOC_OPTIONS: Mutex<Option<TocBuildOptions>> = Mutex::new(None);
+// Mount points forced for each container with set_mount_point. Other containers are mounted at the deepest directory that contains
+// every file in their tree (see get_mount_root)
+pub static MOUNT_POINTS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+pub fn build_table_of_contents(toc_path: &str, version: u32) -> EmulatorResult<TableOfContents> {
+    let options = EMULATED_TOC_OPTIONS.lock().unwrap().unwrap_or_default();
+    build_table_of_contents_with_options(toc_path, version, &options)
+}
+
+// The TOC's file name decides which container's tree gets built
+pub fn build_table_of_contents_with_options(toc_path: &str, version: u32, options: &TocBuildOptions) -> EmulatorResult<TableOfContents> {
+    let container_lock = asset_collector::get_container_lock(get_container_name(toc_path));
+    build_locked_table_of_contents(toc_path, version, options, container_lock.lock().unwrap())
+}
+
+// Same as build_table_of_contents_with_options, for callers that already hold the container's lock (see get_container_lock). The lock
+// is released once the container's tree has been read
+pub fn build_locked_table_of_contents(
+    toc_path: &str, version: u32, options: &TocBuildOptions, container_guard: MutexGuard<()>
+) -> EmulatorResult<TableOfContents> {
+    if !toc_path.ends_with(TOC_EXTENSION) {
+        return Err(EmulatorError::InvalidArgument(format!("\"{}\" isn't a TOC", toc_path)));
+    }
+    let container_name = get_container_name(toc_path);
+    match asset_collector::get_container_root(container_name) {
+        Some(root) => build_table_of_contents_inner(root, toc_path, version, options, Some(container_guard)),
+        None => Err(EmulatorError::NoModFiles(format!("No mod files were loaded for {}", container_name)))
+    }
+}
+
+// Force a container to be mounted at mount_point (e.g ../../../Game/Plugins/[Plugin]/Content/), or go back to working it out
+// from the container's files if it's None. Mount points are relative to the game's root, so they always start with ../../../
+pub fn set_mount_point(container_name: &str, mount_point: Option<&str>) -> EmulatorResult<()> {
+    let mount_point = match mount_point {
+        Some(m) => {
+            let mut m = m.replace('\\', "/");
+            if !m.starts_with(MOUNT_POINT) {
+                return Err(EmulatorError::InvalidArgument(format!("Mount point \"{}\" doesn't start with {}", m, MOUNT_POINT)));
             }
-        }
-    } else {
-        None // Not our target TOC
+            if !m.ends_with('/') {
+                m.push('/');
+            }
+            Some(m)
+        },
+        None => None
+    };
+    let mut mount_points = MOUNT_POINTS.lock().unwrap();
+    mount_points.retain(|name, _| !name.eq_ignore_ascii_case(container_name));
+    if let Some(m) = mount_point {
+        mount_points.insert(container_name.to_owned(), m);
     }
+    Ok(())
 }
-/* 
-pub fn build_container_test(cas_path: &str) {
-    use std::ffi::CStr;
-    use byteorder::WriteBytesExt;
-    let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
 
-    let container_data = unsafe { CONTAINER_DATA.as_ref().unwrap() };
-    for i in &container_data.virtual_blocks {
-        let file_name = unsafe { CStr::from_ptr(i.os_path as *const i8).to_str().unwrap() };
-        let vec = fs::read(file_name).unwrap();
-        println!("cursor at 0x{:x} for {}", writer.stream_position().unwrap(), file_name);
-        writer.write_all(&vec).unwrap();
-        let alignment = writer.stream_position().unwrap() as u32 % DEFAULT_COMPRESSION_BLOCK_ALIGNMENT;
-        if alignment > 0 {
-            let diff = DEFAULT_COMPRESSION_BLOCK_ALIGNMENT - alignment;
-            writer.seek(SeekFrom::Current(diff as i64));
+fn get_mount_point(container_name: &str) -> Option<String> {
+    MOUNT_POINTS.lock().unwrap().iter().find(|(name, _)| name.eq_ignore_ascii_case(container_name)).map(|(_, m)| m.clone())
+}
+
+// Get the mapping from a path's content folder onto it's mount name. Folder names are case insensitive, so a mod that uses
+// p3r instead of P3R still maps onto /Game/. The project folder comes from the mod that the file came from (see
+// add_from_folders_for_project), and if it doesn't have one, every top-level folder besides Engine is treated as the project's folder
+fn get_game_name(hash_path: &str, mod_id: &str) -> EmulatorResult<GameNameImpl> {
+    let components: Vec<&str> = hash_path.split('/').collect();
+    let root = components[0];
+    let is_engine = root.eq_ignore_ascii_case(ENGINE_NAME);
+    if !is_engine {
+        if let Some(project_name) = asset_collector::get_project_name(mod_id).filter(|p| !root.eq_ignore_ascii_case(p)) {
+            return Err(EmulatorError::InvalidAsset(
+                hash_path.to_owned(), format!("Path isn't inside of the {} or {} folder", project_name, ENGINE_NAME)
+            ));
         }
     }
-    writer.write_all(&container_data.header).unwrap();
-    fs::write(cas_path, &writer.into_inner()).unwrap();
+    if let Some(plugin_index) = get_plugin_index(&components) {
+        let plugin_path = components[..=plugin_index].join("/");
+        return Ok(GameNameImpl::new(&plugin_path, &(String::from("/") + components[plugin_index])));
+    }
+    Ok(GameNameImpl::new(root, if is_engine { ENGINE_MOUNT_NAME } else { GAME_MOUNT_NAME }))
+}
+
+// Plugins can be grouped into folders inside of Plugins (e.g Plugins/Runtime/[PluginName]), so the plugin's folder is the one
+// that contains the first Content folder. The last component is the file name, which can't be a Content folder
+fn get_plugin_index(components: &[&str]) -> Option<usize> {
+    if !components.get(1)?.eq_ignore_ascii_case(PLUGINS_NAME) {
+        return None;
+    }
+    (2..components.len().saturating_sub(2)).find(|i| components[i + 1].eq_ignore_ascii_case(CONTENT_NAME))
+}
+
+// Find the directory that the container gets mounted at, along with it's mount point. Directories above it only contain the next
+// directory down, so they're part of the mount point instead of the directory index. A forced mount point can be higher up than
+// that, but not any deeper, since every file has to be inside of it
+fn get_mount_root(root: TocDirectorySyncRef, forced_mount_point: Option<&str>) -> EmulatorResult<(TocDirectorySyncRef, String)> {
+    let mut shared_dirs = vec![root];
+    loop {
+        let only_child = {
+            let dir = shared_dirs.last().unwrap().read().unwrap();
+            match dir.first_child.as_ref() {
+                Some(child) if dir.first_file.is_none() && child.read().unwrap().next_sibling.is_none() => Arc::clone(child),
+                _ => break
+            }
+        };
+        shared_dirs.push(only_child);
+    }
+    let shared_names: Vec<String> = shared_dirs[1..].iter().map(|d| d.read().unwrap().name.clone().unwrap_or_default()).collect();
+    let deepest_mount_point: String = MOUNT_POINT.to_owned() + &shared_names.iter().map(|n| format!("{}/", n)).collect::<String>();
+    match forced_mount_point {
+        Some(mount_point) => {
+            let components: Vec<&str> = mount_point[MOUNT_POINT.len()..].split('/').filter(|c| !c.is_empty()).collect();
+            if components.len() > shared_names.len() || components.iter().zip(&shared_names).any(|(c, n)| !c.eq_ignore_ascii_case(n)) {
+                return Err(EmulatorError::InvalidArgument(format!(
+                    "Mount point \"{}\" doesn't contain every file in the container, which can be mounted at {} at the deepest",
+                    mount_point, deepest_mount_point
+                )));
+            }
+            Ok((Arc::clone(&shared_dirs[components.len()]), mount_point.to_owned()))
+        },
+        None => Ok((Arc::clone(shared_dirs.last().unwrap()), deepest_mount_point))
+    }
+}
+
+// Path of a container's TOC inside of the folder that the C# side serves emulated files from
+pub fn get_emulated_toc_path(base_path: &str, container_name: &str) -> String {
+    format!("{}\\{}{}", base_path, container_name, TOC_EXTENSION)
+}
+
+// The container's name is the TOC's file name without it's extension. Both separators are checked since emulated TOC paths always
+// use \ (see get_emulated_toc_path)
+pub fn get_container_name(toc_path: &str) -> &str {
+    let file_name = toc_path.rsplit(['/', '\\']).next().unwrap_or_default();
+    file_name.strip_suffix(TOC_EXTENSION).unwrap_or(file_name)
 }
-*/
 
 // Creates a TOC + CAS given a list of loose directories and files
 // This currently only officially supports 4.25+, 4.26 and 4.27, but TocResolver is implemented in a way that will hopefully make adding support for new versions of
@@ -96,71 +225,335 @@ pub fn build_container_test(cas_path: &str) {
 // 4.25 features a very different UTOC structure compared to the other versions, and it's pretty clear from that implementation that IO Store was still a work in progress.
 // The TOC structure only contains a smaller header followed by a list of toc entries, containing a chunk id (with a different format!) and a offset + length
 // There isn't even a container header in the UCAS, so it's fair to say that this is different enough to warrant it's own TocResolver.
+// Chunk ids use the package's index in the game's global package store rather than a hash of it's path, so containers with mod
+// packages can't be built for 4.25 (see TocResolverType1::serialize)
 //  TocResolver2 (4.25+, 4.26-4.27)
 // TocResolver2 handles a TOC which contains a list of chunk ids, followed by a list of offsets and lengths, then a list of compression blocks, then the directory index
 // (mount point, files, folders and strings) and ends with a "meta" block containing SHA1 hashes of each file
 // The CAS contains each file combined into a single stream, with a container header located at the end
 // The only notable difference between 4.25+/4.26 and 4.27 is the inclusion of a partition size and partition count field that allows for the CAS to
 // be broken into multiple files. This is where a custom IoStoreTocHeader type can be passed
-// UE5 will be dealt with at a later date
+//  TocResolver3 (5.0 - 5.3)
+// UE5 keeps the same layout as 4.27, but the chunk id list is sorted using a perfect hash, with the seeds for that (and a list of chunks
+// that couldn't fit into it) placed after the offsets and lengths. The container header also gained a signature and version, and it's
+// store entries no longer contain a load order
 // NOTE for Scarlet Nexus (4.25+) - container header is at top
-// 4.25+ and 4.26 make their container file the *first* file in the list, while it's the last in 4.27
-// pub struct TocResolverType1; // 4.25 only (only has header + toc entries (chunk id, offset and length))
-pub trait TocResolverCommon { // Currently for 4.25+, 4.26 and 4.27
+// 4.25+ and 4.26 make their container file the *first* file in the list, while it's the last in 4.27 (see ContainerHeaderPosition)
+pub trait TocResolverCommon { // Currently for 4.25, 4.25+, 4.26, 4.27 and 5.0 - 5.3
     //type TocHeaderType: IoStoreTocHeaderCommon; // make TocHeader (IoStoreTocHeaderType2 or IoStoreTocHeaderType3)
     //type ContainerHeaderType: PackageIoSummaryDeserialize; // Container Header in UCAS
     fn new<THeaderType: IoStoreTocHeaderCommon>(toc_name: &str, block_align: u32) -> impl TocResolverCommon;
 
     fn flatten_toc_tree(&mut self, tracker: &mut TocFlattenTracker, root: TocDirectorySyncRef);
 
+    // 4.25 doesn't have a directory index, so there's no mount point
+    fn set_mount_point(&mut self, mount_point: String) {}
+
     fn serialize<
-        TSummary: PackageIoSummaryDeserialize,
-        TIoTocHeader: IoStoreTocHeaderCommon
-    >(&mut self, profiler: &mut TocBuilderProfiler, toc_path: &str) -> (Vec<u8>, ContainerData);
+        TSummary: PackageIoSummaryDeserialize + PackageIoSummarySerialize,
+        TIoTocHeader: IoStoreTocHeaderCommon,
+        TPosition: ContainerHeaderPosition
+    >(&mut self, profiler: &mut TocBuilderProfiler, toc_path: &str) -> EmulatorResult<(Vec<u8>, ContainerData)>;
+
+    // 4.25 has no compression blocks, so chunks can't be compressed, encrypted or signed
+    fn set_build_options(&mut self, options: &TocBuildOptions) -> EmulatorResult<()> {
+        if options.is_encoded() {
+            return Err(EmulatorError::UnsupportedVersion("Containers for this version can't be compressed, encrypted or signed".to_owned()));
+        }
+        match options.partition_size {
+            None => Ok(()),
+            Some(_) => Err(EmulatorError::UnsupportedVersion(PARTITIONS_UNSUPPORTED.to_owned()))
+        }
+    }
 
     // Common across all versions
-    fn create_chunk_id(&self, file_path: &str, chunk_type: IoChunkType4) -> IoChunkId {
-        // remove Content from path
-        let path_to_replace_split = file_path.split_once("/Content").unwrap();
-        let path_to_replace = "/".to_owned() + path_to_replace_split.0 + path_to_replace_split.1;
-        println!("{}", path_to_replace);
-        IoChunkId::new(&path_to_replace, chunk_type)
-        /* 
-        // replace [BaseDirectory]/Content with /Game/
-        //let path_to_replace = PROJECT_NAME.to_owned() + "/Content";
-        let path_to_replace =  "Game/Content";
-        if let Some((_, suffix)) = file_path.to_owned().split_once(&path_to_replace) {
-            let path_to_hash = String::from("/Game") + suffix;
-            println!("{}", path_to_hash);
-            IoChunkId::new(&path_to_hash, chunk_type)
-        } else {
-            panic!("Path \"{}\" is missing root containing project name + content. Path components were not handled properly", file_path);
-        }
-        */
-    }
-
-    fn get_file_hash(&self, curr_file: &IoFileIndexEntry) -> IoChunkId {
-        // unwrap a bunch. any errors related to this would've been handled in the asset collection stage
-        let chunk_type = match SUITABLE_FILE_EXTENSIONS.iter().find(
-                |exist| **exist == PathBuf::from(&curr_file.os_path).extension().unwrap().to_str().unwrap()
-            ) {
-            Some(io_ext) => {
-                match *io_ext {
-                    "uasset" | "umap" => IoChunkType4::ExportBundleData, //.uasset, .umap
-                    "ubulk" => IoChunkType4::BulkData, // .ubulk
-                    "uptnl" => IoChunkType4::OptionalBulkData, // .uptnl
-                    _ => panic!("CRITICAL ERROR: Did not get a supported file extension. This should've been handled earlier")
+    fn create_chunk_id(&self, file_path: &str, mod_id: &str, chunk_type: IoChunkType4) -> EmulatorResult<IoChunkId> {
+        // replace [ProjectName]/Content with /Game (or Engine/Content with /Engine, [Root]/Plugins/[PluginName]/Content with /[PluginName])
+        let game_path = get_game_name(file_path, mod_id)?.project_path_to_game_path(file_path)
+            .map_err(|e| EmulatorError::InvalidAsset(file_path.to_owned(), e.to_owned()))?;
+        Ok(IoChunkId::new(&game_path, chunk_type))
+    }
+
+    fn get_file_hash(&self, curr_file: &IoFileIndexEntry) -> EmulatorResult<IoChunkId> {
+        // unsupported extensions should've been skipped, see add_from_folders_inner in asset_collector.rs
+        let os_path = PathBuf::from(&curr_file.os_path);
+        let extension = os_path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+        let chunk_type = match extension {
+            "uasset" | "umap" => IoChunkType4::ExportBundleData, //.uasset, .umap
+            "ubulk" => IoChunkType4::BulkData, // .ubulk
+            "uptnl" => IoChunkType4::OptionalBulkData, // .uptnl
+            _ => return Err(EmulatorError::InvalidAsset(
+                curr_file.os_path.clone(), format!("Can't create a chunk id for file extension \"{}\"", extension)
+            ))
+        };
+        self.create_chunk_id(&curr_file.hash_path, &curr_file.mod_id, chunk_type)
+    }
+
+}
+
+// travel upwards through parents to build the path that gets hashed into the file's chunk id
+fn get_hash_path(node: TocDirectorySyncRef, file_name: &str) -> String {
+    let mut path_comps: Vec<String> = vec![];
+    let mut curr_parent = node;
+    loop {
+        if let Some(t) = curr_parent.read().unwrap().name.as_ref() {
+            path_comps.insert(0, t.to_owned());
+        }
+        match Arc::clone(&curr_parent).read().unwrap().parent.upgrade() {
+            Some(ip) => curr_parent = Arc::clone(&ip),
+            None => break
+        }
+    }
+    let filename_buf = PathBuf::from(file_name);
+    // file names come from valid Unicode names in the asset collector
+    path_comps.join("/") + "/" + filename_buf.file_stem().and_then(|s| s.to_str()).unwrap_or_default()
+}
+
+// Convert a single cooked package, returning the path and size of the converted package. Packages that were already converted from
+// the same files are reused from the cache
+fn convert_cooked_package<TSummary: PackageIoSummarySerialize>(os_path: &str, hash_path: &str, mod_id: &str) -> Result<(String, u64), Box<dyn Error>> {
+    let uexp_path = PathBuf::from(os_path).with_extension(COOKED_EXPORT_DATA_EXTENSION);
+    let game_name = get_game_name(hash_path, mod_id)?;
+    let asset = fs::read(os_path)?;
+    let uexp = if uexp_path.exists() { Some(fs::read(uexp_path)?) } else { None };
+    // The package's path in the game and the summary type change what it's converted into, so they're part of the key too
+    let cache_key = format!("{}:{}", game_name.project_path_to_game_path(hash_path)?, std::any::type_name::<TSummary>());
+    let extension = Path::new(os_path).extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let cache_path = get_cache_path(CONVERTED_PACKAGE_CACHE, &cache_key, &[&asset, uexp.as_deref().unwrap_or_default()], extension)?;
+    if !cache_path.exists() {
+        let converted = TSummary::from_cooked_package::<GameNameImpl, byteorder::NativeEndian>(&asset, uexp.as_deref(), hash_path, &game_name)?;
+        write_cache_file(&cache_path, &converted)?;
+    }
+    Ok((cache_path.to_str().ok_or("Cache path isn't valid Unicode")?.to_owned(), fs::metadata(&cache_path)?.len()))
+}
+
+// Cache files are named after a hash of everything that they're made from, so a rebuild never writes over a file that the container
+// from an earlier build is still streaming from. A file that's already in the cache has the same contents, so it gets reused
+fn get_cache_path(cache_name: &str, key: &str, sources: &[&[u8]], extension: &str) -> io::Result<PathBuf> {
+    let mut cache_path = std::env::temp_dir();
+    cache_path.push(EMULATOR_NAME);
+    cache_path.push(cache_name);
+    fs::create_dir_all(&cache_path)?;
+    let key_hash: u64 = cityhasher::hash(key);
+    let source_hash = sources.iter().fold(key_hash, |hash, source| cityhasher::hash_with_seed(source, hash));
+    cache_path.push(format!("{:016x}{:016x}.{}", key_hash, source_hash, extension));
+    Ok(cache_path)
+}
+
+// Write a cache file through a temporary file, so that one that was only partially written is never used. If another build wrote the
+// same file first, it has the same contents and is kept
+fn write_cache_file(cache_path: &Path, data: &[u8]) -> io::Result<()> {
+    static TEMP_FILE_INDEX: AtomicU64 = AtomicU64::new(0);
+    let temp_path = cache_path.with_extension(format!("{}.{}.tmp", std::process::id(), TEMP_FILE_INDEX.fetch_add(1, Ordering::Relaxed)));
+    fs::write(&temp_path, data)?;
+    let result = fs::rename(&temp_path, cache_path);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        if cache_path.exists() {
+            return Ok(());
+        }
+    }
+    result
+}
+
+// Chunks can't be split between partitions, since each block is read from the partition that it's offset is in. Move to the start
+// of the next partition if there isn't room for the chunk in this one
+fn reserve_partition_space(cas_pointer: u64, size: u64, partition_size: Option<u64>) -> Result<u64, String> {
+    let partition_size = match partition_size {
+        Some(s) => s,
+        None => return Ok(cas_pointer)
+    };
+    if size > partition_size {
+        return Err(format!("It's {} bytes, which is bigger than the partition size ({} bytes)", size, partition_size));
+    }
+    let partition_offset = cas_pointer % partition_size;
+    match partition_offset + size > partition_size {
+        true => Ok(cas_pointer + partition_size - partition_offset),
+        false => Ok(cas_pointer)
+    }
+}
+
+fn align_cas_pointer(cas_pointer: u64, alignment: u32) -> u64 {
+    let alignment_amount = cas_pointer % alignment as u64;
+    if alignment_amount > 0 { // align to compression block alignment
+        cas_pointer + alignment as u64 - alignment_amount
+    } else {
+        cas_pointer
+    }
+}
+
+pub struct TocResolverType1 { // 4.25 only (only has header + toc entries (chunk id, offset and length))
+    pub files: Vec<IoFileIndexEntry>,
+    block_alignment: u32,
+    pub entries: Vec<TocEntry>,
+    pub cas_pointer: u64, // Current position of container file. 4.25 has no compression blocks, so this is also the chunk's offset
+}
+
+impl TocResolverCommon for TocResolverType1 {
+    fn new<
+        THeaderType: IoStoreTocHeaderCommon
+    >(toc_name: &str, block_align: u32) -> impl TocResolverCommon {
+        Self {
+            files: vec![], // There's no directory index in 4.25, files are only used to create toc entries
+            block_alignment: if block_align < 0x10 { 0x10 } else { block_align },
+            entries: vec![],
+            cas_pointer: 0
+        }
+    }
+    // Flatten the tree of directories + files into a list of files
+    fn flatten_toc_tree(&mut self, tracker: &mut TocFlattenTracker, root: TocDirectorySyncRef) {
+        if TocDirectory::has_files(Arc::clone(&root)) {
+            let mut curr_file = Arc::clone(root.read().unwrap().first_file.as_ref().unwrap());
+            loop {
+                let file_name = curr_file.read().unwrap().name.clone();
+                self.files.push(IoFileIndexEntry {
+                    name: u32::MAX,
+                    next_file: u32::MAX,
+                    user_data: tracker.resolved_files,
+                    file_size: curr_file.read().unwrap().file_size,
+                    os_path: curr_file.read().unwrap().os_file_path.clone(),
+                    hash_path: get_hash_path(Arc::clone(&root), &file_name),
+                    mod_id: curr_file.read().unwrap().mod_id.clone(),
+                    cooked_package: curr_file.read().unwrap().cooked_package
+                });
+                tracker.resolved_files += 1;
+                match Arc::clone(&curr_file).read().unwrap().next.as_ref() {
+                    Some(next) => curr_file = Arc::clone(next),
+                    None => break
                 }
             }
-            // this file should've been skipped, see add_from_folders_inner in asset_collector.rs
-            None => panic!("CRITICAL ERROR: Did not get a supported file extension. This should've been handled earlier")
-        };
-        self.create_chunk_id(&curr_file.hash_path, chunk_type)
+        }
+        tracker.resolved_directories += 1;
+        if TocDirectory::has_children(Arc::clone(&root)) {
+            let mut curr_child = Arc::clone(root.read().unwrap().first_child.as_ref().unwrap());
+            loop {
+                self.flatten_toc_tree(tracker, Arc::clone(&curr_child));
+                match Arc::clone(&curr_child).read().unwrap().next_sibling.as_ref() {
+                    Some(next) => curr_child = Arc::clone(next),
+                    None => break
+                }
+            }
+        }
+    }
+    fn serialize<
+        TSummary: PackageIoSummaryDeserialize + PackageIoSummarySerialize,
+        TIoTocHeader: IoStoreTocHeaderCommon,
+        TPosition: ContainerHeaderPosition
+    >(
+        &mut self, 
+        profiler: &mut TocBuilderProfiler, 
+        toc_path: &str
+    ) -> EmulatorResult<(Vec<u8>, ContainerData)> {
+        type CV = Cursor<Vec<u8>>;
+        type EN = byteorder::NativeEndian;
+        let mut toc_storage: CV = Cursor::new(vec![]);
+        // There's no container header in 4.25, store entries are in the global package store (global.utoc) instead. Chunks are keyed
+        // by their package's index in it, which is fixed when the game is packaged, so mod packages have no index to use
+        if let Some(target_file) = self.files.first() {
+            return Err(EmulatorError::UnsupportedVersion(format!(
+                "UE 4.25 containers refer to packages by their index in the game's global package store, which mods can't be added to, \
+                so \"{}\" can't be put into a container", target_file.hash_path
+            )));
+        }
+        let container_data = ContainerData::new(vec![], 0, u64::MAX);
+        // Write our TOC
+        let toc_header = TIoTocHeader::new(IoContainerId::default(), self.entries.len() as u32, 0, 0, 0);
+        toc_header.to_buffer::  <CV, EN>(&mut toc_storage)?; // FIoStoreTocHeader
+        TocEntry::list_to_buffer::<CV, EN>(&self.entries, &mut toc_storage)?; // FIoStoreTocEntry
+
+        Ok((toc_storage.into_inner(), container_data))
     }
+}
 
+pub struct TocResolverType3 { // 5.0 - 5.3
+    base: TocResolverType2, // directory index and container layout are the same as 4.27
+    toc_version: IoStoreTocVersion,
 }
 
-pub const DEFAULT_COMPRESSION_BLOCK_ALIGNMENT: u32 = 0x800;
+impl TocResolverType3 {
+    pub fn new_with_version(toc_name: &str, block_align: u32, toc_version: IoStoreTocVersion, container_header_version: IoContainerHeaderVersion) -> Self {
+        Self {
+            base: TocResolverType2::new_inner(toc_name, block_align, container_header_version),
+            toc_version
+        }
+    }
+}
+
+impl TocResolverCommon for TocResolverType3 {
+    fn new<
+        THeaderType: IoStoreTocHeaderCommon
+    >(toc_name: &str, block_align: u32) -> impl TocResolverCommon {
+        Self::new_with_version(toc_name, block_align, IoStoreTocVersion::PerfectHashWithOverflow, IoContainerHeaderVersion::NoExportInfo)
+    }
+    fn flatten_toc_tree(&mut self, tracker: &mut TocFlattenTracker, root: TocDirectorySyncRef) {
+        self.base.flatten_toc_tree(tracker, root);
+    }
+    fn set_mount_point(&mut self, mount_point: String) {
+        self.base.set_mount_point(mount_point);
+    }
+    fn set_build_options(&mut self, options: &TocBuildOptions) -> EmulatorResult<()> {
+        self.base.set_build_options(options)
+    }
+    fn serialize<
+        TSummary: PackageIoSummaryDeserialize + PackageIoSummarySerialize,
+        TIoTocHeader: IoStoreTocHeaderCommon,
+        TPosition: ContainerHeaderPosition
+    >(
+        &mut self, 
+        profiler: &mut TocBuilderProfiler, 
+        toc_path: &str
+    ) -> EmulatorResult<(Vec<u8>, ContainerData)> {
+        type CV = Cursor<Vec<u8>>;
+        type EN = byteorder::NativeEndian;
+        let mut toc_storage: CV = Cursor::new(vec![]);
+        let container_data = self.base.layout_container::<TSummary, TPosition>()?;
+        // Sort chunks into their perfect hash slots. Compression blocks stay where they are, since offsets still point to them
+        let perfect_hash = IoPerfectHash::new::<EN>(&self.base.chunk_ids, self.toc_version >= IoStoreTocVersion::PerfectHashWithOverflow);
+        self.base.chunk_ids = perfect_hash.reorder(mem::take(&mut self.base.chunk_ids));
+        self.base.offsets_and_lengths = perfect_hash.reorder(mem::take(&mut self.base.offsets_and_lengths));
+        self.base.metas = perfect_hash.reorder(mem::take(&mut self.base.metas));
+        for file in &mut self.base.files {
+            file.user_data = perfect_hash.slots[file.user_data as usize] as u32;
+        }
+        // Write our TOC
+        let mut toc_header = TIoTocHeader::new(
+            self.base.container_id, 
+            self.base.chunk_ids.len() as u32,
+            self.base.compression_blocks.len() as u32,
+            self.base.compression_block_size,
+            self.base.get_directory_index_size()
+        );
+        toc_header.set_perfect_hash(self.toc_version, perfect_hash.seeds.len() as u32, perfect_hash.chunks_without_perfect_hash.len() as u32);
+        let compression_methods = self.base.get_compression_methods();
+        toc_header.set_compression_methods(compression_methods.len() as u32);
+        if let Some((guid, _)) = self.base.options.encryption {
+            toc_header.set_encryption_key_guid(guid);
+        }
+        if self.base.options.sign {
+            toc_header.set_signed();
+        }
+        if let Some(partition_size) = self.base.options.partition_size {
+            toc_header.set_partitions(container_data.get_partition_count() as u32, partition_size);
+        }
+        toc_header.to_buffer::                          <CV, EN>(&mut toc_storage)?; // FIoStoreTocHeader
+        IoChunkId::list_to_buffer5::                    <CV, EN>(&self.base.chunk_ids, &mut toc_storage)?; // FIoChunkId
+        IoOffsetAndLength::list_to_buffer::             <CV, EN>(&self.base.offsets_and_lengths, &mut toc_storage)?; // FIoOffsetAndLength
+        IoPerfectHash::list_to_buffer::                 <CV, EN>(&perfect_hash.seeds, &mut toc_storage)?; // Perfect hash seeds
+        if self.toc_version >= IoStoreTocVersion::PerfectHashWithOverflow {
+            IoPerfectHash::list_to_buffer::             <CV, EN>(&perfect_hash.chunks_without_perfect_hash, &mut toc_storage)?; // Chunks without perfect hash
+        }
+        IoStoreTocCompressedBlockEntry::list_to_buffer::<CV, EN>(&self.base.compression_blocks, &mut toc_storage)?; // FIoStoreTocCompressedBlockEntry
+        CompressionMethod::list_to_buffer::             <CV, EN>(&compression_methods, &mut toc_storage)?; // Compression method names
+        self.base.write_signatures::                    <CV, EN>(&mut toc_storage)?; // Hashes of the TOC and each block
+        self.base.directory_index_to_buffer::           <CV, EN>(&mut toc_storage)?; // FIoDirectoryIndexResource
+        IoStoreTocEntryMeta::list_to_buffer::           <CV, EN>(&self.base.metas, &mut toc_storage)?; // FIoStoreTocEntryMeta
+
+        Ok((toc_storage.into_inner(), container_data))
+    }
+}
+
+pub const DEFAULT_COMPRESSION_BLOCK_ALIGNMENT: u32 = 0x800; // UE 4.27
+pub const DIRECTORY_INDEX_COMPRESSION_BLOCK_ALIGNMENT: u32 = 0; // UE 4.25+, 4.26 (clamped to 0x10 by the resolver)
 
 pub struct TocFlattenTracker {
     // Used to set the correct directory/file/string indices when flattening TocDirectory tree into Directory Index entries
@@ -169,6 +562,12 @@ pub struct TocFlattenTracker {
     pub resolved_strings: u32,
 }
 
+impl Default for TocFlattenTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TocFlattenTracker {
     pub fn new() -> Self {
         Self {
@@ -181,14 +580,18 @@ impl TocFlattenTracker {
 
 pub struct TocResolverType2 { // Currently for 4.25+, 4.26 and 4.27
     pub directories: Vec<IoDirectoryIndexEntry>,
+    container_header_version: IoContainerHeaderVersion,
     pub files: Vec<IoFileIndexEntry>,
     pub strings: Vec<String>,
     compression_block_size: u32,
     compression_block_alignment: u32,
-    toc_name_hash: u64,
+    options: TocBuildOptions,
+    container_id: IoContainerId,
+    mount_point: String, // the root directory's path, which every path in the directory index is relative to
     pub chunk_ids: Vec<IoChunkId>,
     pub offsets_and_lengths: Vec<IoOffsetAndLength>,
     pub compression_blocks: Vec<IoStoreTocCompressedBlockEntry>,
+    pub block_hashes: Vec<[u8; 0x14]>, // only for signed containers, one for each compression block
     pub metas: Vec<IoStoreTocEntryMeta>,
     pub cas_pointer: u64, // Current virtual position of container file
 }
@@ -199,71 +602,172 @@ impl TocResolverCommon for TocResolverType2 {
     fn new<
         THeaderType: IoStoreTocHeaderCommon
     >(toc_name: &str, block_align: u32) -> impl TocResolverCommon {
-        Self { 
-            // Directory block
-            directories: vec![], // The resulting directory list will be serialized as an FIoDirectoryIndexEntry
-            files: vec![], // Our file list will be serialized as an FIoFileIndexEntry
-            strings: vec![], // Strings will be owned by a string pool where there'll be serialized into an FString32NoHash array
-            compression_block_size: 0x10000, // default for UE 4.26/4.27 - used for offset + length offset
-            compression_block_alignment: if block_align < 0x10 { 0x10 } else { block_align }, // 0x800 is default for UE 4.27 (isn't saved in toc), 0x0 is used for UE 4.26
-            // every file is virtually put on an alignment of [compression_block_size] (in reality, they're only aligned to nearest 16 bytes)
-            // offset section defines where each file's data starts, while compress blocks section defines each compression block
-            toc_name_hash: Hasher16::get_cityhash64("Game"), // used for container id (is also the last file in partition) (verified)
-            chunk_ids: vec![],
-            offsets_and_lengths: vec![],
-            compression_blocks: vec![],
-            metas: vec![],
-            cas_pointer: 0
-        }
+        Self::new_inner(toc_name, block_align, IoContainerHeaderVersion::BeforeVersionWasAdded)
     }
     // Flatten the tree of directories + files into a list of directories and list of files
     fn flatten_toc_tree(&mut self, tracker: &mut TocFlattenTracker, root: TocDirectorySyncRef) {
-        self.directories = self.flatten_toc_tree_dir(tracker, Arc::clone(&root));
+        self.directories = self.flatten_toc_tree_dir(tracker, Arc::clone(&root), true);
+    }
+    fn set_mount_point(&mut self, mount_point: String) {
+        self.mount_point = mount_point;
+    }
+    fn set_build_options(&mut self, options: &TocBuildOptions) -> EmulatorResult<()> {
+        if options.partition_size == Some(0) {
+            return Err(EmulatorError::InvalidArgument("Partition size can't be 0".to_owned()));
+        }
+        self.options = *options;
+        Ok(())
     }
     fn serialize<
-        TSummary: PackageIoSummaryDeserialize,
-        TIoTocHeader: IoStoreTocHeaderCommon
+        TSummary: PackageIoSummaryDeserialize + PackageIoSummarySerialize,
+        TIoTocHeader: IoStoreTocHeaderCommon,
+        TPosition: ContainerHeaderPosition
     >(
         &mut self, 
         profiler: &mut TocBuilderProfiler, 
         toc_path: &str
-    ) -> (Vec<u8>, ContainerData) {
+    ) -> EmulatorResult<(Vec<u8>, ContainerData)> {
         type CV = Cursor<Vec<u8>>;
         type EN = byteorder::NativeEndian;
         let mut toc_storage: CV = Cursor::new(vec![]); // TOC Storage gets stored as a MemoryStream
-        // CAS storage will be a MultiStream of FileStreams with a MemoryStream of gaps between it
-        // Set capacity so that vec doesn't realloc
-        let mut container_string_pool = CONTAINER_ENTRIES_OSPATH_POOL.lock().unwrap();
-        *container_string_pool = Some(Vec::with_capacity(self.files.len()));
-        let mut container_header = ContainerHeader::new(self.toc_name_hash);
-        let mut container_data = ContainerData { header: vec![], virtual_blocks: vec![] };
-        let file_count = self.files.len();
-        for i in 0..self.files.len() {
-            container_data.virtual_blocks.push(self.serialize_entry::<TSummary>(
-                i, &mut container_header, &mut container_string_pool
-            ));
+        if self.options.partition_size.is_some() && !TIoTocHeader::supports_partitions() {
+            return Err(EmulatorError::UnsupportedVersion(PARTITIONS_UNSUPPORTED.to_owned()));
         }
-        container_data.header = self.serialize_container_header::<EN>(&mut container_header);
+        let container_data = self.layout_container::<TSummary, TPosition>()?;
         // Write our TOC
-        let toc_header = TIoTocHeader::new(
-            self.toc_name_hash, 
+        let mut toc_header = TIoTocHeader::new(
+            self.container_id, 
             self.files.len() as u32 + 1, // + 1 for container header
             self.compression_blocks.len() as u32,
             self.compression_block_size,
             self.get_directory_index_size()
         );
+        let compression_methods = self.get_compression_methods();
+        toc_header.set_compression_methods(compression_methods.len() as u32);
+        if let Some((guid, _)) = self.options.encryption {
+            toc_header.set_encryption_key_guid(guid);
+        }
+        if self.options.sign {
+            toc_header.set_signed();
+        }
+        if let Some(partition_size) = self.options.partition_size {
+            toc_header.set_partitions(container_data.get_partition_count() as u32, partition_size);
+        }
         // FIoStoreTocHeader
-        toc_header.to_buffer::                          <CV, EN>(&mut toc_storage).unwrap(); // FIoStoreTocHeader
-        IoChunkId::list_to_buffer::                     <CV, EN>(&self.chunk_ids, &mut toc_storage).unwrap(); // FIoChunkId
-        IoOffsetAndLength::list_to_buffer::             <CV, EN>(&self.offsets_and_lengths, &mut toc_storage).unwrap(); // FIoOffsetAndLength
-        IoStoreTocCompressedBlockEntry::list_to_buffer::<CV, EN>(&self.compression_blocks, &mut toc_storage).unwrap(); // FIoStoreTocCompressedBlockEntry
-        FString32NoHash::to_buffer::                    <CV, EN>(MOUNT_POINT, &mut toc_storage).unwrap(); // Mount Point
-        IoDirectoryIndexEntry::list_to_buffer::         <CV, EN>(&self.directories, &mut toc_storage).unwrap(); // FIoDirectoryIndexEntry
-        IoFileIndexEntry::list_to_buffer::              <CV, EN>(&self.files, &mut toc_storage).unwrap(); // FIoFileIndexEntry
-        IoStringPool::list_to_buffer::                  <CV, EN>(&self.strings, &mut toc_storage).unwrap(); // FIoStringIndexEntry
-        IoStoreTocEntryMeta::list_to_buffer::           <CV, EN>(&self.metas, &mut toc_storage).unwrap(); // FIoStoreTocEntryMeta
+        toc_header.to_buffer::                          <CV, EN>(&mut toc_storage)?; // FIoStoreTocHeader
+        IoChunkId::list_to_buffer::                     <CV, EN>(&self.chunk_ids, &mut toc_storage)?; // FIoChunkId
+        IoOffsetAndLength::list_to_buffer::             <CV, EN>(&self.offsets_and_lengths, &mut toc_storage)?; // FIoOffsetAndLength
+        IoStoreTocCompressedBlockEntry::list_to_buffer::<CV, EN>(&self.compression_blocks, &mut toc_storage)?; // FIoStoreTocCompressedBlockEntry
+        CompressionMethod::list_to_buffer::             <CV, EN>(&compression_methods, &mut toc_storage)?; // Compression method names
+        self.write_signatures::                         <CV, EN>(&mut toc_storage)?; // Hashes of the TOC and each block
+        self.directory_index_to_buffer::                <CV, EN>(&mut toc_storage)?; // FIoDirectoryIndexResource
+        IoStoreTocEntryMeta::list_to_buffer::           <CV, EN>(&self.metas, &mut toc_storage)?; // FIoStoreTocEntryMeta
+
+        Ok((toc_storage.into_inner(), container_data))
+    }
+}
+
+impl TocResolverType2 {
+    fn new_inner(toc_name: &str, block_align: u32, container_header_version: IoContainerHeaderVersion) -> Self {
+        Self { 
+            // Directory block
+            directories: vec![], // The resulting directory list will be serialized as an FIoDirectoryIndexEntry
+            container_header_version,
+            files: vec![], // Our file list will be serialized as an FIoFileIndexEntry
+            strings: vec![], // Strings will be owned by a string pool where there'll be serialized into an FString32NoHash array
+            compression_block_size: 0x10000, // default for UE 4.26/4.27 - used for offset + length offset
+            compression_block_alignment: if block_align < 0x10 { 0x10 } else { block_align }, // 0x800 is default for UE 4.27 (isn't saved in toc), 0x0 is used for UE 4.26
+            options: TocBuildOptions::default(),
+            // every file is virtually put on an alignment of [compression_block_size] (in reality, they're only aligned to nearest 16 bytes)
+            // offset section defines where each file's data starts, while compress blocks section defines each compression block
+            container_id: IoContainerId::from_name(toc_name), // also used for the container header's chunk id, which is the last chunk
+            mount_point: MOUNT_POINT.to_owned(),
+            chunk_ids: vec![],
+            offsets_and_lengths: vec![],
+            compression_blocks: vec![],
+            block_hashes: vec![],
+            metas: vec![],
+            cas_pointer: 0
+        }
+    }
+
+    // Generate chunk ids, store entries and the position of every chunk in the container
+    fn layout_container<
+        TSummary: PackageIoSummaryDeserialize + PackageIoSummarySerialize,
+        TPosition: ContainerHeaderPosition
+    >(&mut self) -> EmulatorResult<ContainerData> {
+        type EN = byteorder::NativeEndian;
+        // CAS storage will be a MultiStream of FileStreams with a MemoryStream of gaps between it
+        // Generate chunk ids and store entries before placing any data, since 4.25+ and 4.26 need to know the
+        // container header's size before the first file can be placed
+        let mut container_header = ContainerHeader::new_with_version(self.container_id, self.container_header_version);
+        // Files that can't be read are dropped instead of failing the whole container, so that other mods still load
+        let errors: Vec<Option<EmulatorError>> = (0..self.files.len())
+            .map(|i| self.convert_file::<TSummary>(i).and_then(|_| self.serialize_store_entry::<TSummary>(i, &mut container_header)).err())
+            .collect();
+        let mut dropped: Vec<bool> = errors.iter().map(|e| e.is_some()).collect();
+        let readable: Vec<bool> = errors.iter().map(|e| e.is_none()).collect();
+        for (i, e) in errors.into_iter().enumerate() {
+            if let Some(e) = e {
+                self.skip_file(i, e, &mut dropped);
+            }
+        }
+        // chunk ids were only made for files that could be read, some of which may have been dropped along with their package
+        let mut chunk_ids = mem::take(&mut self.chunk_ids).into_iter();
+        for (is_readable, is_dropped) in readable.iter().zip(&dropped) {
+            if *is_readable {
+                let chunk_id = chunk_ids.next().unwrap(); // one for each readable file
+                if !is_dropped {
+                    self.chunk_ids.push(chunk_id);
+                }
+            }
+        }
+        self.remove_files(&dropped);
+        let mut container_data = ContainerData::new(
+            self.serialize_container_header::<EN>(&mut container_header)?, self.files.len(), self.options.partition_size.unwrap_or(u64::MAX)
+        );
+        let header_size = container_data.header.len() as u64;
+        #[cfg(feature = "hash_meta")]
+        let header_meta = IoStoreTocEntryMeta::new_with_hash(&container_data.header);
+        #[cfg(not(feature = "hash_meta"))]
+        let header_meta = IoStoreTocEntryMeta::new_empty();
+        if self.options.is_encoded() { // padding fits into the gap left by the block alignment
+            encryption::align_to_aes_block(&mut container_data.header);
+        }
+        if let Some((_, key)) = self.options.encryption.as_ref() {
+            key.encrypt(&mut container_data.header)?;
+        }
+        let mut header_entry = if TPosition::is_header_first() { Some(self.place_container_header(header_size, &container_data.header)?) } else { None };
+        let file_hashes = self.get_file_hashes();
+        for (i, hash) in file_hashes.into_iter().enumerate() {
+            self.serialize_entry(i, &mut container_data, hash)?;
+        }
+        if header_entry.is_none() {
+            header_entry = Some(self.place_container_header(header_size, &container_data.header)?);
+        }
+        let (header_offset_length, header_offset) = header_entry.unwrap(); // always placed by this point
+        container_data.set_header_offset(header_offset);
+        // The container header's TOC entry is always last so that each file's user data still points to it's chunk index
+        self.chunk_ids.push(IoChunkId::new_container_header(self.container_id)); // header chunk id
+        self.offsets_and_lengths.push(header_offset_length); // header offset + length
+        self.metas.push(header_meta);
+        Ok(container_data)
+    }
 
-        (toc_storage.into_inner(), container_data)
+    // FIoDirectoryIndexResource: mount point, directories, files and strings
+    fn directory_index_to_buffer<W: Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let mut index_writer = Cursor::new(vec![]);
+        FString32NoHash::to_buffer::            <_, E>(&self.mount_point, &mut index_writer)?; // Mount Point
+        IoDirectoryIndexEntry::list_to_buffer:: <_, E>(&self.directories, &mut index_writer)?; // FIoDirectoryIndexEntry
+        IoFileIndexEntry::list_to_buffer::      <_, E>(&self.files, &mut index_writer)?; // FIoFileIndexEntry
+        IoStringPool::list_to_buffer::          <_, E>(&self.strings, &mut index_writer)?; // FIoStringIndexEntry
+        let mut index = index_writer.into_inner();
+        if let Some((_, key)) = self.options.encryption.as_ref() {
+            encryption::align_to_aes_block(&mut index);
+            key.encrypt(&mut index)?;
+        }
+        writer.write_all(&index)?;
+        Ok(())
     }
 }
 
@@ -279,12 +783,13 @@ impl TocResolverType2 {
             },
         }) as u32
     }
-    fn flatten_toc_tree_dir(&mut self, tracker: &mut TocFlattenTracker, node: TocDirectorySyncRef) -> Vec<IoDirectoryIndexEntry> {
+    // The mount root's name is part of the mount point, so it doesn't have one in the directory index
+    fn flatten_toc_tree_dir(&mut self, tracker: &mut TocFlattenTracker, node: TocDirectorySyncRef, is_mount_root: bool) -> Vec<IoDirectoryIndexEntry> {
         let mut values = vec![];
         let mut flat_value = IoDirectoryIndexEntry {
             name: match node.read().unwrap().name.as_ref() {
-                Some(t) => self.get_flat_string_index(tracker, t),
-                None => u32::MAX
+                Some(t) if !is_mount_root => self.get_flat_string_index(tracker, t),
+                _ => u32::MAX
             },
             first_child: u32::MAX,
             next_sibling: u32::MAX,
@@ -301,26 +806,12 @@ impl TocResolverType2 {
                     user_data: tracker.resolved_files,
                     file_size: curr_file.read().unwrap().file_size,
                     os_path: curr_file.read().unwrap().os_file_path.clone(),
-                    hash_path: String::new()
-
+                    hash_path: String::new(),
+                    mod_id: curr_file.read().unwrap().mod_id.clone(),
+                    cooked_package: curr_file.read().unwrap().cooked_package
                 };
-                // travel upwards through parents to build hash path
                 // calculate hash after validation so it's easier to remove incorrectly formatted uassets
-                let mut path_comps: Vec<String> = vec![];
-                let mut curr_parent = Arc::clone(&node);
-                loop {
-                    if let Some(t) = curr_parent.read().unwrap().name.as_ref() {
-                        path_comps.insert(0, t.to_owned());
-                    }
-                    match Arc::clone(&curr_parent).read().unwrap().parent.upgrade() {
-                        Some(ip) => curr_parent = Arc::clone(&ip),
-                        None => break
-                    }
-                }
-                let filename_buf = PathBuf::from(&curr_file.read().unwrap().name);
-                let path = path_comps.join("/") + "/" + filename_buf.file_stem().unwrap().to_str().unwrap();
-                //println!("{} PATH: {}, OS: {}", &curr_file.borrow().name, &path, &curr_file.borrow().os_file_path);
-                flat_file.hash_path = path;
+                flat_file.hash_path = get_hash_path(Arc::clone(&node), &curr_file.read().unwrap().name);
                 // go to next file
                 tracker.resolved_files += 1;
                 match Arc::clone(&curr_file).read().unwrap().next.as_ref() {
@@ -344,7 +835,7 @@ impl TocResolverType2 {
             values.push(flat_value);
             let mut curr_child = Arc::clone(node.read().unwrap().first_child.as_ref().unwrap());
             loop {
-                let mut children = self.flatten_toc_tree_dir(tracker, Arc::clone(&curr_child));
+                let mut children = self.flatten_toc_tree_dir(tracker, Arc::clone(&curr_child), false);
                 match Arc::clone(&curr_child).read().unwrap().next_sibling.as_ref() { // get the next child (if they exist)
                     Some(next) => {
                         children[0].next_sibling = tracker.resolved_directories;
@@ -376,88 +867,482 @@ impl TocResolverType2 {
         gen_blocks
     }
 
-    fn serialize_container_header<TEndian: byteorder::ByteOrder>(&mut self, container_header: &mut ContainerHeader) -> Vec<u8> {
+    fn serialize_container_header<TEndian: byteorder::ByteOrder>(&mut self, container_header: &mut ContainerHeader) -> EmulatorResult<Vec<u8>> {
         let mut container_header_buffer = Cursor::new(vec![]);
-        let container_header = container_header.to_buffer::<Cursor<Vec<u8>>, TEndian>(&mut container_header_buffer).unwrap(); // write our container header in the buffer
-        self.chunk_ids.push(IoChunkId::new_from_hash(self.toc_name_hash, IoChunkType4::ContainerHeader)); // header chunk id
+        Ok(container_header.to_buffer::<Cursor<Vec<u8>>, TEndian>(&mut container_header_buffer)?) // write our container header in the buffer
+    }
+
+    // Reserve space for the container header at the current CAS position. Returns it's virtual offset + length and it's physical offset
+    fn place_container_header(&mut self, header_size: u64, header: &[u8]) -> EmulatorResult<(IoOffsetAndLength, u64)> {
         let header_offset = self.compression_blocks.len() as u64 * self.compression_block_size as u64; 
-        self.offsets_and_lengths.push(IoOffsetAndLength::new(header_offset, container_header.len() as u64)); // header offset + length
-        self.compression_blocks.append(&mut TocResolverType2::create_compression_blocks(container_header.len() as u64, self.cas_pointer, self.compression_block_size));
-        self.metas.push(IoStoreTocEntryMeta::new_empty());
-        container_header
+        self.cas_pointer = reserve_partition_space(self.cas_pointer, header.len() as u64, self.options.partition_size)
+            .map_err(|e| EmulatorError::InvalidValue(format!("Couldn't place container header. {}", e)))?;
+        let mut blocks = TocResolverType2::create_compression_blocks(header_size, self.cas_pointer, self.compression_block_size);
+        if self.options.sign {
+            self.block_hashes.append(&mut get_block_hashes(header, self.cas_pointer, &blocks));
+        }
+        self.compression_blocks.append(&mut blocks);
+        let physical_offset = self.cas_pointer;
+        self.cas_pointer += header_size;
+        self.align_cas_pointer();
+        Ok((IoOffsetAndLength::new(header_offset, header_size), physical_offset))
     }
 
-    fn serialize_entry<TSummary: PackageIoSummaryDeserialize>(&mut self, index: usize, container_header: &mut ContainerHeader, pool_guard: &mut MutexGuard<Option<Vec<String>>>) -> PartitionBlock {
+
+    fn serialize_store_entry<
+        TSummary: PackageIoSummaryDeserialize
+    >(&mut self, index: usize, container_header: &mut ContainerHeader) -> EmulatorResult<()> {
         let target_file = &self.files[index];
-        let generated_chunk_id = self.get_file_hash(target_file); // create the hash for the new file
-        self.chunk_ids.push(generated_chunk_id); // push once we're sure that the file's valid
-        let curr_file = &self.files[index]; // Generate FIoOffsetAndLength
-        let file_offset = self.compression_blocks.len() as u64 * self.compression_block_size as u64;
-        let generated_offset_length = IoOffsetAndLength::new(file_offset, curr_file.file_size);
-        self.offsets_and_lengths.push(generated_offset_length);
-        // Generate compression blocks
-        self.compression_blocks.append(&mut TocResolverType2::create_compression_blocks(target_file.file_size, self.cas_pointer, self.compression_block_size));
-        self.metas.push(IoStoreTocEntryMeta::new_empty()); // Generate meta - SHA1 hash of the file's contents (doesn't seem to be required)
-        if self.chunk_ids[index].get_type() == IoChunkType4::ExportBundleData {
-            let os_file = File::open(&target_file.os_path).unwrap(); // Export Bundles (.uasset) have store entry data written
+        let generated_chunk_id = self.get_file_hash(target_file)?; // create the hash for the new file
+        if generated_chunk_id.get_type() == IoChunkType4::ExportBundleData {
+            // Export Bundles (.uasset) have store entry data written
+            let os_file = File::open(&target_file.os_path).map_err(|e| EmulatorError::Io(target_file.os_path.clone(), e))?;
             let mut file_reader = BufReader::with_capacity(Self::FILE_SUMMARY_READER_ALLOC, os_file);
             container_header.packages.push(ContainerHeaderPackage::from_package_summary::<
-                ExportBundleHeader4, TSummary, BufReader<File>, byteorder::NativeEndian
+                TSummary, BufReader<File>, byteorder::NativeEndian
             >(
                 &mut file_reader, 
-                self.chunk_ids[index].get_raw_hash(), curr_file.file_size
-            ));
+                generated_chunk_id.get_raw_hash(), target_file.file_size
+            ).map_err(|e| EmulatorError::InvalidAsset(target_file.os_path.clone(), e.to_string()))?);
+        }
+        self.chunk_ids.push(generated_chunk_id); // push once we're sure that the file's valid
+        Ok(())
+    }
+
+    // Cooked packages can't be placed in the container as is, so convert each one into an IO Store package for the target engine
+    // version. The container reads every chunk from a file, so the converted package is written into a cache folder and this build's
+    // copy of the file is pointed to it. The tree keeps the cooked package, so that the next build converts it again if it changes
+    fn convert_file<TSummary: PackageIoSummarySerialize>(&mut self, index: usize) -> EmulatorResult<()> {
+        let target_file = &mut self.files[index];
+        if !target_file.cooked_package {
+            return Ok(());
+        }
+        if let Some(reason) = TSummary::get_unsupported_reason() {
+            return Err(EmulatorError::InvalidAsset(target_file.os_path.clone(), reason.to_owned()));
         }
-        // write into container data
-        (**pool_guard).as_mut().unwrap().push(target_file.os_path.to_owned() + "\0"); // make C formatted string
-        let curr_ospath = &(**pool_guard).as_ref().unwrap()[index];
-        let new_partition_block = PartitionBlock {
-            os_path: curr_ospath.as_ptr() as usize,
-            start: self.cas_pointer,
-            length: target_file.file_size
+        let (converted_path, converted_size) = convert_cooked_package::<TSummary>(&target_file.os_path, &target_file.hash_path, &target_file.mod_id)
+            .map_err(|e| EmulatorError::InvalidAsset(target_file.os_path.clone(), format!("Couldn't convert cooked package: {}", e)))?;
+        target_file.os_path = converted_path;
+        target_file.file_size = converted_size;
+        target_file.cooked_package = false;
+        Ok(())
+    }
+
+    // Report a file that couldn't be read as skipped for it's mod. A package's bulk data can't be loaded without it, so every other
+    // file with the same path stem is dropped along with it and listed in the same entry
+    fn skip_file(&self, index: usize, error: EmulatorError, dropped: &mut [bool]) {
+        let target_file = &self.files[index];
+        println!("WARNING: Skipping file from {}. {}", target_file.mod_id, error);
+        let mut reason = match error {
+            EmulatorError::InvalidAsset(_, reason) => reason,
+            e => e.to_string()
+        };
+        let mut size = target_file.file_size;
+        let is_package = |path: &str| matches!(Path::new(path).extension().and_then(|e| e.to_str()), Some("uasset" | "umap"));
+        if is_package(&target_file.os_path) {
+            let mut companions = vec![];
+            for (i, file) in self.files.iter().enumerate() {
+                if !dropped[i] && !is_package(&file.os_path) && asset_collector::is_same_name(&file.hash_path, &target_file.hash_path) {
+                    dropped[i] = true;
+                    size += file.file_size;
+                    companions.push(file.os_path.as_str());
+                }
+            }
+            if !companions.is_empty() {
+                reason = format!("{} (also skipped {})", reason, companions.join(", "));
+            }
+        }
+        if let Some(profiler) = ASSET_COLLECTOR_PROFILER.lock().unwrap().as_mut() {
+            profiler.add_skipped_file(&target_file.mod_id, &target_file.os_path, reason, size);
+        }
+    }
+
+    // Remove dropped files from the flattened directory index. Remaining files are renumbered so that their user data still
+    // points to their chunk index
+    fn remove_files(&mut self, dropped: &[bool]) {
+        if !dropped.contains(&true) {
+            return;
+        }
+        let mut new_indices = Vec::with_capacity(dropped.len());
+        let mut kept = 0;
+        for is_dropped in dropped {
+            new_indices.push(if *is_dropped { u32::MAX } else { kept += 1; kept - 1 });
+        }
+        // skip over dropped files in each directory's file list
+        let resolve = |files: &[IoFileIndexEntry], mut index: u32| {
+            while index != u32::MAX && dropped[index as usize] {
+                index = files[index as usize].next_file;
+            }
+            if index == u32::MAX { u32::MAX } else { new_indices[index as usize] }
+        };
+        let first_files: Vec<u32> = self.directories.iter().map(|d| resolve(&self.files, d.first_file)).collect();
+        let next_files: Vec<u32> = self.files.iter().map(|f| resolve(&self.files, f.next_file)).collect();
+        for (dir, first_file) in self.directories.iter_mut().zip(first_files) {
+            dir.first_file = first_file;
+        }
+        let files = mem::take(&mut self.files);
+        for (i, (mut file, next_file)) in files.into_iter().zip(next_files).enumerate() {
+            if !dropped[i] {
+                file.next_file = next_file;
+                file.user_data = new_indices[i];
+                self.files.push(file);
+            }
+        }
+    }
+
+    // SHA1 hash of each file's data for it's meta. Files are hashed on multiple threads, and the hashes are kept between builds
+    #[cfg(feature = "hash_meta")]
+    fn get_file_hashes(&self) -> Vec<Option<[u8; 0x14]>> {
+        let mut cache = crate::hash_cache::MetaHashCache::load();
+        let hashes = cache.hash_files(&self.files.iter().map(|f| f.os_path.as_str()).collect::<Vec<_>>());
+        if let Err(e) = cache.save() {
+            println!("WARNING: Couldn't save meta hash cache: {}", e);
+        }
+        hashes
+    }
+    #[cfg(not(feature = "hash_meta"))]
+    fn get_file_hashes(&self) -> Vec<Option<[u8; 0x14]>> {
+        vec![None; self.files.len()]
+    }
+
+    fn serialize_entry(&mut self, index: usize, container_data: &mut ContainerData, hash: Option<[u8; 0x14]>) -> EmulatorResult<()> {
+        let target_file = &self.files[index]; // Generate FIoOffsetAndLength
+        let file_offset = self.compression_blocks.len() as u64 * self.compression_block_size as u64;
+        let generated_offset_length = IoOffsetAndLength::new(file_offset, target_file.file_size);
+        self.offsets_and_lengths.push(generated_offset_length);
+        let mut meta = match hash { // Generate meta - SHA1 hash of the file's contents (doesn't seem to be required)
+            Some(hash) => IoStoreTocEntryMeta::new_from_hash(&hash),
+            None => IoStoreTocEntryMeta::new_empty()
         };
-        self.cas_pointer += target_file.file_size; // move cas pointer
-        let alignment_amount = self.cas_pointer % self.compression_block_alignment as u64;
-        if alignment_amount > 0 { // align to compression block alignment
-            let diff = self.compression_block_alignment as u64 - alignment_amount;
-            self.cas_pointer += diff;
+        let physical_size = match self.options.is_encoded() {
+            false => {
+                self.cas_pointer = reserve_partition_space(self.cas_pointer, target_file.file_size, self.options.partition_size)
+                    .map_err(|e| EmulatorError::InvalidAsset(target_file.os_path.clone(), e))?;
+                // Generate compression blocks
+                self.compression_blocks.append(&mut TocResolverType2::create_compression_blocks(target_file.file_size, self.cas_pointer, self.compression_block_size));
+                // write into container data
+                container_data.add_block(&target_file.os_path, self.cas_pointer, target_file.file_size);
+                target_file.file_size
+            },
+            true => {
+                let mut chunk = encode_chunk(&target_file.os_path, self.compression_block_size, &self.options)
+                    .map_err(|e| EmulatorError::InvalidAsset(target_file.os_path.clone(), e.to_string()))?;
+                self.cas_pointer = reserve_partition_space(self.cas_pointer, chunk.size, self.options.partition_size)
+                    .map_err(|e| EmulatorError::InvalidAsset(target_file.os_path.clone(), e))?;
+                for block in &mut chunk.blocks { // encoded blocks start from 0
+                    block.set_offset(block.get_offset() + self.cas_pointer);
+                }
+                if chunk.compressed {
+                    meta.set_compressed();
+                }
+                self.compression_blocks.append(&mut chunk.blocks);
+                self.block_hashes.append(&mut chunk.block_hashes);
+                container_data.add_block(&chunk.os_path, self.cas_pointer, chunk.size);
+                chunk.size
+            }
+        };
+        self.metas.push(meta);
+        self.cas_pointer += physical_size; // move cas pointer
+        self.align_cas_pointer();
+        Ok(())
+    }
+
+    // Signed containers have a signature for the TOC and for the list of block hashes, followed by the hash of each block. These are
+    // meant to be RSA signatures made with the game's private key, which we don't have, so the SHA1 hashes are written as is instead.
+    // The game's signature check has to be patched to accept them (see toc_unpacker's verify_blocks)
+    fn write_signatures<W: Read + Write + Seek, E: byteorder::ByteOrder>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        if !self.options.sign {
+            return Ok(())
         }
-        new_partition_block
+        let toc_end = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(0))?;
+        let mut toc_data = vec![];
+        (&mut *writer).take(toc_end).read_to_end(&mut toc_data)?;
+        let block_hashes: Vec<u8> = self.block_hashes.iter().flatten().copied().collect();
+        writer.write_i32::<E>(0x14)?; // hash size
+        writer.write_all(&IoStoreTocCompressedBlockEntry::hash_block(&toc_data))?; // TOC signature
+        writer.write_all(&IoStoreTocCompressedBlockEntry::hash_block(&block_hashes))?; // block signature
+        writer.write_all(&block_hashes)?; // hash for each block
+        Ok(())
+    }
+
+    // Names of the compression methods that blocks can refer to, in the order that they're written to the TOC
+    fn get_compression_methods(&self) -> Vec<CompressionMethod> {
+        match self.options.compression {
+            CompressionMethod::None => vec![],
+            method => vec![method]
+        }
+    }
+
+    fn align_cas_pointer(&mut self) {
+        self.cas_pointer = align_cas_pointer(self.cas_pointer, self.compression_block_alignment);
     }
 
     pub const FILE_SUMMARY_READER_ALLOC: usize = 0x2000;
 
     fn get_directory_index_size(&self) -> u32 {
-        // Get DirectoryIndexSize = Directory Entries + File Entries + Strings
+        // Get DirectoryIndexSize = Mount Point + Directory Entries + File Entries + Strings
         // Each section contains a u32 to note the object count
+        let mount_point_bytes = FString32NoHash::get_expected_length(&self.mount_point) as u32;
         let directory_index_bytes = (self.directories.len() * std::mem::size_of::<IoDirectoryIndexEntry>() + mem::size_of::<u32>()) as u32;
         let file_index_bytes = (self.files.len() * IO_FILE_INDEX_ENTRY_SERIALIZED_SIZE + mem::size_of::<u32>()) as u32;
         let mut string_index_bytes = mem::size_of::<u32>() as u32;
         self.strings.iter().for_each(|name| string_index_bytes += FString32NoHash::get_expected_length(name) as u32);
-        directory_index_bytes + file_index_bytes + string_index_bytes
+        let index_bytes = mount_point_bytes + directory_index_bytes + file_index_bytes + string_index_bytes;
+        match self.options.encryption { // encrypted directory index is padded to the AES block size
+            Some(_) => align_cas_pointer(index_bytes as u64, encryption::AES_BLOCK_SIZE as u32) as u32,
+            None => index_bytes
+        }
     }
 }
 
-// TODO: Set the mount point further up in mods where the file structure doesn't diverge at root
-// TODO: Pass version param (probably as trait) to customize how TOC is produced depenending on the target version
-// TODO: Support UE5 (sometime soon)
+// A chunk that's been split into compression blocks and written into the compressed chunk cache
+struct EncodedChunk {
+    os_path: String,
+    blocks: Vec<IoStoreTocCompressedBlockEntry>,
+    size: u64, // physical size, including the padding after each block
+    compressed: bool, // false if none of the blocks got any smaller
+    block_hashes: Vec<[u8; 0x14]> // only for signed containers
+}
+
+// Hash each block's data on disk. data is the physical data starting at pointer, which includes the padding for the last block
+fn get_block_hashes(data: &[u8], pointer: u64, blocks: &[IoStoreTocCompressedBlockEntry]) -> Vec<[u8; 0x14]> {
+    blocks.iter().map(|block| {
+        let start = ((block.get_offset() - pointer) as usize).min(data.len());
+        let end = (start + block.get_disk_size() as usize).min(data.len());
+        IoStoreTocCompressedBlockEntry::hash_block(&data[start..end])
+    }).collect()
+}
 
-pub fn build_table_of_contents_inner(root: TocDirectorySyncRef, toc_path: &str) -> Vec<u8> {
+// Compress and/or encrypt each block of a chunk. The container reads every chunk from a file, so the encoded blocks are written into a
+// cache file which the chunk's partition block points to. Blocks that don't get any smaller are stored uncompressed, and each block is
+// aligned to 16 bytes (the AES block size) like Unreal does, so encrypting the whole chunk at once is the same as encrypting each block.
+// Block offsets are relative to the start of the chunk, since where it goes depends on it's encoded size (see reserve_partition_space)
+fn encode_chunk(os_path: &str, block_size: u32, options: &TocBuildOptions) -> Result<EncodedChunk, Box<dyn Error>> {
+    let method = options.compression;
+    let data = fs::read(os_path)?;
+    let mut compressed_data = Vec::with_capacity(data.len());
+    let mut blocks = vec![];
+    let mut compressed = false;
+    for block in data.chunks(block_size as usize) {
+        let compressed_block = method.compress(block)?;
+        let (block_data, method_index) = match compressed_block.len() < block.len() {
+            true => (compressed_block.as_slice(), 1), // only one method is used, see get_compression_methods
+            false => (block, 0)
+        };
+        compressed |= method_index != 0;
+        blocks.push(IoStoreTocCompressedBlockEntry::new_compressed(
+            compressed_data.len() as u64, block_data.len() as u32, block.len() as u32, method_index));
+        compressed_data.extend_from_slice(block_data);
+        encryption::align_to_aes_block(&mut compressed_data);
+    }
+    if blocks.is_empty() { // need at least 1 compression block
+        blocks.push(IoStoreTocCompressedBlockEntry::new(0, 0));
+    }
+    if let Some((_, key)) = options.encryption.as_ref() {
+        key.encrypt(&mut compressed_data)?;
+    }
+    let block_hashes = if options.sign { get_block_hashes(&compressed_data, 0, &blocks) } else { vec![] };
+    let cache_key = format!("{}:{}", method.get_name(), options.encryption.map(|(guid, _)| format!("{:032x}", guid)).unwrap_or_default());
+    let extension = String::from(method.get_name()) + if options.encryption.is_some() { ".Encrypted" } else { "" };
+    let cache_path = get_cache_path(COMPRESSED_CHUNK_CACHE, &cache_key, &[&compressed_data], &extension)?;
+    if !cache_path.exists() {
+        write_cache_file(&cache_path, &compressed_data)?;
+    }
+    Ok(EncodedChunk {
+        os_path: cache_path.to_str().ok_or("Cache path isn't valid Unicode")?.to_owned(),
+        blocks,
+        size: compressed_data.len() as u64,
+        compressed,
+        block_hashes
+    })
+}
+
+// container_guard is the lock for the container that root belongs to, if it's being held. It's released as soon as the tree has been
+// flattened, since the rest of the build only reads the flattened copy of it
+pub fn build_table_of_contents_inner(
+    root: TocDirectorySyncRef, toc_path: &str, version: u32, options: &TocBuildOptions, container_guard: Option<MutexGuard<()>>
+) -> EmulatorResult<TableOfContents> {
     //println!("BUILD TABLE OF CONTENTS FOR {}", TARGET_TOC);
+    let container_name = get_container_name(toc_path);
+    let (mount_root, mount_point) = get_mount_root(Arc::clone(&root), get_mount_point(container_name).as_deref())?;
+    // The lowest byte is the TOC version. For UE5, the next byte contains the engine's minor version, since container headers and
+    // package summaries changed between UE 5.0 - 5.3 without a new TOC version
+    let toc_version = version & 0xff;
+    let engine_minor_version = (version >> 8) & 0xff;
     let mut profiler = TocBuilderProfiler::new();
-    let mut resolver = TocResolverType2::new::<
-        IoStoreTocHeaderType2
-    >(TARGET_TOC, DEFAULT_COMPRESSION_BLOCK_ALIGNMENT);
-    resolver.flatten_toc_tree(&mut TocFlattenTracker::new(), Arc::clone(&root));
-    let serialize_results = resolver.serialize::<PackageSummary2, IoStoreTocHeaderType3>(&mut profiler, toc_path);
-    let mut container_lock = CONTAINER_DATA.lock().unwrap();
-    *container_lock = Some(serialize_results.1);
-    serialize_results.0
+    // The TOC version decides the header type, where the container header goes, which package summary
+    // store entries are read from and how each chunk is aligned in the container
+    let serialize_results = match IoStoreTocVersion::try_from(toc_version as u8)? {
+        IoStoreTocVersion::Initial => { // 4.25
+            let mut resolver = TocResolverType1::new::<
+                IoStoreTocHeaderType1
+            >(container_name, DIRECTORY_INDEX_COMPRESSION_BLOCK_ALIGNMENT);
+            resolver.set_build_options(options)?;
+            resolver.flatten_toc_tree(&mut TocFlattenTracker::new(), Arc::clone(&root));
+            drop(container_guard);
+            resolver.serialize::<PackageSummary1, IoStoreTocHeaderType1, ContainerHeaderPosition1>(&mut profiler, toc_path)?
+        },
+        IoStoreTocVersion::DirectoryIndex => { // 4.25+, 4.26
+            let mut resolver = TocResolverType2::new::<
+                IoStoreTocHeaderType2
+            >(container_name, DIRECTORY_INDEX_COMPRESSION_BLOCK_ALIGNMENT);
+            resolver.set_build_options(options)?;
+            resolver.set_mount_point(mount_point);
+            resolver.flatten_toc_tree(&mut TocFlattenTracker::new(), Arc::clone(&mount_root));
+            drop(container_guard);
+            resolver.serialize::<PackageSummary2, IoStoreTocHeaderType2, ContainerHeaderPosition1>(&mut profiler, toc_path)?
+        },
+        IoStoreTocVersion::PartitionSize => { // 4.27
+            let mut resolver = TocResolverType2::new::<
+                IoStoreTocHeaderType3
+            >(container_name, DEFAULT_COMPRESSION_BLOCK_ALIGNMENT);
+            resolver.set_build_options(options)?;
+            resolver.set_mount_point(mount_point);
+            resolver.flatten_toc_tree(&mut TocFlattenTracker::new(), Arc::clone(&mount_root));
+            drop(container_guard);
+            resolver.serialize::<PackageSummary2, IoStoreTocHeaderType3, ContainerHeaderPosition2>(&mut profiler, toc_path)?
+        },
+        ue5_version => { // 5.0 - 5.3
+            let container_header_version = IoContainerHeaderVersion::from_engine_minor_version(engine_minor_version);
+            let mut resolver = TocResolverType3::new_with_version(
+                container_name, DEFAULT_COMPRESSION_BLOCK_ALIGNMENT, ue5_version, container_header_version
+            );
+            resolver.set_build_options(options)?;
+            resolver.set_mount_point(mount_point);
+            resolver.flatten_toc_tree(&mut TocFlattenTracker::new(), Arc::clone(&mount_root));
+            drop(container_guard);
+            if container_header_version >= IoContainerHeaderVersion::NoExportInfo {
+                resolver.serialize::<ZenPackageSummaryType2, IoStoreTocHeaderType4, ContainerHeaderPosition2>(&mut profiler, toc_path)?
+            } else {
+                resolver.serialize::<ZenPackageSummaryType1, IoStoreTocHeaderType4, ContainerHeaderPosition2>(&mut profiler, toc_path)?
+            }
+        }
+    };
+    Ok(TableOfContents { toc: serialize_results.0, container: serialize_results.1 })
+}
+
+// A built UTOC and the layout of it's UCAS. This is passed to the C# side as a handle which owns everything that the
+// TOC data, partition blocks and container header pointers refer to, so it has to stay alive until FreeTableOfContents is called
+pub struct TableOfContents {
+    pub toc: Vec<u8>,
+    pub container: ContainerData
 }
 
 pub struct ContainerData {
     pub header: Vec<u8>,
-    pub virtual_blocks: Vec<PartitionBlock>
+    header_offset: u64, // physical offset of the container header, which includes the partition that it's in
+    partition_size: u64, // u64::MAX if the container isn't split into partitions
+    partitions: Vec<Vec<PartitionBlock>>, // blocks in each .ucas file, with offsets from the start of that file
+    os_paths: Vec<String> // C strings that each partition block's path points to
+}
+
+// Blocks and container header for one .ucas file of a container. header is empty if the container header is in another partition
+pub struct ContainerPartition<'a> {
+    pub blocks: &'a [PartitionBlock],
+    pub header: &'a [u8],
+    pub header_offset: u64
+}
+
+impl ContainerData {
+    fn new(header: Vec<u8>, file_count: usize, partition_size: u64) -> Self {
+        Self { header, header_offset: 0, partition_size, partitions: vec![Vec::with_capacity(file_count)], os_paths: Vec::with_capacity(file_count) }
+    }
+
+    // Store the file's path as a C string so the C# side can open it. Moving the string into the list doesn't move it's buffer
+    fn add_block(&mut self, os_path: &str, start: u64, length: u64) {
+        self.os_paths.push(os_path.to_owned() + "\0"); // make C formatted string
+        let partition = self.get_partition_for_offset(start);
+        self.partitions[partition].push(PartitionBlock {
+            os_path: self.os_paths.last().unwrap().as_ptr() as usize, start: start % self.partition_size, length
+        });
+    }
+
+    fn set_header_offset(&mut self, offset: u64) {
+        self.header_offset = offset;
+        self.get_partition_for_offset(offset);
+    }
+
+    // Partitions are added as blocks are placed into them. Chunks only move forward by one partition at a time, so none are empty
+    fn get_partition_for_offset(&mut self, offset: u64) -> usize {
+        let partition = (offset / self.partition_size) as usize;
+        if partition >= self.partitions.len() {
+            self.partitions.resize_with(partition + 1, Vec::new);
+        }
+        partition
+    }
+
+    pub fn get_partition_count(&self) -> usize {
+        self.partitions.len()
+    }
+
+    pub fn get_partition(&self, index: usize) -> Option<ContainerPartition<'_>> {
+        let blocks = self.partitions.get(index)?;
+        let (header, header_offset) = match (self.header_offset / self.partition_size) as usize == index {
+            true => (self.header.as_slice(), self.header_offset % self.partition_size),
+            false => (&[] as &[u8], 0)
+        };
+        Some(ContainerPartition { blocks, header, header_offset })
+    }
+}
+
+// Partitions after the first one are named [container]_s1.ucas, [container]_s2.ucas...
+pub fn get_partition_path(cas_path: &str, index: usize) -> String {
+    match index {
+        0 => cas_path.to_owned(),
+        i => format!("{}_s{}{}", cas_path.strip_suffix(CAS_EXTENSION).unwrap_or(cas_path), i, CAS_EXTENSION)
+    }
+}
+
+impl ContainerData {
+    // Write the UCAS that the partition blocks describe to disk, for containers that are baked ahead of time instead of emulated.
+    // Alignment gaps between blocks are filled with zeroes, and the container header goes wherever the TOC builder placed it
+    pub fn write_container(&self, cas_path: &str) -> EmulatorResult<()> {
+        let mut os_paths = self.os_paths.iter();
+        for i in 0..self.get_partition_count() {
+            let partition = self.get_partition(i).unwrap(); // within partition count
+            Self::write_partition(&get_partition_path(cas_path, i), &partition, os_paths.by_ref())?;
+        }
+        Ok(())
+    }
+
+    // Blocks are stored in the same order as their paths, so each partition takes the next paths from os_paths
+    fn write_partition<'a>(cas_path: &str, partition: &ContainerPartition, mut os_paths: impl Iterator<Item = &'a String>) -> EmulatorResult<()> {
+        let map_err = |e| EmulatorError::Io(cas_path.to_owned(), e);
+        let mut writer = io::BufWriter::new(File::create(cas_path).map_err(map_err)?);
+        let mut position = 0;
+        let mut header_written = partition.header.is_empty(); // 4.25 doesn't have a container header
+        for block in partition.blocks {
+            let os_path = os_paths.next().unwrap(); // every block has a path
+            if !header_written && partition.header_offset <= block.start {
+                position = write_padding(&mut writer, position, partition.header_offset).map_err(map_err)?;
+                writer.write_all(partition.header).map_err(map_err)?;
+                position += partition.header.len() as u64;
+                header_written = true;
+            }
+            position = write_padding(&mut writer, position, block.start).map_err(map_err)?;
+            let os_path = os_path.trim_end_matches('\0');
+            let file = File::open(os_path).map_err(|e| EmulatorError::Io(os_path.to_owned(), e))?;
+            let copied = io::copy(&mut file.take(block.length), &mut writer).map_err(map_err)?;
+            if copied != block.length {
+                return Err(EmulatorError::InvalidAsset(os_path.to_owned(), "File was changed while the container was being written".to_owned()));
+            }
+            position += block.length;
+        }
+        if !header_written {
+            write_padding(&mut writer, position, partition.header_offset).map_err(map_err)?;
+            writer.write_all(partition.header).map_err(map_err)?;
+        }
+        writer.flush().map_err(map_err)
+    }
+}
+
+// Fill the space between the end of the last block and the start of the next one
+pub fn write_padding<W: Write>(writer: &mut W, position: u64, target: u64) -> io::Result<u64> {
+    if target > position {
+        io::copy(&mut io::repeat(0).take(target - position), writer)?;
+    }
+    Ok(position.max(target))
 }
 
 #[repr(C)]
@@ -521,4 +1406,252 @@ impl TocBuilderProfiler {
         println!("Flatten Time: {} ms", self.time_to_flatten as f64 / 1000f64);
         println!("Serialize Time: {} ms", self.time_to_serialize as f64 / 1000f64);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_BLOCK_SIZE: u32 = 0x10000;
+
+    // The first block compresses well, the second is noise that doesn't get any smaller and the last one is partially filled
+    fn get_test_data() -> Vec<u8> {
+        let mut data: Vec<u8> = (0..TEST_BLOCK_SIZE).map(|i| (i % 7) as u8).collect();
+        let mut state: u32 = 0x12345678;
+        data.extend((0..TEST_BLOCK_SIZE + 0x123).map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        }));
+        data
+    }
+
+    fn write_test_file(name: &str, data: &[u8]) -> String {
+        let mut path = std::env::temp_dir();
+        path.push(EMULATOR_NAME);
+        path.push("Tests");
+        fs::create_dir_all(&path).unwrap();
+        path.push(name);
+        fs::write(&path, data).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    // Read an encoded chunk back the same way that the game does: decrypt the whole chunk, then decompress each block
+    fn decode_chunk(chunk: &EncodedChunk, options: &TocBuildOptions) -> Vec<u8> {
+        let mut data = fs::read(&chunk.os_path).unwrap();
+        assert_eq!(data.len() as u64, chunk.size);
+        if let Some((_, key)) = options.encryption.as_ref() {
+            key.decrypt(&mut data).unwrap();
+        }
+        let mut decoded = vec![];
+        for block in &chunk.blocks {
+            assert_eq!(block.get_offset() % encryption::AES_BLOCK_SIZE, 0);
+            let start = block.get_offset() as usize;
+            let block_data = &data[start..start + block.get_compressed_size() as usize];
+            match block.get_compression_method() {
+                0 => decoded.extend_from_slice(block_data),
+                _ => decoded.extend(options.compression.decompress(block_data, block.get_uncompressed_size() as usize).unwrap())
+            }
+        }
+        decoded
+    }
+
+    #[test]
+    fn encode_chunk_compression_round_trip() {
+        let data = get_test_data();
+        for compression in [CompressionMethod::None, CompressionMethod::Zlib, CompressionMethod::LZ4, CompressionMethod::Zstd] {
+            let os_path = write_test_file(&format!("compression_{}.ubulk", compression.get_name()), &data);
+            let options = TocBuildOptions { compression, ..Default::default() };
+            let chunk = encode_chunk(&os_path, TEST_BLOCK_SIZE, &options).unwrap();
+            assert_eq!(chunk.blocks.len(), 3);
+            assert_eq!(chunk.compressed, compression != CompressionMethod::None);
+            // blocks that don't get smaller are stored as is
+            assert_eq!(chunk.blocks[1].get_compression_method(), 0);
+            assert_eq!(decode_chunk(&chunk, &options), data);
+        }
+    }
+
+    #[test]
+    fn encode_chunk_encryption_round_trip() {
+        let data = get_test_data();
+        let key = EncryptionKey::from_hex("0x000102030405060708090A0B0C0D0E0F101112131415161718191A1B1C1D1E1F").unwrap();
+        for compression in [CompressionMethod::None, CompressionMethod::Zlib] {
+            let os_path = write_test_file(&format!("encryption_{}.ubulk", compression.get_name()), &data);
+            let options = TocBuildOptions { compression, encryption: Some((0, key)), ..Default::default() };
+            let chunk = encode_chunk(&os_path, TEST_BLOCK_SIZE, &options).unwrap();
+            // every block is padded to the AES block size, so the whole chunk can be decrypted at once
+            assert_eq!(chunk.size % encryption::AES_BLOCK_SIZE, 0);
+            let encrypted = fs::read(&chunk.os_path).unwrap();
+            assert_ne!(&encrypted[..0x10], &data[..0x10]);
+            assert_eq!(decode_chunk(&chunk, &options), data);
+        }
+    }
+
+    fn new_test_file(os_path: &str, hash_path: &str, next_file: u32, index: u32) -> IoFileIndexEntry {
+        IoFileIndexEntry {
+            name: u32::MAX, next_file, user_data: index, file_size: 0,
+            os_path: os_path.to_owned(), hash_path: hash_path.to_owned(), mod_id: "Test".to_owned(), cooked_package: false
+        }
+    }
+
+    fn new_test_directory(first_file: u32, next_sibling: u32) -> IoDirectoryIndexEntry {
+        IoDirectoryIndexEntry { name: u32::MAX, first_child: u32::MAX, next_sibling, first_file }
+    }
+
+    // Two directories, with files 0 -> 1 -> 2 in the first and 3 -> 4 in the second
+    fn new_test_resolver() -> TocResolverType2 {
+        let mut resolver = TocResolverType2::new_inner("Test", DEFAULT_COMPRESSION_BLOCK_ALIGNMENT, IoContainerHeaderVersion::Initial);
+        resolver.directories = vec![new_test_directory(0, 1), new_test_directory(3, u32::MAX)];
+        resolver.files = vec![
+            new_test_file("P/Content/a.uasset", "P/Content/a", 1, 0),
+            new_test_file("P/Content/a.ubulk", "P/Content/a", 2, 1),
+            new_test_file("P/Content/b.ubulk", "P/Content/b", u32::MAX, 2),
+            new_test_file("P/Content/Sub/c.ubulk", "P/Content/Sub/c", 4, 3),
+            new_test_file("P/Content/Sub/d.ubulk", "P/Content/Sub/d", u32::MAX, 4)
+        ];
+        resolver
+    }
+
+    fn get_file_list(resolver: &TocResolverType2, directory: usize) -> Vec<String> {
+        let mut files = vec![];
+        let mut curr_file = resolver.directories[directory].first_file;
+        while curr_file != u32::MAX {
+            let file = &resolver.files[curr_file as usize];
+            assert_eq!(file.user_data, curr_file);
+            files.push(file.os_path.clone());
+            curr_file = file.next_file;
+        }
+        files
+    }
+
+    #[test]
+    fn remove_files_relinks_file_lists() {
+        let mut resolver = new_test_resolver();
+        resolver.remove_files(&[true, false, true, false, true]);
+        assert_eq!(resolver.files.len(), 2);
+        assert_eq!(get_file_list(&resolver, 0), ["P/Content/a.ubulk"]);
+        assert_eq!(get_file_list(&resolver, 1), ["P/Content/Sub/c.ubulk"]);
+    }
+
+    #[test]
+    fn remove_every_file_in_a_directory() {
+        let mut resolver = new_test_resolver();
+        resolver.remove_files(&[false, false, false, true, true]);
+        assert_eq!(get_file_list(&resolver, 0), ["P/Content/a.uasset", "P/Content/a.ubulk", "P/Content/b.ubulk"]);
+        assert!(get_file_list(&resolver, 1).is_empty());
+    }
+
+    #[test]
+    fn skipped_package_drops_its_bulk_data() {
+        let resolver = new_test_resolver();
+        let mut dropped = vec![true, false, false, false, false];
+        resolver.skip_file(0, EmulatorError::InvalidAsset("P/Content/a.uasset".to_owned(), "Test".to_owned()), &mut dropped);
+        assert_eq!(dropped, [true, true, false, false, false]);
+        // bulk data that can't be read doesn't take anything else with it
+        let mut dropped = vec![false, false, false, true, false];
+        resolver.skip_file(3, EmulatorError::InvalidAsset("P/Content/Sub/c.ubulk".to_owned(), "Test".to_owned()), &mut dropped);
+        assert_eq!(dropped, [false, false, false, true, false]);
+    }
+
+    // Cooked packages are converted in this build's copy of the file list, so the tree still has the cooked package for the next build
+    #[test]
+    fn layout_converts_cooked_packages() {
+        use crate::pak_package::tests::{get_cooked_package, TEST_PACKAGE_PATH};
+        let (asset, uexp) = get_cooked_package(&[1, 2, 3, 4]);
+        let asset_path = write_test_file("TestAsset.uasset", &asset);
+        write_test_file("TestAsset.uexp", &uexp);
+        let mut resolver = TocResolverType2::new_inner("Test", DEFAULT_COMPRESSION_BLOCK_ALIGNMENT, IoContainerHeaderVersion::Initial);
+        resolver.directories = vec![new_test_directory(0, u32::MAX)];
+        resolver.files = vec![IoFileIndexEntry {
+            file_size: asset.len() as u64, cooked_package: true, ..new_test_file(&asset_path, TEST_PACKAGE_PATH, u32::MAX, 0)
+        }];
+        let container_data = resolver.layout_container::<PackageSummary2, ContainerHeaderPosition2>().unwrap();
+        assert_eq!(resolver.files.len(), 1);
+        let converted = &resolver.files[0];
+        assert!(!converted.cooked_package);
+        assert_ne!(converted.os_path, asset_path);
+        assert_eq!(converted.file_size, fs::metadata(&converted.os_path).unwrap().len());
+        assert!(fs::read(&converted.os_path).unwrap().ends_with(&[1, 2, 3, 4]));
+        assert_eq!(resolver.chunk_ids.len(), 2); // the package and the container header
+        assert!(!container_data.header.is_empty());
+    }
+
+    // A cooked package that can't be converted is skipped like any other package that can't be read. It's bulk data is dropped along
+    // with it, and it's reported as a skipped file for the mod that it came from
+    #[test]
+    fn unconvertible_package_is_skipped_for_its_mod() {
+        use crate::pak_package::tests::get_cooked_package;
+        const CONTAINER_NAME: &str = "SkipCookedPackage";
+        const MOD_ID: &str = "SkipCookedPackageMod";
+        let mut mod_path = std::env::temp_dir();
+        mod_path.push(EMULATOR_NAME);
+        mod_path.push("Tests");
+        mod_path.push(CONTAINER_NAME);
+        let _ = fs::remove_dir_all(&mod_path);
+        let content_path = mod_path.join("Test").join("Content");
+        fs::create_dir_all(&content_path).unwrap();
+        let (asset, uexp) = get_cooked_package(&[1, 2, 3, 4]);
+        fs::write(content_path.join("TestAsset.uasset"), &asset).unwrap();
+        fs::write(content_path.join("TestAsset.uexp"), &uexp).unwrap();
+        fs::write(content_path.join("Bad.uasset"), &asset).unwrap();
+        fs::write(content_path.join("Bad.uexp"), &uexp[..4]).unwrap(); // missing the package file tag
+        fs::write(content_path.join("Bad.ubulk"), [0; 0x10]).unwrap();
+        asset_collector::add_from_folders_to_container(MOD_ID, mod_path.to_str().unwrap(), CONTAINER_NAME).unwrap();
+        let root = asset_collector::get_container_root(CONTAINER_NAME).unwrap();
+        let toc = build_table_of_contents_inner(
+            Arc::clone(&root), &(String::from(CONTAINER_NAME) + TOC_EXTENSION), IoStoreTocVersion::PartitionSize as u32, &TocBuildOptions::default(), None
+        ).unwrap();
+        assert_eq!(toc.container.partitions[0].len(), 1); // only TestAsset.uasset
+        let profiler = ASSET_COLLECTOR_PROFILER.lock().unwrap();
+        let skipped = profiler.as_ref().unwrap().get_skipped_files(MOD_ID).unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].os_path.ends_with("Bad.uasset"));
+        assert!(skipped[0].reason.starts_with("Couldn't convert cooked package"));
+        assert!(skipped[0].reason.contains("Bad.ubulk"));
+        // the tree still has the cooked package, so it can be converted again once it's fixed
+        let content = TocDirectory::get_dir_from_path(root, &["Test", "Content"], false).unwrap();
+        assert!(TocDirectory::get_file(content, "Bad.uasset").unwrap().read().unwrap().cooked_package);
+    }
+
+    // Hot reload rebuilds while the previous container is still streaming from the cache, so changed chunks go into a new file
+    #[test]
+    fn changed_chunk_gets_new_cache_file() {
+        let options = TocBuildOptions { compression: CompressionMethod::Zlib, ..Default::default() };
+        let os_path = write_test_file("cache.ubulk", &[1; 0x100]);
+        let first = encode_chunk(&os_path, TEST_BLOCK_SIZE, &options).unwrap();
+        let first_data = fs::read(&first.os_path).unwrap();
+        assert_eq!(encode_chunk(&os_path, TEST_BLOCK_SIZE, &options).unwrap().os_path, first.os_path);
+        write_test_file("cache.ubulk", &[2; 0x100]);
+        assert_ne!(encode_chunk(&os_path, TEST_BLOCK_SIZE, &options).unwrap().os_path, first.os_path);
+        assert_eq!(fs::read(&first.os_path).unwrap(), first_data);
+        // each key GUID gets it's own file
+        let key = EncryptionKey::from_hex("0x000102030405060708090A0B0C0D0E0F101112131415161718191A1B1C1D1E1F").unwrap();
+        let first_key = encode_chunk(&os_path, TEST_BLOCK_SIZE, &TocBuildOptions { encryption: Some((1, key)), ..options }).unwrap();
+        let second_key = encode_chunk(&os_path, TEST_BLOCK_SIZE, &TocBuildOptions { encryption: Some((2, key)), ..options }).unwrap();
+        assert_ne!(first_key.os_path, second_key.os_path);
+    }
+
+    #[test]
+    fn changed_package_gets_new_cache_file() {
+        use crate::pak_package::tests::{get_cooked_package, TEST_PACKAGE_PATH};
+        let (asset, uexp) = get_cooked_package(&[1, 2, 3, 4]);
+        let asset_path = write_test_file("CachedAsset.uasset", &asset);
+        write_test_file("CachedAsset.uexp", &uexp);
+        let (first_path, first_size) = convert_cooked_package::<PackageSummary2>(&asset_path, TEST_PACKAGE_PATH, "Test").unwrap();
+        let first_data = fs::read(&first_path).unwrap();
+        assert_eq!(first_size, first_data.len() as u64);
+        assert_eq!(convert_cooked_package::<PackageSummary2>(&asset_path, TEST_PACKAGE_PATH, "Test").unwrap().0, first_path);
+        write_test_file("CachedAsset.uexp", &get_cooked_package(&[5, 6, 7, 8]).1);
+        let (second_path, _) = convert_cooked_package::<PackageSummary2>(&asset_path, TEST_PACKAGE_PATH, "Test").unwrap();
+        assert_ne!(second_path, first_path);
+        assert!(fs::read(&second_path).unwrap().ends_with(&[5, 6, 7, 8]));
+        assert_eq!(fs::read(&first_path).unwrap(), first_data);
+    }
+
+    #[test]
+    fn encode_empty_chunk() {
+        let os_path = write_test_file("empty.ubulk", &[]);
+        let chunk = encode_chunk(&os_path, TEST_BLOCK_SIZE, &TocBuildOptions { compression: CompressionMethod::Zlib, ..Default::default() }).unwrap();
+        assert_eq!(chunk.blocks.len(), 1);
+        assert_eq!(chunk.size, 0);
+    }
+}
@@ -0,0 +1,346 @@
+use byteorder::ReadBytesExt;
+use std::{
+    error::Error,
+    fs,
+    io::{Cursor, Read, Seek, SeekFrom}
+};
+use crate::{
+    encryption, encryption::EncryptionKey,
+    error::{EmulatorError, EmulatorResult},
+    io_toc::{
+        IoChunkId, IoChunkType4, IoChunkType5, IoContainerFlags, IoFileResource, IoOffsetAndLength, IoPerfectHash,
+        IoStoreTocCompressedBlockEntry, IoStoreTocEntryMeta, IoStoreTocHeader, IoStoreTocVersion, TocEntry
+    }
+};
+
+// Reads an existing UTOC back into the same types that the TOC builder writes out, so that base game containers can be inspected,
+// mod files can be checked against real chunk ids and the builder's output can be read back in.
+// The TOC is laid out as:
+//  - FIoStoreTocHeader
+//  - 4.25: FIoStoreTocEntry for each chunk, and nothing else
+//  - FIoChunkId and FIoOffsetAndLength for each chunk
+//  - 5.0+: Perfect hash seeds, then the slots of chunks without a perfect hash (PerfectHashWithOverflow)
+//  - FIoStoreTocCompressedBlockEntry for each compression block, then the compression method names
+//  - Signed: hash size, TOC signature, block signature and a SHA1 hash for each compression block
+//  - Indexed: FIoDirectoryIndexResource (encrypted if the container is)
+//  - FIoStoreTocEntryMeta for each chunk
+pub struct IoStoreToc {
+    pub header: IoStoreTocHeader,
+    pub chunk_ids: Vec<IoChunkId>,
+    pub chunk_types5: Vec<IoChunkType5>, // UE5 only, since chunk types added in UE5 are Invalid in chunk_ids
+    pub offsets_and_lengths: Vec<IoOffsetAndLength>,
+    pub perfect_hash_seeds: Vec<i32>,
+    pub chunks_without_perfect_hash: Vec<i32>,
+    pub compression_blocks: Vec<IoStoreTocCompressedBlockEntry>,
+    pub compression_methods: Vec<String>, // doesn't include None, which is compression method 0
+    pub toc_signature: Vec<u8>,
+    pub block_signature: Vec<u8>,
+    pub chunk_block_signatures: Vec<[u8; 0x14]>,
+    pub toc_hash: [u8; 0x14], // SHA1 of the TOC up until the signatures, which is what the TOC signature is made from
+    pub directory_index_buffer: Vec<u8>, // kept as is, since it can't be read without the key if the container is encrypted
+    pub directory_index: Option<IoFileResource>, // None if there's no directory index, or it's encrypted and there's no key for it
+    pub metas: Vec<IoStoreTocEntryMeta>,
+    file_paths: Vec<String> // full path (including mount point) of each entry in directory_index.file_entries
+}
+
+impl IoStoreToc {
+    pub fn from_file(toc_path: &str) -> EmulatorResult<Self> {
+        let buffer = fs::read(toc_path).map_err(|e| EmulatorError::Io(toc_path.to_owned(), e))?;
+        Self::from_buffer::<_, byteorder::NativeEndian>(&mut Cursor::new(buffer))
+            .map_err(|e| EmulatorError::InvalidAsset(toc_path.to_owned(), e.to_string()))
+    }
+
+    pub fn from_buffer<R: Read + Seek, E: byteorder::ByteOrder>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let header = IoStoreTocHeader::from_buffer::<R, E>(reader)?;
+        let entry_count = header.get_entry_count() as usize;
+        let version = header.get_version();
+        let mut toc = Self {
+            header,
+            chunk_ids: vec![],
+            chunk_types5: vec![],
+            offsets_and_lengths: vec![],
+            perfect_hash_seeds: vec![],
+            chunks_without_perfect_hash: vec![],
+            compression_blocks: vec![],
+            compression_methods: vec![],
+            toc_signature: vec![],
+            block_signature: vec![],
+            chunk_block_signatures: vec![],
+            toc_hash: [0; 0x14],
+            directory_index_buffer: vec![],
+            directory_index: None,
+            metas: vec![],
+            file_paths: vec![]
+        };
+        if let IoStoreTocHeader::Type1(_) = toc.header { // 4.25 only has a list of entries
+            for entry in TocEntry::list_from_buffer::<R, E>(reader, entry_count)? {
+                toc.chunk_ids.push(entry.get_chunk_id().to_chunk_id());
+                toc.offsets_and_lengths.push(*entry.get_offset_length());
+            }
+            return Ok(toc);
+        }
+        if version >= IoStoreTocVersion::PerfectHash {
+            let (chunk_ids, chunk_types5) = IoChunkId::list_from_buffer5::<R, E>(reader, entry_count)?.into_iter().unzip();
+            toc.chunk_ids = chunk_ids;
+            toc.chunk_types5 = chunk_types5;
+        } else {
+            toc.chunk_ids = IoChunkId::list_from_buffer::<R, E>(reader, entry_count)?;
+        }
+        toc.offsets_and_lengths = IoOffsetAndLength::list_from_buffer::<R, E>(reader, entry_count)?;
+        toc.perfect_hash_seeds = IoPerfectHash::list_from_buffer::<R, E>(reader, toc.header.get_perfect_hash_seeds_count() as usize)?;
+        toc.chunks_without_perfect_hash = IoPerfectHash::list_from_buffer::<R, E>(reader, toc.header.get_chunks_without_perfect_hash_count() as usize)?;
+        toc.compression_blocks = IoStoreTocCompressedBlockEntry::list_from_buffer::<R, E>(
+            reader, toc.header.get_compressed_block_entry_count() as usize)?;
+        // Compression method names are fixed length, null padded ASCII strings
+        for _ in 0..toc.header.get_compression_method_name_count() {
+            let mut name = vec![0; toc.header.get_compression_method_name_length() as usize];
+            reader.read_exact(&mut name)?;
+            toc.compression_methods.push(String::from_utf8_lossy(&name).trim_end_matches('\0').to_owned());
+        }
+        let flags = toc.header.get_container_flags();
+        if flags.contains(IoContainerFlags::Signed) {
+            let signature_start = reader.stream_position()?;
+            let mut signed_data = vec![0; signature_start as usize];
+            reader.seek(SeekFrom::Start(0))?;
+            reader.read_exact(&mut signed_data)?;
+            toc.toc_hash = IoStoreTocCompressedBlockEntry::hash_block(&signed_data);
+            let hash_size = reader.read_i32::<E>()? as usize;
+            toc.toc_signature = vec![0; hash_size];
+            reader.read_exact(&mut toc.toc_signature)?;
+            toc.block_signature = vec![0; hash_size];
+            reader.read_exact(&mut toc.block_signature)?;
+            for _ in 0..toc.compression_blocks.len() {
+                let mut hash = [0; 0x14];
+                reader.read_exact(&mut hash)?;
+                toc.chunk_block_signatures.push(hash);
+            }
+        }
+        if flags.contains(IoContainerFlags::Indexed) {
+            toc.directory_index_buffer = vec![0; toc.header.get_directory_index_size() as usize];
+            reader.read_exact(&mut toc.directory_index_buffer)?;
+        }
+        toc.metas = IoStoreTocEntryMeta::list_from_buffer::<R, E>(reader, entry_count)?;
+        if !toc.directory_index_buffer.is_empty() {
+            match flags.contains(IoContainerFlags::Encrypted) {
+                false => toc.read_directory_index::<E>(None)?,
+                // the directory index is left unread if the key hasn't been added, since the chunks can still be found by their id
+                true => if let Ok(key) = encryption::get_encryption_key(toc.header.get_encryption_key_guid()) {
+                    toc.read_directory_index::<E>(Some(&key))
+                        .map_err(|e| format!("Couldn't read encrypted directory index, the AES key might be wrong: {}", e))?;
+                }
+            }
+        }
+        Ok(toc)
+    }
+
+    // Parse the directory index and resolve the full path of every file in it. Encrypted directory indexes are decrypted with key first
+    pub fn read_directory_index<E: byteorder::ByteOrder>(&mut self, key: Option<&EncryptionKey>) -> Result<(), Box<dyn Error>> {
+        let mut buffer = self.directory_index_buffer.clone();
+        if let Some(key) = key {
+            key.decrypt(&mut buffer)?;
+        }
+        let index = IoFileResource::from_buffer::<_, E>(&mut Cursor::new(&buffer))?;
+        let mut file_paths = vec![String::new(); index.file_entries.len()];
+        if !index.directory_entries.is_empty() {
+            get_file_paths(&index, 0, &index.mount_point, &mut file_paths)?;
+        }
+        self.file_paths = file_paths;
+        self.directory_index = Some(index);
+        Ok(())
+    }
+
+    pub fn get_version(&self) -> IoStoreTocVersion {
+        self.header.get_version()
+    }
+
+    pub fn get_chunk_count(&self) -> usize {
+        self.chunk_ids.len()
+    }
+
+    // Find the index of a chunk in the TOC. UE5 TOCs are searched with their perfect hash, which also checks that the seeds are valid.
+    // 4.25 chunk ids hold the package's global package store index instead of a hash (see IoChunk1::to_chunk_id)
+    pub fn find_chunk(&self, chunk_id: &IoChunkId) -> Option<usize> {
+        if self.perfect_hash_seeds.is_empty() {
+            return self.chunk_ids.iter().position(|id| id == chunk_id);
+        }
+        let chunk_bytes: Vec<[u8; 0xc]> = self.chunk_ids.iter().zip(&self.chunk_types5)
+            .map(|(id, chunk_type)| get_chunk_bytes5(id, *chunk_type))
+            .collect();
+        let target = chunk_id.to_bytes5::<byteorder::NativeEndian>();
+        IoPerfectHash::get_slot(&self.perfect_hash_seeds, &self.chunks_without_perfect_hash, &chunk_bytes, &target)
+            .filter(|slot| chunk_bytes[*slot] == target)
+    }
+
+    // Chunk id for a package path, e.g "/Game/Characters/Player" (without extension). 4.25 packages can't be found by their path
+    pub fn find_package(&self, package_path: &str, chunk_type: IoChunkType4) -> Option<usize> {
+        self.find_chunk(&IoChunkId::new(package_path, chunk_type))
+    }
+
+    // Chunk index that the container header is stored in. There isn't one in 4.25
+    pub fn find_container_header(&self) -> Option<usize> {
+        self.chunk_ids.iter().position(|id| id.get_type() == IoChunkType4::ContainerHeader)
+    }
+
+    pub fn get_file_paths(&self) -> &[String] {
+        &self.file_paths
+    }
+
+    // Get the chunk index for a file, using the full path that's stored in the directory index (mount point + path)
+    pub fn find_file(&self, file_path: &str) -> Option<usize> {
+        let index = self.directory_index.as_ref()?;
+        self.file_paths.iter().position(|p| p == file_path).map(|i| index.file_entries[i].user_data as usize)
+    }
+
+    // Offset and length of a chunk within the uncompressed container
+    pub fn get_chunk_offset_and_length(&self, chunk_index: usize) -> Option<(u64, u64)> {
+        self.offsets_and_lengths.get(chunk_index).map(|v| (v.get_offset(), v.get_length()))
+    }
+}
+
+// Rebuild the serialized UE5 chunk id using the original chunk type, for chunk types that can't be stored in IoChunkId
+fn get_chunk_bytes5(chunk_id: &IoChunkId, chunk_type: IoChunkType5) -> [u8; 0xc] {
+    let mut bytes = chunk_id.to_bytes5::<byteorder::NativeEndian>();
+    bytes[0xb] = chunk_type.into();
+    bytes
+}
+
+// Walk through a directory and it's children, building the path for each of their files
+fn get_file_paths(index: &IoFileResource, directory: u32, path: &str, file_paths: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
+    let get_string = |i: u32| index.strings.get(i as usize).ok_or_else(|| EmulatorError::InvalidValue(
+        format!("String index {} is out of range of the directory index's {} strings", i, index.strings.len())
+    ));
+    let mut curr_dir = directory;
+    while curr_dir != u32::MAX {
+        let dir = index.directory_entries.get(curr_dir as usize).ok_or_else(|| EmulatorError::InvalidValue(
+            format!("Directory index {} is out of range", curr_dir)
+        ))?;
+        let dir_path = match dir.name {
+            u32::MAX => path.to_owned(), // root has no name
+            name => path.to_owned() + get_string(name)? + "/"
+        };
+        let mut curr_file = dir.first_file;
+        while curr_file != u32::MAX {
+            let file = index.file_entries.get(curr_file as usize).ok_or_else(|| EmulatorError::InvalidValue(
+                format!("File index {} is out of range", curr_file)
+            ))?;
+            file_paths[curr_file as usize] = dir_path.clone() + get_string(file.name)?;
+            curr_file = file.next_file;
+        }
+        if dir.first_child != u32::MAX {
+            get_file_paths(index, dir.first_child, &dir_path, file_paths)?;
+        }
+        curr_dir = dir.next_sibling;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asset_collector, toc_factory, toc_factory::TocBuildOptions,
+        io_toc::{IoChunk1, IoContainerId, IoStoreTocHeaderCommon, IoStoreTocHeaderType1}
+    };
+    use std::path::PathBuf;
+
+    const TEST_FILES: [(&str, usize); 3] = [("Proj/Content/a.ubulk", 100), ("Proj/Content/Sub/b.ubulk", 0x12345), ("Proj/Content/Sub/c.uptnl", 0)];
+
+    // Each test builds it's own container, since containers with the same name would share a tree
+    fn build_test_container(container_name: &str, version: IoStoreTocVersion, engine_minor_version: u32) -> IoStoreToc {
+        let toc = try_build_test_container(container_name, version, engine_minor_version).unwrap();
+        IoStoreToc::from_buffer::<_, byteorder::NativeEndian>(&mut Cursor::new(toc.toc)).unwrap()
+    }
+
+    fn try_build_test_container(container_name: &str, version: IoStoreTocVersion, engine_minor_version: u32) -> EmulatorResult<toc_factory::TableOfContents> {
+        let mut test_path = std::env::temp_dir();
+        test_path.push(asset_collector::EMULATOR_NAME);
+        test_path.push("Tests");
+        test_path.push(container_name);
+        for (path, size) in TEST_FILES {
+            let os_path: PathBuf = test_path.join("Mod").join(path);
+            fs::create_dir_all(os_path.parent().unwrap()).unwrap();
+            fs::write(&os_path, (0..size).map(|i| i as u8).collect::<Vec<u8>>()).unwrap();
+        }
+        let mod_path = test_path.join("Mod");
+        asset_collector::add_from_folders_to_container(container_name, mod_path.to_str().unwrap(), container_name).unwrap();
+        let toc_path = test_path.join(format!("{}{}", container_name, toc_factory::TOC_EXTENSION));
+        let version = u8::from(version) as u32 | engine_minor_version << 8;
+        toc_factory::build_table_of_contents_with_options(toc_path.to_str().unwrap(), version, &TocBuildOptions::default())
+    }
+
+    fn check_test_container(toc: &IoStoreToc) {
+        assert_eq!(toc.get_chunk_count(), TEST_FILES.len() + 1); // and the container header
+        assert!(toc.find_container_header().is_some());
+        let mut file_paths = toc.get_file_paths().to_vec();
+        file_paths.sort();
+        let mut expected_paths: Vec<String> = TEST_FILES.iter().map(|(path, _)| format!("../../../{}", path)).collect();
+        expected_paths.sort();
+        assert_eq!(file_paths, expected_paths);
+        for ((path, size), (package_path, chunk_type)) in TEST_FILES.iter().zip([
+            ("/Game/a", IoChunkType4::BulkData), ("/Game/Sub/b", IoChunkType4::BulkData), ("/Game/Sub/c", IoChunkType4::OptionalBulkData)
+        ]) {
+            let chunk_index = toc.find_file(&format!("../../../{}", path)).unwrap();
+            assert_eq!(toc.find_package(package_path, chunk_type), Some(chunk_index));
+            assert_eq!(toc.get_chunk_offset_and_length(chunk_index).unwrap().1, *size as u64);
+        }
+    }
+
+    // 4.25 containers can't be built (see TocResolverType1::serialize), so write one the way the game's are laid out
+    #[test]
+    fn read_initial_container() {
+        let entries = [
+            TocEntry::new(IoChunk1::new(7, 0, IoChunkType4::ExportBundleData), IoOffsetAndLength::new(0, 0x123)),
+            TocEntry::new(IoChunk1::new(7, 0, IoChunkType4::BulkData), IoOffsetAndLength::new(0x130, 0x40)),
+            TocEntry::new(IoChunk1::new(0x10000, 1, IoChunkType4::ExportBundleData), IoOffsetAndLength::new(0x170, 0x8000)),
+        ];
+        let mut writer = Cursor::new(vec![]);
+        IoStoreTocHeaderType1::new(IoContainerId::default(), entries.len() as u32, 0, 0, 0)
+            .to_buffer::<_, byteorder::NativeEndian>(&mut writer).unwrap();
+        TocEntry::list_to_buffer::<_, byteorder::NativeEndian>(&entries, &mut writer).unwrap();
+        let toc = IoStoreToc::from_buffer::<_, byteorder::NativeEndian>(&mut Cursor::new(writer.into_inner())).unwrap();
+        assert_eq!(toc.get_version(), IoStoreTocVersion::Initial);
+        assert_eq!(toc.get_chunk_count(), entries.len());
+        assert!(toc.find_container_header().is_none());
+        assert!(toc.get_file_paths().is_empty());
+        for (i, entry) in entries.iter().enumerate() {
+            assert_eq!(toc.chunk_ids[i], entry.get_chunk_id().to_chunk_id());
+            assert_eq!(toc.find_chunk(&entry.get_chunk_id().to_chunk_id()), Some(i));
+            let offset_length = entry.get_offset_length();
+            assert_eq!(toc.get_chunk_offset_and_length(i), Some((offset_length.get_offset(), offset_length.get_length())));
+        }
+        assert_eq!(toc.chunk_ids[2].get_raw_hash(), 0x10000);
+        assert_eq!(toc.find_chunk(&IoChunkId::new_from_hash(7, IoChunkType4::OptionalBulkData)), None);
+    }
+
+    #[test]
+    fn initial_container_is_refused() {
+        match try_build_test_container("ReaderTest425", IoStoreTocVersion::Initial, 0) {
+            Err(EmulatorError::UnsupportedVersion(reason)) => assert!(reason.contains("global package store")),
+            Err(e) => panic!("Expected an unsupported version error, got {}", e),
+            Ok(_) => panic!("Built a 4.25 container, which can't refer to mod packages")
+        }
+    }
+
+    #[test]
+    fn read_directory_index_container() {
+        let toc = build_test_container("ReaderTest426", IoStoreTocVersion::DirectoryIndex, 0);
+        assert_eq!(toc.get_version(), IoStoreTocVersion::DirectoryIndex);
+        assert!(toc.perfect_hash_seeds.is_empty());
+        check_test_container(&toc);
+    }
+
+    #[test]
+    fn read_partition_size_container() {
+        let toc = build_test_container("ReaderTest427", IoStoreTocVersion::PartitionSize, 0);
+        assert_eq!(toc.get_version(), IoStoreTocVersion::PartitionSize);
+        check_test_container(&toc);
+    }
+
+    #[test]
+    fn read_perfect_hash_container() {
+        let toc = build_test_container("ReaderTest51", IoStoreTocVersion::PerfectHashWithOverflow, 1);
+        assert_eq!(toc.get_version(), IoStoreTocVersion::PerfectHashWithOverflow);
+        assert!(!toc.perfect_hash_seeds.is_empty());
+        check_test_container(&toc);
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as FmtWrite,
+    fs, fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf}
+};
+use crate::{
+    compression::CompressionMethod,
+    encryption, encryption::EncryptionKey,
+    error::{EmulatorError, EmulatorResult},
+    io_toc::{ContainerHeader, IoContainerFlags, IoChunkType4, IoStoreTocCompressedBlockEntry, IoStoreTocHeader},
+    toc_factory,
+    toc_reader::IoStoreToc
+};
+
+// Extracts the chunks in an existing UTOC/UCAS pair back out into loose files, so that modders have the original assets to start from.
+// Each file in the directory index is read through the compression blocks that cover it's range of the (uncompressed) container, then
+// written out relative to the mount point. Chunks that aren't in the directory index are written into a Chunks folder, named by chunk id.
+// 4.25 containers have no directory index, so all of their chunks end up there. Encrypted containers need their key to be added first
+// (see encryption.rs), and blocks that use a compression method without a Rust implementation can't be read (see CompressionMethod)
+pub const UNPACKED_CHUNKS_FOLDER: &str = "Chunks";
+pub const STORE_ENTRIES_DUMP: &str = "StoreEntries.txt";
+
+pub struct IoStoreContainer {
+    pub toc: IoStoreToc,
+    partitions: Vec<File> // .ucas, then _s1.ucas, _s2.ucas... for 4.27+ containers that are split into partitions
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyResults {
+    pub checked_blocks: usize,
+    pub mismatched_blocks: Vec<usize>, // blocks that couldn't be read are included here too
+    // Only containers signed by the TOC builder can have their signatures checked, since real ones are RSA signatures. None otherwise
+    pub toc_signature_matches: Option<bool>,
+    pub block_signature_matches: Option<bool>
+}
+
+#[derive(Debug, Default)]
+pub struct UnpackResults {
+    pub extracted_files: u64,
+    pub extracted_size: u64,
+    pub failed: Vec<(String, String)> // path, reason
+}
+
+impl IoStoreContainer {
+    // Open a TOC along with every partition of it's container
+    pub fn open(toc_path: &str) -> EmulatorResult<Self> {
+        let toc = IoStoreToc::from_file(toc_path)?;
+        let cas_path = Path::new(toc_path).with_extension("ucas").to_string_lossy().to_string();
+        let mut partitions = vec![];
+        for i in 0..toc.header.get_partition_count() {
+            let partition_path = toc_factory::get_partition_path(&cas_path, i as usize);
+            partitions.push(File::open(&partition_path).map_err(|e| EmulatorError::Io(partition_path, e))?);
+        }
+        Ok(Self { toc, partitions })
+    }
+
+    // Read a chunk's data from the container
+    pub fn read_chunk(&mut self, chunk_index: usize) -> EmulatorResult<Vec<u8>> {
+        let (offset, length) = self.toc.get_chunk_offset_and_length(chunk_index).ok_or_else(|| EmulatorError::InvalidArgument(
+            format!("Chunk {} is out of range of the TOC's {} chunks", chunk_index, self.toc.get_chunk_count())
+        ))?;
+        if let IoStoreTocHeader::Type1(_) = self.toc.header { // 4.25 has no compression blocks, offsets point straight into the container
+            return self.read_physical(offset, length as usize);
+        }
+        let key = match self.toc.header.get_container_flags().contains(IoContainerFlags::Encrypted) {
+            true => Some(encryption::get_encryption_key(self.toc.header.get_encryption_key_guid())?),
+            false => None
+        };
+        if length == 0 {
+            return Ok(vec![]);
+        }
+        let block_size = self.toc.header.get_compression_block_size() as u64;
+        let first_block = offset / block_size;
+        let last_block = (offset + length - 1) / block_size;
+        let mut data = Vec::with_capacity(((last_block - first_block + 1) * block_size) as usize);
+        for block_index in first_block..=last_block {
+            let block = self.toc.compression_blocks.get(block_index as usize).ok_or_else(|| EmulatorError::InvalidValue(
+                format!("Chunk {} uses compression block {}, but there's only {}", chunk_index, block_index, self.toc.compression_blocks.len())
+            ))?;
+            let method = match block.get_compression_method() {
+                0 => CompressionMethod::None,
+                i => CompressionMethod::from_name(self.toc.compression_methods.get(i as usize - 1).ok_or_else(|| EmulatorError::InvalidValue(
+                    format!("Compression method {} is out of range of the TOC's {} methods", i, self.toc.compression_methods.len())
+                ))?)?
+            };
+            let (block_offset, block_length, uncompressed_size) = (block.get_offset(), block.get_compressed_size() as usize, block.get_uncompressed_size() as usize);
+            let block_data = match key.as_ref() {
+                Some(key) => self.read_encrypted(block_offset, block_length, key)?,
+                None => self.read_physical(block_offset, block_length)?
+            };
+            data.extend_from_slice(&method.decompress(&block_data, uncompressed_size)?);
+        }
+        let start = (offset % block_size) as usize;
+        data.get(start..start + length as usize).map(|d| d.to_vec()).ok_or_else(|| EmulatorError::InvalidValue(
+            format!("Compression blocks for chunk {} are shorter than it's length", chunk_index)
+        ))
+    }
+
+    // Read from the container's physical offsets, which are split across partitions of partition_size bytes
+    fn read_physical(&mut self, offset: u64, length: usize) -> EmulatorResult<Vec<u8>> {
+        let partition_size = self.toc.header.get_partition_size();
+        let partition = (offset / partition_size) as usize;
+        let map_err = |e| EmulatorError::Io(format!("container partition {}", partition), e);
+        let file = self.partitions.get_mut(partition).ok_or_else(|| EmulatorError::InvalidValue(
+            format!("Offset {:x} is in partition {}, which doesn't exist", offset, partition)
+        ))?;
+        file.seek(SeekFrom::Start(offset % partition_size)).map_err(map_err)?;
+        let mut data = vec![0; length];
+        file.read_exact(&mut data).map_err(map_err)?;
+        Ok(data)
+    }
+
+    // Encrypted blocks are padded out to the AES block size
+    fn read_encrypted(&mut self, offset: u64, length: usize, key: &EncryptionKey) -> EmulatorResult<Vec<u8>> {
+        let aligned_length = length.div_ceil(encryption::AES_BLOCK_SIZE as usize) * encryption::AES_BLOCK_SIZE as usize;
+        let mut data = self.read_physical(offset, aligned_length)?;
+        key.decrypt(&mut data)?;
+        data.truncate(length);
+        Ok(data)
+    }
+
+    // Check the data of every block in the container against the hashes in a signed TOC, to find blocks that have been corrupted or changed
+    pub fn verify_blocks(&mut self) -> EmulatorResult<VerifyResults> {
+        if !self.toc.header.get_container_flags().contains(IoContainerFlags::Signed) {
+            return Err(EmulatorError::InvalidArgument("Container isn't signed, so it doesn't have any block hashes to check".to_owned()));
+        }
+        let mut results = VerifyResults::default();
+        for i in 0..self.toc.compression_blocks.len() {
+            let (offset, disk_size) = (self.toc.compression_blocks[i].get_offset(), self.toc.compression_blocks[i].get_disk_size() as usize);
+            let matches = match self.read_physical(offset, disk_size) {
+                Ok(data) => self.toc.chunk_block_signatures.get(i) == Some(&IoStoreTocCompressedBlockEntry::hash_block(&data)),
+                Err(_) => false
+            };
+            if !matches {
+                results.mismatched_blocks.push(i);
+            }
+            results.checked_blocks += 1;
+        }
+        let block_hashes: Vec<u8> = self.toc.chunk_block_signatures.iter().flatten().copied().collect();
+        let is_hash = |signature: &[u8]| signature.len() == 0x14;
+        results.toc_signature_matches = is_hash(&self.toc.toc_signature).then(|| self.toc.toc_signature == self.toc.toc_hash);
+        results.block_signature_matches = is_hash(&self.toc.block_signature)
+            .then(|| self.toc.block_signature == IoStoreTocCompressedBlockEntry::hash_block(&block_hashes));
+        Ok(results)
+    }
+
+    // The container header holds the store entry of every package in the container. There isn't one in 4.25
+    pub fn read_container_header(&mut self) -> EmulatorResult<Option<ContainerHeader>> {
+        let chunk_index = match self.toc.find_container_header() {
+            Some(i) => i,
+            None => return Ok(None)
+        };
+        let data = self.read_chunk(chunk_index)?;
+        Ok(Some(ContainerHeader::from_buffer::<_, byteorder::NativeEndian>(&mut Cursor::new(data))?))
+    }
+
+    // Extract every chunk into output_path. Chunks that fail to be read or written are skipped
+    pub fn extract_all(&mut self, output_path: &Path) -> EmulatorResult<UnpackResults> {
+        let mut results = UnpackResults::default();
+        let mut chunk_paths: Vec<Option<PathBuf>> = vec![None; self.toc.get_chunk_count()];
+        if let Some(index) = self.toc.directory_index.as_ref() {
+            for (file, file_path) in index.file_entries.iter().zip(self.toc.get_file_paths()) {
+                match (chunk_paths.get_mut(file.user_data as usize), get_relative_path(file_path)) {
+                    (Some(chunk_path), Some(relative_path)) => *chunk_path = Some(relative_path),
+                    _ => results.failed.push((file_path.clone(), "File doesn't have a valid path or chunk".to_owned()))
+                }
+            }
+        }
+        for (chunk_index, chunk_path) in chunk_paths.into_iter().enumerate() {
+            let chunk_id = &self.toc.chunk_ids[chunk_index];
+            let chunk_path = match chunk_path {
+                Some(p) => p,
+                None if chunk_id.get_type() == IoChunkType4::ContainerHeader => continue, // see dump_store_entries
+                None => {
+                    let chunk_type = match self.toc.chunk_types5.get(chunk_index) {
+                        Some(t) => format!("{:?}", t),
+                        None => format!("{:?}", chunk_id.get_type())
+                    };
+                    PathBuf::from(UNPACKED_CHUNKS_FOLDER).join(format!("{:016x}_{}_{}.bin", chunk_id.get_raw_hash(), chunk_id.get_index(), chunk_type))
+                }
+            };
+            let os_path = output_path.join(&chunk_path);
+            match self.extract_chunk(chunk_index, &os_path) {
+                Ok(size) => {
+                    results.extracted_files += 1;
+                    results.extracted_size += size;
+                },
+                Err(e) => {
+                    println!("WARNING: Couldn't extract {}: {}", chunk_path.to_string_lossy(), e);
+                    results.failed.push((chunk_path.to_string_lossy().to_string(), e.to_string()));
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn extract_chunk(&mut self, chunk_index: usize, os_path: &Path) -> EmulatorResult<u64> {
+        let data = self.read_chunk(chunk_index)?;
+        let map_err = |e| EmulatorError::Io(os_path.to_string_lossy().to_string(), e);
+        if let Some(parent) = os_path.parent() {
+            fs::create_dir_all(parent).map_err(map_err)?;
+        }
+        fs::write(os_path, &data).map_err(map_err)?;
+        Ok(data.len() as u64)
+    }
+
+    // Write out the store entry for each package in the container header, along with the package's path if it's in the directory index
+    pub fn dump_store_entries(&mut self, dump_path: &Path) -> EmulatorResult<()> {
+        let header = match self.read_container_header()? {
+            Some(h) => h,
+            None => return Err(EmulatorError::NoModFiles("Container doesn't have a container header".to_owned()))
+        };
+        // export bundle chunk ids are made from the package id, so packages can be matched up to their file through that
+        let mut package_paths: HashMap<u64, &str> = HashMap::new();
+        if let Some(index) = self.toc.directory_index.as_ref() {
+            for (file, file_path) in index.file_entries.iter().zip(self.toc.get_file_paths()) {
+                if let Some(chunk_id) = self.toc.chunk_ids.get(file.user_data as usize) {
+                    if chunk_id.get_type() == IoChunkType4::ExportBundleData {
+                        package_paths.insert(chunk_id.get_raw_hash(), file_path);
+                    }
+                }
+            }
+        }
+        let mut dump = String::new();
+        writeln!(dump, "Container 0x{:016x}, header version {:?}, {} packages", header.get_container_id(), header.get_version(), header.packages.len()).unwrap();
+        for package in &header.packages {
+            writeln!(dump, "0x{:016x} {}", package.hash, package_paths.get(&package.hash).unwrap_or(&"(not in directory index)")).unwrap();
+            writeln!(dump, "    Export Bundle Size: {}, Export Count: {}, Export Bundle Count: {}, Load Order: {}",
+                package.get_export_bundle_size(), package.get_export_count(), package.get_export_bundle_count(), package.get_load_order()).unwrap();
+            let imports: Vec<String> = package.get_import_ids().iter().map(|i| format!("0x{:016x}", i)).collect();
+            writeln!(dump, "    Imports ({}): {}", imports.len(), imports.join(", ")).unwrap();
+        }
+        fs::write(dump_path, dump).map_err(|e| EmulatorError::Io(dump_path.to_string_lossy().to_string(), e))
+    }
+}
+
+// Strip the mount point's parent folders (e.g "../../../Game/Content/A.uasset" -> "Game/Content/A.uasset"). Paths that would still end up
+// outside of the output folder are rejected
+fn get_relative_path(file_path: &str) -> Option<PathBuf> {
+    let relative_path = PathBuf::from(file_path.trim_start_matches("../"));
+    match relative_path.components().all(|c| matches!(c, Component::Normal(_))) {
+        true => Some(relative_path),
+        false => None
+    }
+}